@@ -0,0 +1,114 @@
+//! Signed audit exports: a time range of entries plus an Ed25519 signature that verifies offline.
+//! Used by: handlers::audit.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::audit::sqlite::AuditEntry;
+use crate::error::Result;
+use crate::token::keys::{PinnedKey, SigningDomain};
+
+/// What the signature covers. Bounds are echoed back as given; `None` means open-ended.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct BundleContents {
+    pub since: Option<String>,
+    pub until: Option<String>,
+    /// The `after` this page was requested with, so a signed page cannot be passed off as another.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub after: Option<i64>,
+    pub generated_at: String,
+    pub entries: Vec<AuditEntry>,
+    /// Pass as `after` to fetch the rest of the range; absent once the range is exhausted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub next_after: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct AuditBundle {
+    /// JSON-encoded `BundleContents`; the signature covers exactly these bytes.
+    pub payload: String,
+    /// base64url Ed25519 signature over `payload` in `SigningDomain::AuditBundle`.
+    pub signature: String,
+    /// base64url signing public key. A verifier should compare it with a copy obtained out of band.
+    pub public_key: String,
+}
+
+impl AuditBundle {
+    pub fn sign(contents: &BundleContents, key: &SigningKey) -> Result<Self> {
+        let payload = serde_json::to_string(contents)?;
+        let signature = key.sign(&SigningDomain::AuditBundle.message(payload.as_bytes()));
+        Ok(Self {
+            payload,
+            signature: URL_SAFE_NO_PAD.encode(signature.to_bytes()),
+            public_key: URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes()),
+        })
+    }
+
+    /// Checks the signature against `key` (never the embedded `public_key`) before parsing the payload.
+    pub fn verify(&self, key: &VerifyingKey) -> Result<BundleContents> {
+        let signature = URL_SAFE_NO_PAD.decode(&self.signature)?;
+        PinnedKey::EdDSA(*key).verify(&SigningDomain::AuditBundle.message(self.payload.as_bytes()), &signature)?;
+        Ok(serde_json::from_str(&self.payload)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::token::sign::generate_keypair;
+
+    fn bundle(key: &SigningKey) -> Result<AuditBundle> {
+        let contents = BundleContents {
+            since: Some("2026-01-01T00:00:00+00:00".into()),
+            until: None,
+            after: None,
+            generated_at: "2026-01-02T00:00:00+00:00".into(),
+            entries: vec![AuditEntry {
+                event_type: "verify".into(),
+                jti: "jti-1".into(),
+                sub: "agent".into(),
+                action: "deploy".into(),
+                verified_at: "2026-01-01T12:00:00+00:00".into(),
+                detail: None,
+                approved_by: None,
+            }],
+            next_after: None,
+        };
+        AuditBundle::sign(&contents, key)
+    }
+
+    #[test]
+    fn bundle_verifies_with_signing_key() -> Result<()> {
+        let key = generate_keypair();
+        let contents = bundle(&key)?.verify(&key.verifying_key())?;
+        assert_eq!(contents.entries[0].jti, "jti-1");
+        Ok(())
+    }
+
+    #[test]
+    fn altered_byte_or_wrong_key_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let mut tampered = bundle(&key)?;
+        tampered.payload = tampered.payload.replacen("agent", "agenT", 1);
+        assert!(matches!(tampered.verify(&key.verifying_key()), Err(Error::InvalidSignature)));
+
+        let other = generate_keypair().verifying_key();
+        assert!(matches!(bundle(&key)?.verify(&other), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn signature_outside_the_audit_domain_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let mut bundle = bundle(&key)?;
+        for signed in [bundle.payload.clone().into_bytes(), SigningDomain::VerificationBundle.message(bundle.payload.as_bytes())] {
+            bundle.signature = URL_SAFE_NO_PAD.encode(key.sign(&signed).to_bytes());
+            assert!(matches!(bundle.verify(&key.verifying_key()), Err(Error::InvalidSignature)));
+        }
+        Ok(())
+    }
+}
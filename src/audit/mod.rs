@@ -1,4 +1,8 @@
 //! Audit logging for token verification events.
-//! Used by: handlers, state.
+//! Used by: handlers, config, state, main.
 
+pub mod bundle;
+pub mod retention;
+pub mod sink;
 pub mod sqlite;
+pub mod writer;
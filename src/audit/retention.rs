@@ -0,0 +1,195 @@
+//! Audit retention: how long entries are kept, per action type, and the background task that prunes the rest.
+//! Used by: config, state, main.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::audit::sqlite::AuditLog;
+use crate::error::{Error, Result};
+use crate::policy::normalize_action;
+use crate::state::AppState;
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+const PRUNE_TASK: &str = "audit_retention";
+const SECONDS_PER_DAY: u64 = 86_400;
+/// Rows deleted per statement, so the audit lock is released between batches and writers keep flowing.
+const PRUNE_BATCH: usize = 1_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AuditRetention {
+    /// Window for action types without their own; `None` keeps them forever.
+    pub default: Option<Duration>,
+    /// Windows by normalized action type, the part of an action before its first `:` (`refund` for `refund:amount:50`).
+    pub by_action: BTreeMap<String, Duration>,
+}
+
+impl AuditRetention {
+    /// `AUDIT_RETENTION_DAYS` sets the default window and `AUDIT_RETENTION_BY_ACTION` (`refund=2555,read=7`)
+    /// overrides it per action type; neither set disables pruning.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let days = |name: &str, v: &str| -> Result<Duration> {
+            match v.trim().parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Duration::from_secs(n.saturating_mul(SECONDS_PER_DAY))),
+                _ => Err(Error::Config(format!("{name} must be a positive number of days, got {v:?}"))),
+            }
+        };
+        let default = get("AUDIT_RETENTION_DAYS").map(|v| days("AUDIT_RETENTION_DAYS", &v)).transpose()?;
+        let mut by_action = BTreeMap::new();
+        for entry in get("AUDIT_RETENTION_BY_ACTION").iter().flat_map(|v| v.split(',')).map(str::trim) {
+            if entry.is_empty() {
+                continue;
+            }
+            let (action_type, window) = entry
+                .split_once('=')
+                .map(|(a, w)| (a.trim(), w))
+                .filter(|(a, _)| !a.is_empty() && !a.contains(':'))
+                .ok_or_else(|| {
+                    Error::Config(format!("AUDIT_RETENTION_BY_ACTION: expected action_type=days, got {entry:?}"))
+                })?;
+            let window = days("AUDIT_RETENTION_BY_ACTION", window)?;
+            let action_type = normalize_action(action_type);
+            if by_action.insert(action_type.clone(), window).is_some() {
+                return Err(Error::Config(format!("AUDIT_RETENTION_BY_ACTION lists {action_type} twice")));
+            }
+        }
+        if default.is_none() && by_action.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(Self { default, by_action }))
+    }
+
+    /// Deletes every entry older than its action type's window at `now`, `PRUNE_BATCH` rows at a
+    /// time; returns how many went. Blocks on SQLite, so async callers run it on a blocking thread.
+    pub fn prune(&self, audit: &AuditLog, now: DateTime<Utc>) -> Result<usize> {
+        let cutoff = |window: &Duration| now - chrono::Duration::seconds(window.as_secs().min(i64::MAX as u64) as i64);
+        let mut pruned = 0;
+        for (action_type, window) in &self.by_action {
+            pruned += in_batches(|| audit.prune_action_type(action_type, cutoff(window), PRUNE_BATCH))?;
+        }
+        if let Some(window) = &self.default {
+            let kept: Vec<&str> = self.by_action.keys().map(String::as_str).collect();
+            pruned += in_batches(|| audit.prune_other_action_types(&kept, cutoff(window), PRUNE_BATCH))?;
+        }
+        Ok(pruned)
+    }
+}
+
+/// Repeats `delete` until a batch comes back short.
+fn in_batches(mut delete: impl FnMut() -> Result<usize>) -> Result<usize> {
+    let mut total = 0;
+    loop {
+        let deleted = delete()?;
+        total += deleted;
+        if deleted < PRUNE_BATCH {
+            return Ok(total);
+        }
+    }
+}
+
+/// Prunes `state.audit_retention` every `PRUNE_INTERVAL` on a blocking thread; returns at once if unset.
+pub async fn run_pruner(state: AppState) {
+    if state.audit_retention.is_none() {
+        return;
+    }
+    state.heartbeats.register(PRUNE_TASK, PRUNE_INTERVAL);
+    let mut ticker = tokio::time::interval(PRUNE_INTERVAL);
+    loop {
+        ticker.tick().await;
+        let task_state = state.clone();
+        let pruned = tokio::task::spawn_blocking(move || match &task_state.audit_retention {
+            Some(retention) => retention.prune(&task_state.audit_log, Utc::now()),
+            None => Ok(0),
+        });
+        match pruned.await {
+            Ok(Ok(0)) => {}
+            Ok(Ok(pruned)) => tracing::info!(pruned, "audit entries past retention pruned"),
+            Ok(Err(e)) => tracing::error!(error = %e, "audit retention pruning failed"),
+            Err(e) => tracing::error!(error = %e, "audit retention task panicked"),
+        }
+        state.heartbeats.beat(PRUNE_TASK);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Option<AuditRetention>> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        AuditRetention::from_lookup(&|name| vars.get(name).map(|v| v.to_string()))
+    }
+
+    fn days(n: i64) -> chrono::Duration {
+        chrono::Duration::days(n)
+    }
+
+    #[test]
+    fn old_reads_pruned_while_refunds_in_their_long_window_kept() -> Result<()> {
+        let retention = load(&[("AUDIT_RETENTION_BY_ACTION", "read=7, refund=2555")])?
+            .ok_or_else(|| Error::Config("retention not loaded".into()))?;
+        let audit = AuditLog::open_in_memory()?;
+        let now = Utc::now();
+        audit.log("read-old", "agent", "read", now - days(10))?;
+        audit.log("read-new", "agent", "read:invoices", now - days(2))?;
+        audit.log("refund-old", "agent", "refund:amount:50", now - days(400))?;
+        audit.log("readme-old", "agent", "readme", now - days(400))?;
+
+        assert_eq!(retention.prune(&audit, now)?, 1);
+        let kept: Vec<_> = audit.range(None, None, None, 10)?.into_iter().map(|(_, e)| e.jti).collect();
+        assert_eq!(kept, ["read-new", "refund-old", "readme-old"]);
+        Ok(())
+    }
+
+    #[test]
+    fn default_window_spares_action_types_with_their_own() -> Result<()> {
+        let retention = load(&[("AUDIT_RETENTION_DAYS", "30"), ("AUDIT_RETENTION_BY_ACTION", "refund=2555")])?
+            .ok_or_else(|| Error::Config("retention not loaded".into()))?;
+        let audit = AuditLog::open_in_memory()?;
+        let now = Utc::now();
+        audit.log("deploy-old", "agent", "deploy", now - days(31))?;
+        audit.log("deploy-new", "agent", "deploy", now - days(29))?;
+        audit.log("refund-old", "agent", "refund", now - days(31))?;
+
+        assert_eq!(retention.prune(&audit, now)?, 1);
+        let kept: Vec<_> = audit.range(None, None, None, 10)?.into_iter().map(|(_, e)| e.jti).collect();
+        assert_eq!(kept, ["deploy-new", "refund-old"]);
+        Ok(())
+    }
+
+    #[test]
+    fn action_types_normalized_like_policy_keys() -> Result<()> {
+        let retention = load(&[("AUDIT_RETENTION_BY_ACTION", " Refund =2555")])?
+            .ok_or_else(|| Error::Config("retention not loaded".into()))?;
+        assert_eq!(retention.by_action.keys().collect::<Vec<_>>(), ["refund"]);
+        Ok(())
+    }
+
+    #[test]
+    fn pruning_spans_several_batches() -> Result<()> {
+        let retention = load(&[("AUDIT_RETENTION_DAYS", "1")])?.ok_or_else(|| Error::Config("retention not loaded".into()))?;
+        let audit = AuditLog::open_in_memory()?;
+        let now = Utc::now();
+        for i in 0..PRUNE_BATCH * 2 + 5 {
+            audit.log(&format!("old-{i}"), "agent", "deploy", now - days(2))?;
+        }
+        audit.log("new", "agent", "deploy", now)?;
+
+        assert_eq!(retention.prune(&audit, now)?, PRUNE_BATCH * 2 + 5);
+        assert_eq!(audit.range(None, None, None, 10)?.len(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_windows_rejected() -> Result<()> {
+        assert_eq!(load(&[])?, None);
+        assert!(matches!(load(&[("AUDIT_RETENTION_DAYS", "0")]), Err(Error::Config(_))));
+        assert!(matches!(load(&[("AUDIT_RETENTION_BY_ACTION", "refund")]), Err(Error::Config(_))));
+        assert!(matches!(load(&[("AUDIT_RETENTION_BY_ACTION", "refund:amount=7")]), Err(Error::Config(_))));
+        assert!(matches!(load(&[("AUDIT_RETENTION_BY_ACTION", "read=7,read=8")]), Err(Error::Config(_))));
+        assert!(matches!(load(&[("AUDIT_RETENTION_BY_ACTION", "read=7, READ=8")]), Err(Error::Config(_))));
+        Ok(())
+    }
+}
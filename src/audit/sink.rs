@@ -0,0 +1,157 @@
+//! Secondary audit sinks (stdout JSON, syslog) that receive a copy of every SQLite audit entry.
+//! Used by: audit::sqlite, state.
+
+use std::net::UdpSocket;
+use std::sync::Arc;
+
+use crate::audit::sqlite::AuditEntry;
+use crate::error::{Error, Result};
+
+const DEFAULT_SYSLOG_ADDR: &str = "127.0.0.1:514";
+/// facility local0, severity info
+const SYSLOG_PRI: u8 = 134;
+
+pub trait AuditSink: Send + Sync {
+    fn name(&self) -> &'static str;
+    fn write(&self, entry: &AuditEntry) -> Result<()>;
+}
+
+/// One JSON object per line on stdout.
+pub struct StdoutSink;
+
+impl AuditSink for StdoutSink {
+    fn name(&self) -> &'static str {
+        "stdout"
+    }
+
+    fn write(&self, entry: &AuditEntry) -> Result<()> {
+        println!("{}", serde_json::to_string(entry)?);
+        Ok(())
+    }
+}
+
+/// RFC 5424 messages with a JSON body, sent over UDP.
+pub struct SyslogSink {
+    socket: UdpSocket,
+    hostname: String,
+}
+
+impl SyslogSink {
+    pub fn connect(addr: &str) -> Result<Self> {
+        let io = |e: std::io::Error| Error::Config(format!("syslog sink {addr}: {e}"));
+        let socket = UdpSocket::bind("0.0.0.0:0").map_err(io)?;
+        socket.connect(addr).map_err(io)?;
+        let hostname = std::env::var("HOSTNAME").unwrap_or_else(|_| "-".into());
+        Ok(Self { socket, hostname })
+    }
+
+    fn format(&self, entry: &AuditEntry) -> Result<String> {
+        Ok(format!(
+            "<{SYSLOG_PRI}>1 {} {} agentmint - audit - {}",
+            chrono::Utc::now().to_rfc3339(),
+            self.hostname,
+            serde_json::to_string(entry)?
+        ))
+    }
+}
+
+impl AuditSink for SyslogSink {
+    fn name(&self) -> &'static str {
+        "syslog"
+    }
+
+    fn write(&self, entry: &AuditEntry) -> Result<()> {
+        let message = self.format(entry)?;
+        self.socket
+            .send(message.as_bytes())
+            .map_err(|e| Error::ServiceUnavailable(format!("syslog send: {e}")))?;
+        Ok(())
+    }
+}
+
+/// A secondary sink named in `AUDIT_SINK`, resolved at startup and opened when state is built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SinkSpec {
+    Stdout,
+    Syslog { addr: String },
+}
+
+/// Parses `AUDIT_SINK` (e.g. `sqlite,stdout`). SQLite is always on; the rest are secondary.
+pub fn parse(spec: &str, syslog_addr: Option<&str>) -> Result<Vec<SinkSpec>> {
+    let mut sinks = Vec::new();
+    for name in spec.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        match name {
+            "sqlite" => {}
+            "stdout" => sinks.push(SinkSpec::Stdout),
+            "syslog" => sinks.push(SinkSpec::Syslog { addr: syslog_addr.unwrap_or(DEFAULT_SYSLOG_ADDR).into() }),
+            other => return Err(Error::Config(format!("AUDIT_SINK: unknown sink {other}"))),
+        }
+    }
+    Ok(sinks)
+}
+
+/// `AUDIT_SINK`, with `AUDIT_SYSLOG_ADDR` rejected unless a syslog sink is named.
+pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Vec<SinkSpec>> {
+    let syslog_addr = get("AUDIT_SYSLOG_ADDR");
+    let sinks = parse(&get("AUDIT_SINK").unwrap_or_default(), syslog_addr.as_deref())?;
+    if syslog_addr.is_some() && !sinks.iter().any(|s| matches!(s, SinkSpec::Syslog { .. })) {
+        return Err(Error::Config("AUDIT_SYSLOG_ADDR has no effect without syslog in AUDIT_SINK".into()));
+    }
+    Ok(sinks)
+}
+
+pub fn open(specs: &[SinkSpec]) -> Result<Vec<Arc<dyn AuditSink>>> {
+    let sinks = specs
+        .iter()
+        .map(|spec| -> Result<Arc<dyn AuditSink>> {
+            match spec {
+                SinkSpec::Stdout => Ok(Arc::new(StdoutSink)),
+                SinkSpec::Syslog { addr } => Ok(Arc::new(SyslogSink::connect(addr)?)),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if !sinks.is_empty() {
+        tracing::info!(sinks = ?sinks.iter().map(|s| s.name()).collect::<Vec<_>>(), "secondary audit sinks enabled");
+    }
+    Ok(sinks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_accepts_known_sinks() -> Result<()> {
+        assert!(parse("sqlite", None)?.is_empty());
+        assert_eq!(parse("sqlite, stdout", None)?, [SinkSpec::Stdout]);
+        assert_eq!(parse("syslog", None)?, [SinkSpec::Syslog { addr: DEFAULT_SYSLOG_ADDR.into() }]);
+        assert_eq!(parse("syslog", Some("10.0.0.5:514"))?, [SinkSpec::Syslog { addr: "10.0.0.5:514".into() }]);
+        assert!(matches!(parse("sqlite,kafka", None), Err(Error::Config(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn syslog_sink_sends_rfc5424_json() -> Result<()> {
+        let io = |e: std::io::Error| Error::Config(e.to_string());
+        let receiver = UdpSocket::bind("127.0.0.1:0").map_err(io)?;
+        receiver.set_read_timeout(Some(std::time::Duration::from_secs(2))).map_err(io)?;
+        let sink = SyslogSink::connect(&receiver.local_addr().map_err(io)?.to_string())?;
+
+        sink.write(&AuditEntry {
+            event_type: "verify".into(),
+            jti: "jti-1".into(),
+            sub: "agent".into(),
+            action: "deploy".into(),
+            verified_at: chrono::Utc::now().to_rfc3339(),
+            detail: None,
+            approved_by: None,
+        })?;
+
+        let mut buf = [0u8; 1024];
+        let n = receiver.recv(&mut buf).map_err(io)?;
+        let message = String::from_utf8_lossy(&buf[..n]);
+        assert!(message.starts_with("<134>1 "));
+        assert!(message.contains(r#""jti":"jti-1""#));
+        Ok(())
+    }
+}
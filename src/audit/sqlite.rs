@@ -1,82 +1,397 @@
-//! SQLite-backed audit log for token usage.
-//! Used by: handlers::proxy, handlers::audit, state.
+//! SQLite-backed audit log for token usage, fanned out to any secondary sinks.
+//! Used by: handlers::proxy, handlers::delegate, handlers::audit, audit::bundle, state.
 
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
-use rusqlite::Connection;
-use serde::Serialize;
+use rusqlite::{Connection, ErrorCode};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::error::{Result, lock_err};
+use crate::audit::sink::AuditSink;
+use crate::audit::writer::AuditRecord;
+use crate::error::{Error, Result, lock_err};
 
 const MAX_SUB_LEN: usize = 256;
 const MAX_ACTION_LEN: usize = 64;
+const MAX_DETAIL_LEN: usize = 512;
+/// How long SQLite itself waits on another connection's lock before reporting busy.
+const BUSY_TIMEOUT: Duration = Duration::from_millis(250);
+/// Retries after `busy_timeout` gives up, backing off from `BUSY_BACKOFF` and doubling each time.
+const BUSY_RETRIES: u32 = 4;
+const BUSY_BACKOFF: Duration = Duration::from_millis(10);
+/// An entry's action type: its action up to the first `:`, as `policy` reads it.
+const ACTION_TYPE_SQL: &str = "CASE WHEN instr(action, ':') > 0 THEN substr(action, 1, instr(action, ':') - 1) ELSE action END";
 
 pub struct AuditLog {
     conn: Mutex<Connection>,
+    sinks: Vec<Arc<dyn AuditSink>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EventType {
+    Verify,
+    Delegate,
+    Replay,
+    Breakglass,
+    OidcVerified,
+    OidcRejected,
+    Outcome,
+    Exchange,
+    Revoke,
+}
+
+impl EventType {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Verify => "verify",
+            Self::Delegate => "delegate",
+            Self::Replay => "replay",
+            Self::Breakglass => "breakglass",
+            Self::OidcVerified => "oidc_verified",
+            Self::OidcRejected => "oidc_rejected",
+            Self::Outcome => "outcome",
+            Self::Exchange => "exchange",
+            Self::Revoke => "revoke",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AuditEntry {
+    pub event_type: String,
     pub jti: String,
     pub sub: String,
     pub action: String,
     pub verified_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    /// Human approver named in the token for verify events; the OIDC subject for oidc_* events.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approved_by: Option<String>,
+}
+
+fn is_constraint_violation(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(f, _) if f.code == ErrorCode::ConstraintViolation)
+}
+
+fn is_busy(e: &rusqlite::Error) -> bool {
+    matches!(e, rusqlite::Error::SqliteFailure(f, _) if matches!(f.code, ErrorCode::DatabaseBusy | ErrorCode::DatabaseLocked))
 }
 
 fn truncate(value: &str, max: usize) -> &str {
     value.char_indices().nth(max).map_or(value, |(i, _)| &value[..i])
 }
 
+fn has_column(conn: &Connection, table: &str, column: &str) -> Result<bool> {
+    let mut stmt = conn.prepare(&format!("PRAGMA table_info({table})"))?;
+    let mut names = stmt.query_map([], |row| row.get::<_, String>(1))?;
+    Ok(names.any(|name| name.is_ok_and(|n| n == column)))
+}
+
+fn migrate_legacy_schema(conn: &Connection) -> Result<()> {
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'audit_log')",
+        [],
+        |row| row.get(0),
+    )?;
+    if !exists || has_column(conn, "audit_log", "event_type")? {
+        return Ok(());
+    }
+    tracing::info!("migrating audit_log to event-typed schema");
+    conn.execute_batch(
+        "ALTER TABLE audit_log RENAME TO audit_log_legacy;
+        DROP INDEX IF EXISTS idx_audit_verified_at;",
+    )?;
+    create_schema(conn)?;
+    conn.execute_batch(
+        "INSERT INTO audit_log (event_type, jti, sub, action, verified_at)
+            SELECT 'verify', jti, sub, action, verified_at FROM audit_log_legacy ORDER BY rowid;
+        DROP TABLE audit_log_legacy;",
+    )?;
+    Ok(())
+}
+
+fn create_schema(conn: &Connection) -> Result<()> {
+    conn.execute_batch(
+        "CREATE TABLE IF NOT EXISTS audit_log (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            event_type TEXT NOT NULL DEFAULT 'verify',
+            jti TEXT NOT NULL,
+            sub TEXT NOT NULL,
+            action TEXT NOT NULL,
+            verified_at TEXT NOT NULL,
+            detail TEXT,
+            approved_by TEXT
+        );
+        CREATE INDEX IF NOT EXISTS idx_audit_jti ON audit_log(jti);
+        CREATE INDEX IF NOT EXISTS idx_audit_verified_at ON audit_log(verified_at);
+        CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_outcome_jti ON audit_log(jti) WHERE event_type = 'outcome';",
+    )?;
+    Ok(())
+}
+
+fn add_text_column(conn: &Connection, column: &str) -> Result<()> {
+    if has_column(conn, "audit_log", "event_type")? && !has_column(conn, "audit_log", column)? {
+        conn.execute_batch(&format!("ALTER TABLE audit_log ADD COLUMN {column} TEXT;"))?;
+    }
+    Ok(())
+}
+
+fn init_schema(conn: &Connection) -> Result<()> {
+    migrate_legacy_schema(conn)?;
+    add_text_column(conn, "detail")?;
+    add_text_column(conn, "approved_by")?;
+    create_schema(conn)?;
+    create_verify_jti_index(conn)
+}
+
+/// One `verify` row per jti: a durable replay barrier that survives restarts of an in-memory replay store.
+fn create_verify_jti_index(conn: &Connection) -> Result<()> {
+    conn.execute_batch("CREATE UNIQUE INDEX IF NOT EXISTS idx_audit_verify_jti ON audit_log(jti) WHERE event_type = 'verify';")
+        .map_err(|e| match is_constraint_violation(&e) {
+            true => Error::Config("audit_log holds repeated verify rows for a jti; set AUDIT_ALLOW_JTI_REUSE=true to keep them".into()),
+            false => e.into(),
+        })
+}
+
 impl AuditLog {
     pub fn open(path: &str) -> Result<Self> {
         let conn = Connection::open(path)?;
-        conn.execute_batch(
-            "CREATE TABLE IF NOT EXISTS audit_log (
-                jti TEXT PRIMARY KEY,
-                sub TEXT NOT NULL,
-                action TEXT NOT NULL,
-                verified_at TEXT NOT NULL
-            );
-            CREATE INDEX IF NOT EXISTS idx_audit_verified_at ON audit_log(verified_at);",
-        )?;
+        conn.busy_timeout(BUSY_TIMEOUT)?;
+        init_schema(&conn)?;
         Ok(Self {
             conn: Mutex::new(conn),
+            sinks: Vec::new(),
         })
     }
 
+    /// `AUDIT_ALLOW_JTI_REUSE`: record every use of a multi-use jti instead of refusing a second `verify` row.
+    pub fn with_jti_reuse(self, allowed: bool) -> Result<Self> {
+        if allowed {
+            self.conn.lock().map_err(lock_err("audit"))?.execute_batch("DROP INDEX IF EXISTS idx_audit_verify_jti;")?;
+        }
+        Ok(self)
+    }
+
+    pub fn with_sinks(mut self, sinks: Vec<Arc<dyn AuditSink>>) -> Self {
+        self.sinks = sinks;
+        self
+    }
+
     pub fn open_in_memory() -> Result<Self> {
         Self::open(":memory:")
     }
 
     pub fn log(&self, jti: &str, sub: &str, action: &str, verified_at: DateTime<Utc>) -> Result<()> {
-        let sub = truncate(sub, MAX_SUB_LEN);
-        let action = truncate(action, MAX_ACTION_LEN);
-        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        self.log_event(EventType::Verify, jti, sub, action, verified_at)
+    }
+
+    pub fn log_event(
+        &self,
+        event_type: EventType,
+        jti: &str,
+        sub: &str,
+        action: &str,
+        at: DateTime<Utc>,
+    ) -> Result<()> {
+        self.log_event_with_detail(event_type, jti, sub, action, at, None)
+    }
+
+    /// `detail` carries free-form context such as a break-glass reason.
+    pub fn log_event_with_detail(
+        &self,
+        event_type: EventType,
+        jti: &str,
+        sub: &str,
+        action: &str,
+        at: DateTime<Utc>,
+        detail: Option<&str>,
+    ) -> Result<()> {
+        self.write(AuditEntry {
+            event_type: event_type.as_str().into(),
+            jti: jti.into(),
+            sub: truncate(sub, MAX_SUB_LEN).into(),
+            action: truncate(action, MAX_ACTION_LEN).into(),
+            verified_at: at.to_rfc3339(),
+            detail: detail.map(|d| truncate(d, MAX_DETAIL_LEN).into()),
+            approved_by: None,
+        })
+    }
+
+    pub fn log_record(&self, record: &AuditRecord) -> Result<()> {
+        self.write(AuditEntry {
+            event_type: record.event_type.as_str().into(),
+            jti: record.jti.clone(),
+            sub: truncate(&record.sub, MAX_SUB_LEN).into(),
+            action: truncate(&record.action, MAX_ACTION_LEN).into(),
+            verified_at: record.at.to_rfc3339(),
+            detail: record.detail.as_deref().map(|d| truncate(d, MAX_DETAIL_LEN).into()),
+            approved_by: record.approved_by.as_deref().map(|a| truncate(a, MAX_SUB_LEN).into()),
+        })
+    }
+
+    /// `log_record` on a blocking thread, so a busy database's retries never stall a runtime worker.
+    pub async fn log_record_async(self: &Arc<Self>, record: AuditRecord) -> Result<()> {
+        let log = Arc::clone(self);
+        tokio::task::spawn_blocking(move || log.log_record(&record))
+            .await
+            .map_err(|e| Error::ServiceUnavailable(format!("audit write task failed: {e}")))?
+    }
+
+    fn write(&self, entry: AuditEntry) -> Result<()> {
+        let stored = self.insert(&entry);
+        self.fan_out(&entry);
+        stored
+    }
+
+    /// Another connection holding the database past `busy_timeout` costs a few bounded retries, not a
+    /// failed request. The mutex is released while backing off so reads and other writes proceed.
+    fn insert(&self, entry: &AuditEntry) -> Result<()> {
+        let mut delay = BUSY_BACKOFF;
+        let mut attempt = 0;
+        loop {
+            let inserted = Self::insert_once(&*self.conn.lock().map_err(lock_err("audit"))?, entry);
+            match inserted {
+                Err(e) if is_busy(&e) && attempt < BUSY_RETRIES => {
+                    attempt += 1;
+                    tracing::warn!(attempt, jti = %entry.jti, "audit database busy; retrying");
+                    std::thread::sleep(delay);
+                    delay *= 2;
+                }
+                Err(e) if is_constraint_violation(&e) && entry.event_type == EventType::Verify.as_str() => {
+                    return Err(Error::ReplayDetected(entry.jti.clone()));
+                }
+                result => return Ok(result?),
+            }
+        }
+    }
+
+    fn insert_once(conn: &Connection, entry: &AuditEntry) -> rusqlite::Result<()> {
         conn.execute(
-            "INSERT INTO audit_log (jti, sub, action, verified_at) VALUES (?1, ?2, ?3, ?4)",
-            (jti, sub, action, verified_at.to_rfc3339()),
+            "INSERT INTO audit_log (event_type, jti, sub, action, verified_at, detail, approved_by) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            (
+                &entry.event_type,
+                &entry.jti,
+                &entry.sub,
+                &entry.action,
+                &entry.verified_at,
+                &entry.detail,
+                &entry.approved_by,
+            ),
         )?;
         Ok(())
     }
 
+    /// Secondary sinks are best-effort: a failure is logged and never blocks the others.
+    fn fan_out(&self, entry: &AuditEntry) {
+        for sink in &self.sinks {
+            if let Err(e) = sink.write(entry) {
+                tracing::error!(sink = sink.name(), error = %e, jti = %entry.jti, "audit sink write failed");
+            }
+        }
+    }
+
     pub fn recent(&self, limit: usize) -> Result<Vec<AuditEntry>> {
         let conn = self.conn.lock().map_err(lock_err("audit"))?;
         let mut stmt = conn.prepare(
-            "SELECT jti, sub, action, verified_at FROM audit_log ORDER BY rowid DESC LIMIT ?1",
+            "SELECT event_type, jti, sub, action, verified_at, detail, approved_by FROM audit_log ORDER BY id DESC LIMIT ?1",
+        )?;
+        let entries = stmt.query_map([limit], entry_from_row)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// Whether `jti` was ever consumed, long after the replay store has forgotten it.
+    pub fn was_verified(&self, jti: &str) -> Result<bool> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM audit_log WHERE jti = ?1 AND event_type = 'verify')",
+            [jti],
+            |row| row.get(0),
+        )?)
+    }
+
+    /// Every recorded use of `jti`, oldest first.
+    pub fn uses(&self, jti: &str) -> Result<Vec<AuditEntry>> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        let mut stmt = conn.prepare(
+            "SELECT event_type, jti, sub, action, verified_at, detail, approved_by FROM audit_log \
+             WHERE jti = ?1 AND event_type = 'verify' ORDER BY id ASC",
+        )?;
+        let entries = stmt.query_map([jti], entry_from_row)?.collect::<std::result::Result<Vec<_>, _>>()?;
+        Ok(entries)
+    }
+
+    /// The business outcome reported for `jti` through `/proxy/complete`, if any.
+    pub fn outcome(&self, jti: &str) -> Result<Option<AuditEntry>> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        let mut stmt = conn.prepare(
+            "SELECT event_type, jti, sub, action, verified_at, detail, approved_by FROM audit_log \
+             WHERE jti = ?1 AND event_type = 'outcome'",
+        )?;
+        let entry = stmt.query_map([jti], entry_from_row)?.next().transpose()?;
+        Ok(entry)
+    }
+
+    /// Entries with `since <= verified_at < until`, oldest first, each with its row id. Bounds are
+    /// RFC 3339 UTC, compared as stored; `after` resumes past the last row id of a previous page.
+    pub fn range(
+        &self,
+        since: Option<&str>,
+        until: Option<&str>,
+        after: Option<i64>,
+        limit: usize,
+    ) -> Result<Vec<(i64, AuditEntry)>> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        let mut stmt = conn.prepare(
+            "SELECT event_type, jti, sub, action, verified_at, detail, approved_by, id FROM audit_log \
+             WHERE (?1 IS NULL OR verified_at >= ?1) AND (?2 IS NULL OR verified_at < ?2) AND (?3 IS NULL OR id > ?3) \
+             ORDER BY id ASC LIMIT ?4",
         )?;
         let entries = stmt
-            .query_map([limit], |row| {
-                Ok(AuditEntry {
-                    jti: row.get(0)?,
-                    sub: row.get(1)?,
-                    action: row.get(2)?,
-                    verified_at: row.get(3)?,
-                })
-            })?
+            .query_map((since, until, after, limit), |row| Ok((row.get(7)?, entry_from_row(row)?)))?
             .collect::<std::result::Result<Vec<_>, _>>()?;
         Ok(entries)
     }
+
+    /// Deletes up to `limit` entries of `action_type` recorded before `cutoff`.
+    pub fn prune_action_type(&self, action_type: &str, cutoff: DateTime<Utc>, limit: usize) -> Result<usize> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        let sql = format!(
+            "DELETE FROM audit_log WHERE id IN
+                (SELECT id FROM audit_log WHERE {ACTION_TYPE_SQL} = ?1 AND verified_at < ?2 LIMIT ?3)"
+        );
+        Ok(conn.execute(&sql, (action_type, cutoff.to_rfc3339(), limit as i64))?)
+    }
+
+    /// Deletes up to `limit` entries recorded before `cutoff` whose action type is none of `excluded`.
+    pub fn prune_other_action_types(&self, excluded: &[&str], cutoff: DateTime<Utc>, limit: usize) -> Result<usize> {
+        let conn = self.conn.lock().map_err(lock_err("audit"))?;
+        let placeholders: Vec<String> = (3..excluded.len() + 3).map(|i| format!("?{i}")).collect();
+        let sql = format!(
+            "DELETE FROM audit_log WHERE id IN
+                (SELECT id FROM audit_log WHERE verified_at < ?1 AND {ACTION_TYPE_SQL} NOT IN ({}) LIMIT ?2)",
+            placeholders.join(", ")
+        );
+        let (cutoff, limit) = (cutoff.to_rfc3339(), limit as i64);
+        let params: Vec<&dyn rusqlite::ToSql> =
+            [&cutoff as &dyn rusqlite::ToSql, &limit].into_iter().chain(excluded.iter().map(|e| e as &dyn rusqlite::ToSql)).collect();
+        Ok(conn.execute(&sql, params.as_slice())?)
+    }
+}
+
+fn entry_from_row(row: &rusqlite::Row) -> rusqlite::Result<AuditEntry> {
+    Ok(AuditEntry {
+        event_type: row.get(0)?,
+        jti: row.get(1)?,
+        sub: row.get(2)?,
+        action: row.get(3)?,
+        verified_at: row.get(4)?,
+        detail: row.get(5)?,
+        approved_by: row.get(6)?,
+    })
 }
 
 #[cfg(test)]
@@ -108,6 +423,25 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn range_is_half_open_and_oldest_first() -> Result<()> {
+        let audit = AuditLog::open_in_memory()?;
+        let base = Utc::now();
+        for (i, jti) in ["jti-1", "jti-2", "jti-3"].iter().enumerate() {
+            audit.log(jti, "agent", "deploy", base + chrono::Duration::minutes(i as i64))?;
+        }
+        let since = (base + chrono::Duration::minutes(1)).to_rfc3339();
+        let until = (base + chrono::Duration::minutes(2)).to_rfc3339();
+        let entries = audit.range(Some(&since), Some(&until), None, 10)?;
+        assert_eq!(entries.iter().map(|(_, e)| e.jti.as_str()).collect::<Vec<_>>(), ["jti-2"]);
+        let all = audit.range(None, None, None, 10)?;
+        assert_eq!(all[0].1.jti, "jti-1");
+
+        let resumed = audit.range(None, None, Some(all[0].0), 10)?;
+        assert_eq!(resumed.iter().map(|(_, e)| e.jti.as_str()).collect::<Vec<_>>(), ["jti-2", "jti-3"]);
+        Ok(())
+    }
+
     #[test]
     fn empty_log_returns_empty_vec() -> Result<()> {
         let audit = AuditLog::open_in_memory()?;
@@ -117,11 +451,106 @@ mod tests {
     }
 
     #[test]
-    fn duplicate_jti_rejected_by_db() -> Result<()> {
+    fn each_use_of_a_jti_recorded_when_reuse_allowed() -> Result<()> {
+        let audit = AuditLog::open_in_memory()?.with_jti_reuse(true)?;
+        let first = Utc::now();
+        audit.log("jti-1", "a", "x", first)?;
+        audit.log("jti-1", "a", "x", first + chrono::Duration::seconds(1))?;
+        audit.log("jti-2", "a", "x", first)?;
+        let uses = audit.uses("jti-1")?;
+        assert_eq!(uses.len(), 2);
+        assert!(uses[0].verified_at < uses[1].verified_at);
+        Ok(())
+    }
+
+    #[test]
+    fn one_outcome_per_jti() -> Result<()> {
         let audit = AuditLog::open_in_memory()?;
+        assert!(audit.outcome("jti-1")?.is_none());
+        audit.log_event_with_detail(EventType::Outcome, "jti-1", "a", "success", Utc::now(), Some("order 42"))?;
+        assert!(audit.log_event(EventType::Outcome, "jti-1", "a", "failure", Utc::now()).is_err());
+        let outcome = audit.outcome("jti-1")?;
+        let recorded = outcome.as_ref().map(|e| (e.action.as_str(), e.detail.as_deref()));
+        assert_eq!(recorded, Some(("success", Some("order 42"))));
+        Ok(())
+    }
+
+    #[test]
+    fn second_verify_of_a_jti_is_a_replay_by_default() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("agentmint-audit-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        AuditLog::open(&path)?.log("jti-1", "a", "x", Utc::now())?;
+        let reopened = AuditLog::open(&path)?;
+        assert!(matches!(reopened.log("jti-1", "a", "x", Utc::now()), Err(Error::ReplayDetected(_))));
+        reopened.log_event(EventType::Replay, "jti-1", "a", "x", Utc::now())?;
+        assert_eq!(reopened.uses("jti-1")?.len(), 1);
+        drop(reopened);
+        std::fs::remove_file(&path).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    #[test]
+    fn repeated_verify_rows_need_reuse_allowed_to_reopen() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        create_schema(&conn)?;
+        let audit = AuditLog { conn: Mutex::new(conn), sinks: Vec::new() };
+        audit.log("jti-1", "a", "x", Utc::now())?;
         audit.log("jti-1", "a", "x", Utc::now())?;
-        let result = audit.log("jti-1", "a", "x", Utc::now());
-        assert!(result.is_err());
+        let conn = audit.conn.lock().map_err(lock_err("audit"))?;
+        assert!(matches!(init_schema(&conn), Err(Error::Config(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn replay_events_allowed_for_verified_jti() -> Result<()> {
+        let audit = AuditLog::open_in_memory()?;
+        audit.log("jti-1", "agent", "deploy", Utc::now())?;
+        audit.log_event(EventType::Replay, "jti-1", "agent", "deploy", Utc::now())?;
+        audit.log_event(EventType::Replay, "jti-1", "agent", "deploy", Utc::now())?;
+        let entries = audit.recent(10)?;
+        assert_eq!(entries.len(), 3);
+        assert_eq!(entries[0].event_type, "replay");
+        assert_eq!(entries[2].event_type, "verify");
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_schema_migrated() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE audit_log (jti TEXT PRIMARY KEY, sub TEXT NOT NULL, action TEXT NOT NULL, verified_at TEXT NOT NULL);
+            INSERT INTO audit_log VALUES ('jti-old', 'agent', 'deploy', '2025-01-01T00:00:00+00:00');",
+        )?;
+        init_schema(&conn)?;
+        let audit = AuditLog { conn: Mutex::new(conn), sinks: Vec::new() };
+        audit.log_event(EventType::Replay, "jti-old", "agent", "deploy", Utc::now())?;
+        let entries = audit.recent(10)?;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[1].jti, "jti-old");
+        assert_eq!(entries[1].event_type, "verify");
+        Ok(())
+    }
+
+    #[test]
+    fn detail_and_approver_columns_added_to_event_typed_schema() -> Result<()> {
+        let conn = Connection::open_in_memory()?;
+        conn.execute_batch(
+            "CREATE TABLE audit_log (id INTEGER PRIMARY KEY AUTOINCREMENT, event_type TEXT NOT NULL DEFAULT 'verify',
+                jti TEXT NOT NULL, sub TEXT NOT NULL, action TEXT NOT NULL, verified_at TEXT NOT NULL);",
+        )?;
+        init_schema(&conn)?;
+        let audit = AuditLog { conn: Mutex::new(conn), sinks: Vec::new() };
+        audit.log_event_with_detail(EventType::Breakglass, "jti-1", "oncall", "deploy", Utc::now(), Some("INC-1"))?;
+        assert_eq!(audit.recent(1)?[0].detail.as_deref(), Some("INC-1"));
+        audit.log_record(&AuditRecord {
+            event_type: EventType::Verify,
+            jti: "jti-2".into(),
+            sub: "agent".into(),
+            action: "deploy".into(),
+            at: Utc::now(),
+            detail: None,
+            approved_by: Some("alice@example.com".into()),
+        })?;
+        assert_eq!(audit.recent(1)?[0].approved_by.as_deref(), Some("alice@example.com"));
         Ok(())
     }
 
@@ -144,4 +573,64 @@ mod tests {
         assert_eq!(entries[0].action.len(), MAX_ACTION_LEN);
         Ok(())
     }
+
+    struct CaptureSink(Mutex<Vec<AuditEntry>>);
+
+    impl AuditSink for CaptureSink {
+        fn name(&self) -> &'static str {
+            "capture"
+        }
+
+        fn write(&self, entry: &AuditEntry) -> Result<()> {
+            self.0.lock().map_err(lock_err("capture"))?.push(entry.clone());
+            Ok(())
+        }
+    }
+
+    struct FailingSink;
+
+    impl AuditSink for FailingSink {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn write(&self, _: &AuditEntry) -> Result<()> {
+            Err(crate::error::Error::ServiceUnavailable("sink down".into()))
+        }
+    }
+
+    #[test]
+    fn entry_fans_out_to_sqlite_and_secondary_sink() -> Result<()> {
+        let capture = Arc::new(CaptureSink(Mutex::new(Vec::new())));
+        let audit = AuditLog::open_in_memory()?.with_sinks(vec![Arc::new(FailingSink), capture.clone()]);
+        audit.log_event(EventType::Replay, "jti-1", "mallory", "deploy", Utc::now())?;
+
+        assert_eq!(audit.recent(10)?[0].jti, "jti-1");
+        let captured = capture.0.lock().map_err(lock_err("capture"))?;
+        assert_eq!(captured.len(), 1);
+        assert_eq!(captured[0].event_type, "replay");
+        assert_eq!(captured[0].sub, "mallory");
+        Ok(())
+    }
+
+    #[test]
+    fn busy_database_retried_until_lock_released() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("agentmint-audit-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        let audit = AuditLog::open(&path)?;
+        audit.conn.lock().map_err(lock_err("audit"))?.busy_timeout(Duration::ZERO)?;
+
+        let holder = Connection::open(&path)?;
+        holder.execute_batch("BEGIN EXCLUSIVE;")?;
+        let release = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(30));
+            holder.execute_batch("COMMIT;")
+        });
+
+        audit.log("jti-1", "agent", "deploy", Utc::now())?;
+        release.join().map_err(|_| crate::error::Error::Signing("lock holder panicked".into()))??;
+        assert_eq!(audit.recent(1)?[0].jti, "jti-1");
+        std::fs::remove_file(&path).ok();
+        Ok(())
+    }
 }
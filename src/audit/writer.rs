@@ -0,0 +1,213 @@
+//! Bounded background audit writer with a configurable overflow policy.
+//! Used by: state, handlers::proxy.
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use tokio::sync::mpsc::{self, error::SendTimeoutError, error::TrySendError};
+
+use crate::audit::sqlite::{AuditLog, EventType};
+use crate::error::{Error, Result, lock_err};
+use crate::heartbeat::Heartbeats;
+use crate::telemetry::Metrics;
+
+const DEFAULT_ENQUEUE_TIMEOUT: Duration = Duration::from_millis(100);
+const BATCH_SIZE: usize = 256;
+/// An idle writer still beats this often, so quiet traffic never reads as a dead task.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+pub const HEARTBEAT_TASK: &str = "audit_writer";
+
+/// What `submit` does when the queue is full.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Overflow {
+    /// Wait up to the timeout for space, then fail the request (`AUDIT_STRICT=true`).
+    Block(Duration),
+    /// Drop the record and count it (`AUDIT_STRICT=false`).
+    Drop,
+}
+
+/// `AUDIT_QUEUE_CAPACITY` and what happens when the queue fills.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AuditQueueSettings {
+    pub capacity: usize,
+    pub overflow: Overflow,
+}
+
+impl AuditQueueSettings {
+    /// Unset `AUDIT_QUEUE_CAPACITY` keeps audit writes synchronous; `AUDIT_STRICT` and
+    /// `AUDIT_ENQUEUE_TIMEOUT_MS` are rejected without it.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let timeout = get("AUDIT_ENQUEUE_TIMEOUT_MS")
+            .map(|v| {
+                v.parse::<u64>()
+                    .map(Duration::from_millis)
+                    .map_err(|_| Error::Config(format!("AUDIT_ENQUEUE_TIMEOUT_MS must be a number of milliseconds, got {v:?}")))
+            })
+            .transpose()?;
+        let strict = get("AUDIT_STRICT");
+        let Some(capacity) = get("AUDIT_QUEUE_CAPACITY") else {
+            if strict.is_some() || timeout.is_some() {
+                return Err(Error::Config("AUDIT_STRICT / AUDIT_ENQUEUE_TIMEOUT_MS have no effect without AUDIT_QUEUE_CAPACITY".into()));
+            }
+            return Ok(None);
+        };
+        let capacity = capacity
+            .parse()
+            .ok()
+            .filter(|c| *c > 0)
+            .ok_or_else(|| Error::Config(format!("AUDIT_QUEUE_CAPACITY must be a positive integer, got {capacity:?}")))?;
+        let overflow = match strict.as_deref() {
+            Some("false") => Overflow::Drop,
+            _ => Overflow::Block(timeout.unwrap_or(DEFAULT_ENQUEUE_TIMEOUT)),
+        };
+        Ok(Some(Self { capacity, overflow }))
+    }
+}
+
+pub struct AuditRecord {
+    pub event_type: EventType,
+    pub jti: String,
+    pub sub: String,
+    pub action: String,
+    pub at: DateTime<Utc>,
+    pub detail: Option<String>,
+    pub approved_by: Option<String>,
+}
+
+pub struct AuditWriter {
+    tx: mpsc::Sender<AuditRecord>,
+    rx: Mutex<Option<mpsc::Receiver<AuditRecord>>>,
+    capacity: usize,
+    overflow: Overflow,
+}
+
+impl AuditWriter {
+    pub fn new(capacity: usize, overflow: Overflow) -> Self {
+        let capacity = capacity.max(1);
+        let (tx, rx) = mpsc::channel(capacity);
+        Self { tx, rx: Mutex::new(Some(rx)), capacity, overflow }
+    }
+
+    pub fn from_settings(settings: AuditQueueSettings) -> Self {
+        tracing::info!(capacity = settings.capacity, overflow = ?settings.overflow, "audit writer queue enabled");
+        Self::new(settings.capacity, settings.overflow)
+    }
+
+    pub fn depth(&self) -> usize {
+        self.capacity - self.tx.capacity()
+    }
+
+    pub async fn submit(&self, record: AuditRecord, metrics: &Metrics) -> Result<()> {
+        let result = match self.overflow {
+            Overflow::Block(timeout) => self.tx.send_timeout(record, timeout).await.map_err(|e| match e {
+                SendTimeoutError::Timeout(_) => Error::ServiceUnavailable("audit queue full".into()),
+                SendTimeoutError::Closed(_) => Error::ServiceUnavailable("audit writer stopped".into()),
+            }),
+            Overflow::Drop => match self.tx.try_send(record) {
+                Ok(()) => Ok(()),
+                Err(TrySendError::Full(r)) => {
+                    metrics.record_audit_dropped();
+                    tracing::warn!(jti = %r.jti, "audit queue full; record dropped");
+                    Ok(())
+                }
+                Err(TrySendError::Closed(_)) => Err(Error::ServiceUnavailable("audit writer stopped".into())),
+            },
+        };
+        metrics.set_audit_queue_depth(self.depth());
+        result
+    }
+
+    /// Drains the queue into the audit log in batches, each written on a blocking thread, beating
+    /// `heartbeats` after each batch and while idle. Runs once; later calls return immediately.
+    pub async fn run(&self, log: &Arc<AuditLog>, metrics: &Metrics, heartbeats: &Heartbeats) -> Result<()> {
+        let Some(mut rx) = self.rx.lock().map_err(lock_err("audit writer"))?.take() else {
+            return Ok(());
+        };
+        heartbeats.register(HEARTBEAT_TASK, HEARTBEAT_INTERVAL);
+        let mut idle = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut batch = Vec::with_capacity(BATCH_SIZE);
+        loop {
+            tokio::select! {
+                received = rx.recv_many(&mut batch, BATCH_SIZE) => {
+                    if received == 0 {
+                        return Ok(());
+                    }
+                    let records = std::mem::replace(&mut batch, Vec::with_capacity(BATCH_SIZE));
+                    let log = Arc::clone(log);
+                    let written = tokio::task::spawn_blocking(move || {
+                        for r in &records {
+                            if let Err(e) = log.log_record(r) {
+                                tracing::error!(error = %e, jti = %r.jti, "audit write failed");
+                            }
+                        }
+                    });
+                    if let Err(e) = written.await {
+                        tracing::error!(error = %e, "audit write batch failed");
+                    }
+                    metrics.set_audit_queue_depth(self.depth());
+                }
+                _ = idle.tick() => {}
+            }
+            heartbeats.beat(HEARTBEAT_TASK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(jti: &str) -> AuditRecord {
+        AuditRecord {
+            event_type: EventType::Verify,
+            jti: jti.into(),
+            sub: "agent".into(),
+            action: "deploy".into(),
+            at: Utc::now(),
+            detail: None,
+            approved_by: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn stalled_writer_applies_backpressure_in_strict_mode() -> Result<()> {
+        let metrics = Metrics::new();
+        let writer = AuditWriter::new(2, Overflow::Block(Duration::from_millis(10)));
+        writer.submit(record("a"), &metrics).await?;
+        writer.submit(record("b"), &metrics).await?;
+        let result = writer.submit(record("c"), &metrics).await;
+        assert!(matches!(result, Err(Error::ServiceUnavailable(_))));
+        assert_eq!(metrics.snapshot().audit_queue_depth, 2);
+        assert_eq!(metrics.snapshot().audit_dropped, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stalled_writer_drops_and_counts_in_lenient_mode() -> Result<()> {
+        let metrics = Metrics::new();
+        let writer = AuditWriter::new(2, Overflow::Drop);
+        for jti in ["a", "b", "c", "d"] {
+            writer.submit(record(jti), &metrics).await?;
+        }
+        assert_eq!(metrics.snapshot().audit_dropped, 2);
+        assert_eq!(metrics.snapshot().audit_queue_depth, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn running_writer_drains_into_log() -> Result<()> {
+        let metrics = Metrics::new();
+        let log = Arc::new(AuditLog::open_in_memory()?);
+        let writer = AuditWriter::new(8, Overflow::Drop);
+        writer.submit(record("a"), &metrics).await?;
+        writer.submit(record("b"), &metrics).await?;
+        let heartbeats = Heartbeats::new();
+        let _ = tokio::time::timeout(Duration::from_millis(50), writer.run(&log, &metrics, &heartbeats)).await;
+        let jtis: Vec<String> = log.recent(10)?.into_iter().map(|e| e.jti).collect();
+        assert_eq!(jtis, vec!["b", "a"]);
+        assert_eq!(metrics.snapshot().audit_queue_depth, 0);
+        assert!(heartbeats.stalled(std::time::Instant::now()).is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,90 @@
+//! Background self-test: mints and verifies an internal canary token in-process.
+//! Used by: main, handlers::mint.
+
+use std::time::Duration;
+
+use crate::error::{Error, Result};
+use crate::state::AppState;
+use crate::token::claims::{Audience, Claims};
+
+/// Reserved subject; client mints for it are rejected.
+pub const CANARY_SUBJECT: &str = "agentmint:canary";
+const CANARY_ACTION: &str = "canary";
+const CANARY_TTL: i64 = 60;
+const HEARTBEAT_TASK: &str = "canary";
+
+/// Sign with the live key and verify through `verify`. Never touches the audit log or jti store.
+pub fn probe_with(state: &AppState, verify: impl Fn(&str) -> Result<Claims>) -> Result<()> {
+    let mut claims = Claims::new(CANARY_SUBJECT.into(), CANARY_ACTION.into(), CANARY_TTL);
+    claims.iss = state.issuer.clone();
+    claims.aud = state
+        .default_audience
+        .clone()
+        .or_else(|| state.require_audience.then(|| CANARY_SUBJECT.into()))
+        .map(Audience::One);
+    let token = state.sign(&claims)?;
+    let verified = verify(&token)?;
+    if verified != claims {
+        return Err(Error::Signing("canary claims changed across sign/verify".into()));
+    }
+    Ok(())
+}
+
+pub fn probe(state: &AppState) -> bool {
+    record(state, probe_with(state, |token| state.verify(token)))
+}
+
+fn record(state: &AppState, result: Result<()>) -> bool {
+    if let Err(e) = &result {
+        tracing::error!(error = %e, "canary self-test failed");
+    }
+    state.metrics.set_canary(result.is_ok());
+    result.is_ok()
+}
+
+pub fn interval_from_env() -> Option<Duration> {
+    std::env::var("CANARY_INTERVAL_SECS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|secs| *secs > 0)
+        .map(Duration::from_secs)
+}
+
+/// Started only where `can_sign` holds: the probe is a signed token, which a verify-only deployment cannot mint.
+pub async fn run(state: &AppState, interval: Duration) {
+    state.heartbeats.register(HEARTBEAT_TASK, interval);
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        ticker.tick().await;
+        probe(state);
+        state.heartbeats.beat(HEARTBEAT_TASK);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::build_test_state;
+    use crate::token::sign::generate_keypair;
+    use crate::token::verify::verify_token;
+
+    #[test]
+    fn canary_passes_and_sets_metric() -> Result<()> {
+        let state = build_test_state()?;
+        assert_eq!(state.metrics.snapshot().canary_ok, None);
+        assert!(probe(&state));
+        assert_eq!(state.metrics.snapshot().canary_ok, Some(true));
+        assert!(state.audit_log.recent(1)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn forced_verify_failure_flips_canary() -> Result<()> {
+        let state = build_test_state()?;
+        assert!(probe(&state));
+        let wrong = generate_keypair().verifying_key();
+        assert!(!record(&state, probe_with(&state, |token| verify_token(token, &wrong))));
+        assert_eq!(state.metrics.snapshot().canary_ok, Some(false));
+        Ok(())
+    }
+}
@@ -0,0 +1,650 @@
+//! Startup configuration: every cross-cutting env setting read and validated once, before state is built.
+//! Used by: main, state.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use url::Url;
+
+use crate::audit::retention::AuditRetention;
+use crate::audit::sink::{self, SinkSpec};
+use crate::audit::writer::AuditQueueSettings;
+use crate::cors::CorsSettings;
+use crate::error::{Error, Result};
+use crate::handlers::health::HealthBody;
+use crate::handlers::mint::{validate_audience, MAX_TTL};
+use crate::ipfilter::IpAllowlist;
+use crate::oidc::DEFAULT_RESULT_CACHE_CAPACITY;
+use crate::policy::{BelowFloor, TtlFloor};
+use crate::ratelimit::{RateLimitConfig, DEFAULT_BATCH_CONCURRENCY, DEFAULT_MAX_BATCH_SIZE};
+use crate::spike::SpikeSettings;
+use crate::storage::StorageBackend;
+use crate::telemetry::{DEFAULT_HISTORY_DEPTH, DEFAULT_HISTORY_INTERVAL};
+use crate::token::claims::{Audience, DEFAULT_EXPIRY_LEEWAY, DEFAULT_IAT_LEEWAY};
+use crate::token::keys::MIN_HMAC_SECRET_BYTES;
+use crate::token::keyset::DEFAULT_RELOAD_INTERVAL;
+use crate::token::sign::validate_prefix;
+use crate::webauthn::DEFAULT_CHALLENGES_PER_USER;
+
+pub const DEFAULT_BIND_ADDR: &str = "0.0.0.0:3000";
+pub(crate) const DEFAULT_SLOW_REQUEST: Duration = Duration::from_secs(1);
+const DEFAULT_GRACE_SECONDS: i64 = 3600;
+pub(crate) const DEFAULT_MAX_SCOPES: usize = 32;
+pub(crate) const DEFAULT_AUDIT_BUNDLE_ENTRIES: usize = 10_000;
+/// Beyond this a leeway stops covering clock drift and starts extending every token's lifetime. Tied
+/// to the default future-`iat` tolerance so a clock skew accepted at one end of a token is accepted at the other.
+const MAX_EXPIRY_LEEWAY_SECS: u64 = DEFAULT_IAT_LEEWAY.as_secs();
+/// The longest a token can live: an `iat` further ahead than that cannot belong to a token minted now.
+const MAX_IAT_LEEWAY_SECS: u64 = MAX_TTL.unsigned_abs();
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcSettings {
+    pub issuer: String,
+    pub audience: String,
+    pub jwks_uri: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebAuthnSettings {
+    pub rp_id: String,
+    pub rp_origin: String,
+    pub max_challenges_per_user: usize,
+    /// `WEBAUTHN_STRICT_USER_IDS=true`: only UUID `user_id`s are accepted.
+    pub strict_user_ids: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PreviousKeySettings {
+    pub path: String,
+    pub grace_seconds: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct MinterKeysSettings {
+    pub path: String,
+    pub reload_every: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub bind_addr: String,
+    pub signing_key_path: Option<String>,
+    /// `SIGNING_KEY_CREATED_AT` (RFC 3339): when the signing key was generated. Unset, the key is
+    /// dated by when this service first saw it.
+    pub signing_key_created_at: Option<DateTime<Utc>>,
+    /// Set with `VERIFY_ONLY=true`: the public key to verify with. No private key is loaded.
+    pub verifying_key_path: Option<String>,
+    pub previous_key: Option<PreviousKeySettings>,
+    /// `VERIFYING_KEYS_JWKS`: other minters' keys, accepted alongside this service's own.
+    pub minter_keys: Option<MinterKeysSettings>,
+    /// `TOKEN_HMAC_SECRET`: sign and verify HS256 with this shared secret instead of any Ed25519 key.
+    pub hmac_secret: Option<String>,
+    pub token_prefix: Option<String>,
+    pub token_issuer: Option<String>,
+    pub default_audience: Option<String>,
+    pub require_audience: bool,
+    /// This service's audience: `/proxy` rejects tokens whose `aud` does not include it.
+    pub proxy_audience: Option<String>,
+    /// Accept headerless two-segment tokens; on unless `TOKEN_ACCEPT_LEGACY=false`.
+    pub accept_legacy_tokens: bool,
+    /// `TOKEN_EXPIRY_LEEWAY_SECS`: clock-skew tolerance on `exp`, for our tokens and OIDC id_tokens alike.
+    pub expiry_leeway: Duration,
+    /// `TOKEN_IAT_LEEWAY_SECS`: how far ahead of our clock a token's `iat` may sit.
+    pub iat_leeway: Duration,
+    /// `iss` values `/proxy` accepts; `None` accepts any.
+    pub proxy_allowed_issuers: Option<Vec<String>>,
+    /// Audiences `/token/exchange` may mint for; `None` disables exchange.
+    pub exchange_audiences: Option<Vec<String>>,
+    /// `VERIFICATION_AUDIENCES`: audiences `/verification-bundle` tells offline verifiers to accept;
+    /// `None` falls back to `DEFAULT_AUDIENCE`.
+    pub verification_audiences: Option<Vec<String>>,
+    pub admin_key: Option<String>,
+    pub require_oidc: bool,
+    pub allow_oidc_lockdown: bool,
+    pub oidc: Option<OidcSettings>,
+    pub oidc_cache_capacity: usize,
+    /// `CAP_TTL_TO_ID_TOKEN`: an OIDC-backed mint never outlives the id_token that justified it.
+    pub cap_ttl_to_id_token: bool,
+    pub webauthn: Option<WebAuthnSettings>,
+    pub slow_request_threshold: Duration,
+    pub mint_daily_quota: Option<u64>,
+    pub max_scopes: usize,
+    /// `MIN_TTL_SECONDS` / `MIN_TTL_MODE`: the shortest lifetime a mint may ask for, at most `MAX_TTL`.
+    pub ttl_floor: TtlFloor,
+    /// `CRYPTO_CONCURRENCY`: requests signing or verifying at once; defaults to one fewer than the CPUs.
+    pub crypto_concurrency: Option<usize>,
+    /// `RATE_LIMIT_*` and `RATE_EXEMPT_*`: global budgets, opt-in per-IP budgets, and exemptions.
+    pub rate_limits: RateLimitConfig,
+    /// `MAX_BATCH_SIZE`: items one `/mint/batch` may carry.
+    pub max_batch_size: usize,
+    /// `BATCH_CONCURRENCY`: batch items processed at once across all requests.
+    pub batch_concurrency: usize,
+    /// `MINT_IP_ALLOWLIST`: networks the token-issuing endpoints accept; `None` accepts any.
+    pub mint_ip_allowlist: Option<IpAllowlist>,
+    /// `TRUSTED_PROXIES`: peers whose `X-Forwarded-For` names the client.
+    pub trusted_proxies: Option<IpAllowlist>,
+    /// `METRICS_HISTORY_DEPTH` snapshots kept for `/metrics/history`, one per `METRICS_HISTORY_INTERVAL_SECS`.
+    pub metrics_history_depth: usize,
+    pub metrics_history_interval: Duration,
+    pub storage_backend: StorageBackend,
+    /// `AUDIT_QUEUE_CAPACITY`: write verify entries from a bounded background queue; `None` writes inline.
+    pub audit_queue: Option<AuditQueueSettings>,
+    /// `AUDIT_SINK`: where each audit entry is copied besides SQLite.
+    pub audit_sinks: Vec<SinkSpec>,
+    /// Entries per `/audit/bundle` page; a longer range is resumed with `after`.
+    pub audit_bundle_max_entries: usize,
+    /// `AUDIT_RETENTION_DAYS` / `AUDIT_RETENTION_BY_ACTION`: audit entries older than their action type's window are pruned.
+    pub audit_retention: Option<AuditRetention>,
+    /// `AUDIT_ALLOW_JTI_REUSE`: drop the one-verify-row-per-jti index, for deployments with multi-use tokens.
+    pub audit_allow_jti_reuse: bool,
+    pub normalize_actions: bool,
+    /// `STRICT_REQUESTS`: mint requests with unrecognized fields are rejected instead of ignored.
+    pub strict_requests: bool,
+    pub cors: CorsSettings,
+    pub health_body: HealthBody,
+    pub request_spike: Option<SpikeSettings>,
+    /// Base path every route is nested under, e.g. `/auth/agentmint`.
+    pub route_prefix: Option<String>,
+    /// Keep `/health`, `/ready`, and `/metrics*` at the root when `route_prefix` is set.
+    pub ops_routes_at_root: bool,
+}
+
+impl Config {
+    pub fn from_env() -> Result<Self> {
+        Self::from_lookup(|name| std::env::var(name).ok())
+    }
+
+    /// Empty values count as unset. Any contradiction is an `Error::Config` naming the variables involved.
+    pub fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let get = |name: &str| lookup(name).filter(|v| !v.is_empty());
+        let flag = |name: &str| get(name).is_some_and(|v| v == "true");
+
+        let config = Self {
+            bind_addr: get("BIND_ADDR").unwrap_or_else(|| DEFAULT_BIND_ADDR.into()),
+            signing_key_path: get("SIGNING_KEY_PATH"),
+            signing_key_created_at: parse::<DateTime<Utc>>(&get, "SIGNING_KEY_CREATED_AT")?,
+            verifying_key_path: verifying_key_path(&get, flag("VERIFY_ONLY"))?,
+            previous_key: previous_key(&get)?,
+            minter_keys: minter_keys(&get)?,
+            hmac_secret: hmac_secret(&get)?,
+            token_prefix: get("TOKEN_PREFIX").map(|p| validate_prefix(&p).map(|_| p)).transpose()?,
+            token_issuer: get("TOKEN_ISSUER"),
+            default_audience: audience(&get, "DEFAULT_AUDIENCE")?,
+            proxy_audience: audience(&get, "PROXY_AUDIENCE")?,
+            require_audience: flag("REQUIRE_AUDIENCE"),
+            accept_legacy_tokens: get("TOKEN_ACCEPT_LEGACY").as_deref() != Some("false"),
+            expiry_leeway: parse::<u64>(&get, "TOKEN_EXPIRY_LEEWAY_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_EXPIRY_LEEWAY),
+            iat_leeway: parse::<u64>(&get, "TOKEN_IAT_LEEWAY_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_IAT_LEEWAY),
+            proxy_allowed_issuers: list(&get, "PROXY_ALLOWED_ISSUERS"),
+            exchange_audiences: list(&get, "TOKEN_EXCHANGE_AUDIENCES"),
+            verification_audiences: list(&get, "VERIFICATION_AUDIENCES"),
+            admin_key: get("ADMIN_API_KEY"),
+            require_oidc: flag("REQUIRE_OIDC"),
+            allow_oidc_lockdown: flag("ALLOW_OIDC_LOCKDOWN"),
+            oidc: oidc(&get)?,
+            oidc_cache_capacity: parse::<usize>(&get, "OIDC_CACHE_CAPACITY")?.unwrap_or(DEFAULT_RESULT_CACHE_CAPACITY),
+            cap_ttl_to_id_token: flag("CAP_TTL_TO_ID_TOKEN"),
+            webauthn: webauthn(&get)?,
+            slow_request_threshold: parse::<u64>(&get, "SLOW_REQUEST_MS")?
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_SLOW_REQUEST),
+            mint_daily_quota: parse::<u64>(&get, "MINT_DAILY_QUOTA")?,
+            normalize_actions: get("NORMALIZE_ACTIONS").as_deref() != Some("false"),
+            strict_requests: flag("STRICT_REQUESTS"),
+            max_scopes: parse::<usize>(&get, "MAX_TOKEN_SCOPES")?.unwrap_or(DEFAULT_MAX_SCOPES),
+            ttl_floor: TtlFloor::from_lookup(&get)?,
+            crypto_concurrency: parse::<usize>(&get, "CRYPTO_CONCURRENCY")?,
+            rate_limits: RateLimitConfig::from_lookup(&get)?,
+            max_batch_size: parse::<usize>(&get, "MAX_BATCH_SIZE")?.unwrap_or(DEFAULT_MAX_BATCH_SIZE),
+            batch_concurrency: parse::<usize>(&get, "BATCH_CONCURRENCY")?.unwrap_or(DEFAULT_BATCH_CONCURRENCY),
+            mint_ip_allowlist: IpAllowlist::from_lookup(&get, "MINT_IP_ALLOWLIST")?,
+            trusted_proxies: IpAllowlist::from_lookup(&get, "TRUSTED_PROXIES")?,
+            metrics_history_depth: parse::<usize>(&get, "METRICS_HISTORY_DEPTH")?.unwrap_or(DEFAULT_HISTORY_DEPTH),
+            metrics_history_interval: parse::<u64>(&get, "METRICS_HISTORY_INTERVAL_SECS")?
+                .map(Duration::from_secs)
+                .unwrap_or(DEFAULT_HISTORY_INTERVAL),
+            storage_backend: StorageBackend::from_lookup(&get)?,
+            audit_queue: AuditQueueSettings::from_lookup(&get)?,
+            audit_sinks: sink::from_lookup(&get)?,
+            audit_bundle_max_entries: parse::<usize>(&get, "AUDIT_BUNDLE_MAX_ENTRIES")?
+                .unwrap_or(DEFAULT_AUDIT_BUNDLE_ENTRIES),
+            audit_retention: AuditRetention::from_lookup(&get)?,
+            audit_allow_jti_reuse: flag("AUDIT_ALLOW_JTI_REUSE"),
+            cors: CorsSettings::from_lookup(&get)?,
+            health_body: HealthBody::parse(get("HEALTH_BODY"))?,
+            request_spike: SpikeSettings::from_lookup(&get)?,
+            route_prefix: route_prefix(&get)?,
+            ops_routes_at_root: flag("OPS_ROUTES_AT_ROOT"),
+        };
+
+        if config.hmac_secret.is_some()
+            && (config.signing_key_path.is_some()
+                || config.verifying_key_path.is_some()
+                || config.previous_key.is_some()
+                || config.minter_keys.is_some())
+        {
+            return Err(Error::Config(
+                "TOKEN_HMAC_SECRET must not be combined with SIGNING_KEY_PATH, VERIFY_ONLY, PREVIOUS_SIGNING_KEY_PATH, or VERIFYING_KEYS_JWKS".into(),
+            ));
+        }
+        if config.verifying_key_path.is_some() && config.signing_key_path.is_some() {
+            return Err(Error::Config("VERIFY_ONLY=true must not be combined with SIGNING_KEY_PATH".into()));
+        }
+        if config.signing_key_created_at.is_some() && config.signing_key_path.is_none() {
+            return Err(Error::Config("SIGNING_KEY_CREATED_AT is set but SIGNING_KEY_PATH is not".into()));
+        }
+        if config.previous_key.is_some() && config.signing_key_path.is_none() {
+            return Err(Error::Config(
+                "PREVIOUS_SIGNING_KEY_PATH is set but SIGNING_KEY_PATH is not; an ephemeral key cannot rotate".into(),
+            ));
+        }
+        if config.previous_key.as_ref().is_some_and(|p| Some(&p.path) == config.signing_key_path.as_ref()) {
+            return Err(Error::Config("PREVIOUS_SIGNING_KEY_PATH must differ from SIGNING_KEY_PATH".into()));
+        }
+        if config.require_oidc && config.oidc.is_none() && !config.allow_oidc_lockdown {
+            return Err(Error::Config(
+                "REQUIRE_OIDC=true but no OIDC configured; set OIDC_* or ALLOW_OIDC_LOCKDOWN=true".into(),
+            ));
+        }
+        if config.cap_ttl_to_id_token && config.oidc.is_none() {
+            return Err(Error::Config("CAP_TTL_TO_ID_TOKEN has no effect without OIDC_*".into()));
+        }
+        if config.mint_daily_quota == Some(0) {
+            return Err(Error::Config("MINT_DAILY_QUOTA must be positive; unset it to disable".into()));
+        }
+        if config.proxy_allowed_issuers.as_ref().is_some_and(Vec::is_empty) {
+            return Err(Error::Config("PROXY_ALLOWED_ISSUERS lists no issuers; unset it to accept any".into()));
+        }
+        if let Some(targets) = &config.exchange_audiences {
+            if targets.is_empty() {
+                return Err(Error::Config("TOKEN_EXCHANGE_AUDIENCES lists no audiences; unset it to disable exchange".into()));
+            }
+            for target in targets {
+                validate_audience(&Audience::One(target.clone()))
+                    .map_err(|e| Error::Config(format!("TOKEN_EXCHANGE_AUDIENCES: {e}")))?;
+            }
+        }
+        if let Some(audiences) = &config.verification_audiences {
+            if audiences.is_empty() {
+                return Err(Error::Config("VERIFICATION_AUDIENCES lists no audiences; unset it to use DEFAULT_AUDIENCE".into()));
+            }
+            for aud in audiences {
+                validate_audience(&Audience::One(aud.clone()))
+                    .map_err(|e| Error::Config(format!("VERIFICATION_AUDIENCES: {e}")))?;
+            }
+        }
+        if config.expiry_leeway.as_secs() > MAX_EXPIRY_LEEWAY_SECS {
+            return Err(Error::Config(format!("TOKEN_EXPIRY_LEEWAY_SECS must be at most {MAX_EXPIRY_LEEWAY_SECS}")));
+        }
+        if config.iat_leeway.as_secs() > MAX_IAT_LEEWAY_SECS {
+            return Err(Error::Config(format!("TOKEN_IAT_LEEWAY_SECS must be at most {MAX_IAT_LEEWAY_SECS}")));
+        }
+        if config.max_scopes == 0 {
+            return Err(Error::Config("MAX_TOKEN_SCOPES must be at least 1".into()));
+        }
+        if config.ttl_floor.seconds > MAX_TTL {
+            return Err(Error::Config(format!("MIN_TTL_SECONDS must be at most {MAX_TTL}, the longest TTL a mint can grant")));
+        }
+        if config.crypto_concurrency == Some(0) {
+            return Err(Error::Config("CRYPTO_CONCURRENCY must be at least 1".into()));
+        }
+        if config.max_batch_size == 0 {
+            return Err(Error::Config("MAX_BATCH_SIZE must be at least 1".into()));
+        }
+        if config.batch_concurrency == 0 {
+            return Err(Error::Config("BATCH_CONCURRENCY must be at least 1".into()));
+        }
+        if config.metrics_history_depth == 0 {
+            return Err(Error::Config("METRICS_HISTORY_DEPTH must be at least 1".into()));
+        }
+        if config.metrics_history_interval.is_zero() {
+            return Err(Error::Config("METRICS_HISTORY_INTERVAL_SECS must be at least 1".into()));
+        }
+        if config.audit_bundle_max_entries == 0 {
+            return Err(Error::Config("AUDIT_BUNDLE_MAX_ENTRIES must be at least 1".into()));
+        }
+        if config.ops_routes_at_root && config.route_prefix.is_none() {
+            return Err(Error::Config("OPS_ROUTES_AT_ROOT has no effect without ROUTE_PREFIX".into()));
+        }
+        Ok(config)
+    }
+
+    pub fn log_summary(&self) {
+        tracing::info!(
+            bind = %self.bind_addr,
+            signing_key = match (&self.signing_key_path, &self.verifying_key_path) {
+                (_, Some(_)) => "none (verify-only)",
+                (Some(_), None) => "file",
+                (None, None) => "ephemeral",
+            },
+            signing_key_created_at = self.signing_key_created_at.map(|at| at.to_rfc3339()),
+            previous_key = self.previous_key.is_some(),
+            minter_keys_jwks = self.minter_keys.as_ref().map(|m| m.path.as_str()).unwrap_or("-"),
+            hs256 = self.hmac_secret.is_some(),
+            token_prefix = self.token_prefix.as_deref().unwrap_or("-"),
+            issuer = self.token_issuer.as_deref().unwrap_or("-"),
+            default_audience = self.default_audience.as_deref().unwrap_or("-"),
+            require_audience = self.require_audience,
+            proxy_audience = self.proxy_audience.as_deref().unwrap_or("-"),
+            accept_legacy_tokens = self.accept_legacy_tokens,
+            expiry_leeway_secs = self.expiry_leeway.as_secs(),
+            iat_leeway_secs = self.iat_leeway.as_secs(),
+            proxy_allowed_issuers = self.proxy_allowed_issuers.as_ref().map(Vec::len),
+            exchange_audiences = self.exchange_audiences.as_ref().map(Vec::len),
+            verification_audiences = self.verification_audiences.as_ref().map(Vec::len),
+            admin_api = self.admin_key.is_some(),
+            oidc = self.oidc.as_ref().map(|o| o.issuer.as_str()).unwrap_or("disabled"),
+            require_oidc = self.require_oidc,
+            oidc_lockdown = self.require_oidc && self.oidc.is_none(),
+            cap_ttl_to_id_token = self.cap_ttl_to_id_token,
+            webauthn = self.webauthn.as_ref().map(|w| w.rp_id.as_str()).unwrap_or("disabled"),
+            slow_request_ms = self.slow_request_threshold.as_millis() as u64,
+            mint_daily_quota = self.mint_daily_quota,
+            max_scopes = self.max_scopes,
+            min_ttl_seconds = self.ttl_floor.seconds,
+            min_ttl_reject = self.ttl_floor.below == BelowFloor::Reject,
+            crypto_concurrency = self.crypto_concurrency,
+            rate_limit_global_read_per_sec = self.rate_limits.global_read_per_sec,
+            rate_limit_global_write_per_sec = self.rate_limits.global_write_per_sec,
+            rate_limit_read_per_ip_per_min = self.rate_limits.read_per_ip_per_min,
+            rate_limit_write_per_ip_per_min = self.rate_limits.write_per_ip_per_min,
+            rate_limit_max_tracked = self.rate_limits.max_tracked,
+            max_batch_size = self.max_batch_size,
+            batch_concurrency = self.batch_concurrency,
+            mint_ip_allowlist = self.mint_ip_allowlist.is_some(),
+            trusted_proxies = self.trusted_proxies.is_some(),
+            metrics_history_depth = self.metrics_history_depth,
+            metrics_history_interval_secs = self.metrics_history_interval.as_secs(),
+            storage_backend = ?self.storage_backend,
+            audit_queue_capacity = self.audit_queue.map(|q| q.capacity),
+            audit_sinks = ?self.audit_sinks,
+            audit_bundle_max_entries = self.audit_bundle_max_entries,
+            audit_retention_default_days = self.audit_retention.as_ref().and_then(|r| r.default).map(|d| d.as_secs() / 86_400),
+            audit_allow_jti_reuse = self.audit_allow_jti_reuse,
+            audit_retention_action_types = self.audit_retention.as_ref().map(|r| r.by_action.len()),
+            normalize_actions = self.normalize_actions,
+            strict_requests = self.strict_requests,
+            cors_any_origin = self.cors.origins.is_none(),
+            request_spike_per_sec = self.request_spike.as_ref().map(|s| s.threshold_per_sec),
+            route_prefix = self.route_prefix.as_deref().unwrap_or("-"),
+            ops_routes_at_root = self.ops_routes_at_root,
+            "configuration loaded"
+        );
+    }
+}
+
+fn parse<T: std::str::FromStr>(get: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<T>> {
+    get(name)
+        .map(|v| v.parse().map_err(|_| Error::Config(format!("{name}: invalid value {v:?}"))))
+        .transpose()
+}
+
+fn verifying_key_path(get: &impl Fn(&str) -> Option<String>, verify_only: bool) -> Result<Option<String>> {
+    match (verify_only, get("VERIFYING_KEY_PATH")) {
+        (true, None) => Err(Error::Config("VERIFY_ONLY=true requires VERIFYING_KEY_PATH".into())),
+        (false, Some(_)) => Err(Error::Config("VERIFYING_KEY_PATH is only used with VERIFY_ONLY=true".into())),
+        (_, path) => Ok(path),
+    }
+}
+
+fn previous_key(get: &impl Fn(&str) -> Option<String>) -> Result<Option<PreviousKeySettings>> {
+    let grace_seconds = parse::<i64>(get, "PREVIOUS_KEY_GRACE_SECONDS")?;
+    let Some(path) = get("PREVIOUS_SIGNING_KEY_PATH") else {
+        if grace_seconds.is_some() {
+            return Err(Error::Config("PREVIOUS_KEY_GRACE_SECONDS is set without PREVIOUS_SIGNING_KEY_PATH".into()));
+        }
+        return Ok(None);
+    };
+    Ok(Some(PreviousKeySettings { path, grace_seconds: grace_seconds.unwrap_or(DEFAULT_GRACE_SECONDS) }))
+}
+
+fn minter_keys(get: &impl Fn(&str) -> Option<String>) -> Result<Option<MinterKeysSettings>> {
+    let reload_secs = parse::<u64>(get, "VERIFYING_KEYS_RELOAD_SECS")?;
+    let Some(path) = get("VERIFYING_KEYS_JWKS") else {
+        if reload_secs.is_some() {
+            return Err(Error::Config("VERIFYING_KEYS_RELOAD_SECS is set without VERIFYING_KEYS_JWKS".into()));
+        }
+        return Ok(None);
+    };
+    if reload_secs == Some(0) {
+        return Err(Error::Config("VERIFYING_KEYS_RELOAD_SECS must be at least 1".into()));
+    }
+    let reload_every = reload_secs.map(Duration::from_secs).unwrap_or(DEFAULT_RELOAD_INTERVAL);
+    Ok(Some(MinterKeysSettings { path, reload_every }))
+}
+
+fn hmac_secret(get: &impl Fn(&str) -> Option<String>) -> Result<Option<String>> {
+    let Some(secret) = get("TOKEN_HMAC_SECRET") else {
+        return Ok(None);
+    };
+    if secret.len() < MIN_HMAC_SECRET_BYTES {
+        return Err(Error::Config(format!("TOKEN_HMAC_SECRET must be at least {MIN_HMAC_SECRET_BYTES} bytes")));
+    }
+    Ok(Some(secret))
+}
+
+/// Comma-separated, blanks dropped.
+fn list(get: &impl Fn(&str) -> Option<String>, name: &str) -> Option<Vec<String>> {
+    get(name).map(|v| v.split(',').map(str::trim).filter(|i| !i.is_empty()).map(str::to_owned).collect())
+}
+
+fn audience(get: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<String>> {
+    let Some(aud) = get(name) else {
+        return Ok(None);
+    };
+    validate_audience(&Audience::One(aud.clone())).map_err(|e| Error::Config(format!("{name}: {e}")))?;
+    Ok(Some(aud))
+}
+
+/// A trailing slash is dropped; the result must be a non-root absolute path without whitespace.
+fn route_prefix(get: &impl Fn(&str) -> Option<String>) -> Result<Option<String>> {
+    let Some(raw) = get("ROUTE_PREFIX") else {
+        return Ok(None);
+    };
+    let prefix = raw.trim_end_matches('/');
+    if !prefix.starts_with('/') || prefix.chars().any(char::is_whitespace) {
+        return Err(Error::Config(format!("ROUTE_PREFIX must be an absolute path such as /auth/agentmint, got {raw:?}")));
+    }
+    Ok(Some(prefix.to_owned()))
+}
+
+fn oidc(get: &impl Fn(&str) -> Option<String>) -> Result<Option<OidcSettings>> {
+    const VARS: [&str; 3] = ["OIDC_ISSUER", "OIDC_AUDIENCE", "OIDC_JWKS_URI"];
+    match VARS.map(get) {
+        [Some(issuer), Some(audience), Some(jwks_uri)] => Ok(Some(OidcSettings { issuer, audience, jwks_uri })),
+        [None, None, None] => Ok(None),
+        values => {
+            let missing: Vec<_> = VARS.iter().zip(&values).filter(|(_, v)| v.is_none()).map(|(n, _)| *n).collect();
+            Err(Error::Config(format!("OIDC partially configured; missing {}", missing.join(", "))))
+        }
+    }
+}
+
+fn webauthn(get: &impl Fn(&str) -> Option<String>) -> Result<Option<WebAuthnSettings>> {
+    let cap = parse::<usize>(get, "WEBAUTHN_MAX_CHALLENGES_PER_USER")?;
+    let strict_user_ids = get("WEBAUTHN_STRICT_USER_IDS").is_some_and(|v| v == "true");
+    if cap == Some(0) {
+        return Err(Error::Config("WEBAUTHN_MAX_CHALLENGES_PER_USER must be at least 1".into()));
+    }
+    let (rp_id, rp_origin) = match (get("WEBAUTHN_RP_ID"), get("WEBAUTHN_RP_ORIGIN")) {
+        (Some(rp_id), Some(rp_origin)) => (rp_id, rp_origin),
+        (None, None) if cap.is_some() => {
+            return Err(Error::Config("WEBAUTHN_MAX_CHALLENGES_PER_USER is set without WEBAUTHN_RP_ID".into()));
+        }
+        (None, None) if strict_user_ids => {
+            return Err(Error::Config("WEBAUTHN_STRICT_USER_IDS is set without WEBAUTHN_RP_ID".into()));
+        }
+        (None, None) => return Ok(None),
+        _ => return Err(Error::Config("WEBAUTHN_RP_ID and WEBAUTHN_RP_ORIGIN must be set together".into())),
+    };
+    let host = Url::parse(&rp_origin)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_owned))
+        .ok_or_else(|| Error::Config(format!("WEBAUTHN_RP_ORIGIN: not a URL: {rp_origin}")))?;
+    if host != rp_id && !host.ends_with(&format!(".{rp_id}")) {
+        return Err(Error::Config(format!("WEBAUTHN_RP_ORIGIN host {host} is not within WEBAUTHN_RP_ID {rp_id}")));
+    }
+    let max_challenges_per_user = cap.unwrap_or(DEFAULT_CHALLENGES_PER_USER);
+    Ok(Some(WebAuthnSettings { rp_id, rp_origin, max_challenges_per_user, strict_user_ids }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<Config> {
+        let vars: HashMap<String, String> = vars.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        Config::from_lookup(|name| vars.get(name).cloned())
+    }
+
+    fn config_error(vars: &[(&str, &str)]) -> String {
+        match load(vars) {
+            Err(Error::Config(msg)) => msg,
+            other => panic!("expected config error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn empty_environment_uses_defaults() -> Result<()> {
+        let config = load(&[])?;
+        assert_eq!(config.bind_addr, DEFAULT_BIND_ADDR);
+        assert!(config.oidc.is_none() && config.webauthn.is_none() && config.signing_key_path.is_none());
+        assert_eq!(config.slow_request_threshold, DEFAULT_SLOW_REQUEST);
+        assert!(config.accept_legacy_tokens);
+        assert!(!load(&[("TOKEN_ACCEPT_LEGACY", "false")])?.accept_legacy_tokens);
+        assert_eq!((config.expiry_leeway, config.iat_leeway), (DEFAULT_EXPIRY_LEEWAY, DEFAULT_IAT_LEEWAY));
+        assert_eq!((config.max_batch_size, config.batch_concurrency), (DEFAULT_MAX_BATCH_SIZE, DEFAULT_BATCH_CONCURRENCY));
+        assert_eq!(config.storage_backend, StorageBackend::Memory);
+        assert!(config.audit_queue.is_none() && config.audit_sinks.is_empty() && config.mint_ip_allowlist.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn complete_oidc_and_webauthn_accepted() -> Result<()> {
+        let config = load(&[
+            ("OIDC_ISSUER", "https://idp.test"),
+            ("OIDC_AUDIENCE", "agentmint"),
+            ("OIDC_JWKS_URI", "https://idp.test/jwks"),
+            ("WEBAUTHN_RP_ID", "example.com"),
+            ("WEBAUTHN_RP_ORIGIN", "https://app.example.com"),
+            ("REQUIRE_OIDC", "true"),
+        ])?;
+        assert_eq!(config.oidc.map(|o| o.audience).as_deref(), Some("agentmint"));
+        assert_eq!(config.webauthn.map(|w| w.rp_id).as_deref(), Some("example.com"));
+        Ok(())
+    }
+
+    #[test]
+    fn partial_oidc_names_missing_vars() {
+        let msg = config_error(&[("OIDC_ISSUER", "https://idp.test")]);
+        assert!(msg.contains("OIDC_AUDIENCE, OIDC_JWKS_URI"), "{msg}");
+    }
+
+    #[test]
+    fn webauthn_requires_both_vars_and_matching_origin() {
+        assert!(config_error(&[("WEBAUTHN_RP_ID", "example.com")]).contains("must be set together"));
+        let msg = config_error(&[("WEBAUTHN_RP_ID", "example.com"), ("WEBAUTHN_RP_ORIGIN", "https://evil.test")]);
+        assert!(msg.contains("not within WEBAUTHN_RP_ID"), "{msg}");
+        assert!(config_error(&[("WEBAUTHN_MAX_CHALLENGES_PER_USER", "1")]).contains("without WEBAUTHN_RP_ID"));
+        assert!(config_error(&[("WEBAUTHN_STRICT_USER_IDS", "true")]).contains("without WEBAUTHN_RP_ID"));
+    }
+
+    #[test]
+    fn require_oidc_without_oidc_rejected_unless_lockdown() -> Result<()> {
+        assert!(config_error(&[("REQUIRE_OIDC", "true")]).contains("ALLOW_OIDC_LOCKDOWN"));
+        assert!(load(&[("REQUIRE_OIDC", "true"), ("ALLOW_OIDC_LOCKDOWN", "true")])?.require_oidc);
+        Ok(())
+    }
+
+    #[test]
+    fn previous_key_requires_persistent_current_key() {
+        assert!(config_error(&[("PREVIOUS_SIGNING_KEY_PATH", "old.key")]).contains("ephemeral"));
+        let same = [("SIGNING_KEY_PATH", "k.key"), ("PREVIOUS_SIGNING_KEY_PATH", "k.key")];
+        assert!(config_error(&same).contains("must differ"));
+        assert!(config_error(&[("PREVIOUS_KEY_GRACE_SECONDS", "60")]).contains("without PREVIOUS_SIGNING_KEY_PATH"));
+    }
+
+    #[test]
+    fn minter_keys_reload_interval_needs_a_jwks_file() -> Result<()> {
+        assert!(config_error(&[("VERIFYING_KEYS_RELOAD_SECS", "60")]).contains("without VERIFYING_KEYS_JWKS"));
+        let zero = [("VERIFYING_KEYS_JWKS", "minters.json"), ("VERIFYING_KEYS_RELOAD_SECS", "0")];
+        assert!(config_error(&zero).contains("at least 1"));
+        let secret = ("TOKEN_HMAC_SECRET", "an-internal-shared-secret-of-32-bytes");
+        assert!(config_error(&[secret, ("VERIFYING_KEYS_JWKS", "minters.json")]).contains("VERIFYING_KEYS_JWKS"));
+        let config = load(&[("VERIFYING_KEYS_JWKS", "minters.json")])?;
+        assert_eq!(config.minter_keys.map(|m| m.reload_every), Some(DEFAULT_RELOAD_INTERVAL));
+        Ok(())
+    }
+
+    #[test]
+    fn verify_only_requires_public_key_and_no_private_key() -> Result<()> {
+        assert!(config_error(&[("VERIFY_ONLY", "true")]).contains("VERIFYING_KEY_PATH"));
+        assert!(config_error(&[("VERIFYING_KEY_PATH", "pub.key")]).contains("VERIFY_ONLY"));
+        let both = [("VERIFY_ONLY", "true"), ("VERIFYING_KEY_PATH", "pub.key"), ("SIGNING_KEY_PATH", "k.key")];
+        assert!(config_error(&both).contains("SIGNING_KEY_PATH"));
+        let config = load(&[("VERIFY_ONLY", "true"), ("VERIFYING_KEY_PATH", "pub.key")])?;
+        assert_eq!(config.verifying_key_path.as_deref(), Some("pub.key"));
+        Ok(())
+    }
+
+    #[test]
+    fn route_prefix_trailing_slash_dropped() -> Result<()> {
+        let config = load(&[("ROUTE_PREFIX", "/auth/agentmint/"), ("OPS_ROUTES_AT_ROOT", "true")])?;
+        assert_eq!(config.route_prefix.as_deref(), Some("/auth/agentmint"));
+        assert!(config.ops_routes_at_root);
+        Ok(())
+    }
+
+    #[test]
+    fn malformed_values_rejected() {
+        assert!(config_error(&[("TOKEN_HMAC_SECRET", "short")]).contains("at least 32 bytes"));
+        let secret = ("TOKEN_HMAC_SECRET", "an-internal-shared-secret-of-32-bytes");
+        assert!(config_error(&[secret, ("SIGNING_KEY_PATH", "/keys/current")]).contains("TOKEN_HMAC_SECRET"));
+        assert!(config_error(&[secret, ("VERIFY_ONLY", "true"), ("VERIFYING_KEY_PATH", "/k")]).contains("TOKEN_HMAC_SECRET"));
+        assert!(config_error(&[("SLOW_REQUEST_MS", "fast")]).starts_with("SLOW_REQUEST_MS"));
+        assert!(config_error(&[("SIGNING_KEY_PATH", "/k"), ("SIGNING_KEY_CREATED_AT", "last week")]).starts_with("SIGNING_KEY_CREATED_AT"));
+        assert!(config_error(&[("SIGNING_KEY_CREATED_AT", "2026-01-01T00:00:00Z")]).contains("SIGNING_KEY_PATH"));
+        assert!(config_error(&[("TOKEN_EXPIRY_LEEWAY_SECS", "600")]).contains("at most 60"));
+        assert!(config_error(&[("TOKEN_IAT_LEEWAY_SECS", "600")]).contains("at most 300"));
+        assert!(config_error(&[("AUDIT_BUNDLE_MAX_ENTRIES", "0")]).contains("at least 1"));
+        assert!(config_error(&[("AUDIT_RETENTION_BY_ACTION", "refund=forever")]).starts_with("AUDIT_RETENTION_BY_ACTION"));
+        assert!(config_error(&[("TOKEN_EXCHANGE_AUDIENCES", " , ")]).contains("no audiences"));
+        assert!(config_error(&[("VERIFICATION_AUDIENCES", " , ")]).contains("no audiences"));
+        assert!(config_error(&[("AUDIT_STRICT", "false")]).contains("AUDIT_QUEUE_CAPACITY"));
+        assert!(config_error(&[("AUDIT_ENQUEUE_TIMEOUT_MS", "50")]).contains("AUDIT_QUEUE_CAPACITY"));
+        assert!(config_error(&[("AUDIT_QUEUE_CAPACITY", "0")]).starts_with("AUDIT_QUEUE_CAPACITY"));
+        assert!(config_error(&[("AUDIT_QUEUE_CAPACITY", "8"), ("AUDIT_ENQUEUE_TIMEOUT_MS", "soon")]).starts_with("AUDIT_ENQUEUE_TIMEOUT_MS"));
+        assert!(config_error(&[("AUDIT_SINK", "kafka")]).starts_with("AUDIT_SINK"));
+        assert!(config_error(&[("AUDIT_SYSLOG_ADDR", "10.0.0.5:514")]).contains("without syslog"));
+        assert!(config_error(&[("STORAGE_BACKEND", "redis")]).starts_with("STORAGE_BACKEND"));
+        assert!(config_error(&[("MAX_BATCH_SIZE", "0")]).contains("at least 1"));
+        assert!(config_error(&[("BATCH_CONCURRENCY", "four")]).starts_with("BATCH_CONCURRENCY"));
+        assert!(config_error(&[("METRICS_HISTORY_DEPTH", "0")]).contains("at least 1"));
+        assert!(config_error(&[("METRICS_HISTORY_INTERVAL_SECS", "0")]).contains("at least 1"));
+        assert!(config_error(&[("MINT_IP_ALLOWLIST", "10.0.0.0/33")]).starts_with("MINT_IP_ALLOWLIST"));
+        assert!(config_error(&[("TRUSTED_PROXIES", "proxy.internal")]).starts_with("TRUSTED_PROXIES"));
+        assert!(config_error(&[("MINT_DAILY_QUOTA", "0")]).contains("must be positive"));
+        assert!(config_error(&[("MAX_TOKEN_SCOPES", "0")]).contains("at least 1"));
+        assert!(config_error(&[("MIN_TTL_SECONDS", "301")]).contains("at most 300"));
+        assert!(config_error(&[("MIN_TTL_SECONDS", "0")]).contains("positive integer"));
+        assert!(config_error(&[("MIN_TTL_MODE", "round")]).contains("clamp or reject"));
+        assert!(config_error(&[("CRYPTO_CONCURRENCY", "0")]).contains("at least 1"));
+        assert!(config_error(&[("CRYPTO_CONCURRENCY", "many")]).starts_with("CRYPTO_CONCURRENCY"));
+        assert!(config_error(&[("RATE_LIMIT_WRITE_PER_MIN", "0")]).starts_with("RATE_LIMIT_WRITE_PER_MIN"));
+        assert!(config_error(&[("RATE_LIMIT_MAX_TRACKED", "0")]).starts_with("RATE_LIMIT_MAX_TRACKED"));
+        assert!(config_error(&[("RATE_LIMIT_GLOBAL_WRITE_PER_SEC", "lots")]).starts_with("RATE_LIMIT_GLOBAL_WRITE_PER_SEC"));
+        assert!(config_error(&[("PROXY_ALLOWED_ISSUERS", " , ")]).contains("no issuers"));
+        assert!(config_error(&[("WEBAUTHN_MAX_CHALLENGES_PER_USER", "0")]).contains("at least 1"));
+        assert!(config_error(&[("DEFAULT_AUDIENCE", &"a".repeat(65))]).starts_with("DEFAULT_AUDIENCE"));
+        assert!(config_error(&[("PROXY_AUDIENCE", &"a".repeat(65))]).starts_with("PROXY_AUDIENCE"));
+        assert!(config_error(&[("ROUTE_PREFIX", "auth")]).starts_with("ROUTE_PREFIX"));
+        assert!(config_error(&[("ROUTE_PREFIX", "/")]).starts_with("ROUTE_PREFIX"));
+        assert!(config_error(&[("OPS_ROUTES_AT_ROOT", "true")]).contains("without ROUTE_PREFIX"));
+        assert!(config_error(&[("CAP_TTL_TO_ID_TOKEN", "true")]).contains("without OIDC"));
+    }
+}
@@ -1,38 +1,87 @@
 //! Pretty terminal output with colors and badges.
 
+use std::io::{IsTerminal, Write};
+
 use colored::Colorize;
 
 // === Startup ===
 
-pub fn print_banner() {
-    println!();
-    println!("{}", "╔═══════════════════════════════════════════════════════════╗".cyan());
-    println!("{}", "║                                                           ║".cyan());
-    println!("║     {}     ║", "🔐 AgentMint v0.1.0".bold().white());
-    println!("║     {}     ║", "Cryptographic proof of human authorization".dimmed());
-    println!("{}", "║                                                           ║".cyan());
-    println!("{}", "╚═══════════════════════════════════════════════════════════╝".cyan());
-    println!();
-}
-
-pub fn print_startup(addr: &str) {
-    println!("{} {}", "✓".green().bold(), "Server ready".white().bold());
-    println!("  {} {}", "→".dimmed(), format!("http://{}", addr).cyan().underline());
-    println!();
-    println!("{}", "Endpoints:".white().bold());
-    println!("  {} {}  {}", "POST".yellow(), "/mint".white(), "Issue signed token".dimmed());
-    println!("  {} {}  {}", "POST".yellow(), "/proxy".white(), "Verify & consume token".dimmed());
-    println!("  {} {} {}", "POST".yellow(), "/delegate".white(), "Delegate scoped authorization".dimmed());
-    println!("  {} {}  {}", "GET ".green(), "/audit".white(), "View audit log".dimmed());
-    println!("  {} {} {}", "GET ".green(), "/metrics".white(), "Telemetry".dimmed());
-    println!("  {} {} {}", "GET ".green(), "/health".white(), "Health check".dimmed());
-    println!();
-    println!("{}", "WebAuthn:".white().bold());
-    println!("  {} {} {}", "POST".yellow(), "/webauthn/register/start".white(), "Begin registration".dimmed());
-    println!("  {} {} {}", "POST".yellow(), "/webauthn/register/finish".white(), "Complete registration".dimmed());
-    println!("  {} {} {}", "POST".yellow(), "/webauthn/auth/start".white(), "Begin authentication".dimmed());
-    println!("  {} {} {}", "POST".yellow(), "/webauthn/auth/finish".white(), "Complete authentication".dimmed());
-    println!();
+/// Decorative startup output is for a human at a terminal: `QUIET=true`, `--quiet`, or a
+/// stdout that is not a TTY (containers, systemd, pipes) suppresses it.
+pub fn quiet_mode(args: impl IntoIterator<Item = String>) -> bool {
+    args.into_iter().any(|a| a == "--quiet")
+        || std::env::var("QUIET").is_ok_and(|v| v == "true")
+        || !std::io::stdout().is_terminal()
+}
+
+pub fn print_banner(quiet: bool) {
+    if !quiet {
+        let _ = write_banner(&mut std::io::stdout().lock());
+    }
+}
+
+/// In quiet mode the endpoint listing becomes one structured log line.
+pub fn print_startup(addr: &str, quiet: bool) {
+    let _ = startup(&mut std::io::stdout().lock(), addr, quiet);
+}
+
+fn startup(out: &mut impl Write, addr: &str, quiet: bool) -> std::io::Result<()> {
+    if quiet {
+        tracing::info!(addr, version = env!("CARGO_PKG_VERSION"), "server ready");
+        return Ok(());
+    }
+    write_startup(out, addr)
+}
+
+fn write_banner(out: &mut impl Write) -> std::io::Result<()> {
+    writeln!(out)?;
+    writeln!(out, "{}", "╔═══════════════════════════════════════════════════════════╗".cyan())?;
+    writeln!(out, "{}", "║                                                           ║".cyan())?;
+    writeln!(out, "║     {}     ║", "🔐 AgentMint v0.1.0".bold().white())?;
+    writeln!(out, "║     {}     ║", "Cryptographic proof of human authorization".dimmed())?;
+    writeln!(out, "{}", "║                                                           ║".cyan())?;
+    writeln!(out, "{}", "╚═══════════════════════════════════════════════════════════╝".cyan())?;
+    writeln!(out)?;
+    Ok(())
+}
+
+fn write_startup(out: &mut impl Write, addr: &str) -> std::io::Result<()> {
+    writeln!(out, "{} {}", "✓".green().bold(), "Server ready".white().bold())?;
+    writeln!(out, "  {} {}", "→".dimmed(), format!("http://{}", addr).cyan().underline())?;
+    writeln!(out)?;
+    writeln!(out, "{}", "Endpoints:".white().bold())?;
+    writeln!(out, "  {} {}  {}", "POST".yellow(), "/mint".white(), "Issue signed token".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/mint/batch".white(), "Issue tokens in bulk".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/mint/breakglass".white(), "Audited emergency mint (admin)".dimmed())?;
+    writeln!(out, "  {} {}  {}", "POST".yellow(), "/proxy".white(), "Verify & consume token".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/proxy/binary".white(), "Verify & consume a binary token".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/proxy/complete".white(), "Record an action's outcome".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/proxy/external".white(), "Verify with a supplied minter key (admin)".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/refresh".white(), "Rotate refresh token".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/preauth".white(), "Pre-authorize action batch".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/delegate".white(), "Delegate scoped authorization".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/token/exchange".white(), "Exchange for a narrower downstream token".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/verification-bundle".white(), "Signed kit for offline verification".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/pubkey".white(), "Verifying key as JWK, PEM or hex".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/jwks.json".white(), "Verifying keys as a JWK set, by kid".dimmed())?;
+    writeln!(out, "  {} {}  {}", "GET ".green(), "/audit".white(), "View audit log".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/audit/bundle".white(), "Signed audit export (admin)".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/metrics".white(), "Telemetry".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/health".white(), "Health check".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/ready".white(), "Readiness (canary)".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/openapi.json".white(), "API schema".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/oidc/whoami".white(), "Echo id_token claims (admin)".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/revoke".white(), "Revoke a jti or a subject (admin)".dimmed())?;
+    writeln!(out, "  {} {} {}", "GET ".green(), "/signing-key/status".white(), "Active kid and key age (admin)".dimmed())?;
+    writeln!(out)?;
+    writeln!(out, "{}", "WebAuthn:".white().bold())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/webauthn/register/start".white(), "Begin registration".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/webauthn/register/finish".white(), "Complete registration".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/webauthn/auth/start".white(), "Begin authentication".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/webauthn/auth/finish".white(), "Complete authentication".dimmed())?;
+    writeln!(out, "  {} {} {}", "POST".yellow(), "/webauthn/reenroll/start".white(), "Replace credential".dimmed())?;
+    writeln!(out)?;
+    Ok(())
 }
 
 // === Badges ===
@@ -80,6 +129,26 @@ pub fn log_replay(jti: &str) {
     );
 }
 
+pub fn log_refresh_reuse(reason: &str) {
+    println!(
+        "{} {} {}",
+        badge("REFRESH", colored::Color::White, colored::Color::Red),
+        reason.red(),
+        "family revoked".red().bold()
+    );
+}
+
+pub fn log_breakglass(sub: &str, action: &str, jti: &str, reason: &str) {
+    println!(
+        "{} {} {} {} {} {} {}",
+        badge("BREAK-GLASS", colored::Color::White, colored::Color::Red),
+        "sub:".dimmed(), sub.yellow(),
+        "action:".dimmed(), action.cyan(),
+        format!("jti:{}", short_jti(jti)).dimmed(),
+        format!("reason: {}", reason).red().bold()
+    );
+}
+
 // === Policy ===
 
 pub fn log_policy_denial(sub: &str, action: &str, action_type: &str, limit: u64, requested: u64) {
@@ -218,3 +287,46 @@ pub fn log_checkpoint_required(agent: &str, action: &str) {
         "⚠ requires human approval".yellow().bold()
     );
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().map_err(|_| std::io::ErrorKind::Other)?.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn run_startup(quiet: bool) -> std::io::Result<(String, String)> {
+        let logs = Captured::default();
+        let writer = logs.clone();
+        let subscriber = tracing_subscriber::fmt().with_ansi(false).with_writer(move || writer.clone()).finish();
+        let mut out = Vec::new();
+        tracing::subscriber::with_default(subscriber, || startup(&mut out, "127.0.0.1:3000", quiet))?;
+        let logs = logs.0.lock().map_err(|_| std::io::ErrorKind::Other)?.clone();
+        Ok((String::from_utf8_lossy(&out).into_owned(), String::from_utf8_lossy(&logs).into_owned()))
+    }
+
+    #[test]
+    fn quiet_startup_logs_one_line_instead_of_the_listing() -> std::io::Result<()> {
+        let (out, logs) = run_startup(true)?;
+        assert!(out.is_empty());
+        assert_eq!(logs.lines().count(), 1);
+        assert!(logs.contains("server ready") && logs.contains("addr=\"127.0.0.1:3000\""));
+
+        let (out, logs) = run_startup(false)?;
+        assert!(out.contains("Endpoints:"));
+        assert!(logs.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,115 @@
+//! CORS policy: allowed origins, methods, request headers, and preflight max-age.
+//! Used by: config, server.
+
+use std::time::Duration;
+
+use axum::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::error::{Error, Result};
+
+const DEFAULT_MAX_AGE: Duration = Duration::from_secs(600);
+/// Every request header a browser client needs; nothing else is accepted in a preflight.
+const DEFAULT_HEADERS: [&str; 4] = ["content-type", "authorization", "x-admin-key", "x-verify-key"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct CorsSettings {
+    /// `None` allows any origin.
+    pub origins: Option<Vec<HeaderValue>>,
+    pub methods: Vec<Method>,
+    pub headers: Vec<HeaderName>,
+    pub max_age: Duration,
+}
+
+impl Default for CorsSettings {
+    fn default() -> Self {
+        Self {
+            origins: None,
+            methods: vec![Method::GET, Method::POST, Method::DELETE],
+            headers: DEFAULT_HEADERS.map(HeaderName::from_static).to_vec(),
+            max_age: DEFAULT_MAX_AGE,
+        }
+    }
+}
+
+fn list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|s| !s.is_empty())
+}
+
+impl CorsSettings {
+    /// Reads `CORS_ALLOWED_ORIGINS` (`*` or a list), `CORS_ALLOWED_METHODS`, `CORS_ALLOWED_HEADERS`,
+    /// and `CORS_MAX_AGE_SECS`; each unset variable keeps its default.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Self> {
+        let mut settings = Self::default();
+        if let Some(origins) = get("CORS_ALLOWED_ORIGINS").filter(|o| o.trim() != "*") {
+            let origins = list(&origins)
+                .map(|o| HeaderValue::from_str(o).map_err(|_| Error::Config(format!("CORS_ALLOWED_ORIGINS: invalid origin {o}"))))
+                .collect::<Result<Vec<_>>>()?;
+            settings.origins = Some(origins);
+        }
+        if let Some(methods) = get("CORS_ALLOWED_METHODS") {
+            settings.methods = list(&methods)
+                .map(|m| {
+                    Method::from_bytes(m.to_ascii_uppercase().as_bytes())
+                        .map_err(|_| Error::Config(format!("CORS_ALLOWED_METHODS: invalid method {m}")))
+                })
+                .collect::<Result<_>>()?;
+        }
+        if let Some(headers) = get("CORS_ALLOWED_HEADERS") {
+            settings.headers = list(&headers)
+                .map(|h| {
+                    HeaderName::from_bytes(h.to_ascii_lowercase().as_bytes())
+                        .map_err(|_| Error::Config(format!("CORS_ALLOWED_HEADERS: invalid header {h}")))
+                })
+                .collect::<Result<_>>()?;
+        }
+        if let Some(secs) = get("CORS_MAX_AGE_SECS") {
+            let secs = secs
+                .parse()
+                .map_err(|_| Error::Config(format!("CORS_MAX_AGE_SECS: invalid value {secs:?}")))?;
+            settings.max_age = Duration::from_secs(secs);
+        }
+        Ok(settings)
+    }
+
+    pub fn layer(&self) -> CorsLayer {
+        let origins = match &self.origins {
+            Some(list) => AllowOrigin::list(list.clone()),
+            None => AllowOrigin::from(Any),
+        };
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(self.methods.clone())
+            .allow_headers(self.headers.clone())
+            .expose_headers(Any)
+            .max_age(self.max_age)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn load(vars: &[(&str, &str)]) -> Result<CorsSettings> {
+        let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+        CorsSettings::from_lookup(&|name| vars.get(name).map(|v| v.to_string()))
+    }
+
+    #[test]
+    fn env_overrides_parsed() -> Result<()> {
+        let settings = load(&[
+            ("CORS_ALLOWED_ORIGINS", "https://a.example, https://b.example"),
+            ("CORS_ALLOWED_METHODS", "get,post"),
+            ("CORS_ALLOWED_HEADERS", "Content-Type"),
+            ("CORS_MAX_AGE_SECS", "60"),
+        ])?;
+        assert_eq!(settings.origins.map(|o| o.len()), Some(2));
+        assert_eq!(settings.methods, [Method::GET, Method::POST]);
+        assert_eq!(settings.headers, [HeaderName::from_static("content-type")]);
+        assert_eq!(settings.max_age, Duration::from_secs(60));
+        assert_eq!(load(&[("CORS_ALLOWED_ORIGINS", "*")])?, CorsSettings::default());
+        assert!(matches!(load(&[("CORS_MAX_AGE_SECS", "soon")]), Err(Error::Config(_))));
+        Ok(())
+    }
+}
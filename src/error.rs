@@ -1,31 +1,70 @@
 //! Unified error types with secure client messages.
 
-use axum::http::StatusCode;
+use axum::http::{header, HeaderValue, StatusCode};
 use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Serialize;
+
+use crate::policy::PolicyDenial;
+use crate::ratelimit::WINDOW;
+
+/// In-memory stores shed expired entries continuously, so a full one frees up within seconds.
+const CAPACITY_RETRY_AFTER_SECS: u64 = 30;
+
+/// Stable sub-code for `InvalidToken`, surfaced as `reason` in error bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TokenFault {
+    MissingSeparator,
+    InvalidEncoding,
+    TooLarge,
+    WrongPrefix,
+    InvalidPayload,
+    InvalidField,
+    WrongAudience,
+    WrongIssuer,
+    NotHumanApproved,
+    AlgorithmMismatch,
+    InvalidProof,
+    UnknownKey,
+    LegacyFormat,
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum Error {
     #[error("token expired")]
     TokenExpired,
 
+    #[error("token not yet valid")]
+    TokenNotYetValid,
+
     #[error("invalid signature")]
     InvalidSignature,
 
-    #[error("invalid token: {0}")]
-    InvalidToken(String),
+    #[error("invalid token: {1}")]
+    InvalidToken(TokenFault, String),
 
     #[error("replay: {0}")]
     ReplayDetected(String),
 
+    #[error("revoked: {0}")]
+    Revoked(String),
+
     #[error("policy: {0}")]
-    PolicyViolation(String),
+    PolicyViolation(PolicyDenial),
 
     #[error("unauthorized: {0}")]
     Unauthorized(String),
 
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+
     #[error("rate limited: {0}")]
     RateLimited(String),
 
+    #[error("at capacity: {0}")]
+    CapacityExceeded(String),
+
     #[error("validation: {0}")]
     Validation(String),
 
@@ -43,20 +82,49 @@ pub enum Error {
 
     #[error("signing: {0}")]
     Signing(String),
+
+    #[error("config: {0}")]
+    Config(String),
 }
 
 impl Error {
     fn status(&self) -> StatusCode {
         match self {
-            Self::TokenExpired | Self::InvalidSignature | Self::InvalidToken(_) | Self::Unauthorized(_) => {
+            Self::TokenExpired
+            | Self::TokenNotYetValid
+            | Self::InvalidSignature
+            | Self::InvalidToken(..)
+            | Self::Revoked(_)
+            | Self::Unauthorized(_) => {
                 StatusCode::UNAUTHORIZED
             }
             Self::ReplayDetected(_) => StatusCode::CONFLICT,
-            Self::PolicyViolation(_) => StatusCode::FORBIDDEN,
-            Self::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            Self::PolicyViolation(_) | Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::RateLimited(_) | Self::CapacityExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::Validation(_) | Self::Base64(_) => StatusCode::BAD_REQUEST,
             Self::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
-            Self::Database(_) | Self::Serialization(_) | Self::Signing(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::Database(_) | Self::Serialization(_) | Self::Signing(_) | Self::Config(_) => {
+                StatusCode::INTERNAL_SERVER_ERROR
+            }
+        }
+    }
+
+    pub(crate) fn code(&self) -> &'static str {
+        match self {
+            Self::TokenExpired => "token_expired",
+            Self::TokenNotYetValid => "token_not_yet_valid",
+            Self::InvalidSignature => "invalid_signature",
+            Self::InvalidToken(..) => "invalid_token",
+            Self::ReplayDetected(_) => "replay_detected",
+            Self::Revoked(_) => "token_revoked",
+            Self::PolicyViolation(_) => "policy_violation",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::RateLimited(_) => "rate_limited",
+            Self::CapacityExceeded(_) => "capacity_exceeded",
+            Self::Validation(_) | Self::Base64(_) => "invalid_request",
+            Self::ServiceUnavailable(_) => "service_unavailable",
+            Self::Database(_) | Self::Serialization(_) | Self::Signing(_) | Self::Config(_) => "internal_error",
         }
     }
 
@@ -64,24 +132,67 @@ impl Error {
     fn client_msg(&self) -> &'static str {
         match self {
             Self::TokenExpired => "token expired",
+            Self::TokenNotYetValid => "token not yet valid",
             Self::InvalidSignature => "invalid signature",
-            Self::InvalidToken(_) => "invalid token",
+            Self::InvalidToken(..) => "invalid token",
             Self::ReplayDetected(_) => "token already used",
+            Self::Revoked(_) => "token revoked",
             Self::PolicyViolation(_) => "policy violation",
             Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
             Self::RateLimited(_) => "rate limited",
+            Self::CapacityExceeded(_) => "server at capacity",
             Self::Validation(_) => "invalid request",
             Self::ServiceUnavailable(_) => "service unavailable",
-            Self::Database(_) | Self::Serialization(_) | Self::Signing(_) | Self::Base64(_) => "internal error",
+            Self::Database(_) | Self::Serialization(_) | Self::Signing(_) | Self::Base64(_) | Self::Config(_) => {
+                "internal error"
+            }
+        }
+    }
+
+    /// Seconds a client should wait before retrying; sent as `Retry-After` on 429s.
+    fn retry_after(&self) -> Option<u64> {
+        match self {
+            Self::RateLimited(_) => Some(WINDOW.as_secs()),
+            Self::CapacityExceeded(_) => Some(CAPACITY_RETRY_AFTER_SECS),
+            _ => None,
         }
     }
 }
 
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'static str,
+    message: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<TokenFault>,
+    #[serde(flatten, skip_serializing_if = "Option::is_none")]
+    policy: Option<&'a PolicyDenial>,
+}
+
+impl Error {
+    fn body(&self) -> ErrorBody<'_> {
+        let policy = match self {
+            Self::PolicyViolation(denial) => Some(denial),
+            _ => None,
+        };
+        let reason = match self {
+            Self::InvalidToken(fault, _) => Some(*fault),
+            _ => None,
+        };
+        ErrorBody { error: self.code(), message: self.client_msg(), reason, policy }
+    }
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         let status = self.status();
         tracing::warn!(error = %self, status = %status.as_u16(), "request failed");
-        (status, self.client_msg()).into_response()
+        let mut response = (status, Json(self.body())).into_response();
+        if let Some(secs) = self.retry_after() {
+            response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        }
+        response
     }
 }
 
@@ -95,15 +206,95 @@ pub fn lock_err<T>(name: &str) -> impl FnOnce(std::sync::PoisonError<T>) -> Erro
 mod tests {
     use super::*;
 
+    fn denial() -> PolicyDenial {
+        PolicyDenial { action_type: "refund".into(), limit: 50, requested: 51, unit: "USD".into() }
+    }
+
+    async fn body_json(err: Error) -> Result<serde_json::Value> {
+        let resp = err.into_response();
+        let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX)
+            .await
+            .map_err(|e| Error::Signing(e.to_string()))?;
+        Ok(serde_json::from_slice(&bytes)?)
+    }
+
+    #[tokio::test]
+    async fn policy_violation_body_is_structured() -> Result<()> {
+        let body = body_json(Error::PolicyViolation(denial())).await?;
+        assert_eq!(body["error"], "policy_violation");
+        assert_eq!(body["action_type"], "refund");
+        assert_eq!(body["limit"], 50);
+        assert_eq!(body["requested"], 51);
+        assert_eq!(body["unit"], "USD");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_token_body_carries_reason() -> Result<()> {
+        let body = body_json(Error::InvalidToken(TokenFault::MissingSeparator, "missing separator".into())).await?;
+        assert_eq!(body["error"], "invalid_token");
+        assert_eq!(body["message"], "invalid token");
+        assert_eq!(body["reason"], "missing_separator");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn other_errors_have_code_without_detail() -> Result<()> {
+        let body = body_json(Error::Signing("secret key".into())).await?;
+        assert_eq!(body["error"], "internal_error");
+        assert_eq!(body["message"], "internal error");
+        assert!(body.get("limit").is_none());
+        assert!(body.get("reason").is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn token_failures_share_401_with_distinct_codes() -> Result<()> {
+        let cases = [
+            (Error::TokenExpired, "token_expired"),
+            (Error::InvalidSignature, "invalid_signature"),
+            (Error::Revoked("jti-1".into()), "token_revoked"),
+            (Error::InvalidToken(TokenFault::InvalidEncoding, "x".into()), "invalid_token"),
+        ];
+        for (err, code) in cases {
+            assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(body_json(err).await?["error"], code);
+        }
+        Ok(())
+    }
+
     #[test]
     fn status_codes() {
         assert_eq!(Error::TokenExpired.status(), StatusCode::UNAUTHORIZED);
         assert_eq!(Error::ReplayDetected("x".into()).status(), StatusCode::CONFLICT);
-        assert_eq!(Error::PolicyViolation("x".into()).status(), StatusCode::FORBIDDEN);
+        assert_eq!(Error::PolicyViolation(denial()).status(), StatusCode::FORBIDDEN);
+        assert_eq!(Error::Forbidden("x".into()).status(), StatusCode::FORBIDDEN);
         assert_eq!(Error::RateLimited("x".into()).status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(Error::CapacityExceeded("x".into()).status(), StatusCode::TOO_MANY_REQUESTS);
         assert_eq!(Error::ServiceUnavailable("x".into()).status(), StatusCode::SERVICE_UNAVAILABLE);
     }
 
+    #[test]
+    fn throttling_errors_carry_retry_after() {
+        let resp = Error::RateLimited("x".into()).into_response();
+        assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(resp.headers().get(header::RETRY_AFTER), Some(&HeaderValue::from(WINDOW.as_secs())));
+        let resp = Error::CapacityExceeded("x".into()).into_response();
+        assert_eq!(resp.headers().get(header::RETRY_AFTER), Some(&HeaderValue::from(CAPACITY_RETRY_AFTER_SECS)));
+        assert!(Error::ServiceUnavailable("x".into()).into_response().headers().get(header::RETRY_AFTER).is_none());
+    }
+
+    #[tokio::test]
+    async fn unauthorized_maps_to_401_without_detail() -> Result<()> {
+        let err = Error::Unauthorized("OIDC verification failed: kid abc123 not in JWKS".into());
+        assert_eq!(err.status(), StatusCode::UNAUTHORIZED);
+        let body = body_json(err).await?;
+        assert_eq!(body["error"], "unauthorized");
+        assert_eq!(body["message"], "unauthorized");
+        assert!(!body.to_string().contains("abc123"));
+        Ok(())
+    }
+
     #[test]
     fn no_internal_leak() {
         assert_eq!(Error::Database(rusqlite::Error::QueryReturnedNoRows).client_msg(), "internal error");
@@ -0,0 +1,338 @@
+//! Admin-gated operational endpoints and the shared admin key check.
+//! Used by: server.
+
+use axum::extract::{Path, Query, State};
+use axum::http::HeaderMap;
+use axum::Json;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+
+use crate::audit::sqlite::EventType;
+use crate::error::{Error, Result};
+use crate::handlers::mint::{validate_sub, MAX_NOT_BEFORE_SECONDS, MAX_TTL};
+use crate::ratelimit::SubjectRateState;
+use crate::state::AppState;
+use crate::telemetry::SubjectCount;
+use crate::token::keys::key_id;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+const DEFAULT_TOP: usize = 10;
+const MAX_TOP: usize = 100;
+/// The longest any token can stay valid after it is minted; a revocation this long outlives it.
+const DEFAULT_REVOCATION_SECONDS: i64 = MAX_NOT_BEFORE_SECONDS + MAX_TTL;
+const MAX_REVOCATION_SECONDS: i64 = 30 * 86_400;
+
+fn keys_match(expected: &str, provided: &str) -> bool {
+    expected.len() == provided.len()
+        && expected.bytes().zip(provided.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+pub fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<()> {
+    let expected = state
+        .admin_key
+        .as_deref()
+        .ok_or_else(|| Error::Unauthorized("admin API disabled".into()))?;
+    let provided = headers
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| Error::Unauthorized("missing admin key".into()))?;
+    if !keys_match(expected, provided) {
+        return Err(Error::Unauthorized("invalid admin key".into()));
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct TopQuery {
+    pub limit: Option<usize>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/replays",
+    params(TopQuery, ("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = [SubjectCount]),
+        (status = 401, description = "missing or invalid admin key"),
+    )
+)]
+pub async fn replay_offenders(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<TopQuery>,
+) -> Result<Json<Vec<SubjectCount>>> {
+    require_admin(&state, &headers)?;
+    let limit = query.limit.unwrap_or(DEFAULT_TOP).min(MAX_TOP);
+    Ok(Json(state.metrics.replays_by_subject.top(limit)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/ratelimit/subject/{sub}",
+    params(("sub" = String, Path, description = "subject"), ("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = SubjectRateState),
+        (status = 401, description = "missing or invalid admin key"),
+    )
+)]
+pub async fn subject_rate(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(sub): Path<String>,
+) -> Result<Json<SubjectRateState>> {
+    require_admin(&state, &headers)?;
+    Ok(Json(state.rate_limiter.subject_state(&sub)?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/ratelimit/subject/{sub}",
+    params(("sub" = String, Path, description = "subject"), ("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = SubjectRateState, description = "state after the reset"),
+        (status = 401, description = "missing or invalid admin key"),
+    )
+)]
+pub async fn reset_subject_rate(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(sub): Path<String>,
+) -> Result<Json<SubjectRateState>> {
+    require_admin(&state, &headers)?;
+    if state.rate_limiter.reset_subject(&sub)? {
+        tracing::info!(sub = %sub, "per-user rate limit window cleared by admin");
+    }
+    Ok(Json(state.rate_limiter.subject_state(&sub)?))
+}
+
+#[derive(Deserialize, ToSchema)]
+pub struct RevokeRequest {
+    /// Revoke this one token.
+    pub jti: Option<String>,
+    /// Revoke every token for this subject, including ones minted while the revocation holds.
+    pub sub: Option<String>,
+    /// How long the revocation holds; defaults to the longest a token can live (86700s).
+    pub ttl_seconds: Option<i64>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RevokeResponse {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub jti: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
+    /// RFC 3339 time the revocation lapses.
+    pub until: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/revoke",
+    request_body = RevokeRequest,
+    params(("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = RevokeResponse),
+        (status = 400, description = "neither jti nor sub given, or ttl_seconds out of range"),
+        (status = 401, description = "missing or invalid admin key"),
+        (status = 429, description = "revocation store at capacity"),
+    )
+)]
+pub async fn revoke(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<RevokeRequest>,
+) -> Result<Json<RevokeResponse>> {
+    require_admin(&state, &headers)?;
+    if req.jti.is_none() && req.sub.is_none() {
+        return Err(Error::Validation("give a jti, a sub, or both".into()));
+    }
+    if let Some(sub) = &req.sub {
+        validate_sub(sub)?;
+    }
+    if req.jti.as_ref().is_some_and(|jti| jti.is_empty() || jti.len() > 256) {
+        return Err(Error::Validation("jti must be 1-256 characters".into()));
+    }
+    let ttl = req.ttl_seconds.unwrap_or(DEFAULT_REVOCATION_SECONDS);
+    if !(1..=MAX_REVOCATION_SECONDS).contains(&ttl) {
+        return Err(Error::Validation(format!("ttl_seconds must be 1-{MAX_REVOCATION_SECONDS}")));
+    }
+
+    let now = Utc::now();
+    let until = now + chrono::Duration::seconds(ttl);
+    state.revoke(req.jti.as_deref(), req.sub.as_deref(), until.timestamp()).await?;
+    let detail = format!("until {}", until.to_rfc3339());
+    state.audit_log.log_event_with_detail(
+        EventType::Revoke,
+        req.jti.as_deref().unwrap_or("-"),
+        req.sub.as_deref().unwrap_or("-"),
+        "revoke",
+        now,
+        Some(&detail),
+    )?;
+    tracing::warn!(jti = ?req.jti, sub = ?req.sub, until = %until.to_rfc3339(), "revoked by admin");
+    Ok(Json(RevokeResponse { jti: req.jti, sub: req.sub, until: until.to_rfc3339() }))
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct AcceptedKid {
+    pub kid: String,
+    /// RFC 3339 time its grace window ends.
+    pub until: String,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct SigningKeyStatus {
+    pub active_kid: String,
+    /// RFC 3339; `SIGNING_KEY_CREATED_AT`, else when the key file was first seen, or startup for an ephemeral key.
+    pub created_at: String,
+    pub age_seconds: i64,
+    /// Retired keys whose tokens are still accepted (`PREVIOUS_SIGNING_KEY_PATH`).
+    pub accepted_kids: Vec<AcceptedKid>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/signing-key/status",
+    params(("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = SigningKeyStatus),
+        (status = 401, description = "missing or invalid admin key"),
+        (status = 403, description = "verify-only or HS256 mode: no Ed25519 signing key"),
+    )
+)]
+pub async fn signing_key_status(State(state): State<AppState>, headers: HeaderMap) -> Result<Json<SigningKeyStatus>> {
+    require_admin(&state, &headers)?;
+    let Some(key) = &state.signing_key else {
+        return Err(Error::Forbidden("no Ed25519 signing key in verify-only or HS256 mode".into()));
+    };
+    let accepted_kids = state
+        .previous_key
+        .iter()
+        .filter(|k| k.is_active())
+        .map(|k| AcceptedKid { kid: key_id(&k.key), until: k.until.to_rfc3339() })
+        .collect();
+    Ok(Json(SigningKeyStatus {
+        active_kid: key_id(&key.verifying_key()),
+        created_at: state.signing_key_created.to_rfc3339(),
+        age_seconds: (Utc::now() - state.signing_key_created).num_seconds().max(0),
+        accepted_kids,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::state::{build_test_state, TEST_ADMIN_KEY};
+
+    fn admin_headers(key: &'static str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_KEY_HEADER, HeaderValue::from_static(key));
+        headers
+    }
+
+    #[test]
+    fn correct_key_accepted() -> Result<()> {
+        let state = build_test_state()?;
+        require_admin(&state, &admin_headers(TEST_ADMIN_KEY))
+    }
+
+    #[test]
+    fn wrong_or_missing_key_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        assert!(matches!(require_admin(&state, &admin_headers("nope")), Err(Error::Unauthorized(_))));
+        assert!(matches!(require_admin(&state, &HeaderMap::new()), Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn offenders_sorted_by_count() -> Result<()> {
+        let state = build_test_state()?;
+        state.metrics.record_replay("mallory");
+        state.metrics.record_replay("mallory");
+        state.metrics.record_replay("alice");
+        let Json(top) = replay_offenders(
+            State(state),
+            admin_headers(TEST_ADMIN_KEY),
+            Query(TopQuery { limit: None }),
+        ).await?;
+        assert_eq!(top[0].sub, "mallory");
+        assert_eq!(top[0].count, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subject_rate_reflects_usage_and_reset_unthrottles() -> Result<()> {
+        let state = build_test_state()?;
+        let limit = state.rate_limiter.subject_state("bot")?.limit;
+        for _ in 0..limit {
+            state.check_user_rate("bot")?;
+        }
+        assert!(matches!(state.check_user_rate("bot"), Err(Error::RateLimited(_))));
+
+        let headers = admin_headers(TEST_ADMIN_KEY);
+        let Json(before) = subject_rate(State(state.clone()), headers.clone(), Path("bot".into())).await?;
+        assert_eq!(before.count, limit + 1);
+
+        let Json(after) = reset_subject_rate(State(state.clone()), headers, Path("bot".into())).await?;
+        assert_eq!(after.count, 0);
+        state.check_user_rate("bot")?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn subject_rate_requires_admin() -> Result<()> {
+        let state = build_test_state()?;
+        let result = reset_subject_rate(State(state), HeaderMap::new(), Path("bot".into())).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rotation_retires_the_active_kid_into_the_accepted_set() -> Result<()> {
+        use crate::token::keys::GraceKey;
+        use crate::token::sign::generate_keypair;
+        let (old, new) = (generate_keypair(), generate_keypair());
+
+        let mut before = crate::state::test_builder()?;
+        before.signing_key = Some(old.clone());
+        let state = before.build()?;
+        let Json(status) = signing_key_status(State(state.clone()), admin_headers(TEST_ADMIN_KEY)).await?;
+        assert_eq!(status.active_kid, key_id(&old.verifying_key()));
+        assert!(status.accepted_kids.is_empty());
+        assert!(state.metrics.snapshot().signing_key_age_seconds.is_some());
+        let unauthenticated = signing_key_status(State(state), HeaderMap::new()).await;
+        assert!(matches!(unauthenticated, Err(Error::Unauthorized(_))));
+
+        let mut after = crate::state::test_builder()?;
+        after.signing_key = Some(new.clone());
+        after.previous_key = Some(GraceKey::new(old.verifying_key(), chrono::Duration::hours(1)));
+        let Json(status) = signing_key_status(State(after.build()?), admin_headers(TEST_ADMIN_KEY)).await?;
+        assert_eq!(status.active_kid, key_id(&new.verifying_key()));
+        let accepted: Vec<&str> = status.accepted_kids.iter().map(|k| k.kid.as_str()).collect();
+        assert_eq!(accepted, [key_id(&old.verifying_key())]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoke_needs_a_target_and_bounded_ttl() -> Result<()> {
+        let state = build_test_state()?;
+        let call = |jti: Option<&str>, sub: Option<&str>, ttl_seconds| {
+            let req = RevokeRequest { jti: jti.map(str::to_owned), sub: sub.map(str::to_owned), ttl_seconds };
+            revoke(State(state.clone()), admin_headers(TEST_ADMIN_KEY), Json(req))
+        };
+        assert!(matches!(call(None, None, None).await, Err(Error::Validation(_))));
+        assert!(matches!(call(Some("jti-1"), None, Some(0)).await, Err(Error::Validation(_))));
+
+        let Json(resp) = call(Some("jti-1"), Some("agent-1"), None).await?;
+        assert_eq!((resp.jti.as_deref(), resp.sub.as_deref()), (Some("jti-1"), Some("agent-1")));
+        let entry = &state.audit_log.recent(1)?[0];
+        assert_eq!((entry.event_type.as_str(), entry.jti.as_str()), ("revoke", "jti-1"));
+
+        let unauthenticated = RevokeRequest { jti: Some("jti-2".into()), sub: None, ttl_seconds: None };
+        let result = revoke(State(state), HeaderMap::new(), Json(unauthenticated)).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+}
@@ -1,14 +1,168 @@
-//! Audit log query endpoint.
+//! Audit log query and signed export endpoints.
 //! Used by: server.
 
-use axum::extract::State;
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
 use axum::Json;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use utoipa::IntoParams;
 
+use crate::audit::bundle::{AuditBundle, BundleContents};
 use crate::audit::sqlite::AuditEntry;
-use crate::error::Result;
+use crate::error::{Error, Result};
+use crate::handlers::admin::require_admin;
 use crate::state::AppState;
 
+#[utoipa::path(
+    get,
+    path = "/audit",
+    responses((status = 200, body = [AuditEntry]))
+)]
 pub async fn recent(State(state): State<AppState>) -> Result<Json<Vec<AuditEntry>>> {
     let entries = state.audit_log.recent(100)?;
     Ok(Json(entries))
 }
+
+#[derive(Deserialize, IntoParams)]
+pub struct BundleQuery {
+    /// Inclusive lower bound (RFC 3339); open-ended if omitted.
+    #[param(value_type = Option<String>)]
+    pub since: Option<DateTime<Utc>>,
+    /// Exclusive upper bound (RFC 3339); open-ended if omitted.
+    #[param(value_type = Option<String>)]
+    pub until: Option<DateTime<Utc>>,
+    /// A previous page's `next_after`: resume the range past that entry.
+    pub after: Option<i64>,
+    /// Required to export without `since` or `until`, i.e. the whole log, or a range longer than one page.
+    #[serde(default)]
+    pub confirm_unbounded: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/audit/bundle",
+    params(BundleQuery, ("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = AuditBundle),
+        (status = 400, description = "empty range, or no range or more than a page without confirm_unbounded"),
+        (status = 401, description = "missing or invalid admin key"),
+    )
+)]
+pub async fn bundle(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<BundleQuery>,
+) -> Result<Json<AuditBundle>> {
+    require_admin(&state, &headers)?;
+    if let (Some(since), Some(until)) = (query.since, query.until) {
+        if since >= until {
+            return Err(Error::Validation("since must be before until".into()));
+        }
+    }
+
+    if query.since.is_none() && query.until.is_none() && !query.confirm_unbounded {
+        return Err(Error::Validation(
+            "set since and/or until, or confirm_unbounded=true to export the whole log".into(),
+        ));
+    }
+
+    let since = query.since.map(|t| t.to_rfc3339());
+    let until = query.until.map(|t| t.to_rfc3339());
+    let page = state.audit_bundle_max_entries;
+    let mut rows = state.audit_log.range(since.as_deref(), until.as_deref(), query.after, page + 1)?;
+    let more = rows.len() > page;
+    if more && !query.confirm_unbounded {
+        return Err(Error::Validation(format!(
+            "range holds more than {page} entries; narrow it, or confirm_unbounded=true to page through it"
+        )));
+    }
+    rows.truncate(page);
+    let next_after = rows.last().map(|(id, _)| *id).filter(|_| more);
+    let entries = rows.into_iter().map(|(_, entry)| entry).collect();
+
+    let contents = BundleContents { since, until, after: query.after, generated_at: Utc::now().to_rfc3339(), entries, next_after };
+    Ok(Json(AuditBundle::sign(&contents, state.signing_key()?)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::handlers::admin::ADMIN_KEY_HEADER;
+    use crate::state::{build_test_state, TEST_ADMIN_KEY};
+
+    fn admin_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_KEY_HEADER, HeaderValue::from_static(TEST_ADMIN_KEY));
+        headers
+    }
+
+    #[tokio::test]
+    async fn bundle_covers_range_and_verifies_with_server_key() -> Result<()> {
+        let state = build_test_state()?;
+        let now = Utc::now();
+        state.audit_log.log("old", "agent", "deploy", now - chrono::Duration::hours(2))?;
+        state.audit_log.log("new", "agent", "deploy", now)?;
+
+        let query = BundleQuery { since: Some(now - chrono::Duration::hours(1)), until: None, after: None, confirm_unbounded: false };
+        let Json(bundle) = bundle(State(state.clone()), admin_headers(), Query(query)).await?;
+        let contents = bundle.verify(&state.verifying_key)?;
+        assert_eq!(contents.entries.iter().map(|e| e.jti.as_str()).collect::<Vec<_>>(), ["new"]);
+
+        let mut altered = bundle;
+        altered.payload = altered.payload.replacen("new", "neW", 1);
+        assert!(matches!(altered.verify(&state.verifying_key), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bundle_requires_admin_and_ordered_bounds() -> Result<()> {
+        let state = build_test_state()?;
+        let open = BundleQuery { since: None, until: None, after: None, confirm_unbounded: true };
+        let result = bundle(State(state.clone()), HeaderMap::new(), Query(open)).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+
+        let now = Utc::now();
+        let inverted = BundleQuery { since: Some(now), until: Some(now), after: None, confirm_unbounded: false };
+        let result = bundle(State(state), admin_headers(), Query(inverted)).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unbounded_export_needs_confirmation_and_pages_resume() -> Result<()> {
+        let mut builder = crate::state::test_builder()?;
+        builder.audit_bundle_max_entries = 2;
+        let state = builder.build()?;
+        let start = Utc::now();
+        for i in 0..5 {
+            state.audit_log.log(&format!("jti-{i}"), "agent", "deploy", start + chrono::Duration::seconds(i))?;
+        }
+
+        let open = BundleQuery { since: None, until: None, after: None, confirm_unbounded: false };
+        let result = bundle(State(state.clone()), admin_headers(), Query(open)).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+
+        let long = BundleQuery { since: Some(start), until: None, after: None, confirm_unbounded: false };
+        let result = bundle(State(state.clone()), admin_headers(), Query(long)).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+
+        let mut jtis = Vec::new();
+        let mut after = None;
+        loop {
+            let query = BundleQuery { since: Some(start), until: None, after, confirm_unbounded: true };
+            let Json(page) = bundle(State(state.clone()), admin_headers(), Query(query)).await?;
+            let contents = page.verify(&state.verifying_key)?;
+            assert_eq!(contents.after, after);
+            assert!(contents.entries.len() <= 2);
+            jtis.extend(contents.entries.into_iter().map(|e| e.jti));
+            after = contents.next_after;
+            if after.is_none() {
+                break;
+            }
+        }
+        assert_eq!(jtis, ["jti-0", "jti-1", "jti-2", "jti-3", "jti-4"]);
+        Ok(())
+    }
+}
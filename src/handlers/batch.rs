@@ -0,0 +1,139 @@
+//! Batch minting: many mint requests in one call, bounded by MAX_BATCH_SIZE and BATCH_CONCURRENCY.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::Result;
+use crate::handlers::mint::{mint_one, MintRequest, MintResponse};
+use crate::state::AppState;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BatchMintRequest {
+    pub items: Vec<MintRequest>,
+}
+
+/// One result per item, in request order; a failed item does not fail the batch.
+#[derive(Serialize, ToSchema)]
+pub struct BatchMintItem {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub minted: Option<MintResponse>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct BatchMintResponse {
+    pub items: Vec<BatchMintItem>,
+}
+
+/// Items are minted one at a time, yielding between them: signing is CPU-bound, and single-item
+/// requests get in between batch items.
+#[utoipa::path(
+    post,
+    path = "/mint/batch",
+    request_body = BatchMintRequest,
+    responses(
+        (status = 200, body = BatchMintResponse),
+        (status = 400, description = "empty batch or more than MAX_BATCH_SIZE items"),
+    )
+)]
+pub async fn mint_batch(
+    State(state): State<AppState>,
+    Json(req): Json<BatchMintRequest>,
+) -> Result<Json<BatchMintResponse>> {
+    state.batch_limits.check_size(req.items.len())?;
+
+    let mut items = Vec::with_capacity(req.items.len());
+    for item in req.items {
+        let permit = state.batch_limits.acquire().await?;
+        let result = mint_one(&state, item).await;
+        drop(permit);
+        items.push(match result {
+            Ok(minted) => BatchMintItem { minted: Some(minted), error: None },
+            Err(e) => BatchMintItem { minted: None, error: Some(e.code().into()) },
+        });
+        tokio::task::yield_now().await;
+    }
+    Ok(Json(BatchMintResponse { items }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    use crate::error::Error;
+    use crate::ratelimit::BatchLimits;
+    use crate::state::test_builder;
+
+    fn item(sub: &str, action: &str) -> MintRequest {
+        MintRequest { sub: sub.into(), action: action.into(), ..Default::default() }
+    }
+
+    fn state_with(max_size: usize, concurrency: usize) -> Result<AppState> {
+        let mut builder = test_builder()?;
+        builder.batch_limits = BatchLimits::new(max_size, concurrency);
+        builder.build()
+    }
+
+    #[tokio::test]
+    async fn oversize_batch_rejected() -> Result<()> {
+        let state = state_with(3, 1)?;
+        let items = (0..4).map(|i| item(&format!("agent-{i}"), "deploy")).collect();
+        let result = mint_batch(State(state.clone()), Json(BatchMintRequest { items })).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert_eq!(state.metrics.snapshot().tokens_minted, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn item_failures_reported_per_item() -> Result<()> {
+        let state = state_with(10, 1)?;
+        let items = vec![item("agent-1", "deploy"), item("agent-2", "bad action!")];
+        let Json(res) = mint_batch(State(state), Json(BatchMintRequest { items })).await?;
+        assert!(res.items[0].minted.is_some());
+        assert_eq!(res.items[1].error.as_deref(), Some("invalid_token"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn policy_violation_fails_only_its_item() -> Result<()> {
+        let limit = crate::policy::PolicyLimit { max_amount: Some(50), ..Default::default() };
+        let mut builder = test_builder()?;
+        builder.policy = crate::policy::PolicyEngine::new([(Box::from("refund"), limit)].into_iter().collect());
+        let state = builder.build()?;
+
+        let items = vec![item("agent-1", "refund:amount:20"), item("agent-2", "refund:amount:500"), item("agent-3", "deploy")];
+        let Json(res) = mint_batch(State(state.clone()), Json(BatchMintRequest { items })).await?;
+        let errors: Vec<Option<&str>> = res.items.iter().map(|i| i.error.as_deref()).collect();
+        assert_eq!(errors, [None, Some("policy_violation"), None]);
+        assert_eq!(state.metrics.snapshot().tokens_minted, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn large_batch_does_not_block_single_mint() -> Result<()> {
+        let state = state_with(100, 1)?;
+        let items = (0..100).map(|i| item(&format!("agent-{i}"), "deploy")).collect();
+        let batch_done = AtomicBool::new(false);
+
+        let batch = async {
+            let result = mint_batch(State(state.clone()), Json(BatchMintRequest { items })).await;
+            batch_done.store(true, Ordering::SeqCst);
+            result
+        };
+        let single = async {
+            let result = mint_one(&state, item("solo", "deploy")).await;
+            (result, batch_done.load(Ordering::SeqCst))
+        };
+        let (batch, (single, batch_finished_first)) = tokio::join!(batch, single);
+
+        assert_eq!(batch?.0.items.iter().filter(|i| i.minted.is_some()).count(), 100);
+        single?;
+        assert!(!batch_finished_first, "single mint waited for the whole batch");
+        Ok(())
+    }
+}
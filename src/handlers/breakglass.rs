@@ -0,0 +1,165 @@
+//! Break-glass minting: admin-gated, skips OIDC and policy, always audited with a reason.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::audit::sqlite::EventType;
+use crate::error::{Error, Result};
+use crate::handlers::admin::require_admin;
+use crate::handlers::mint::{resolve_ttl, validate_action, validate_sub, MintResponse};
+use crate::state::AppState;
+use crate::token::claims::Claims;
+
+const MAX_REASON_LEN: usize = 512;
+
+#[derive(Deserialize, ToSchema)]
+pub struct BreakglassRequest {
+    pub sub: String,
+    pub action: String,
+    pub ttl_seconds: Option<i64>,
+    /// Why the normal checks are being bypassed; recorded verbatim in the audit log.
+    pub reason: String,
+}
+
+fn validate_reason(reason: &str) -> Result<&str> {
+    let reason = reason.trim();
+    if reason.is_empty() || reason.len() > MAX_REASON_LEN {
+        return Err(Error::Validation(format!("reason must be 1-{MAX_REASON_LEN} characters")));
+    }
+    if reason.chars().any(|c| c.is_control()) {
+        return Err(Error::Validation("reason contains control characters".into()));
+    }
+    Ok(reason)
+}
+
+/// The audit entry is written before signing: no record, no token.
+#[utoipa::path(
+    post,
+    path = "/mint/breakglass",
+    request_body = BreakglassRequest,
+    params(("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = MintResponse),
+        (status = 400, description = "missing reason or invalid request"),
+        (status = 401, description = "missing or invalid admin key"),
+    )
+)]
+pub async fn breakglass(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(mut req): Json<BreakglassRequest>,
+) -> Result<Json<MintResponse>> {
+    require_admin(&state, &headers)?;
+    req.action = state.normalize_action(req.action);
+    validate_sub(&req.sub)?;
+    validate_action(&req.action)?;
+    let reason = validate_reason(&req.reason)?;
+
+    let ttl = resolve_ttl(&state.policy, state.ttl_floor, &req.action, req.ttl_seconds)?;
+    state.metrics.record_mint_ttl(ttl, req.ttl_seconds.is_some_and(|requested| requested != ttl));
+    let claims = Claims::new(req.sub, req.action, ttl);
+
+    state.audit_log.log_event_with_detail(
+        EventType::Breakglass,
+        &claims.jti,
+        &claims.sub,
+        &claims.action,
+        Utc::now(),
+        Some(reason),
+    )?;
+    let token = state.sign(&claims)?;
+
+    tracing::warn!(sub = %claims.sub, action = %claims.action, jti = %claims.jti, reason = %reason, "break-glass token minted");
+    crate::console::log_breakglass(&claims.sub, &claims.action, &claims.jti, reason);
+    state.metrics.record_breakglass();
+    state.metrics.record_mint();
+
+    Ok(Json(MintResponse {
+        token,
+        jti: claims.jti,
+        exp: claims.exp.to_rfc3339(),
+        receipt_type: claims.receipt_type,
+        refresh_token: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::handlers::admin::ADMIN_KEY_HEADER;
+    use crate::state::{build_test_state, TEST_ADMIN_KEY};
+
+    fn req(reason: &str) -> BreakglassRequest {
+        BreakglassRequest {
+            sub: "oncall@example.com".into(),
+            action: "deploy:prod".into(),
+            ttl_seconds: None,
+            reason: reason.into(),
+        }
+    }
+
+    fn admin_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_KEY_HEADER, HeaderValue::from_static(TEST_ADMIN_KEY));
+        headers
+    }
+
+    #[tokio::test]
+    async fn records_reason_and_metric() -> Result<()> {
+        let state = build_test_state()?;
+        let Json(res) = breakglass(State(state.clone()), admin_headers(), Json(req("INC-42 rollback"))).await?;
+
+        let entry = &state.audit_log.recent(1)?[0];
+        assert_eq!(entry.event_type, "breakglass");
+        assert_eq!(entry.jti, res.jti);
+        assert_eq!(entry.detail.as_deref(), Some("INC-42 rollback"));
+        assert_eq!(state.metrics.snapshot().breakglass_mints, 1);
+        assert_eq!(state.verify(&res.token)?.sub, "oncall@example.com");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn action_normalized_like_a_mint() -> Result<()> {
+        let state = build_test_state()?;
+        let request = BreakglassRequest { action: " Deploy:Prod".into(), ..req("INC-42") };
+        let Json(res) = breakglass(State(state.clone()), admin_headers(), Json(request)).await?;
+        assert_eq!(state.verify(&res.token)?.action, "deploy:prod");
+        assert_eq!(state.audit_log.recent(1)?[0].action, "deploy:prod");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn rejected_without_admin_key() -> Result<()> {
+        let state = build_test_state()?;
+        let result = breakglass(State(state.clone()), HeaderMap::new(), Json(req("INC-42"))).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        assert!(state.audit_log.recent(1)?.is_empty());
+        assert_eq!(state.metrics.snapshot().breakglass_mints, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn blank_reason_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        let result = breakglass(State(state), admin_headers(), Json(req("   "))).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn control_chars_in_reason_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        for reason in ["INC-42\nforged: entry", "INC-42\u{1b}[2J", "INC-\u{7f}42"] {
+            let result = breakglass(State(state.clone()), admin_headers(), Json(req(reason))).await;
+            assert!(matches!(result, Err(Error::Validation(_))));
+        }
+        assert!(state.audit_log.recent(1)?.is_empty());
+        Ok(())
+    }
+}
@@ -1,24 +1,27 @@
 //! Delegation endpoint: creates scoped delegated receipts from a parent receipt.
-//! Used by: server.
+//! Used by: server, handlers::proxy (scope matching).
 
 use axum::extract::State;
 use axum::Json;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::error::{Error, Result};
+use crate::audit::sqlite::EventType;
+use crate::error::{Error, Result, TokenFault};
 use crate::state::AppState;
 use crate::token::claims::Claims;
-use crate::token::sign::sign_token;
-use crate::token::verify::verify_token;
+use crate::token::pop::check_proof;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct DelegateRequest {
     pub parent_token: String,
     pub agent_id: String,
     pub action: String,
+    /// Proof of possession, required when `parent_token` is key-bound.
+    pub proof: Option<String>,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct DelegateResponse {
     pub status: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -41,7 +44,7 @@ fn action_matches_pattern(action: &str, pattern: &str) -> bool {
     action == pattern
 }
 
-fn action_in_scope(action: &str, scope: &[String]) -> bool {
+pub(crate) fn action_in_scope(action: &str, scope: &[String]) -> bool {
     scope.iter().any(|pattern| action_matches_pattern(action, pattern))
 }
 
@@ -54,28 +57,40 @@ fn build_chain(parent: &Claims) -> Vec<String> {
     chain
 }
 
+#[utoipa::path(
+    post,
+    path = "/delegate",
+    request_body = DelegateRequest,
+    responses(
+        (status = 200, body = DelegateResponse),
+        (status = 401, description = "invalid or revoked parent token, or missing proof of possession"),
+    )
+)]
 pub async fn delegate(
     State(state): State<AppState>,
     Json(req): Json<DelegateRequest>,
 ) -> Result<Json<DelegateResponse>> {
     // Validate input
     if req.agent_id.is_empty() || req.agent_id.len() > 256 {
-        return Err(Error::InvalidToken("agent_id must be 1-256 characters".into()));
+        return Err(Error::InvalidToken(TokenFault::InvalidField, "agent_id must be 1-256 characters".into()));
     }
     if req.action.is_empty()
         || req.action.len() > 64
         || !req.action.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '-')
     {
         return Err(Error::InvalidToken(
+            TokenFault::InvalidField,
             "action must be 1-64 chars (alphanumeric, underscore, colon, hyphen)".into(),
         ));
     }
 
     // Verify the parent token
-    let parent = verify_token(&req.parent_token, &state.verifying_key).map_err(|e| {
+    let parent = state.verify(&req.parent_token).map_err(|e| {
         tracing::warn!(error = %e, "delegate: parent token verification failed");
         e
     })?;
+    state.check_revocation(&parent).await?;
+    check_proof(&parent, req.proof.as_deref())?;
 
     let chain = build_chain(&parent);
 
@@ -167,10 +182,10 @@ pub async fn delegate(
         &parent,
     );
     let jti = claims.jti.clone();
-    let token = sign_token(&claims, &state.signing_key)?;
+    let token = state.sign(&claims)?;
 
     // Audit log
-    state.audit_log.log(&jti, &req.agent_id, &req.action, chrono::Utc::now())?;
+    state.audit_log.log_event(EventType::Delegate, &jti, &req.agent_id, &req.action, chrono::Utc::now())?;
 
     tracing::info!(
         agent = %req.agent_id,
@@ -213,6 +228,32 @@ mod tests {
         assert!(!action_matches_pattern("deploy:production", "deploy:staging"));
     }
 
+    #[tokio::test]
+    async fn key_bound_parent_needs_proof_and_binds_the_child() -> Result<()> {
+        use crate::token::pop::{confirmation, sign_proof};
+        use base64::Engine;
+        let state = crate::state::build_test_state()?;
+        let holder = crate::token::sign::generate_keypair();
+        let mut parent = Claims::new_plan("agent-1".into(), "deploy".into(), 120, vec!["deploy:*".into()], vec!["agent-2".into()], vec![], 2);
+        let public_key = base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(holder.verifying_key().as_bytes());
+        parent.cnf = Some(confirmation(&public_key)?);
+        let parent_token = state.sign(&parent)?;
+        let request = |proof: Option<String>| DelegateRequest {
+            parent_token: parent_token.clone(),
+            agent_id: "agent-2".into(),
+            action: "deploy:staging".into(),
+            proof,
+        };
+
+        let unproven = delegate(State(state.clone()), Json(request(None))).await;
+        assert!(matches!(unproven, Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+        let Json(resp) = delegate(State(state.clone()), Json(request(Some(sign_proof(&parent.jti, &holder))))).await?;
+        assert_eq!(resp.status, "ok");
+        let child = state.verify(resp.token.as_deref().unwrap_or_default())?;
+        assert_eq!(child.cnf, parent.cnf);
+        Ok(())
+    }
+
     #[test]
     fn scope_check() {
         let scope = vec!["build:*".into(), "test:*".into(), "deploy:staging".into()];
@@ -0,0 +1,211 @@
+//! Token exchange: trades a verified token for a narrower, short-lived one bound to a downstream audience.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::Json;
+use chrono::Utc;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::audit::sqlite::EventType;
+use crate::error::{Error, Result};
+use crate::handlers::delegate::action_in_scope;
+use crate::handlers::mint::{
+    enforce_identity_requirements, enforce_policy, validate_action, validate_audience, MintResponse, MAX_TTL,
+};
+use crate::state::AppState;
+use crate::token::claims::{Audience, Claims};
+use crate::token::pop::check_proof;
+
+/// Lifetime of an exchanged token when the caller names none.
+const DEFAULT_EXCHANGE_TTL: i64 = 60;
+
+#[derive(Deserialize, ToSchema)]
+pub struct ExchangeRequest {
+    /// The token being exchanged; verified exactly as `/proxy` would, but not consumed.
+    pub subject_token: String,
+    /// Proof of possession, required when `subject_token` is key-bound.
+    pub proof: Option<String>,
+    /// A granted action, or a refinement of one (`deploy` → `deploy:service-x`).
+    pub action: String,
+    /// The next hop; must be listed in `TOKEN_EXCHANGE_AUDIENCES` and, when the subject token has an `aud`, in it.
+    pub audience: String,
+    /// Capped at 300 and at the subject token's remaining lifetime.
+    pub ttl_seconds: Option<i64>,
+}
+
+/// `requested` stays within `granted` when a scope pattern covers it or it adds `:` segments to a
+/// granted action; never the other way round.
+fn narrows(granted: &[String], requested: &str) -> bool {
+    action_in_scope(requested, granted)
+        || granted.iter().any(|g| requested.strip_prefix(g.as_str()).is_some_and(|rest| rest.starts_with(':')))
+}
+
+#[utoipa::path(
+    post,
+    path = "/token/exchange",
+    request_body = ExchangeRequest,
+    responses(
+        (status = 200, body = MintResponse),
+        (status = 400, description = "malformed action or audience"),
+        (status = 401, description = "invalid, expired, or revoked subject token, missing proof of possession, or the action's policy requires an approval the subject token lacks"),
+        (status = 403, description = "exchange disabled, audience not an exchange target or not in the subject token's aud, action broader than the subject token, policy violation, or no delegation depth left"),
+    )
+)]
+pub async fn exchange(State(state): State<AppState>, Json(req): Json<ExchangeRequest>) -> Result<Json<MintResponse>> {
+    let Some(targets) = &state.exchange_audiences else {
+        return Err(Error::Forbidden("token exchange is disabled; set TOKEN_EXCHANGE_AUDIENCES".into()));
+    };
+    let action = state.normalize_action(req.action);
+    validate_action(&action)?;
+    validate_audience(&Audience::One(req.audience.clone()))?;
+    if !targets.contains(&req.audience) {
+        return Err(Error::Forbidden(format!("{} is not a token exchange audience", req.audience)));
+    }
+
+    let parent = state.verify(&req.subject_token)?;
+    state.check_revocation(&parent).await?;
+    check_proof(&parent, req.proof.as_deref())?;
+    if parent.depth.unwrap_or(0) >= parent.max_delegation_depth.unwrap_or(1) {
+        crate::console::log_delegation_denied(&parent.sub, &action, "max_depth_exceeded");
+        return Err(Error::Forbidden("the subject token may not be exchanged or delegated further".into()));
+    }
+    if parent.aud.as_ref().is_some_and(|aud| !aud.contains(&req.audience)) {
+        crate::console::log_delegation_denied(&parent.sub, &action, "exchange_audience");
+        return Err(Error::Forbidden(format!("the subject token is not valid for {}", req.audience)));
+    }
+    let granted = parent.scope.as_deref().unwrap_or(std::slice::from_ref(&parent.action));
+    if !narrows(granted, &action) {
+        crate::console::log_delegation_denied(&parent.sub, &action, "exchange_broadens");
+        return Err(Error::Forbidden(format!("{action} is broader than the subject token allows")));
+    }
+    enforce_identity_requirements(&state, &action, parent.approved_by.as_deref(), false)?;
+    enforce_policy(&state, &parent.sub, parent.approved_by.as_deref(), &action)?;
+
+    let remaining = (parent.exp - Utc::now()).num_seconds().max(1);
+    let ttl = req.ttl_seconds.unwrap_or(DEFAULT_EXCHANGE_TTL).clamp(1, MAX_TTL).min(remaining);
+    let claims = Claims::exchanged(action, Audience::One(req.audience.clone()), ttl, &parent);
+    let token = state.sign(&claims)?;
+
+    let detail = format!("from {} for {}", parent.jti, req.audience);
+    state.audit_log.log_event_with_detail(
+        EventType::Exchange,
+        &claims.jti,
+        &claims.sub,
+        &claims.action,
+        Utc::now(),
+        Some(&detail),
+    )?;
+    tracing::info!(sub = %claims.sub, action = %claims.action, jti = %claims.jti, parent_jti = %parent.jti, audience = %req.audience, "token exchanged");
+    state.metrics.record_mint();
+
+    Ok(Json(MintResponse {
+        token,
+        jti: claims.jti,
+        exp: claims.exp.to_rfc3339(),
+        receipt_type: claims.receipt_type,
+        refresh_token: None,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_builder;
+
+    fn exchange_state() -> Result<AppState> {
+        let mut builder = test_builder()?;
+        builder.exchange_audiences = Some(vec!["service-x".into()]);
+        builder.build()
+    }
+
+    fn request(subject_token: &str, action: &str, audience: &str) -> ExchangeRequest {
+        ExchangeRequest {
+            subject_token: subject_token.into(),
+            proof: None,
+            action: action.into(),
+            audience: audience.into(),
+            ttl_seconds: Some(600),
+        }
+    }
+
+    #[tokio::test]
+    async fn exchange_narrows_action_audience_and_lifetime() -> Result<()> {
+        let state = exchange_state()?;
+        let parent = Claims::new("agent-1".into(), "deploy".into(), 120);
+        let subject = state.sign(&parent)?;
+
+        let Json(resp) = exchange(State(state.clone()), Json(request(&subject, "deploy:service-x", "service-x"))).await?;
+        let exchanged = state.verify(&resp.token)?;
+        assert_eq!(exchanged.action, "deploy:service-x");
+        assert_eq!(exchanged.aud, Some(Audience::One("service-x".into())));
+        assert_eq!(exchanged.parent_jti.as_deref(), Some(parent.jti.as_str()));
+        assert!(exchanged.exp <= parent.exp);
+        assert_eq!(resp.receipt_type.as_deref(), Some("exchanged"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn broadening_rejected() -> Result<()> {
+        let state = exchange_state()?;
+        let narrow = state.sign(&Claims::new("agent-1".into(), "deploy:service-x".into(), 120))?;
+
+        for (action, audience) in [("deploy", "service-x"), ("deploy:service-y", "service-x"), ("deploy:service-x", "service-y")] {
+            let result = exchange(State(state.clone()), Json(request(&narrow, action, audience))).await;
+            assert!(matches!(result, Err(Error::Forbidden(_))), "{action} for {audience}");
+        }
+
+        let Json(resp) = exchange(State(state.clone()), Json(request(&narrow, "deploy:service-x", "service-x"))).await?;
+        assert_eq!(state.verify(&resp.token)?.max_delegation_depth, Some(0));
+        let rechanged = exchange(State(state.clone()), Json(request(&resp.token, "deploy:service-x:canary", "service-x"))).await;
+        assert!(matches!(rechanged, Err(Error::Forbidden(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn refined_action_held_to_policy() -> Result<()> {
+        let refunds = crate::policy::PolicyLimit { max_amount: Some(50), ..Default::default() };
+        let guarded = crate::policy::PolicyLimit { require_approval: true, ..Default::default() };
+        let mut builder = test_builder()?;
+        builder.exchange_audiences = Some(vec!["service-x".into()]);
+        builder.policy = crate::policy::PolicyEngine::new(
+            [(Box::from("refund"), refunds), (Box::from("wire"), guarded)].into_iter().collect(),
+        );
+        let state = builder.build()?;
+        let subject = state.sign(&Claims::new("agent-1".into(), "refund".into(), 120))?;
+
+        let over = exchange(State(state.clone()), Json(request(&subject, "refund:amount:999999", "service-x"))).await;
+        assert!(matches!(over, Err(Error::PolicyViolation(_))));
+        let Json(resp) = exchange(State(state.clone()), Json(request(&subject, "refund:amount:30", "service-x"))).await?;
+        assert_eq!(state.verify(&resp.token)?.action, "refund:amount:30");
+
+        let unapproved = state.sign(&Claims::new("agent-1".into(), "wire".into(), 120))?;
+        let result = exchange(State(state.clone()), Json(request(&unapproved, "wire:acct-9", "service-x"))).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn audience_must_be_within_the_subject_tokens() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.exchange_audiences = Some(vec!["service-x".into(), "service-y".into()]);
+        let state = builder.build()?;
+        let parent = Claims { aud: Some(Audience::One("service-x".into())), ..Claims::new("agent-1".into(), "deploy".into(), 120) };
+        let subject = state.sign(&parent)?;
+
+        let elsewhere = exchange(State(state.clone()), Json(request(&subject, "deploy", "service-y"))).await;
+        assert!(matches!(elsewhere, Err(Error::Forbidden(_))));
+        let Json(resp) = exchange(State(state.clone()), Json(request(&subject, "deploy", "service-x"))).await?;
+        assert_eq!(state.verify(&resp.token)?.aud, parent.aud);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn disabled_without_configured_audiences() -> Result<()> {
+        let state = test_builder()?.build()?;
+        let subject = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 120))?;
+        let result = exchange(State(state), Json(request(&subject, "deploy", "service-x"))).await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+        Ok(())
+    }
+}
@@ -1,8 +1,137 @@
-//! Health check endpoint.
-//! Used by: server.
+//! Health and readiness endpoints.
+//! Used by: server, config.
 
-use axum::http::StatusCode;
+use std::time::Instant;
 
-pub async fn health() -> StatusCode {
+use axum::extract::State;
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::response::IntoResponse;
+use axum::Json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::error::{Error, Result};
+use crate::state::AppState;
+
+const MAX_HEALTH_BODY: usize = 1024;
+
+/// What `GET /health` answers with; JSON bodies are served as `application/json`, anything else as text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct HealthBody {
+    pub body: String,
+    pub content_type: HeaderValue,
+}
+
+impl Default for HealthBody {
+    fn default() -> Self {
+        Self { body: "ok".into(), content_type: HeaderValue::from_static("text/plain; charset=utf-8") }
+    }
+}
+
+impl HealthBody {
+    /// Parses `HEALTH_BODY`; unset keeps the plain `ok`.
+    pub fn parse(body: Option<String>) -> Result<Self> {
+        let Some(body) = body else {
+            return Ok(Self::default());
+        };
+        if body.len() > MAX_HEALTH_BODY {
+            return Err(Error::Config(format!("HEALTH_BODY must be at most {MAX_HEALTH_BODY} bytes")));
+        }
+        let content_type = if serde_json::from_str::<serde_json::Value>(&body).is_ok() {
+            HeaderValue::from_static("application/json")
+        } else {
+            Self::default().content_type
+        };
+        Ok(Self { body, content_type })
+    }
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct ReadyResponse {
+    pub ready: bool,
+    pub canary_ok: Option<bool>,
+    /// Background tasks (`audit_writer`, `metrics_sampler`, `canary`, `jwks_reload`) that have stopped heartbeating.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub stalled_tasks: Vec<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/health",
+    responses((status = 200, description = "service is up; body is `HEALTH_BODY` (default `ok`)", body = String))
+)]
+pub async fn health(State(state): State<AppState>) -> impl IntoResponse {
+    let health = &state.health_body;
+    ([(header::CONTENT_TYPE, health.content_type.clone())], health.body.clone())
+}
+
+#[utoipa::path(
+    head,
+    path = "/health",
+    responses((status = 200, description = "service is up; empty body"))
+)]
+pub async fn health_head() -> StatusCode {
     StatusCode::OK
 }
+
+#[utoipa::path(
+    get,
+    path = "/ready",
+    responses(
+        (status = 200, body = ReadyResponse),
+        (status = 503, body = ReadyResponse, description = "the last canary self-test failed, or a background task stalled"),
+    )
+)]
+pub async fn ready(State(state): State<AppState>) -> (StatusCode, Json<ReadyResponse>) {
+    let canary_ok = state.metrics.canary_ok();
+    let stalled_tasks: Vec<String> = state.heartbeats.stalled(Instant::now()).into_iter().map(String::from).collect();
+    for task in &stalled_tasks {
+        tracing::warn!(task = %task, "background task stopped heartbeating");
+    }
+    let ready = canary_ok != Some(false) && stalled_tasks.is_empty();
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadyResponse { ready, canary_ok, stalled_tasks }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::build_test_state;
+    use std::time::Duration;
+
+    #[test]
+    fn health_body_content_type_follows_body() -> Result<()> {
+        assert_eq!(HealthBody::parse(None)?, HealthBody::default());
+        assert_eq!(HealthBody::parse(Some(r#"{"status":"up"}"#.into()))?.content_type, "application/json");
+        assert_eq!(HealthBody::parse(Some("healthy".into()))?.content_type, "text/plain; charset=utf-8");
+        assert!(matches!(HealthBody::parse(Some("x".repeat(MAX_HEALTH_BODY + 1))), Err(Error::Config(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ready_reflects_canary() -> Result<()> {
+        let state = build_test_state()?;
+        assert_eq!(ready(State(state.clone())).await.0, StatusCode::OK);
+        state.metrics.set_canary(false);
+        let (status, Json(body)) = ready(State(state)).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.canary_ok, Some(false));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stalled_task_degrades_readiness() -> Result<()> {
+        let state = build_test_state()?;
+        state.heartbeats.register("audit_writer", Duration::from_millis(5));
+        assert_eq!(ready(State(state.clone())).await.0, StatusCode::OK);
+
+        tokio::time::sleep(Duration::from_millis(30)).await;
+        let (status, Json(body)) = ready(State(state.clone())).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body.stalled_tasks, ["audit_writer"]);
+
+        state.heartbeats.beat("audit_writer");
+        assert_eq!(ready(State(state)).await.0, StatusCode::OK);
+        Ok(())
+    }
+}
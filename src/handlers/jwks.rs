@@ -0,0 +1,139 @@
+//! JWK set endpoint: the Ed25519 keys tokens may currently be signed with, selected by `kid`.
+//! Used by: server, handlers::verification.
+
+use axum::extract::State;
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::Result;
+use crate::state::AppState;
+use crate::token::keys::key_id;
+
+/// The published set changes at runtime: a retired key drops out when its grace window ends, and
+/// a restart with a new key adds one. Verifiers may cache it only this long.
+const KEY_CACHE_MAX_AGE_SECS: i64 = 60;
+
+/// `Cache-Control` for published keys. Never outlasts the previous key's remaining grace, so no
+/// cached copy keeps vouching for a retired key after this server stops accepting it.
+pub(crate) fn key_cache_control(state: &AppState) -> String {
+    let grace_left = state.previous_key.as_ref().filter(|k| k.is_active()).map(|k| (k.until - Utc::now()).num_seconds());
+    let max_age = grace_left.map_or(KEY_CACHE_MAX_AGE_SECS, |left| left.clamp(0, KEY_CACHE_MAX_AGE_SECS));
+    format!("public, max-age={max_age}")
+}
+
+/// An Ed25519 public key as an RFC 8037 OKP JWK.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Jwk {
+    pub kty: String,
+    pub crv: String,
+    pub alg: String,
+    #[serde(rename = "use")]
+    pub usage: String,
+    pub kid: String,
+    /// Base64url public key bytes.
+    pub x: String,
+}
+
+impl Jwk {
+    pub fn ed25519(key: &VerifyingKey) -> Self {
+        Self {
+            kty: "OKP".into(),
+            crv: "Ed25519".into(),
+            alg: "EdDSA".into(),
+            usage: "sig".into(),
+            kid: key_id(key),
+            x: URL_SAFE_NO_PAD.encode(key.as_bytes()),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct JwkSet {
+    pub keys: Vec<Jwk>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/jwks.json",
+    responses(
+        (status = 200, body = JwkSet, description = "Current key, plus the previous key during its grace window"),
+        (status = 403, description = "HS256 mode: there is no public key")
+    )
+)]
+pub async fn jwks(State(state): State<AppState>) -> Result<impl IntoResponse> {
+    let keys = state.public_keys()?.iter().map(Jwk::ed25519).collect();
+    Ok(([(header::CACHE_CONTROL, key_cache_control(&state))], Json(JwkSet { keys })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::{test_builder, StateBuilder};
+    use crate::testing::TestServer;
+    use crate::token::claims::Claims;
+    use crate::token::keys::GraceKey;
+    use crate::token::sign::generate_keypair;
+    use crate::token::verify::verify_token_with_keys;
+    use std::collections::HashMap;
+
+    fn decode(jwk: &Jwk) -> std::result::Result<VerifyingKey, Box<dyn std::error::Error>> {
+        let bytes: [u8; 32] = URL_SAFE_NO_PAD.decode(&jwk.x)?.try_into().map_err(|_| "x is not 32 bytes")?;
+        Ok(VerifyingKey::from_bytes(&bytes)?)
+    }
+
+    #[tokio::test]
+    async fn hs256_mode_publishes_no_keys() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let mut builder = test_builder()?;
+        builder.hmac_key = Some(crate::token::keys::hmac_key(b"an-internal-shared-secret-of-32-bytes"));
+        let server = TestServer::spawn_with_state(builder.build()?).await?;
+        for path in ["/jwks.json", "/pubkey", "/pubkey?format=pem", "/verification-bundle"] {
+            assert_eq!(reqwest::get(server.url(path)).await?.status(), reqwest::StatusCode::FORBIDDEN, "{path}");
+        }
+
+        let token = server.state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        assert_eq!(server.state.verify(&token)?.sub, "agent-1");
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn published_keys_verify_minted_tokens() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let previous = generate_keypair();
+        let grace = GraceKey::new(previous.verifying_key(), chrono::Duration::seconds(60));
+        let server = TestServer::spawn_with_state(StateBuilder { previous_key: Some(grace), ..test_builder()? }.build()?).await?;
+
+        let set: JwkSet = reqwest::get(server.url("/jwks.json")).await?.json().await?;
+        assert_eq!(set.keys.len(), 2);
+        assert!(set.keys.iter().all(|k| k.kty == "OKP" && k.crv == "Ed25519"));
+        let keys = set.keys.iter().map(|k| Ok((k.kid.clone(), decode(k)?))).collect::<std::result::Result<HashMap<_, _>, Box<dyn std::error::Error>>>()?;
+
+        let token = server.state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        assert_eq!(verify_token_with_keys(&token, &keys, None)?.sub, "agent-1");
+        let rotated_out = crate::token::sign::sign_token(&Claims::new("agent-2".into(), "deploy".into(), 60), &previous)?;
+        assert_eq!(verify_token_with_keys(&rotated_out, &keys, None)?.sub, "agent-2");
+        server.shutdown().await?;
+        Ok(())
+    }
+
+    #[test]
+    fn cached_keys_never_outlive_the_grace_window() -> crate::error::Result<()> {
+        let state = test_builder()?.build()?;
+        assert_eq!(key_cache_control(&state), format!("public, max-age={KEY_CACHE_MAX_AGE_SECS}"));
+
+        let grace = GraceKey::new(generate_keypair().verifying_key(), chrono::Duration::seconds(20));
+        let state = StateBuilder { previous_key: Some(grace), ..test_builder()? }.build()?;
+        let max_age: i64 = key_cache_control(&state)
+            .strip_prefix("public, max-age=")
+            .and_then(|s| s.parse().ok())
+            .ok_or_else(|| crate::error::Error::Validation("malformed Cache-Control".into()))?;
+        assert!((0..=20).contains(&max_age), "{max_age}");
+        Ok(())
+    }
+}
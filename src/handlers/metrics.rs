@@ -5,8 +5,31 @@ use axum::extract::State;
 use axum::Json;
 
 use crate::state::AppState;
-use crate::telemetry::MetricsSnapshot;
+use crate::telemetry::{MetricsSnapshot, RouteLatency, TimedSnapshot};
 
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    responses((status = 200, body = MetricsSnapshot))
+)]
 pub async fn metrics(State(state): State<AppState>) -> Json<MetricsSnapshot> {
     Json(state.metrics.snapshot())
 }
+
+#[utoipa::path(
+    get,
+    path = "/metrics/history",
+    responses((status = 200, body = [TimedSnapshot]))
+)]
+pub async fn history(State(state): State<AppState>) -> Json<Vec<TimedSnapshot>> {
+    Json(state.metrics_history.series())
+}
+
+#[utoipa::path(
+    get,
+    path = "/metrics/latency",
+    responses((status = 200, body = [RouteLatency]))
+)]
+pub async fn latency(State(state): State<AppState>) -> Json<Vec<RouteLatency>> {
+    Json(state.metrics.route_latency.report())
+}
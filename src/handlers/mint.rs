@@ -1,20 +1,31 @@
 //! Token minting endpoint with input validation, policy enforcement, and OIDC verification.
+//! Used by: server, handlers::refresh, handlers::preauth, handlers::batch.
+
+use std::collections::BTreeMap;
 
 use axum::extract::State;
 use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
 
-use crate::error::{Error, Result};
+use crate::audit::sqlite::EventType;
+use crate::audit::writer::AuditRecord;
+use crate::canary::CANARY_SUBJECT;
+use crate::error::{Error, Result, TokenFault};
+use crate::oidc::IdTokenClaims;
+use crate::policy::{BelowFloor, PolicyEngine, TtlFloor};
 use crate::state::AppState;
-use crate::token::claims::Claims;
-use crate::token::sign::sign_token;
+use crate::token::claims::{Audience, Claims};
+use crate::token::pop::confirmation;
 
-#[derive(Deserialize)]
+#[derive(Default, Deserialize, ToSchema)]
 pub struct MintRequest {
     pub sub: String,
     pub action: String,
-    #[serde(default = "default_ttl")]
-    pub ttl_seconds: i64,
+    pub ttl_seconds: Option<i64>,
     pub id_token: Option<String>,
     // Orchestration fields (optional)
     pub scope: Option<Vec<String>>,
@@ -22,106 +33,357 @@ pub struct MintRequest {
     pub requires_checkpoint: Option<Vec<String>>,
     #[serde(default = "default_max_depth")]
     pub max_delegation_depth: Option<u32>,
+    #[serde(default)]
+    pub refresh: bool,
+    pub preauth_id: Option<String>,
+    /// Service(s) the token is valid for: a string or a list.
+    pub aud: Option<Audience>,
+    /// Client Ed25519 public key (base64url) to bind the token to; `/proxy` then requires a proof.
+    #[serde(default)]
+    pub cnf_key: Option<String>,
+    /// Delay before the token becomes valid (`nbf`); its TTL counts from then.
+    #[serde(default)]
+    pub not_before_seconds: Option<i64>,
+    /// `binary` returns the compact form for `/proxy/binary`, base64url-encoded in `token`.
+    #[serde(default)]
+    pub format: TokenFormat,
+    /// Fields this version does not know; ignored, or rejected under `STRICT_REQUESTS`.
+    #[serde(flatten)]
+    pub unknown_fields: BTreeMap<String, serde_json::Value>,
 }
 
-fn default_ttl() -> i64 {
-    60
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenFormat {
+    #[default]
+    Text,
+    /// `sign_token_binary`; cannot carry a proof-of-possession binding.
+    Binary,
 }
 
+const MAX_AUDIENCES: usize = 16;
+/// Tokens may be minted at most a day ahead of use.
+pub(crate) const MAX_NOT_BEFORE_SECONDS: i64 = 86_400;
+const DEFAULT_TTL: i64 = 60;
+pub(crate) const MAX_TTL: i64 = 300;
+
 fn default_max_depth() -> Option<u32> {
     None
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct MintResponse {
     pub token: String,
     pub jti: String,
     pub exp: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub receipt_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub refresh_token: Option<String>,
 }
 
 fn validate_request(req: &MintRequest) -> Result<()> {
-    if req.sub.is_empty() || req.sub.len() > 256 {
-        return Err(Error::InvalidToken("sub must be 1-256 characters".into()));
+    validate_sub(&req.sub)?;
+    validate_action(&req.action)?;
+    if req.not_before_seconds.is_some_and(|s| !(0..=MAX_NOT_BEFORE_SECONDS).contains(&s)) {
+        return Err(Error::Validation(format!("not_before_seconds must be 0-{MAX_NOT_BEFORE_SECONDS}")));
+    }
+    if req.format == TokenFormat::Binary && req.cnf_key.is_some() {
+        return Err(Error::Validation("binary tokens cannot carry cnf_key; /proxy/binary takes no proof".into()));
+    }
+    req.scope.as_deref().map_or(Ok(()), validate_scope)?;
+    req.aud.as_ref().map_or(Ok(()), validate_audience)
+}
+
+/// `STRICT_REQUESTS`: a misspelled field (`ttl_second`) is an error rather than a silent default.
+fn reject_unknown_fields(req: &MintRequest) -> Result<()> {
+    if req.unknown_fields.is_empty() {
+        return Ok(());
     }
-    if req.sub.chars().any(|c| c.is_control()) {
-        return Err(Error::InvalidToken("sub contains control characters".into()));
+    let names: Vec<&str> = req.unknown_fields.keys().map(String::as_str).collect();
+    Err(Error::Validation(format!("unknown field(s): {}", names.join(", "))))
+}
+
+/// Scope entries follow the `action` rules, plus the `*` and `prefix:*` wildcards scope matching understands.
+fn validate_scope(scope: &[String]) -> Result<()> {
+    scope
+        .iter()
+        .filter(|entry| entry.as_str() != "*")
+        .try_for_each(|entry| validate_action(entry.strip_suffix(":*").unwrap_or(entry)))
+}
+
+/// `MAX_TOKEN_SCOPES` bounds token size and the per-verification scope scan.
+fn check_scope_count(scope: Option<&[String]>, max: usize) -> Result<()> {
+    match scope {
+        Some(scope) if scope.len() > max => {
+            Err(Error::Validation(format!("scope lists {} entries; at most {max} allowed", scope.len())))
+        }
+        _ => Ok(()),
     }
-    if req.action.is_empty()
-        || req.action.len() > 64
-        || !req.action.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '-')
+}
+
+pub(crate) fn validate_audience(aud: &Audience) -> Result<()> {
+    let values = aud.values();
+    if values.is_empty() || values.len() > MAX_AUDIENCES {
+        return Err(Error::Validation(format!("aud must list 1-{MAX_AUDIENCES} services")));
+    }
+    if values.iter().any(|a| a.is_empty() || a.len() > 64 || a.chars().any(|c| c.is_control())) {
+        return Err(Error::Validation("aud entries must be 1-64 printable characters".into()));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_sub(sub: &str) -> Result<()> {
+    if sub.is_empty() || sub.len() > 256 {
+        return Err(Error::InvalidToken(TokenFault::InvalidField, "sub must be 1-256 characters".into()));
+    }
+    if sub == CANARY_SUBJECT {
+        return Err(Error::InvalidToken(TokenFault::InvalidField, "sub is reserved".into()));
+    }
+    if sub.chars().any(|c| c.is_control()) {
+        return Err(Error::InvalidToken(TokenFault::InvalidField, "sub contains control characters".into()));
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_action(action: &str) -> Result<()> {
+    if action.is_empty()
+        || action.len() > 64
+        || !action.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':' || c == '-')
     {
         return Err(Error::InvalidToken(
+            TokenFault::InvalidField,
             "action must be 1-64 chars (alphanumeric, underscore, colon, hyphen)".into(),
         ));
     }
     Ok(())
 }
 
-fn clamp_ttl(ttl: i64) -> i64 {
-    ttl.clamp(1, 300)
+fn clamp_ttl(ttl: i64, min: i64) -> i64 {
+    ttl.clamp(min, MAX_TTL.max(min))
+}
+
+/// The ceiling is the tighter of `MAX_TTL` and the action's `max_ttl_seconds`; the floor beats both.
+pub(crate) fn resolve_ttl(policy: &PolicyEngine, floor: TtlFloor, action: &str, requested: Option<i64>) -> Result<i64> {
+    if floor.below == BelowFloor::Reject && requested.is_some_and(|ttl| ttl < floor.seconds) {
+        return Err(Error::Validation(format!("ttl_seconds must be at least {}", floor.seconds)));
+    }
+    let limit = policy.limit_for(action);
+    let ttl = requested
+        .or_else(|| limit.and_then(|l| l.default_ttl_seconds))
+        .unwrap_or(DEFAULT_TTL);
+    let ttl = clamp_ttl(ttl, floor.seconds);
+    Ok(match limit.and_then(|l| l.max_ttl_seconds) {
+        Some(max) => ttl.min(max.max(floor.seconds)),
+        None => ttl,
+    })
+}
+
+/// `approved_by` is the OIDC-verified subject, if any; only that unlocks a raised per-subject limit.
+pub(crate) fn enforce_policy(state: &AppState, sub: &str, approved_by: Option<&str>, action: &str) -> Result<()> {
+    let Err(v) = state.policy.check(sub, approved_by == Some(sub), action) else {
+        return Ok(());
+    };
+    crate::console::log_policy_denial(sub, action, v.action_type, v.limit, v.requested);
+    state.metrics.record_policy_denial();
+    Err(Error::PolicyViolation(v.into()))
 }
 
+/// Holds each scope entry to the same limits as an action, and wildcards to the policy's `allow_wildcard_scope`.
+pub(crate) fn enforce_scope_policy(state: &AppState, sub: &str, approved_by: Option<&str>, scope: &[String]) -> Result<()> {
+    for entry in scope {
+        if !state.policy.allows_scope_wildcard(entry) {
+            return Err(Error::Forbidden(format!("policy does not allow the wildcard scope {entry}")));
+        }
+        enforce_policy(state, sub, approved_by, entry)?;
+    }
+    Ok(())
+}
+
+/// Applies the action's `require_oidc` / `require_approval` policy flags to how the mint was approved.
+/// `preauthorized` approvals were verified earlier, so they never satisfy `require_oidc`.
+pub(crate) fn enforce_identity_requirements(state: &AppState, action: &str, approved_by: Option<&str>, preauthorized: bool) -> Result<()> {
+    let Some(limit) = state.policy.limit_for(action) else {
+        return Ok(());
+    };
+    if limit.require_oidc && (preauthorized || approved_by.is_none()) {
+        return Err(Error::Unauthorized(format!("action {action} requires an OIDC id_token")));
+    }
+    if limit.require_approval && approved_by.is_none() {
+        return Err(Error::Unauthorized(format!("action {action} requires human approval")));
+    }
+    Ok(())
+}
+
+/// Counts an action whose type no policy covers; it is still allowed. Called once a mint or
+/// proxied token is admitted, so rejected and pre-authorized attempts are not counted.
+pub(crate) fn note_unmatched_policy(state: &AppState, sub: &str, action: &str) {
+    if state.policy.limit_for(action).is_none() {
+        tracing::info!(sub = %sub, action = %action, "no policy matches action type");
+        state.metrics.record_policy_unmatched();
+    }
+}
+
+/// The IdP claim matched against `sub`, validated before it is compared or logged.
+fn oidc_subject(claims: &IdTokenClaims) -> Result<&str> {
+    let subject = claims.email.as_deref().unwrap_or(&claims.sub);
+    validate_sub(subject).map_err(|_| Error::Unauthorized("id_token subject is malformed".into()))?;
+    Ok(subject)
+}
+
+/// An id_token that passed verification: whom it names, and when it stops vouching for them.
+pub(crate) struct VerifiedIdentity {
+    pub subject: String,
+    pub expires_at: i64,
+}
+
+/// Returns the verified OIDC identity, or `None` when no id_token was checked.
+/// Every attempt leaves an `oidc_verified` or `oidc_rejected` audit row for `action`, the
+/// action(s) the id_token is presented for; the id_token itself is never recorded.
+/// With `REQUIRE_OIDC` and no verifier (`ALLOW_OIDC_LOCKDOWN`), every mint is refused.
+pub(crate) async fn verify_identity(state: &AppState, sub: &str, action: &str, id_token: Option<&str>) -> Result<Option<VerifiedIdentity>> {
+    let Some(ref oidc) = state.oidc else {
+        if state.require_oidc {
+            crate::console::log_oidc_required(sub);
+            return Err(Error::ServiceUnavailable("minting is locked down: REQUIRE_OIDC=true without an OIDC verifier".into()));
+        }
+        return Ok(None);
+    };
+    let Some(token) = id_token else {
+        if !state.require_oidc {
+            return Ok(None);
+        }
+        crate::console::log_oidc_required(sub);
+        return Err(oidc_failure(state, sub, action, None, "id_token required".into()));
+    };
+
+    let verified = match state.oidc_cache.get(token) {
+        Some(claims) => {
+            state.metrics.record_oidc_cache_hit();
+            Ok(claims)
+        }
+        None => oidc.verify(token).await.inspect(|claims| {
+            if state.oidc_cache.insert(token, claims.clone()) {
+                state.metrics.record_oidc_cache_eviction();
+            }
+        }),
+    };
+    let claims = match verified {
+        Ok(claims) => claims,
+        Err(e) => {
+            crate::console::log_oidc_failure(sub, &e.to_string());
+            return Err(oidc_failure(state, sub, action, None, format!("OIDC verification failed: {}", e)));
+        }
+    };
+
+    // Verify sub matches
+    let oidc_sub = match oidc_subject(&claims) {
+        Ok(oidc_sub) => oidc_sub,
+        Err(_) => return Err(oidc_failure(state, sub, action, None, "id_token subject is malformed".into())),
+    };
+    if oidc_sub != sub {
+        crate::console::log_oidc_mismatch(sub, oidc_sub);
+        let reason = format!("sub mismatch: requested {} but id_token is for {}", sub, oidc_sub);
+        return Err(oidc_failure(state, sub, action, Some(oidc_sub), reason));
+    }
+
+    audit_oidc(state, EventType::OidcVerified, sub, action, Some(oidc_sub), &claims.iss)?;
+    crate::console::log_oidc_success(sub);
+    Ok(Some(VerifiedIdentity { subject: oidc_sub.to_owned(), expires_at: claims.exp as i64 }))
+}
+
+/// `CAP_TTL_TO_ID_TOKEN`: shortens `ttl` to the id_token's remaining life. A refresh token would
+/// outlive it regardless, so one cannot be requested alongside.
+fn cap_ttl_to_identity(ttl: i64, id_token_exp: i64, refresh: bool) -> Result<i64> {
+    let remaining = id_token_exp - Utc::now().timestamp();
+    if remaining < 1 {
+        return Err(Error::Unauthorized("id_token expires before a token minted from it could be used".into()));
+    }
+    if refresh {
+        return Err(Error::Validation("refresh is unavailable while tokens are capped to the id_token's expiry".into()));
+    }
+    Ok(ttl.min(remaining))
+}
+
+/// Counts and audits a rejected attempt. The rejection stands even if the audit write fails.
+fn oidc_failure(state: &AppState, sub: &str, action: &str, oidc_sub: Option<&str>, reason: String) -> Error {
+    state.metrics.record_oidc_failure();
+    if let Err(e) = audit_oidc(state, EventType::OidcRejected, sub, action, oidc_sub, &reason) {
+        tracing::error!(error = %e, sub, "failed to audit OIDC rejection");
+    }
+    Error::Unauthorized(reason)
+}
+
+/// No token exists yet, so `jti` is `-`. `detail` is the issuer for `oidc_verified` and the
+/// reason for `oidc_rejected`; `approved_by` is the OIDC subject once one is known.
+fn audit_oidc(state: &AppState, event_type: EventType, sub: &str, action: &str, oidc_sub: Option<&str>, detail: &str) -> Result<()> {
+    state.audit_log.log_record(&AuditRecord {
+        event_type,
+        jti: "-".into(),
+        sub: sub.into(),
+        action: action.into(),
+        at: Utc::now(),
+        detail: Some(detail.into()),
+        approved_by: oidc_sub.map(str::to_owned),
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/mint",
+    request_body = MintRequest,
+    responses(
+        (status = 200, body = MintResponse),
+        (status = 400, description = "invalid request"),
+        (status = 401, description = "OIDC verification failed or action not pre-authorized"),
+        (status = 403, description = "policy violation"),
+        (status = 429, description = "daily mint quota exceeded"),
+        (status = 503, description = "minting locked down: REQUIRE_OIDC without OIDC configured"),
+    )
+)]
 pub async fn mint(
     State(state): State<AppState>,
     Json(req): Json<MintRequest>,
 ) -> Result<Json<MintResponse>> {
-    validate_request(&req)?;
+    Ok(Json(mint_one(&state, req).await?))
+}
 
-    // OIDC verification
-    if let Some(ref oidc) = state.oidc {
-        match &req.id_token {
-            Some(token) => {
-                let claims = oidc.verify(token).await.map_err(|e| {
-                    crate::console::log_oidc_failure(&req.sub, &e.to_string());
-                    state.metrics.record_oidc_failure();
-                    Error::Unauthorized(format!("OIDC verification failed: {}", e))
-                })?;
-
-                // Verify sub matches
-                let oidc_sub = claims.email.as_ref().unwrap_or(&claims.sub);
-                if oidc_sub != &req.sub {
-                    crate::console::log_oidc_mismatch(&req.sub, oidc_sub);
-                    state.metrics.record_oidc_failure();
-                    return Err(Error::Unauthorized(format!(
-                        "sub mismatch: requested {} but id_token is for {}",
-                        req.sub, oidc_sub
-                    )));
-                }
-
-                crate::console::log_oidc_success(&req.sub);
-            }
-            None if state.require_oidc => {
-                crate::console::log_oidc_required(&req.sub);
-                state.metrics.record_oidc_failure();
-                return Err(Error::Unauthorized("id_token required".into()));
-            }
-            None => {}
-        }
+pub(crate) async fn mint_one(state: &AppState, mut req: MintRequest) -> Result<MintResponse> {
+    if state.strict_requests {
+        reject_unknown_fields(&req)?;
     }
+    req.action = state.normalize_action(req.action);
+    req.scope = req.scope.map(|scope| scope.into_iter().map(|s| state.normalize_action(s)).collect());
+    validate_request(&req)?;
+    check_scope_count(req.scope.as_deref(), state.max_scopes)?;
+    let cnf = req.cnf_key.as_deref().map(confirmation).transpose()?;
 
-    // Policy check
-    if let Err(v) = state.policy.check(&req.action) {
-        crate::console::log_policy_denial(
-            &req.sub,
-            &req.action,
-            v.action_type,
-            v.limit,
-            v.requested,
-        );
-        state.metrics.record_policy_denial();
-        return Err(Error::PolicyViolation(format!(
-            "{} limit is ${}. Requested: ${}",
-            v.action_type, v.limit, v.requested
-        )));
+    let (approved_by, id_token_exp) = match &req.preauth_id {
+        Some(id) => match state.preauth_store.peek(id, &req.sub, &req.action)? {
+            Some(approval) => (Some(approval.approved_by), Some(approval.id_token_exp)),
+            None => (None, None),
+        },
+        None => match verify_identity(state, &req.sub, &req.action, req.id_token.as_deref()).await? {
+            Some(identity) => (Some(identity.subject), Some(identity.expires_at)),
+            None => (None, None),
+        },
+    };
+    enforce_identity_requirements(state, &req.action, approved_by.as_deref(), req.preauth_id.is_some())?;
+
+    enforce_policy(state, &req.sub, approved_by.as_deref(), &req.action)?;
+    enforce_scope_policy(state, &req.sub, approved_by.as_deref(), req.scope.as_deref().unwrap_or_default())?;
+    let mut ttl = resolve_ttl(&state.policy, state.ttl_floor, &req.action, req.ttl_seconds)?;
+    if let Some(exp) = id_token_exp.filter(|_| state.cap_ttl_to_id_token) {
+        ttl = cap_ttl_to_identity(ttl, exp, req.refresh)?;
     }
+    state.consume_mint_quota(&req.sub, approved_by.as_deref()).await?;
+    note_unmatched_policy(state, &req.sub, &req.action);
 
-    let ttl = clamp_ttl(req.ttl_seconds);
+    state.metrics.record_mint_ttl(ttl, req.ttl_seconds.is_some_and(|requested| requested != ttl));
 
     // Build claims: plan receipt if orchestration fields present, basic receipt otherwise
     let is_plan = req.scope.is_some() || req.delegates_to.is_some();
-    let claims = if is_plan {
+    let mut claims = if is_plan {
         Claims::new_plan(
             req.sub,
             req.action,
@@ -134,17 +396,33 @@ pub async fn mint(
     } else {
         Claims::new(req.sub, req.action, ttl)
     };
+    claims.aud = req.aud;
+    claims.approved_by = approved_by;
+    claims.cnf = cnf;
+    if let Some(delay) = req.not_before_seconds {
+        claims = claims.not_before(delay);
+    }
 
     let jti = claims.jti.clone();
     let exp = claims.exp.to_rfc3339();
     let receipt_type = claims.receipt_type.clone();
-    let token = sign_token(&claims, &state.signing_key)?;
+    let token = match req.format {
+        TokenFormat::Text => state.sign(&claims)?,
+        TokenFormat::Binary => URL_SAFE_NO_PAD.encode(state.sign_binary(&claims)?),
+    };
+    if let Some(id) = &req.preauth_id {
+        state.preauth_store.consume(id, &claims.sub, &claims.action)?;
+    }
+    let refresh_token = match req.refresh {
+        true => Some(state.refresh_store.issue(&claims, ttl)?),
+        false => None,
+    };
 
     tracing::info!(sub = %claims.sub, action = %claims.action, jti = %jti, receipt_type = ?receipt_type, "token minted");
     crate::console::log_mint(&claims.sub, &claims.action, &jti);
     state.metrics.record_mint();
 
-    Ok(Json(MintResponse { token, jti, exp, receipt_type }))
+    Ok(MintResponse { token, jti, exp, receipt_type, refresh_token })
 }
 
 #[cfg(test)]
@@ -152,16 +430,7 @@ mod tests {
     use super::*;
 
     fn req(sub: &str, action: &str, ttl: i64) -> MintRequest {
-        MintRequest {
-            sub: sub.into(),
-            action: action.into(),
-            ttl_seconds: ttl,
-            id_token: None,
-            scope: None,
-            delegates_to: None,
-            requires_checkpoint: None,
-            max_delegation_depth: None,
-        }
+        MintRequest { sub: sub.into(), action: action.into(), ttl_seconds: Some(ttl), ..Default::default() }
     }
 
     #[test]
@@ -184,6 +453,11 @@ mod tests {
         assert!(validate_request(&req("agent\x00", "deploy", 60)).is_err());
     }
 
+    #[test]
+    fn canary_subject_reserved() {
+        assert!(validate_request(&req(CANARY_SUBJECT, "canary", 60)).is_err());
+    }
+
     #[test]
     fn invalid_action_rejected() {
         assert!(validate_request(&req("a", "deploy!", 60)).is_err());
@@ -195,16 +469,430 @@ mod tests {
         assert!(validate_request(&req("a", "deploy-prod", 60)).is_ok());
     }
 
+    #[test]
+    fn audience_validated() {
+        let mut r = req("a", "pay", 60);
+        r.aud = Some(Audience::Many(vec!["payments".into(), "ledger".into()]));
+        assert!(validate_request(&r).is_ok());
+        r.aud = Some(Audience::Many(vec![]));
+        assert!(matches!(validate_request(&r), Err(Error::Validation(_))));
+        r.aud = Some(Audience::One("bad\naud".into()));
+        assert!(matches!(validate_request(&r), Err(Error::Validation(_))));
+    }
+
     #[test]
     fn empty_action_rejected() {
         assert!(validate_request(&req("a", "", 60)).is_err());
     }
 
+    fn id_claims(sub: &str, email: Option<&str>) -> IdTokenClaims {
+        IdTokenClaims {
+            sub: sub.into(),
+            email: email.map(Into::into),
+            aud: "agentmint".into(),
+            iss: "https://issuer".into(),
+            exp: 0,
+            iat: 0,
+        }
+    }
+
+    #[test]
+    fn oidc_subject_prefers_email() -> Result<()> {
+        assert_eq!(oidc_subject(&id_claims("1234", Some("alice@example.com")))?, "alice@example.com");
+        assert_eq!(oidc_subject(&id_claims("1234", None))?, "1234");
+        Ok(())
+    }
+
+    #[test]
+    fn overlong_oidc_subject_rejected() {
+        let long = "a".repeat(257);
+        assert!(matches!(oidc_subject(&id_claims(&long, None)), Err(Error::Unauthorized(_))));
+        assert!(matches!(oidc_subject(&id_claims("1234", Some(&long))), Err(Error::Unauthorized(_))));
+    }
+
+    #[test]
+    fn control_chars_in_oidc_subject_rejected() {
+        let forged = id_claims("1234", Some("alice@example.com\n[INFO] forged"));
+        assert!(matches!(oidc_subject(&forged), Err(Error::Unauthorized(_))));
+        assert!(matches!(oidc_subject(&id_claims("ali\x00ce", None)), Err(Error::Unauthorized(_))));
+    }
+
     #[test]
     fn ttl_clamped_to_bounds() {
-        assert_eq!(clamp_ttl(0), 1);
-        assert_eq!(clamp_ttl(-5), 1);
-        assert_eq!(clamp_ttl(500), 300);
-        assert_eq!(clamp_ttl(60), 60);
+        assert_eq!(clamp_ttl(0, 1), 1);
+        assert_eq!(clamp_ttl(-5, 1), 1);
+        assert_eq!(clamp_ttl(500, 1), 300);
+        assert_eq!(clamp_ttl(60, 1), 60);
+    }
+
+    fn ttl_policy() -> PolicyEngine {
+        let limit = |default, max| crate::policy::PolicyLimit {
+            default_ttl_seconds: Some(default),
+            max_ttl_seconds: Some(max),
+            ..Default::default()
+        };
+        PolicyEngine::new([
+            (Box::from("deploy"), limit(10, 30)),
+            (Box::from("report"), limit(120, 3600)),
+        ].into_iter().collect())
+    }
+
+    #[test]
+    fn omitted_ttl_uses_per_action_default() -> Result<()> {
+        let (policy, floor) = (ttl_policy(), TtlFloor::default());
+        assert_eq!(resolve_ttl(&policy, floor, "deploy:prod", None)?, 10);
+        assert_eq!(resolve_ttl(&policy, floor, "report:daily", None)?, 120);
+        assert_eq!(resolve_ttl(&policy, floor, "refund", None)?, DEFAULT_TTL);
+        Ok(())
+    }
+
+    #[test]
+    fn per_action_ceiling_tightens_global_clamp() -> Result<()> {
+        let (policy, floor) = (ttl_policy(), TtlFloor::default());
+        assert_eq!(resolve_ttl(&policy, floor, "deploy:prod", Some(120))?, 30);
+        assert_eq!(resolve_ttl(&policy, floor, "read", Some(1000))?, MAX_TTL);
+        Ok(())
+    }
+
+    #[test]
+    fn per_action_ceiling_above_global_clamped_to_global() -> Result<()> {
+        let (policy, floor) = (ttl_policy(), TtlFloor::default());
+        assert_eq!(resolve_ttl(&policy, floor, "report", Some(1000))?, MAX_TTL);
+        assert_eq!(resolve_ttl(&policy, floor, "report", Some(200))?, 200);
+        Ok(())
+    }
+
+    #[test]
+    fn ttl_below_floor_raised_to_floor() -> Result<()> {
+        let (policy, floor) = (ttl_policy(), TtlFloor { seconds: 5, below: BelowFloor::Clamp });
+        assert_eq!(resolve_ttl(&policy, floor, "refund", Some(1))?, 5);
+        assert_eq!(resolve_ttl(&policy, floor, "refund", Some(-10))?, 5);
+        assert_eq!(resolve_ttl(&policy, floor, "refund", Some(5000))?, MAX_TTL);
+        assert_eq!(resolve_ttl(&policy, floor, "deploy", Some(2))?, 5);
+        assert_eq!(resolve_ttl(&policy, floor, "deploy", Some(120))?, 30);
+        Ok(())
+    }
+
+    #[test]
+    fn floor_above_per_action_max_wins() -> Result<()> {
+        let floor = TtlFloor { seconds: 45, below: BelowFloor::Clamp };
+        assert_eq!(resolve_ttl(&ttl_policy(), floor, "deploy", Some(120))?, 45);
+        Ok(())
+    }
+
+    #[test]
+    fn ttl_below_floor_rejected_in_reject_mode() -> Result<()> {
+        let (policy, floor) = (ttl_policy(), TtlFloor { seconds: 5, below: BelowFloor::Reject });
+        assert!(matches!(resolve_ttl(&policy, floor, "refund", Some(2)), Err(Error::Validation(_))));
+        assert_eq!(resolve_ttl(&policy, floor, "refund", Some(5))?, 5);
+        assert_eq!(resolve_ttl(&policy, floor, "refund", None)?, DEFAULT_TTL);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oidc_backed_mint_embeds_approver() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        let state = builder.build()?;
+
+        let mut request = req("alice@example.com", "deploy", 60);
+        request.id_token = Some(test_support::sign(&test_support::claims("alice@example.com"))?);
+        let minted = mint_one(&state, request).await?;
+        assert_eq!(state.verify(&minted.token)?.approved_by.as_deref(), Some("alice@example.com"));
+
+        let anonymous = mint_one(&state, req("alice@example.com", "deploy", 60)).await?;
+        assert_eq!(state.verify(&anonymous.token)?.approved_by, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn ttl_capped_to_expiring_id_token() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        builder.cap_ttl_to_id_token = true;
+        let state = builder.build()?;
+        let mut id_claims = test_support::claims("alice@example.com");
+        id_claims.exp = Utc::now().timestamp() as u64 + 10;
+        let id_token = test_support::sign(&id_claims)?;
+
+        let mut request = req("alice@example.com", "deploy", 300);
+        request.id_token = Some(id_token.clone());
+        let minted = mint_one(&state, request).await?;
+        let claims = state.verify(&minted.token)?;
+        assert!(claims.exp.timestamp() <= id_claims.exp as i64);
+        assert_eq!(minted.exp, claims.exp.to_rfc3339());
+        assert_eq!(state.metrics.snapshot().ttl_clamped, 1);
+
+        let mut refreshable = req("alice@example.com", "deploy", 300);
+        refreshable.id_token = Some(id_token);
+        refreshable.refresh = true;
+        assert!(matches!(mint_one(&state, refreshable).await, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn per_action_oidc_requirement_enforced() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let guarded = crate::policy::PolicyLimit { require_oidc: true, require_approval: true, ..Default::default() };
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        builder.policy = PolicyEngine::new([(Box::from("refund"), guarded)].into_iter().collect());
+        let state = builder.build()?;
+
+        let anonymous = mint_one(&state, req("alice@example.com", "refund:amount:20", 60)).await;
+        assert!(matches!(anonymous, Err(Error::Unauthorized(_))));
+        mint_one(&state, req("alice@example.com", "read", 60)).await?;
+
+        let mut request = req("alice@example.com", "refund:amount:20", 60);
+        request.id_token = Some(test_support::sign(&test_support::claims("alice@example.com"))?);
+        let minted = mint_one(&state, request).await?;
+        assert_eq!(state.verify(&minted.token)?.approved_by.as_deref(), Some("alice@example.com"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn identity_required_without_a_verifier_refuses_every_mint() -> Result<()> {
+        let mut builder = crate::state::test_builder()?;
+        builder.require_oidc = true;
+        let locked = builder.build()?;
+        let mut request = req("alice@example.com", "read", 60);
+        request.id_token = Some("unverifiable".into());
+        assert!(matches!(mint_one(&locked, request).await, Err(Error::ServiceUnavailable(_))));
+        assert!(matches!(mint_one(&locked, req("alice@example.com", "read", 60)).await, Err(Error::ServiceUnavailable(_))));
+
+        let guarded = crate::policy::PolicyLimit { require_oidc: true, ..Default::default() };
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = PolicyEngine::new([(Box::from("refund"), guarded)].into_iter().collect());
+        let unverified = builder.build()?;
+        let mut request = req("alice@example.com", "refund:amount:20", 60);
+        request.id_token = Some("unverifiable".into());
+        assert!(matches!(mint_one(&unverified, request).await, Err(Error::Unauthorized(_))));
+        assert_eq!(unverified.metrics.snapshot().tokens_minted, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_scope_rejected_before_signing() -> Result<()> {
+        let mut builder = crate::state::test_builder()?;
+        builder.max_scopes = 2;
+        let state = builder.build()?;
+
+        let mut request = req("agent-1", "deploy", 60);
+        request.scope = Some(vec!["read".into(), "write".into(), "admin".into()]);
+        let result = mint_one(&state, request).await;
+        assert!(matches!(result, Err(Error::Validation(msg)) if msg.contains("at most 2")));
+        assert_eq!(state.metrics.snapshot().tokens_minted, 0);
+
+        let mut request = req("agent-1", "deploy", 60);
+        request.scope = Some(vec!["read".into(), "write".into()]);
+        mint_one(&state, request).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn misspelled_field_rejected_only_in_strict_mode() -> Result<()> {
+        let body = serde_json::json!({"sub": "agent-1", "action": "deploy", "ttl_second": 10});
+        let lenient = crate::state::build_test_state()?;
+        let minted = mint_one(&lenient, serde_json::from_value(body.clone())?).await?;
+        let claims = lenient.verify(&minted.token)?;
+        assert_eq!((claims.exp - claims.iat).num_seconds(), DEFAULT_TTL);
+
+        let mut builder = crate::state::test_builder()?;
+        builder.strict_requests = true;
+        let strict = builder.build()?;
+        let typo = mint_one(&strict, serde_json::from_value(body)?).await;
+        assert!(matches!(typo, Err(Error::Validation(msg)) if msg.contains("ttl_second")));
+        mint_one(&strict, req("agent-1", "deploy", 10)).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn scopes_validated_like_actions_and_embedded() -> Result<()> {
+        let state = crate::state::build_test_state()?;
+        let mut request = req("agent-1", "deploy", 60);
+        request.scope = Some(vec!["read".into(), "deploy drop table".into()]);
+        assert!(matches!(mint_one(&state, request).await, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+
+        let wildcards = crate::policy::PolicyLimit { allow_wildcard_scope: true, ..Default::default() };
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = PolicyEngine::new(["build", "*"].into_iter().map(|t| (Box::from(t), wildcards.clone())).collect());
+        let state = builder.build()?;
+        let mut request = req("agent-1", "deploy", 60);
+        request.scope = Some(vec!["repo:read".into(), "build:*".into(), "*".into()]);
+        let minted = mint_one(&state, request).await?;
+        let scope = state.verify(&minted.token)?.scope.unwrap_or_default();
+        assert_eq!(scope, ["repo:read", "build:*", "*"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn policy_denied_scopes_cannot_be_minted() -> Result<()> {
+        let refunds = crate::policy::PolicyLimit { max_amount: Some(50), ..Default::default() };
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = PolicyEngine::new([(Box::from("refund"), refunds)].into_iter().collect());
+        let state = builder.build()?;
+        for scope in ["refund:amount:5000", "refund:*", "*"] {
+            let mut request = req("agent-1", "deploy", 60);
+            request.scope = Some(vec!["read".into(), scope.into()]);
+            let denied = mint_one(&state, request).await;
+            assert!(matches!(denied, Err(Error::PolicyViolation(_) | Error::Forbidden(_))), "{scope} minted");
+        }
+        let mut request = req("agent-1", "deploy", 60);
+        request.scope = Some(vec!["refund:amount:50".into()]);
+        mint_one(&state, request).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn mixed_case_action_matches_policy_only_when_normalized() -> Result<()> {
+        let policy = || {
+            let limit = crate::policy::PolicyLimit { max_ttl_seconds: Some(30), ..Default::default() };
+            PolicyEngine::new([(Box::from("deploy"), limit)].into_iter().collect())
+        };
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = policy();
+        let state = builder.build()?;
+        let minted = mint_one(&state, req("agent-1", " Deploy:Prod", 120)).await?;
+        let claims = state.verify(&minted.token)?;
+        assert_eq!(claims.action, "deploy:prod");
+        assert_eq!((claims.exp - claims.iat).num_seconds(), 30);
+
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = policy();
+        builder.normalize_actions = false;
+        let state = builder.build()?;
+        assert!(matches!(mint_one(&state, req("agent-1", " Deploy", 120)).await, Err(Error::InvalidToken(..))));
+        let minted = mint_one(&state, req("agent-1", "Deploy", 120)).await?;
+        let claims = state.verify(&minted.token)?;
+        assert_eq!((claims.action.as_str(), (claims.exp - claims.iat).num_seconds()), ("Deploy", 120));
+        assert_eq!(state.metrics.snapshot().policy_unmatched, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn not_before_offset_delays_validity_and_ttl() -> Result<()> {
+        let state = crate::state::test_builder()?.build()?;
+        let minted = mint_one(&state, MintRequest { not_before_seconds: Some(600), ..req("agent-1", "deploy", 60) }).await?;
+        assert!(matches!(state.verify(&minted.token), Err(Error::TokenNotYetValid)));
+        let exp = chrono::DateTime::parse_from_rfc3339(&minted.exp).map_err(|e| Error::Validation(e.to_string()))?;
+        assert!(exp > chrono::Utc::now() + chrono::Duration::seconds(600));
+
+        let too_early = MintRequest { not_before_seconds: Some(-1), ..req("agent-1", "deploy", 60) };
+        assert!(matches!(mint_one(&state, too_early).await, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn binary_format_mints_a_token_for_proxy_binary() -> Result<()> {
+        let state = crate::state::test_builder()?.build()?;
+        let minted = mint_one(&state, MintRequest { format: TokenFormat::Binary, ..req("sensor-7", "read", 60) }).await?;
+        let claims = state.verify_binary_allow_expired(&URL_SAFE_NO_PAD.decode(&minted.token)?)?;
+        assert_eq!((claims.sub.as_str(), claims.jti.as_str()), ("sensor-7", minted.jti.as_str()));
+
+        let bound = MintRequest { format: TokenFormat::Binary, cnf_key: Some("key".into()), ..req("sensor-7", "read", 60) };
+        assert!(matches!(mint_one(&state, bound).await, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn minted_ttls_bucketed_and_clamps_counted() -> Result<()> {
+        let state = crate::state::test_builder()?.build()?;
+        for ttl in [20, 60, 200, 1000] {
+            mint_one(&state, req("agent-1", "deploy", ttl)).await?;
+        }
+        let snapshot = state.metrics.snapshot();
+        assert_eq!(snapshot.ttl_histogram, vec![0, 1, 1, 0, 2, 0, 0]);
+        assert_eq!(snapshot.ttl_clamped, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unmatched_action_counted_but_allowed() -> Result<()> {
+        let mut builder = crate::state::test_builder()?;
+        builder.policy = ttl_policy();
+        builder.mint_daily_quota = Some(2);
+        let state = builder.build()?;
+
+        mint_one(&state, req("agent-1", "deploy:prod", 20)).await?;
+        assert_eq!(state.metrics.snapshot().policy_unmatched, 0);
+        mint_one(&state, req("agent-1", "refund:order:1", 60)).await?;
+        let snapshot = state.metrics.snapshot();
+        assert_eq!((snapshot.policy_unmatched, snapshot.policy_denials), (1, 0));
+        assert!(matches!(mint_one(&state, req("agent-1", "refund:order:1", 60)).await, Err(Error::RateLimited(_))));
+        assert_eq!(state.metrics.snapshot().policy_unmatched, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oidc_attempts_audited_without_token() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        let state = builder.build()?;
+
+        let id_token = test_support::sign(&test_support::claims("alice@example.com"))?;
+        let mut request = req("alice@example.com", "deploy", 60);
+        request.id_token = Some(id_token.clone());
+        mint_one(&state, request).await?;
+        let mut forged = req("mallory@example.com", "deploy", 60);
+        forged.id_token = Some(id_token.clone());
+        assert!(matches!(mint_one(&state, forged).await, Err(Error::Unauthorized(_))));
+
+        let entries = state.audit_log.recent(10)?;
+        let oidc: Vec<_> = entries.iter().filter(|e| e.event_type.starts_with("oidc_")).collect();
+        let [failure, success] = oidc.as_slice() else {
+            return Err(format!("expected two oidc rows, got {}", oidc.len()).into());
+        };
+        assert_eq!((success.event_type.as_str(), success.jti.as_str()), ("oidc_verified", "-"));
+        assert_eq!((success.sub.as_str(), success.action.as_str()), ("alice@example.com", "deploy"));
+        assert_eq!(success.approved_by.as_deref(), Some("alice@example.com"));
+        assert_eq!(success.detail.as_deref(), Some(test_support::ISSUER));
+        assert_eq!((failure.event_type.as_str(), failure.jti.as_str()), ("oidc_rejected", "-"));
+        assert_eq!((failure.sub.as_str(), failure.action.as_str()), ("mallory@example.com", "deploy"));
+        assert!(failure.detail.as_deref().is_some_and(|d| d.starts_with("sub mismatch")));
+        assert!(entries.iter().all(|e| !e.detail.as_deref().unwrap_or_default().contains(&id_token)));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repeated_id_token_served_from_bounded_cache() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        builder.oidc_cache_capacity = 1;
+        let state = builder.build()?;
+
+        let mint_as = |sub: &'static str, id_token: String| {
+            let mut request = req(sub, "deploy", 60);
+            request.id_token = Some(id_token);
+            mint_one(&state, request)
+        };
+        let alice = test_support::sign(&test_support::claims("alice@example.com"))?;
+        let bob = test_support::sign(&test_support::claims("bob@example.com"))?;
+        mint_as("alice@example.com", alice.clone()).await?;
+        mint_as("alice@example.com", alice.clone()).await?;
+        mint_as("bob@example.com", bob).await?;
+        mint_as("alice@example.com", alice).await?;
+
+        let snapshot = state.metrics.snapshot();
+        assert_eq!((snapshot.oidc_cache_hits, snapshot.oidc_cache_evictions), (1, 2));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn daily_quota_enforced_per_subject() -> Result<()> {
+        let mut builder = crate::state::test_builder()?;
+        builder.mint_daily_quota = Some(2);
+        let state = builder.build()?;
+
+        mint_one(&state, req("agent-1", "deploy", 60)).await?;
+        mint_one(&state, req("agent-1", "deploy", 60)).await?;
+        let result = mint_one(&state, req("agent-1", "deploy", 60)).await;
+        assert!(matches!(result, Err(Error::RateLimited(_))));
+        mint_one(&state, req("agent-2", "deploy", 60)).await?;
+        assert_eq!(state.metrics.snapshot().rate_limited, 1);
+        Ok(())
     }
 }
@@ -1,9 +1,19 @@
 //! HTTP handler modules.
 //! Used by: server.
 
+pub mod admin;
 pub mod audit;
+pub mod batch;
+pub mod breakglass;
 pub mod delegate;
+pub mod exchange;
 pub mod health;
+pub mod jwks;
 pub mod metrics;
 pub mod mint;
+pub mod openapi;
+pub mod preauth;
 pub mod proxy;
+pub mod refresh;
+pub mod verification;
+pub mod whoami;
@@ -0,0 +1,17 @@
+//! OpenAPI document endpoint.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::Json;
+use utoipa::openapi::server::Server;
+
+use crate::state::AppState;
+
+/// Under `ROUTE_PREFIX`, the prefix is published as the server URL so the listed paths resolve.
+pub async fn openapi(State(state): State<AppState>) -> Json<utoipa::openapi::OpenApi> {
+    let mut spec = crate::openapi::spec();
+    if let Some(prefix) = &state.route_prefix {
+        spec.servers = Some(vec![Server::new(prefix)]);
+    }
+    Json(spec)
+}
@@ -0,0 +1,223 @@
+//! Pre-authorization endpoint: approve a batch of actions that later mints draw down.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::Result;
+use crate::handlers::mint::{enforce_policy, validate_action, validate_sub, verify_identity};
+use crate::preauth::Approval;
+use crate::state::AppState;
+
+const DEFAULT_PREAUTH_TTL: i64 = 600;
+const MAX_PREAUTH_TTL: i64 = 3600;
+
+#[derive(Deserialize, ToSchema)]
+pub struct PreauthRequest {
+    pub sub: String,
+    pub actions: Vec<String>,
+    pub ttl_seconds: Option<i64>,
+    pub id_token: Option<String>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct PreauthResponse {
+    pub preauth_id: String,
+    pub actions: usize,
+    pub expires_at: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/preauth",
+    request_body = PreauthRequest,
+    responses(
+        (status = 200, body = PreauthResponse),
+        (status = 400, description = "invalid request"),
+        (status = 401, description = "OIDC verification failed"),
+        (status = 403, description = "policy violation"),
+    )
+)]
+pub async fn preauth(
+    State(state): State<AppState>,
+    Json(mut req): Json<PreauthRequest>,
+) -> Result<Json<PreauthResponse>> {
+    req.actions = req.actions.into_iter().map(|a| state.normalize_action(a)).collect();
+    validate_sub(&req.sub)?;
+    for action in &req.actions {
+        validate_action(action)?;
+    }
+
+    let approval = verify_identity(&state, &req.sub, &req.actions.join(","), req.id_token.as_deref())
+        .await?
+        .map(|id| Approval { approved_by: id.subject, id_token_exp: id.expires_at });
+
+    for action in &req.actions {
+        enforce_policy(&state, &req.sub, approval.as_ref().map(|a| a.approved_by.as_str()), action)?;
+    }
+
+    let ttl = req.ttl_seconds.unwrap_or(DEFAULT_PREAUTH_TTL).clamp(1, MAX_PREAUTH_TTL);
+    let expires_at = chrono::Utc::now() + chrono::Duration::seconds(ttl);
+    let actions = req.actions.len();
+    let preauth_id = state.preauth_store.issue(&req.sub, approval, req.actions, expires_at.timestamp())?;
+
+    tracing::info!(sub = %req.sub, actions, "actions pre-authorized");
+
+    Ok(Json(PreauthResponse {
+        preauth_id,
+        actions,
+        expires_at: expires_at.to_rfc3339(),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::Error;
+    use crate::handlers::mint::{MintRequest, mint};
+    use crate::state::{build_test_state, test_builder, StateBuilder};
+
+    fn mint_request(action: &str, preauth_id: &str) -> Json<MintRequest> {
+        Json(MintRequest {
+            sub: "agent-1".into(),
+            action: action.into(),
+            preauth_id: Some(preauth_id.into()),
+            ..Default::default()
+        })
+    }
+
+    async fn approve(state: &AppState, actions: &[&str]) -> Result<String> {
+        let req = PreauthRequest {
+            sub: "agent-1".into(),
+            actions: actions.iter().map(|a| a.to_string()).collect(),
+            ttl_seconds: None,
+            id_token: None,
+        };
+        let Json(resp) = preauth(State(state.clone()), Json(req)).await?;
+        assert_eq!(resp.actions, actions.len());
+        Ok(resp.preauth_id)
+    }
+
+    #[tokio::test]
+    async fn preauthorized_actions_minted_once_each() -> Result<()> {
+        let state = build_test_state()?;
+        let actions = ["refund:amount:30", "deploy:staging", "report:daily"];
+        let id = approve(&state, &actions).await?;
+        assert_eq!(state.metrics.snapshot().policy_unmatched, 0);
+
+        for action in actions {
+            let Json(resp) = mint(State(state.clone()), mint_request(action, &id)).await?;
+            assert_eq!(state.verify(&resp.token)?.action, action);
+        }
+        assert_eq!(state.metrics.snapshot().policy_unmatched, actions.len() as u64);
+
+        let result = mint(State(state.clone()), mint_request("refund:amount:30", &id)).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn unlisted_action_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        let id = approve(&state, &["refund:amount:30", "deploy:staging", "report:daily"]).await?;
+
+        let result = mint(State(state.clone()), mint_request("delete:database", &id)).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        assert_eq!(state.preauth_store.remaining(&id)?, 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_mint_keeps_the_action() -> Result<()> {
+        use crate::policy::{BelowFloor, TtlFloor};
+        let ttl_floor = TtlFloor { seconds: 5, below: BelowFloor::Reject };
+        let state = StateBuilder { ttl_floor, ..test_builder()? }.build()?;
+        let id = approve(&state, &["refund:amount:30"]).await?;
+
+        let mut req = mint_request("refund:amount:30", &id);
+        req.ttl_seconds = Some(1);
+        let result = mint(State(state.clone()), req).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert_eq!(state.preauth_store.remaining(&id)?, 1);
+
+        let Json(resp) = mint(State(state.clone()), mint_request("refund:amount:30", &id)).await?;
+        assert_eq!(state.verify(&resp.token)?.action, "refund:amount:30");
+        assert_eq!(state.preauth_store.remaining(&id)?, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn preauthorized_mints_carry_the_approver() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        let state = builder.build()?;
+        let req = PreauthRequest {
+            sub: "agent-1".into(),
+            actions: vec!["deploy:staging".into()],
+            ttl_seconds: None,
+            id_token: Some(test_support::sign(&test_support::claims("agent-1"))?),
+        };
+        let Json(approved) = preauth(State(state.clone()), Json(req)).await?;
+
+        let Json(resp) = mint(State(state.clone()), mint_request("deploy:staging", &approved.preauth_id)).await?;
+        assert_eq!(state.verify(&resp.token)?.approved_by.as_deref(), Some("agent-1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn preauthorized_mints_capped_to_the_approving_id_token() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        use crate::oidc::test_support;
+        let mut builder = crate::state::test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        builder.cap_ttl_to_id_token = true;
+        let state = builder.build()?;
+        let mut id_claims = test_support::claims("agent-1");
+        id_claims.exp = chrono::Utc::now().timestamp() as u64 + 10;
+        let req = PreauthRequest {
+            sub: "agent-1".into(),
+            actions: vec!["deploy:staging".into(), "deploy:staging".into()],
+            ttl_seconds: None,
+            id_token: Some(test_support::sign(&id_claims)?),
+        };
+        let Json(approved) = preauth(State(state.clone()), Json(req)).await?;
+
+        let mut capped = mint_request("deploy:staging", &approved.preauth_id);
+        capped.ttl_seconds = Some(300);
+        let Json(resp) = mint(State(state.clone()), capped).await?;
+        assert!(state.verify(&resp.token)?.exp.timestamp() <= id_claims.exp as i64);
+
+        let mut refreshable = mint_request("deploy:staging", &approved.preauth_id);
+        refreshable.refresh = true;
+        assert!(matches!(mint(State(state.clone()), refreshable).await, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn concurrent_mints_spend_one_approval_once() -> Result<()> {
+        let state = build_test_state()?;
+        let id = approve(&state, &["deploy:staging"]).await?;
+        let barrier = std::sync::Arc::new(tokio::sync::Barrier::new(2));
+        let racers: Vec<_> = (0..2)
+            .map(|_| {
+                let (state, id, barrier) = (state.clone(), id.clone(), barrier.clone());
+                tokio::spawn(async move {
+                    barrier.wait().await;
+                    mint(State(state), mint_request("deploy:staging", &id)).await
+                })
+            })
+            .collect();
+
+        let mut minted = 0;
+        for racer in racers {
+            match racer.await.map_err(|e| Error::ServiceUnavailable(e.to_string()))? {
+                Ok(_) => minted += 1,
+                Err(e) => assert!(matches!(e, Error::Unauthorized(_)), "{e}"),
+            }
+        }
+        assert_eq!(minted, 1);
+        Ok(())
+    }
+}
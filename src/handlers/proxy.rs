@@ -1,39 +1,423 @@
-//! Token verification proxy endpoint with latency measurement.
+//! Token verification proxy endpoints (own key, a supplied minter key, or binary tokens) with latency measurement.
 //! Used by: server.
 
 use std::time::Instant;
 
-use axum::extract::State;
-use axum::http::{HeaderMap, HeaderValue};
+use axum::body::Bytes;
+use axum::extract::{Query, State};
+use axum::http::{header, HeaderMap, HeaderValue};
 use axum::Json;
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
 
-use crate::error::{Error, Result};
+use crate::audit::sqlite::EventType;
+use crate::audit::writer::AuditRecord;
+use crate::error::{Error, Result, TokenFault};
+use crate::handlers::admin::require_admin;
+use crate::handlers::delegate::action_in_scope;
+use crate::handlers::mint::note_unmatched_policy;
 use crate::state::AppState;
-use crate::token::verify::verify_token;
+use crate::token::claims::Claims;
+use crate::token::keys::decode_public_key;
+use crate::token::pop::check_proof;
+use crate::token::verify::{check_audience, reject_headerless, verify_token_allow_expired};
 
-#[derive(Deserialize)]
+/// Carries the minter's public key for `/proxy/external`.
+pub const VERIFY_KEY_HEADER: &str = "x-verify-key";
+const OCTET_STREAM: &str = "application/octet-stream";
+const MAX_OUTCOME_DETAIL_LEN: usize = 512;
+
+#[derive(Deserialize, ToSchema, Default)]
 pub struct ProxyRequest {
-    pub token: String,
+    /// May instead arrive as `Authorization: Bearer <token>`; when both are sent they must match.
+    #[serde(default)]
+    pub token: Option<String>,
+    #[serde(default)]
+    pub max_age_seconds: Option<i64>,
+    /// When set, the token's `aud` must include this service, on top of any `PROXY_AUDIENCE`.
+    #[serde(default)]
+    pub audience: Option<String>,
+    /// The concrete operation being performed; must fall within the token's `scope` (or its
+    /// `action` when unscoped) and is recorded in the audit entry's `detail`.
+    #[serde(default)]
+    pub downstream_action: Option<String>,
+    /// Proof of possession for key-bound tokens (those with `cnf`): the base64url Ed25519
+    /// signature of `agentmint-pop:<jti>` by the bound key.
+    #[serde(default)]
+    pub proof: Option<String>,
+    /// A permission the token's `scope` must cover; an unscoped token never satisfies it.
+    #[serde(default)]
+    pub required_scope: Option<String>,
+}
+
+#[derive(Deserialize, IntoParams, Default)]
+pub struct ProxyQuery {
+    /// Omit `sub` and `approved_by` from the response; the audit log still records them.
+    #[serde(default)]
+    pub minimal: bool,
+    /// Reject tokens that do not carry `approved_by`.
+    #[serde(default)]
+    pub require_human: bool,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct ProxyResponse {
-    pub sub: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sub: Option<String>,
     pub action: String,
     pub jti: String,
+    /// Human identity that approved the mint, when the token carries one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approved_by: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Success,
+    Failure,
+}
+
+impl Outcome {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Success => "success",
+            Self::Failure => "failure",
+        }
+    }
+}
+
+/// Reports what became of an action after its token was consumed. The token itself is sent
+/// rather than its bare jti, which `/audit` exposes, so only its holder can record the result.
+#[derive(Deserialize, ToSchema)]
+pub struct CompleteRequest {
+    pub token: String,
+    pub outcome: Outcome,
+    #[serde(default)]
+    pub detail: Option<String>,
 }
 
+#[derive(Serialize, ToSchema)]
+pub struct CompleteResponse {
+    pub jti: String,
+    pub outcome: Outcome,
+}
+
+fn timing_headers(timings: &[(&'static str, u128)]) -> Result<HeaderMap> {
+    let mut headers = HeaderMap::new();
+    for (name, micros) in timings {
+        let value = HeaderValue::from_str(&micros.to_string()).map_err(|e| Error::Signing(e.to_string()))?;
+        headers.insert(*name, value);
+    }
+    Ok(headers)
+}
+
+/// The token from the body, else from an `Authorization: Bearer` header. Other schemes are ignored.
+fn presented_token(headers: &HeaderMap, body: Option<String>) -> Result<String> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.split_once(' '))
+        .filter(|(scheme, _)| scheme.eq_ignore_ascii_case("bearer"))
+        .map(|(_, token)| token.trim());
+    match (body, bearer) {
+        (Some(body), Some(bearer)) if body != bearer => Err(Error::InvalidToken(
+            TokenFault::InvalidField,
+            "token in the body and the Authorization header differ".into(),
+        )),
+        (Some(token), _) => Ok(token),
+        (None, Some(bearer)) => Ok(bearer.to_owned()),
+        (None, None) => Err(Error::InvalidToken(
+            TokenFault::InvalidField,
+            "no token: send it in the body or as Authorization: Bearer".into(),
+        )),
+    }
+}
+
+fn check_max_age(claims: &Claims, max_age_seconds: Option<i64>) -> Result<()> {
+    let Some(max_age) = max_age_seconds else {
+        return Ok(());
+    };
+    let age = Utc::now().signed_duration_since(claims.iat).num_seconds();
+    if age > max_age {
+        tracing::warn!(jti = %claims.jti, age, max_age, "token older than max_age_seconds");
+        return Err(Error::TokenExpired);
+    }
+    Ok(())
+}
+
+fn record_replay(state: &AppState, jti: &str, sub: &str, action: &str) {
+    state.metrics.record_replay(sub);
+    tracing::warn!(jti = %jti, sub = %sub, "replay blocked");
+    crate::console::log_replay(jti);
+    if let Err(e) = state.audit_log.log_event(EventType::Replay, jti, sub, action, Utc::now()) {
+        tracing::error!(error = %e, jti = %jti, "failed to audit replay");
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/proxy",
+    request_body(content = ProxyRequest, description = "May be omitted when the token is sent as Authorization: Bearer"),
+    params(ProxyQuery),
+    responses(
+        (status = 200, body = ProxyResponse, headers(
+            ("X-Verify-Time-Us" = u64),
+            ("X-Verify-Signature-Us" = u64),
+            ("X-Verify-Jti-Us" = u64),
+            ("X-Verify-Audit-Us" = u64),
+        )),
+        (status = 401, description = "missing, invalid, expired, revoked, tampered, older than max_age_seconds, not for this audience, not human-approved under require_human, or lacking required_scope; also when the body and Authorization header carry different tokens"),
+        (status = 403, description = "downstream_action outside the token's scope"),
+        (status = 409, description = "token already used"),
+    )
+)]
 pub async fn proxy(
+    state: State<AppState>,
+    headers: HeaderMap,
+    query: Query<ProxyQuery>,
+    body: Bytes,
+) -> Result<(HeaderMap, Json<ProxyResponse>)> {
+    let req = match body.iter().all(u8::is_ascii_whitespace) {
+        true => ProxyRequest::default(),
+        false => serde_json::from_slice(&body).map_err(|e| Error::Validation(format!("invalid request body: {e}")))?,
+    };
+    proxy_request(state, headers, query, Json(req)).await
+}
+
+/// `/proxy` once its body is parsed; an absent body leaves every check at its default.
+pub(crate) async fn proxy_request(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ProxyQuery>,
+    Json(mut req): Json<ProxyRequest>,
+) -> Result<(HeaderMap, Json<ProxyResponse>)> {
+    let token = presented_token(&headers, req.token.take())?;
+    req.downstream_action = req.downstream_action.map(|a| state.normalize_action(a));
+    req.required_scope = req.required_scope.map(|s| state.normalize_action(s));
+    let checks = Checks::from(&req);
+    consume(&state, query, checks, || state.verify_any_minter_allow_expired(&token)).await
+}
+
+#[utoipa::path(
+    post,
+    path = "/proxy/external",
+    request_body = ProxyRequest,
+    params(
+        ProxyQuery,
+        ("x-admin-key" = String, Header, description = "admin API key"),
+        ("x-verify-key" = String, Header, description = "base64url Ed25519 public key of the minter"),
+    ),
+    responses(
+        (status = 200, body = ProxyResponse),
+        (status = 400, description = "missing or malformed x-verify-key"),
+        (status = 401, description = "missing admin key, or token invalid under the supplied key"),
+        (status = 403, description = "downstream_action outside the token's scope"),
+        (status = 409, description = "token already used"),
+    )
+)]
+pub async fn proxy_external(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ProxyQuery>,
+    Json(mut req): Json<ProxyRequest>,
+) -> Result<(HeaderMap, Json<ProxyResponse>)> {
+    require_admin(&state, &headers)?;
+    let key = headers
+        .get(VERIFY_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .ok_or_else(|| Error::Validation(format!("missing {VERIFY_KEY_HEADER} header")))?;
+    let key = decode_public_key(key)?;
+    let token = presented_token(&headers, req.token.take())?;
+    let prefix = state.token_prefix.as_deref();
+    req.downstream_action = req.downstream_action.map(|a| state.normalize_action(a));
+    req.required_scope = req.required_scope.map(|s| state.normalize_action(s));
+    let checks = Checks::from(&req);
+    consume(&state, query, checks, || {
+        if !state.accept_legacy_tokens {
+            reject_headerless(&token, prefix)?;
+        }
+        verify_token_allow_expired(&token, &key, prefix, state.iat_leeway).and_then(|c| state.check_audience_present(c))
+    })
+    .await
+}
+
+#[utoipa::path(
+    post,
+    path = "/proxy/binary",
+    request_body(content = Vec<u8>, content_type = "application/octet-stream", description = "binary token"),
+    params(ProxyQuery),
+    responses(
+        (status = 200, body = ProxyResponse),
+        (status = 400, description = "body is not application/octet-stream"),
+        (status = 401, description = "invalid, truncated, expired, revoked, or tampered"),
+        (status = 409, description = "token already used"),
+    )
+)]
+pub async fn proxy_binary(
     State(state): State<AppState>,
-    Json(req): Json<ProxyRequest>,
+    headers: HeaderMap,
+    Query(query): Query<ProxyQuery>,
+    body: Bytes,
 ) -> Result<(HeaderMap, Json<ProxyResponse>)> {
+    let content_type = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok());
+    if content_type != Some(OCTET_STREAM) {
+        return Err(Error::Validation(format!("binary tokens must be sent as {OCTET_STREAM}")));
+    }
+    consume(&state, query, Checks::default(), || state.verify_binary_allow_expired(&body)).await
+}
+
+/// Expiry is not checked, since the action may finish after the token's short lifetime.
+/// Consumption is read from the replay guard, which `/proxy` updates before responding, and
+/// from the audit log once the guard has let the jti expire. The outcome row is written directly
+/// so the unique index settles concurrent reports and the loser gets 409.
+#[utoipa::path(
+    post,
+    path = "/proxy/complete",
+    request_body = CompleteRequest,
+    responses(
+        (status = 200, body = CompleteResponse),
+        (status = 400, description = "token was never consumed through /proxy"),
+        (status = 401, description = "invalid or tampered token"),
+        (status = 409, description = "outcome already recorded for this token"),
+    )
+)]
+pub async fn complete(State(state): State<AppState>, Json(req): Json<CompleteRequest>) -> Result<Json<CompleteResponse>> {
+    if req.detail.as_ref().is_some_and(|d| d.len() > MAX_OUTCOME_DETAIL_LEN) {
+        return Err(Error::Validation(format!("detail exceeds {MAX_OUTCOME_DETAIL_LEN} bytes")));
+    }
+    let claims = state.verify_any_minter_allow_expired(&req.token)?;
+    if !state.jti_store.was_consumed(&claims.jti).await? && !state.audit_log.was_verified(&claims.jti)? {
+        return Err(Error::Validation("token has not been consumed through /proxy".into()));
+    }
+    let already_recorded = || Error::ReplayDetected(format!("outcome already recorded for {}", claims.jti));
+    if state.audit_log.outcome(&claims.jti)?.is_some() {
+        return Err(already_recorded());
+    }
+    let record = AuditRecord {
+        event_type: EventType::Outcome,
+        jti: claims.jti.clone(),
+        sub: claims.sub.clone(),
+        action: req.outcome.as_str().into(),
+        at: Utc::now(),
+        detail: req.detail,
+        approved_by: claims.approved_by.clone(),
+    };
+    match state.audit_log.log_record_async(record).await {
+        Err(Error::Database(rusqlite::Error::SqliteFailure(f, _))) if f.code == rusqlite::ErrorCode::ConstraintViolation => {
+            return Err(already_recorded());
+        }
+        result => result?,
+    }
+    tracing::info!(jti = %claims.jti, outcome = req.outcome.as_str(), "outcome recorded");
+    Ok(Json(CompleteResponse { jti: claims.jti, outcome: req.outcome }))
+}
+
+/// Request-level constraints applied after the signature checks out.
+#[derive(Default)]
+struct Checks<'a> {
+    max_age_seconds: Option<i64>,
+    audience: Option<&'a str>,
+    downstream_action: Option<&'a str>,
+    proof: Option<&'a str>,
+    required_scope: Option<&'a str>,
+}
+
+impl<'a> From<&'a ProxyRequest> for Checks<'a> {
+    fn from(req: &'a ProxyRequest) -> Self {
+        Self {
+            max_age_seconds: req.max_age_seconds,
+            audience: req.audience.as_deref(),
+            downstream_action: req.downstream_action.as_deref(),
+            proof: req.proof.as_deref(),
+            required_scope: req.required_scope.as_deref(),
+        }
+    }
+}
+
+/// `PROXY_ALLOWED_ISSUERS`: a valid signature alone is not enough; a token without `iss` fails too.
+fn check_issuer(claims: &Claims, allowed: &[String]) -> Result<()> {
+    match &claims.iss {
+        Some(iss) if allowed.contains(iss) => Ok(()),
+        iss => Err(Error::InvalidToken(
+            TokenFault::WrongIssuer,
+            format!("issuer {} is not allowed", iss.as_deref().unwrap_or("(none)")),
+        )),
+    }
+}
+
+fn check_downstream_action(claims: &Claims, downstream: &str) -> Result<()> {
+    let authorized = claims.scope.as_deref().unwrap_or(std::slice::from_ref(&claims.action));
+    if !action_in_scope(downstream, authorized) {
+        return Err(Error::Forbidden(format!("downstream_action {downstream} is outside the token's scope")));
+    }
+    Ok(())
+}
+
+/// Only an explicit `scope` satisfies `required_scope`; the `action` of an unscoped token never does.
+fn check_required_scope(claims: &Claims, required: &str) -> Result<()> {
+    if !claims.scope.as_deref().is_some_and(|scope| action_in_scope(required, scope)) {
+        return Err(Error::Unauthorized(format!("token lacks required scope {required}")));
+    }
+    Ok(())
+}
+
+/// An expired token is rejected either way; if its jti was already consumed, it is also
+/// counted as `expired_replay` so hoarded-token replays stand apart from ordinary expiries.
+fn reject_expired(state: &AppState, claims: &Claims) -> Error {
+    match state.audit_log.was_verified(&claims.jti) {
+        Ok(true) => {
+            state.metrics.record_expired_replay();
+            tracing::warn!(jti = %claims.jti, sub = %claims.sub, "expired token replayed");
+        }
+        Ok(false) => {}
+        Err(e) => tracing::error!(error = %e, jti = %claims.jti, "expired replay lookup failed"),
+    }
+    Error::TokenExpired
+}
+
+/// Verify the signature with `verify` (expiry is checked here), then the shared replay check,
+/// audit, and response for both proxy routes.
+async fn consume(
+    state: &AppState,
+    query: ProxyQuery,
+    checks: Checks<'_>,
+    verify: impl FnOnce() -> Result<Claims>,
+) -> Result<(HeaderMap, Json<ProxyResponse>)> {
+    let require_human = query.require_human;
     state.increment_requests();
     let total_start = Instant::now();
 
     let verify_start = Instant::now();
-    let claims = match verify_token(&req.token, &state.verifying_key) {
+    let verified = verify().and_then(|c| {
+        if let Some(allowed) = &state.proxy_allowed_issuers {
+            check_issuer(&c, allowed)?;
+        }
+        if c.is_expired(state.expiry_leeway) {
+            return Err(reject_expired(state, &c));
+        }
+        check_max_age(&c, checks.max_age_seconds)?;
+        for expected in state.proxy_audience.as_deref().into_iter().chain(checks.audience) {
+            check_audience(&c, expected)?;
+        }
+        if let Some(downstream) = checks.downstream_action {
+            check_downstream_action(&c, downstream)?;
+        }
+        if let Some(required) = checks.required_scope {
+            check_required_scope(&c, required)?;
+        }
+        check_proof(&c, checks.proof)?;
+        if require_human && c.approved_by.is_none() {
+            return Err(Error::InvalidToken(TokenFault::NotHumanApproved, "token carries no human approval".into()));
+        }
+        Ok(c)
+    });
+    let verified = match verified {
+        Ok(c) => state.check_revocation(&c).await.map(|()| c),
+        Err(e) => Err(e),
+    };
+    let claims = match verified {
         Ok(c) => c,
         Err(e) => {
             state.metrics.record_reject();
@@ -45,20 +429,39 @@ pub async fn proxy(
     let verify_us = verify_start.elapsed().as_micros();
 
     let jti_start = Instant::now();
-    if let Err(e) = state.jti_store.check_and_insert(&claims.jti, claims.exp.timestamp()) {
-        state.metrics.record_replay();
-        tracing::warn!(jti = %claims.jti, "replay blocked");
-        crate::console::log_replay(&claims.jti);
+    if let Err(e) = state.jti_store.check_and_insert(&claims.jti, claims.accepted_until(state.expiry_leeway)).await {
+        if matches!(e, Error::ReplayDetected(_)) {
+            record_replay(state, &claims.jti, &claims.sub, &claims.action);
+        }
         return Err(e);
     }
     let jti_us = jti_start.elapsed().as_micros();
+    note_unmatched_policy(state, &claims.sub, &state.normalize_action(claims.action.clone()));
 
     let audit_start = Instant::now();
-    state.audit_log.log(&claims.jti, &claims.sub, &claims.action, Utc::now())?;
+    let record = AuditRecord {
+        event_type: EventType::Verify,
+        jti: claims.jti.clone(),
+        sub: claims.sub.clone(),
+        action: claims.action.clone(),
+        at: Utc::now(),
+        detail: checks.downstream_action.map(str::to_owned),
+        approved_by: claims.approved_by.clone(),
+    };
+    let audited = match &state.audit_writer {
+        Some(writer) => writer.submit(record, &state.metrics).await,
+        None => state.audit_log.log_record_async(record).await,
+    };
+    if let Err(e) = audited {
+        if matches!(e, Error::ReplayDetected(_)) {
+            record_replay(state, &claims.jti, &claims.sub, &claims.action);
+        }
+        return Err(e);
+    }
     let audit_us = audit_start.elapsed().as_micros();
 
     let total_us = total_start.elapsed().as_micros();
-    state.metrics.record_verify();
+    state.metrics.record_verify(total_us as u64);
 
     tracing::info!(
         jti = %claims.jti,
@@ -68,15 +471,527 @@ pub async fn proxy(
     );
     crate::console::log_verify(&claims.jti, total_us);
 
-    let mut headers = HeaderMap::new();
-    headers.insert(
-        "X-Verify-Time-Us",
-        HeaderValue::from_str(&total_us.to_string()).map_err(|e| Error::Signing(e.to_string()))?,
-    );
+    let headers = timing_headers(&[
+        ("X-Verify-Time-Us", total_us),
+        ("X-Verify-Signature-Us", verify_us),
+        ("X-Verify-Jti-Us", jti_us),
+        ("X-Verify-Audit-Us", audit_us),
+    ])?;
 
     Ok((headers, Json(ProxyResponse {
-        sub: claims.sub,
+        sub: (!query.minimal).then_some(claims.sub),
         action: claims.action,
         jti: claims.jti,
+        approved_by: claims.approved_by.filter(|_| !query.minimal),
     })))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+    use base64::Engine;
+    use crate::handlers::admin::ADMIN_KEY_HEADER;
+    use crate::state::{build_test_state, test_builder, TEST_ADMIN_KEY};
+    use crate::token::claims::Audience;
+    use crate::token::pop::sign_proof;
+    use crate::token::sign::{generate_keypair, sign_token, sign_token_binary};
+    use std::sync::atomic::Ordering;
+
+    async fn present(state: &AppState, token: &str) -> Result<ProxyResponse> {
+        present_with_max_age(state, token, None).await
+    }
+
+    async fn present_with_max_age(state: &AppState, token: &str, max_age_seconds: Option<i64>) -> Result<ProxyResponse> {
+        let req = Json(ProxyRequest { token: Some(token.into()), max_age_seconds, ..Default::default() });
+        proxy_request(State(state.clone()), HeaderMap::new(), Query(ProxyQuery::default()), req).await.map(|(_, Json(body))| body)
+    }
+
+    fn minted_ago(state: &AppState, seconds: i64) -> Result<String> {
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.iat -= chrono::Duration::seconds(seconds);
+        state.sign(&claims)
+    }
+
+    async fn present_for(state: &AppState, token: String, downstream_action: &str) -> Result<ProxyResponse> {
+        let req = ProxyRequest { token: Some(token), downstream_action: Some(downstream_action.into()), ..Default::default() };
+        proxy_request(State(state.clone()), HeaderMap::new(), Query(ProxyQuery::default()), Json(req)).await.map(|(_, Json(body))| body)
+    }
+
+    #[tokio::test]
+    async fn replay_inside_expiry_leeway_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.exp = Utc::now() - chrono::Duration::seconds(1);
+        let token = state.sign(&claims)?;
+        present(&state, &token).await?;
+        present(&state, &minted_ago(&state, 0)?).await?;
+        assert!(matches!(present(&state, &token).await, Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn audit_log_blocks_replay_after_replay_store_is_emptied() -> Result<()> {
+        let state = build_test_state()?;
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let token = state.sign(&claims)?;
+        present(&state, &token).await?;
+
+        let mut restarted = test_builder()?;
+        restarted.signing_key = state.signing_key.clone();
+        let restarted = restarted.build()?;
+        for entry in state.audit_log.uses(&claims.jti)? {
+            restarted.audit_log.log(&entry.jti, &entry.sub, &entry.action, Utc::now())?;
+        }
+        assert!(matches!(present(&restarted, &token).await, Err(Error::ReplayDetected(_))));
+        assert_eq!(restarted.metrics.snapshot().replays_blocked, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn approver_surfaced_and_audited() -> Result<()> {
+        let state = build_test_state()?;
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.approved_by = Some("alice@example.com".into());
+        let body = present(&state, &state.sign(&claims)?).await?;
+        assert_eq!(body.approved_by.as_deref(), Some("alice@example.com"));
+        assert_eq!(state.audit_log.recent(1)?[0].approved_by.as_deref(), Some("alice@example.com"));
+
+        assert_eq!(present(&state, &minted_ago(&state, 0)?).await?.approved_by, None);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn required_scope_must_be_granted_explicitly() -> Result<()> {
+        let state = build_test_state()?;
+        let present_requiring = |token: String, required: &str| {
+            let req = ProxyRequest { token: Some(token), required_scope: Some(required.into()), ..Default::default() };
+            proxy_request(State(state.clone()), HeaderMap::new(), Query(ProxyQuery::default()), Json(req))
+        };
+        let mut scoped = Claims::new("agent-1".into(), "deploy".into(), 300);
+        scoped.scope = Some(vec!["repo:read".into(), "build:*".into()]);
+        let (_, Json(body)) = present_requiring(state.sign(&scoped)?, "build:docker").await?;
+        assert_eq!(body.action, "deploy");
+
+        scoped.jti = uuid::Uuid::new_v4().to_string();
+        let missing = present_requiring(state.sign(&scoped)?, "repo:write").await;
+        assert!(matches!(missing, Err(Error::Unauthorized(_))));
+
+        let unscoped = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 300))?;
+        assert!(matches!(present_requiring(unscoped, "deploy").await, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoked_jti_or_subject_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        let until = (Utc::now() + chrono::Duration::minutes(10)).timestamp();
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let token = state.sign(&claims)?;
+        state.verify(&token)?;
+
+        state.revoke(Some(&claims.jti), None, until).await?;
+        assert!(matches!(present(&state, &token).await, Err(Error::Revoked(_))));
+        present(&state, &minted_ago(&state, 0)?).await?;
+
+        state.revoke(None, Some("agent-1"), until).await?;
+        assert!(matches!(present(&state, &minted_ago(&state, 0)?).await, Err(Error::Revoked(_))));
+        let other = state.sign(&Claims::new("agent-2".into(), "deploy".into(), 300))?;
+        assert_eq!(present(&state, &other).await?.action, "deploy");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn require_human_rejects_unapproved_tokens() -> Result<()> {
+        let state = build_test_state()?;
+        let strict = || Query(ProxyQuery { require_human: true, ..ProxyQuery::default() });
+        let request = |token: String| {
+            Json(ProxyRequest { token: Some(token), ..Default::default() })
+        };
+
+        let mut approved = Claims::new("agent-1".into(), "deploy".into(), 300);
+        approved.approved_by = Some("alice@example.com".into());
+        let (_, Json(body)) = proxy_request(State(state.clone()), HeaderMap::new(), strict(), request(state.sign(&approved)?)).await?;
+        assert_eq!(body.approved_by.as_deref(), Some("alice@example.com"));
+
+        let unapproved = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 300))?;
+        let result = proxy_request(State(state.clone()), HeaderMap::new(), strict(), request(unapproved.clone())).await;
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::NotHumanApproved, _))));
+        present(&state, &unapproved).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn permitted_downstream_action_is_audited() -> Result<()> {
+        let state = build_test_state()?;
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.scope = Some(vec!["deploy:*".into()]);
+        present_for(&state, state.sign(&claims)?, "deploy:staging").await?;
+
+        let entry = &state.audit_log.recent(1)?[0];
+        assert_eq!((entry.action.as_str(), entry.detail.as_deref()), ("deploy", Some("deploy:staging")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn downstream_action_outside_scope_rejected_without_consuming() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy:staging".into(), 300))?;
+        let result = present_for(&state, token.clone(), "deploy:production").await;
+        assert!(matches!(result, Err(Error::Forbidden(_))));
+        assert!(state.audit_log.recent(1)?.is_empty());
+        present_for(&state, token, "deploy:staging").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn old_token_rejected_under_max_age() -> Result<()> {
+        let state = build_test_state()?;
+        let token = minted_ago(&state, 60)?;
+        let result = present_with_max_age(&state, &token, Some(30)).await;
+        assert!(matches!(result, Err(Error::TokenExpired)));
+        assert_eq!(present(&state, &token).await?.sub.as_deref(), Some("agent-1"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fresh_token_accepted_under_max_age() -> Result<()> {
+        let state = build_test_state()?;
+        let token = minted_ago(&state, 5)?;
+        assert_eq!(present_with_max_age(&state, &token, Some(30)).await?.sub.as_deref(), Some("agent-1"));
+        Ok(())
+    }
+
+    async fn present_bearer(state: &AppState, body: Option<String>, bearer: Option<&str>) -> Result<ProxyResponse> {
+        let mut headers = HeaderMap::new();
+        if let Some(token) = bearer {
+            let value = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|e| Error::Validation(e.to_string()))?;
+            headers.insert(header::AUTHORIZATION, value);
+        }
+        let req = Json(ProxyRequest { token: body, ..Default::default() });
+        proxy_request(State(state.clone()), headers, Query(ProxyQuery::default()), req).await.map(|(_, Json(body))| body)
+    }
+
+    #[tokio::test]
+    async fn token_taken_from_body_or_bearer_header_but_not_two_different_ones() -> Result<()> {
+        let state = build_test_state()?;
+        let mint = || state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60));
+
+        assert_eq!(present_bearer(&state, None, Some(&mint()?)).await?.action, "deploy");
+        assert_eq!(present_bearer(&state, Some(mint()?), None).await?.action, "deploy");
+        let same = mint()?;
+        present_bearer(&state, Some(same.clone()), Some(&same)).await?;
+
+        let conflicting = present_bearer(&state, Some(mint()?), Some(&mint()?)).await;
+        assert!(matches!(conflicting, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+        let missing = present_bearer(&state, None, None).await;
+        assert!(matches!(missing, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn bearer_token_needs_no_body() -> Result<()> {
+        let state = build_test_state()?;
+        let bearer = |token: &str| -> Result<HeaderMap> {
+            let value = HeaderValue::from_str(&format!("Bearer {token}")).map_err(|e| Error::Validation(e.to_string()))?;
+            Ok(HeaderMap::from_iter([(header::AUTHORIZATION, value)]))
+        };
+        for body in ["", " \n"] {
+            let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+            let (_, Json(res)) = proxy(State(state.clone()), bearer(&token)?, Query(ProxyQuery::default()), Bytes::from(body)).await?;
+            assert_eq!(res.action, "deploy");
+        }
+
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        let malformed = proxy(State(state.clone()), bearer(&token)?, Query(ProxyQuery::default()), Bytes::from("{")).await;
+        assert!(matches!(malformed, Err(Error::Validation(_))));
+        let missing = proxy(State(state), HeaderMap::new(), Query(ProxyQuery::default()), Bytes::new()).await;
+        assert!(matches!(missing, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn stage_timing_headers_present_and_numeric() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        let req = Json(ProxyRequest { token: Some(token), ..Default::default() });
+        let (headers, _) = proxy_request(State(state), HeaderMap::new(), Query(ProxyQuery::default()), req).await?;
+        for name in ["X-Verify-Time-Us", "X-Verify-Signature-Us", "X-Verify-Jti-Us", "X-Verify-Audit-Us"] {
+            let value = headers.get(name).and_then(|v| v.to_str().ok());
+            assert!(value.is_some_and(|v| v.parse::<u128>().is_ok()), "{name} missing or non-numeric");
+        }
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn replays_attributed_to_subject_and_audited() -> Result<()> {
+        let state = build_test_state()?;
+        let claims = Claims::new("mallory".into(), "deploy".into(), 60);
+        let token = state.sign(&claims)?;
+
+        assert_eq!(present(&state, &token).await?.sub.as_deref(), Some("mallory"));
+        for _ in 0..2 {
+            assert!(matches!(present(&state, &token).await, Err(Error::ReplayDetected(_))));
+        }
+
+        assert_eq!(state.metrics.replays_by_subject.get("mallory"), 2);
+        let replays: Vec<_> = state.audit_log.recent(10)?
+            .into_iter()
+            .filter(|e| e.event_type == "replay")
+            .collect();
+        assert_eq!(replays.len(), 2);
+        assert_eq!(replays[0].sub, "mallory");
+        assert_eq!(replays[0].jti, claims.jti);
+        Ok(())
+    }
+
+    async fn present_external(state: &AppState, token: &str, key: &str, admin: bool) -> Result<ProxyResponse> {
+        let mut headers = HeaderMap::new();
+        headers.insert(VERIFY_KEY_HEADER, HeaderValue::from_str(key).map_err(|e| Error::Validation(e.to_string()))?);
+        if admin {
+            headers.insert(ADMIN_KEY_HEADER, HeaderValue::from_static(TEST_ADMIN_KEY));
+        }
+        let req = Json(ProxyRequest { token: Some(token.into()), ..Default::default() });
+        proxy_external(State(state.clone()), headers, Query(ProxyQuery::default()), req).await.map(|(_, Json(body))| body)
+    }
+
+    #[tokio::test]
+    async fn external_key_verifies_other_minters_tokens() -> Result<()> {
+        let state = build_test_state()?;
+        let minter = generate_keypair();
+        let claims = Claims::new("agent-9".into(), "deploy".into(), 60);
+        let token = sign_token(&claims, &minter)?;
+        let minter_key = URL_SAFE_NO_PAD.encode(minter.verifying_key().to_bytes());
+        let own_key = URL_SAFE_NO_PAD.encode(state.verifying_key.to_bytes());
+
+        assert!(matches!(present(&state, &token).await, Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+        assert!(matches!(present_external(&state, &token, &own_key, true).await, Err(Error::InvalidSignature)));
+        assert!(matches!(present_external(&state, &token, &minter_key, false).await, Err(Error::Unauthorized(_))));
+        assert_eq!(present_external(&state, &token, &minter_key, true).await?.sub.as_deref(), Some("agent-9"));
+        assert!(matches!(present_external(&state, &token, &minter_key, true).await, Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn external_legacy_tokens_refused_when_disabled() -> Result<()> {
+        use ed25519_dalek::Signer;
+        let mut builder = test_builder()?;
+        builder.accept_legacy_tokens = false;
+        let state = builder.build()?;
+        let minter = generate_keypair();
+        let minter_key = URL_SAFE_NO_PAD.encode(minter.verifying_key().to_bytes());
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Claims::new("agent-9".into(), "deploy".into(), 60))?);
+        let legacy = format!("{payload}.{}", URL_SAFE_NO_PAD.encode(minter.sign(payload.as_bytes()).to_bytes()));
+
+        let result = present_external(&state, &legacy, &minter_key, true).await;
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::LegacyFormat, _))));
+        let current = sign_token(&Claims::new("agent-9".into(), "deploy".into(), 60), &minter)?;
+        present_external(&state, &current, &minter_key, true).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn external_tokens_need_an_audience_when_required() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.require_audience = true;
+        let state = builder.build()?;
+        let minter = generate_keypair();
+        let minter_key = URL_SAFE_NO_PAD.encode(minter.verifying_key().to_bytes());
+        let mut claims = Claims::new("agent-9".into(), "deploy".into(), 60);
+
+        let unbound = sign_token(&claims, &minter)?;
+        let result = present_external(&state, &unbound, &minter_key, true).await;
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+        claims.aud = Some(Audience::One("billing".into()));
+        present_external(&state, &sign_token(&claims, &minter)?, &minter_key, true).await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn configured_audience_enforced() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.proxy_audience = Some("billing".into());
+        let state = builder.build()?;
+        let token_for = |aud: Option<&str>| {
+            let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+            claims.aud = aud.map(|a| Audience::One(a.into()));
+            state.sign(&claims)
+        };
+
+        present(&state, &token_for(Some("billing"))?).await?;
+        let wrong = present(&state, &token_for(Some("search"))?).await;
+        assert!(matches!(wrong, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+        let missing = present(&state, &token_for(None)?).await;
+        assert!(matches!(missing, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_replay_counted_separately() -> Result<()> {
+        let state = build_test_state()?;
+        let expired = |sub: &str| {
+            let mut claims = Claims::new(sub.into(), "deploy".into(), 60);
+            claims.exp = Utc::now() - chrono::Duration::seconds(5);
+            claims
+        };
+        let hoarded = expired("mallory");
+        state.audit_log.log(&hoarded.jti, &hoarded.sub, &hoarded.action, Utc::now() - chrono::Duration::minutes(2))?;
+
+        let result = present(&state, &state.sign(&hoarded)?).await;
+        assert!(matches!(result, Err(Error::TokenExpired)));
+        let result = present(&state, &state.sign(&expired("alice"))?).await;
+        assert!(matches!(result, Err(Error::TokenExpired)));
+
+        let snapshot = state.metrics.snapshot();
+        assert_eq!(snapshot.expired_replay, 1);
+        assert_eq!(snapshot.tokens_rejected, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn verify_and_reject_counted_from_proxy_path() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        present(&state, &token).await?;
+        let forged = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 60), &generate_keypair())?;
+        assert!(matches!(present(&state, &forged).await, Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+
+        let snapshot = state.metrics.snapshot();
+        assert_eq!((snapshot.tokens_verified, snapshot.tokens_rejected), (1, 1));
+        assert!(snapshot.verify_latency_max_us >= snapshot.verify_latency_avg_us);
+        assert_eq!(snapshot.verify_latency_max_us, state.metrics.verify_latency_us_total.load(Ordering::Relaxed));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn only_allowed_issuers_accepted() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.proxy_allowed_issuers = Some(vec!["https://mint-a.example".into()]);
+        let state = builder.build()?;
+        let issued_by = |iss: Option<&str>| {
+            let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+            claims.iss = iss.map(str::to_owned);
+            state.sign(&claims)
+        };
+
+        present(&state, &issued_by(Some("https://mint-a.example"))?).await?;
+        for iss in [Some("https://mint-b.example"), None] {
+            let result = present(&state, &issued_by(iss)?).await;
+            assert!(matches!(result, Err(Error::InvalidToken(TokenFault::WrongIssuer, _))));
+        }
+        assert_eq!(state.metrics.snapshot().tokens_rejected, 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn binary_token_consumed_once() -> Result<()> {
+        let state = build_test_state()?;
+        let token = sign_token_binary(&Claims::new("sensor-7".into(), "read:temp".into(), 60), state.signing_key()?)?;
+        let call = |content_type: &'static str, body: Vec<u8>| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::CONTENT_TYPE, HeaderValue::from_static(content_type));
+            proxy_binary(State(state.clone()), headers, Query(ProxyQuery::default()), Bytes::from(body))
+        };
+
+        assert!(matches!(call("application/json", token.clone()).await, Err(Error::Validation(_))));
+        assert!(matches!(call(OCTET_STREAM, token[..token.len() - 1].to_vec()).await, Err(Error::InvalidToken(..))));
+        let (_, Json(body)) = call(OCTET_STREAM, token.clone()).await?;
+        assert_eq!(body.sub.as_deref(), Some("sensor-7"));
+        assert!(matches!(call(OCTET_STREAM, token).await, Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn minimal_response_omits_sub_but_audit_keeps_it() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("alice@example.com".into(), "deploy".into(), 60))?;
+        let req = Json(ProxyRequest { token: Some(token), ..Default::default() });
+        let (_, Json(body)) = proxy_request(State(state.clone()), HeaderMap::new(), Query(ProxyQuery { minimal: true, ..ProxyQuery::default() }), req).await?;
+
+        let json = serde_json::to_value(&body)?;
+        assert!(json.get("sub").is_none());
+        assert_eq!(json["action"], "deploy");
+        assert_eq!(state.audit_log.recent(1)?[0].sub, "alice@example.com");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outcome_recorded_once_after_consumption() -> Result<()> {
+        let state = build_test_state()?;
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.approved_by = Some("alice@example.com".into());
+        let token = state.sign(&claims)?;
+        let report = |outcome: Outcome| {
+            let req = CompleteRequest { token: token.clone(), outcome, detail: Some("build 17".into()) };
+            complete(State(state.clone()), Json(req))
+        };
+
+        assert!(matches!(report(Outcome::Success).await, Err(Error::Validation(_))));
+        present(&state, &token).await?;
+        let Json(body) = report(Outcome::Failure).await?;
+        assert_eq!((body.jti.as_str(), body.outcome), (claims.jti.as_str(), Outcome::Failure));
+        assert!(matches!(report(Outcome::Success).await, Err(Error::ReplayDetected(_))));
+
+        let recorded = state.audit_log.outcome(&claims.jti)?;
+        let recorded = recorded.as_ref().map(|e| (e.action.as_str(), e.detail.as_deref(), e.approved_by.as_deref()));
+        assert_eq!(recorded, Some(("failure", Some("build 17"), Some("alice@example.com"))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outcome_accepted_before_queued_verify_is_written() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.audit_writer = Some(crate::audit::writer::AuditWriter::new(8, crate::audit::writer::Overflow::Drop));
+        let state = builder.build()?;
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let token = state.sign(&claims)?;
+        present(&state, &token).await?;
+        assert!(!state.audit_log.was_verified(&claims.jti)?);
+        let req = CompleteRequest { token, outcome: Outcome::Success, detail: None };
+        let Json(body) = complete(State(state.clone()), Json(req)).await?;
+        assert_eq!(body.outcome, Outcome::Success);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn oversized_outcome_detail_rejected() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 300))?;
+        present(&state, &token).await?;
+        let req = CompleteRequest { token, outcome: Outcome::Success, detail: Some("x".repeat(MAX_OUTCOME_DETAIL_LEN + 1)) };
+        assert!(matches!(complete(State(state.clone()), Json(req)).await, Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn outcome_rejected_for_forged_token() -> Result<()> {
+        let state = build_test_state()?;
+        let token = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 300))?;
+        present(&state, &token).await?;
+        let forged = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 300), &generate_keypair())?;
+        let req = CompleteRequest { token: forged, outcome: Outcome::Success, detail: None };
+        assert!(matches!(complete(State(state.clone()), Json(req)).await, Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn key_bound_token_requires_proof_from_bound_key() -> Result<()> {
+        let state = build_test_state()?;
+        let (a, b) = (generate_keypair(), generate_keypair());
+        let cnf_key = URL_SAFE_NO_PAD.encode(a.verifying_key().as_bytes());
+        let req = serde_json::from_value(serde_json::json!({"sub": "agent-1", "action": "deploy", "cnf_key": cnf_key}))?;
+        let minted = crate::handlers::mint::mint_one(&state, req).await?;
+        let (token, jti) = (minted.token, minted.jti);
+        let present_with = |proof: Option<String>| {
+            let req = ProxyRequest { token: Some(token.clone()), proof, ..Default::default() };
+            proxy_request(State(state.clone()), HeaderMap::new(), Query(ProxyQuery::default()), Json(req))
+        };
+
+        let bad_proof = |r: Result<_>| matches!(r, Err(Error::InvalidToken(TokenFault::InvalidProof, _)));
+        assert!(bad_proof(present_with(None).await));
+        assert!(bad_proof(present_with(Some(sign_proof(&jti, &b))).await));
+        let (_, Json(body)) = present_with(Some(sign_proof(&jti, &a))).await?;
+        assert_eq!(body.jti, jti);
+        Ok(())
+    }
+}
@@ -0,0 +1,123 @@
+//! Refresh endpoint: exchanges a rotating refresh token for a new access token.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::Json;
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::error::{Error, Result};
+use crate::handlers::mint::{MintResponse, enforce_policy};
+use crate::state::AppState;
+
+#[derive(Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    pub refresh_token: String,
+}
+
+#[utoipa::path(
+    post,
+    path = "/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, body = MintResponse),
+        (status = 401, description = "unknown, expired, or revoked refresh token, or the token it renews is revoked"),
+        (status = 403, description = "policy violation"),
+        (status = 409, description = "refresh token reuse detected; family revoked"),
+    )
+)]
+pub async fn refresh(
+    State(state): State<AppState>,
+    Json(req): Json<RefreshRequest>,
+) -> Result<Json<MintResponse>> {
+    let grant = state.refresh_store.rotate(&req.refresh_token).map_err(|e| {
+        if matches!(e, Error::ReplayDetected(_)) {
+            state.metrics.record_refresh_reuse();
+            crate::console::log_refresh_reuse(&e.to_string());
+        }
+        e
+    })?;
+
+    let claims = grant.claims;
+    if let Err(e) = state.check_revocation(&claims).await {
+        state.refresh_store.revoke_family_of(&claims.jti)?;
+        return Err(e);
+    }
+    enforce_policy(&state, &claims.sub, claims.approved_by.as_deref(), &claims.action)?;
+
+    let jti = claims.jti.clone();
+    let exp = claims.exp.to_rfc3339();
+    let receipt_type = claims.receipt_type.clone();
+    let token = state.sign(&claims)?;
+
+    tracing::info!(sub = %claims.sub, action = %claims.action, jti = %jti, "token refreshed");
+    crate::console::log_mint(&claims.sub, &claims.action, &jti);
+    state.metrics.record_mint();
+
+    Ok(Json(MintResponse {
+        token,
+        jti,
+        exp,
+        receipt_type,
+        refresh_token: Some(grant.refresh_token),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::build_test_state;
+    use crate::token::claims::Claims;
+
+    fn request(token: &str) -> Json<RefreshRequest> {
+        Json(RefreshRequest { refresh_token: token.into() })
+    }
+
+    #[tokio::test]
+    async fn refresh_issues_verifiable_token() -> Result<()> {
+        let state = build_test_state()?;
+        let original = Claims::new("agent-1".into(), "deploy".into(), 60);
+        let first = state.refresh_store.issue(&original, 60)?;
+
+        let Json(resp) = refresh(State(state.clone()), request(&first)).await?;
+        let claims = state.verify(&resp.token)?;
+        assert_eq!(claims.sub, "agent-1");
+        assert_ne!(claims.jti, original.jti);
+        assert!(resp.refresh_token.is_some_and(|t| t != first));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reused_refresh_token_is_counted() -> Result<()> {
+        let state = build_test_state()?;
+        let first = state.refresh_store.issue(&Claims::new("agent-1".into(), "deploy".into(), 60), 60)?;
+
+        let Json(rotated) = refresh(State(state.clone()), request(&first)).await?;
+        assert!(rotated.refresh_token.is_some());
+        let result = refresh(State(state.clone()), request(&first)).await;
+        assert!(matches!(result, Err(Error::ReplayDetected(_))));
+        assert_eq!(state.metrics.snapshot().refresh_reuse_detected, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn revoked_original_or_refreshed_jti_stops_refresh() -> Result<()> {
+        let state = build_test_state()?;
+        let until = chrono::Utc::now().timestamp() + 600;
+        let original = Claims::new("agent-1".into(), "deploy".into(), 60);
+        let first = state.refresh_store.issue(&original, 60)?;
+        state.revoke(Some(&original.jti), None, until).await?;
+        assert!(matches!(refresh(State(state.clone()), request(&first)).await, Err(Error::Unauthorized(_))));
+
+        let first = state.refresh_store.issue(&Claims::new("agent-1".into(), "deploy".into(), 60), 60)?;
+        let Json(resp) = refresh(State(state.clone()), request(&first)).await?;
+        state.revoke(Some(&state.verify(&resp.token)?.jti), None, until).await?;
+        let next = resp.refresh_token.ok_or_else(|| Error::Validation("no refresh token".into()))?;
+        assert!(matches!(refresh(State(state.clone()), request(&next)).await, Err(Error::Unauthorized(_))));
+
+        let first = state.refresh_store.issue(&Claims::new("agent-2".into(), "deploy".into(), 60), 60)?;
+        state.revoke(None, Some("agent-2"), until).await?;
+        assert!(matches!(refresh(State(state.clone()), request(&first)).await, Err(Error::Revoked(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+//! Verification bundle and public key endpoints: everything a service needs to verify tokens offline.
+//! Used by: server.
+
+use axum::extract::{Query, State};
+use axum::http::header;
+use axum::response::IntoResponse;
+use axum::Json;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+
+use crate::error::Result;
+use crate::handlers::jwks::{key_cache_control, Jwk};
+use crate::state::AppState;
+use crate::token::claims::Algorithm;
+use crate::token::keys::{public_key_hex, public_key_pem};
+use crate::token::offline::{VerificationBundle, VerificationConfig};
+
+/// Signed once, on first request, with the audiences from `VERIFICATION_AUDIENCES`.
+#[utoipa::path(
+    get,
+    path = "/verification-bundle",
+    responses((status = 200, body = VerificationBundle))
+)]
+pub async fn verification_bundle(State(state): State<AppState>) -> Result<Json<VerificationBundle>> {
+    if let Some(bundle) = state.verification_bundle.get() {
+        return Ok(Json(bundle.clone()));
+    }
+    let config = VerificationConfig {
+        public_key: URL_SAFE_NO_PAD.encode(state.published_key()?.to_bytes()),
+        alg: Algorithm::EdDSA,
+        issuer: state.issuer.clone(),
+        audiences: state.verification_audiences.clone(),
+        token_prefix: state.token_prefix.clone(),
+        accept_legacy_tokens: state.accept_legacy_tokens,
+    };
+    let bundle = VerificationBundle::sign(&config, state.signing_key()?)?;
+    Ok(Json(state.verification_bundle.get_or_init(|| bundle).clone()))
+}
+
+#[derive(Deserialize, ToSchema, Default, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyFormat {
+    #[default]
+    Jwk,
+    Pem,
+    Hex,
+}
+
+#[derive(Deserialize, IntoParams)]
+pub struct PubkeyQuery {
+    /// `jwk` (default), `pem` (SubjectPublicKeyInfo) or `hex` (the 32 raw bytes).
+    #[serde(default)]
+    #[param(inline)]
+    pub format: KeyFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/pubkey",
+    params(PubkeyQuery),
+    responses(
+        (status = 200, description = "The Ed25519 verifying key in the requested encoding"),
+        (status = 400, description = "Unknown format"),
+        (status = 403, description = "HS256 mode: there is no public key")
+    )
+)]
+pub async fn pubkey(State(state): State<AppState>, Query(query): Query<PubkeyQuery>) -> Result<impl IntoResponse> {
+    let key = state.published_key()?;
+    let (content_type, body) = match query.format {
+        KeyFormat::Jwk => ("application/jwk+json", serde_json::to_string(&Jwk::ed25519(key))?),
+        KeyFormat::Pem => ("application/x-pem-file", public_key_pem(key)),
+        KeyFormat::Hex => ("text/plain; charset=utf-8", public_key_hex(key)),
+    };
+    Ok(([(header::CONTENT_TYPE, content_type.to_owned()), (header::CACHE_CONTROL, key_cache_control(&state))], body))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_builder;
+    use crate::token::claims::{Audience, Claims};
+    use crate::token::keys::decode_public_key_pem;
+    use crate::token::offline::Verifier;
+    use axum::body::to_bytes;
+
+    async fn fetch_pubkey(state: &AppState, format: KeyFormat) -> std::result::Result<(String, String), Box<dyn std::error::Error>> {
+        let response = pubkey(State(state.clone()), Query(PubkeyQuery { format })).await?.into_response();
+        let content_type = response.headers().get(header::CONTENT_TYPE).ok_or("no content type")?.to_str()?.to_owned();
+        assert_eq!(response.headers().get(header::CACHE_CONTROL).ok_or("no cache-control")?, key_cache_control(state).as_str());
+        let body = to_bytes(response.into_body(), usize::MAX).await?;
+        Ok((content_type, String::from_utf8(body.to_vec())?))
+    }
+
+    #[tokio::test]
+    async fn bundle_verifies_tokens_this_server_mints() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.issuer = Some("https://mint.example".into());
+        builder.verification_audiences = vec!["billing".into(), "search".into()];
+        let state = builder.build()?;
+        let Json(bundle) = verification_bundle(State(state.clone())).await?;
+        let verifier = Verifier::from_bundle(&bundle)?;
+        assert_eq!(verifier.config().audiences, ["billing", "search"]);
+        let Json(again) = verification_bundle(State(state.clone())).await?;
+        assert_eq!(again.signature, bundle.signature);
+
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        claims.aud = Some(Audience::One("search".into()));
+        let verified = verifier.verify(&state.sign(&claims)?)?;
+        assert_eq!(verified.iss.as_deref(), Some("https://mint.example"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn pem_parses_back_to_the_verifying_key() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let state = test_builder()?.build()?;
+        let (content_type, pem) = fetch_pubkey(&state, KeyFormat::Pem).await?;
+        assert_eq!(content_type, "application/x-pem-file");
+        assert_eq!(decode_public_key_pem(&pem)?, state.verifying_key);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn hex_decodes_to_the_public_bytes() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let state = test_builder()?.build()?;
+        let (content_type, hex) = fetch_pubkey(&state, KeyFormat::Hex).await?;
+        assert!(content_type.starts_with("text/plain"));
+        let bytes = (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16)).collect::<std::result::Result<Vec<u8>, _>>()?;
+        assert_eq!(bytes, state.verifying_key.as_bytes());
+
+        let (content_type, jwk) = fetch_pubkey(&state, KeyFormat::Jwk).await?;
+        assert_eq!(content_type, "application/jwk+json");
+        let jwk: serde_json::Value = serde_json::from_str(&jwk)?;
+        assert_eq!(jwk["x"], URL_SAFE_NO_PAD.encode(state.verifying_key.as_bytes()));
+        Ok(())
+    }
+}
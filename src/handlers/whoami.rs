@@ -0,0 +1,112 @@
+//! Admin-gated OIDC debugging endpoint: echoes what AgentMint sees in an id_token.
+//! Used by: server.
+
+use axum::extract::State;
+use axum::http::HeaderMap;
+use axum::Json;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::{Error, Result};
+use crate::handlers::admin::require_admin;
+use crate::oidc::IdTokenClaims;
+use crate::state::AppState;
+
+#[derive(Deserialize, ToSchema)]
+pub struct WhoamiRequest {
+    pub id_token: String,
+}
+
+/// Either the verified claims or the verifier's error detail; nothing is minted.
+#[derive(Serialize, ToSchema)]
+pub struct WhoamiResponse {
+    pub valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub claims: Option<IdTokenClaims>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[utoipa::path(
+    post,
+    path = "/oidc/whoami",
+    request_body = WhoamiRequest,
+    params(("x-admin-key" = String, Header, description = "admin API key")),
+    responses(
+        (status = 200, body = WhoamiResponse),
+        (status = 401, description = "missing or invalid admin key, or OIDC not configured"),
+    )
+)]
+pub async fn whoami(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(req): Json<WhoamiRequest>,
+) -> Result<Json<WhoamiResponse>> {
+    require_admin(&state, &headers)?;
+    let oidc = state
+        .oidc
+        .as_ref()
+        .ok_or_else(|| Error::Unauthorized("OIDC not configured".into()))?;
+    Ok(Json(match oidc.verify(&req.id_token).await {
+        Ok(claims) => WhoamiResponse { valid: true, claims: Some(claims), error: None },
+        Err(e) => WhoamiResponse { valid: false, claims: None, error: Some(e.to_string()) },
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+    use crate::handlers::admin::ADMIN_KEY_HEADER;
+    use crate::oidc::test_support;
+    use crate::state::{test_builder, TEST_ADMIN_KEY};
+
+    type TestResult = std::result::Result<(), Box<dyn std::error::Error>>;
+
+    fn oidc_state() -> std::result::Result<AppState, Box<dyn std::error::Error>> {
+        let mut builder = test_builder()?;
+        builder.oidc = Some(test_support::verifier()?);
+        Ok(builder.build()?)
+    }
+
+    fn admin_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(ADMIN_KEY_HEADER, HeaderValue::from_static(TEST_ADMIN_KEY));
+        headers
+    }
+
+    async fn call(state: AppState, headers: HeaderMap, id_token: String) -> Result<WhoamiResponse> {
+        let Json(res) = whoami(State(state), headers, Json(WhoamiRequest { id_token })).await?;
+        Ok(res)
+    }
+
+    #[tokio::test]
+    async fn valid_token_returns_claims() -> TestResult {
+        let token = test_support::sign(&test_support::claims("alice@example.com"))?;
+        let res = call(oidc_state()?, admin_headers(), token).await?;
+        assert!(res.valid);
+        assert_eq!(res.claims.map(|c| c.sub).as_deref(), Some("alice@example.com"));
+        assert!(res.error.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn invalid_token_returns_error_detail() -> TestResult {
+        let mut claims = test_support::claims("alice@example.com");
+        claims.iss = "https://elsewhere.test".into();
+        let token = test_support::sign(&claims)?;
+        let res = call(oidc_state()?, admin_headers(), token).await?;
+        assert!(!res.valid);
+        assert!(res.claims.is_none());
+        assert!(res.error.is_some_and(|e| e.contains("configured issuer")));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn requires_admin_key() -> TestResult {
+        let token = test_support::sign(&test_support::claims("alice@example.com"))?;
+        let result = call(oidc_state()?, HeaderMap::new(), token).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+}
@@ -0,0 +1,73 @@
+//! Liveness of background tasks: each loop beats on every pass, and `/ready` reports the ones that stopped.
+//! Used by: state, audit::writer, audit::retention, telemetry, canary, token::keyset, handlers::health.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// A task is stalled once it misses this many of its own intervals.
+const STALE_AFTER_INTERVALS: u32 = 3;
+
+struct Beat {
+    every: Duration,
+    last: Instant,
+}
+
+pub struct Heartbeats {
+    tasks: Mutex<HashMap<&'static str, Beat>>,
+}
+
+impl Heartbeats {
+    pub fn new() -> Self {
+        Self { tasks: Mutex::new(HashMap::new()) }
+    }
+
+    /// Starts tracking `task`, which promises to beat at least once per `every`.
+    pub fn register(&self, task: &'static str, every: Duration) {
+        if let Ok(mut tasks) = self.tasks.lock() {
+            tasks.insert(task, Beat { every, last: Instant::now() });
+        }
+    }
+
+    pub fn beat(&self, task: &'static str) {
+        let Ok(mut tasks) = self.tasks.lock() else {
+            return;
+        };
+        if let Some(beat) = tasks.get_mut(task) {
+            beat.last = Instant::now();
+        }
+    }
+
+    /// Registered tasks that have been silent for longer than `STALE_AFTER_INTERVALS` intervals at `now`.
+    pub fn stalled(&self, now: Instant) -> Vec<&'static str> {
+        let Ok(tasks) = self.tasks.lock() else {
+            return Vec::new();
+        };
+        let mut stalled: Vec<_> = tasks
+            .iter()
+            .filter(|(_, b)| now.saturating_duration_since(b.last) > b.every.saturating_mul(STALE_AFTER_INTERVALS))
+            .map(|(task, _)| *task)
+            .collect();
+        stalled.sort_unstable();
+        stalled
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn silent_task_stalls_while_beating_one_stays_healthy() {
+        let heartbeats = Heartbeats::new();
+        heartbeats.register("writer", Duration::from_secs(10));
+        heartbeats.register("sampler", Duration::from_secs(60));
+        let start = Instant::now();
+        assert!(heartbeats.stalled(start).is_empty());
+
+        heartbeats.beat("sampler");
+        assert_eq!(heartbeats.stalled(start + Duration::from_secs(31)), ["writer"]);
+        heartbeats.beat("unregistered");
+        assert_eq!(heartbeats.stalled(start + Duration::from_secs(181)), ["sampler", "writer"]);
+    }
+}
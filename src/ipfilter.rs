@@ -0,0 +1,203 @@
+//! Client IP resolution, CIDR allowlisting for token-issuing endpoints, and per-IP rate limiting.
+//! Used by: server, state.
+
+use std::net::{IpAddr, SocketAddr};
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::HeaderMap;
+use axum::middleware::Next;
+use axum::response::Response;
+use ipnet::IpNet;
+
+use crate::error::{Error, Result};
+use crate::ratelimit::RequestClass;
+use crate::state::AppState;
+
+const FORWARDED_FOR: &str = "x-forwarded-for";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct IpAllowlist {
+    nets: Vec<IpNet>,
+}
+
+impl IpAllowlist {
+    pub fn parse(spec: &str) -> Result<Self> {
+        let nets = spec
+            .split(',')
+            .map(str::trim)
+            .filter(|entry| !entry.is_empty())
+            .map(|entry| {
+                entry
+                    .parse::<IpNet>()
+                    .or_else(|_| entry.parse::<IpAddr>().map(IpNet::from))
+                    .map_err(|_| Error::Config(format!("invalid CIDR block: {entry}")))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        if nets.is_empty() {
+            return Err(Error::Config("CIDR allowlist is empty".into()));
+        }
+        Ok(Self { nets })
+    }
+
+    /// Reads the CIDR list in `name`; unset or blank disables it.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>, name: &str) -> Result<Option<Self>> {
+        let Some(spec) = get(name).filter(|v| !v.trim().is_empty()) else {
+            return Ok(None);
+        };
+        let list = Self::parse(&spec).map_err(|e| match e {
+            Error::Config(msg) => Error::Config(format!("{name}: {msg}")),
+            other => other,
+        })?;
+        tracing::info!(var = name, blocks = list.nets.len(), "IP allowlist enabled");
+        Ok(Some(list))
+    }
+
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        let ip = match ip {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+            v4 => v4,
+        };
+        self.nets.iter().any(|net| net.contains(&ip))
+    }
+}
+
+/// Peer address, or the last `X-Forwarded-For` hop when the peer is a trusted proxy.
+pub fn client_ip(peer: IpAddr, headers: &HeaderMap, trusted_proxies: Option<&IpAllowlist>) -> IpAddr {
+    if !trusted_proxies.is_some_and(|proxies| proxies.contains(peer)) {
+        return peer;
+    }
+    headers
+        .get(FORWARDED_FOR)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.rsplit(',').next())
+        .and_then(|hop| hop.trim().parse().ok())
+        .unwrap_or(peer)
+}
+
+pub async fn require_mint_ip(State(state): State<AppState>, req: Request, next: Next) -> Result<Response> {
+    let Some(allowlist) = &state.mint_ip_allowlist else {
+        return Ok(next.run(req).await);
+    };
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .ok_or_else(|| Error::Forbidden("client address unavailable".into()))?;
+    let ip = client_ip(peer, req.headers(), state.trusted_proxies.as_ref());
+    if !allowlist.contains(ip) {
+        tracing::warn!(ip = %ip, path = %req.uri().path(), "mint from address outside allowlist");
+        return Err(Error::Forbidden(format!("{ip} not in mint allowlist")));
+    }
+    Ok(next.run(req).await)
+}
+
+/// Per-IP budget for verification and read-only routes.
+pub async fn limit_reads(State(state): State<AppState>, req: Request, next: Next) -> Result<Response> {
+    limit_ip(&state, &req, RequestClass::Read)?;
+    Ok(next.run(req).await)
+}
+
+/// Per-IP budget for token issuance and WebAuthn routes.
+pub async fn limit_writes(State(state): State<AppState>, req: Request, next: Next) -> Result<Response> {
+    limit_ip(&state, &req, RequestClass::Write)?;
+    Ok(next.run(req).await)
+}
+
+/// A request without a peer address (only in-process callers) cannot be attributed and is let through.
+fn limit_ip(state: &AppState, req: &Request, class: RequestClass) -> Result<()> {
+    let Some(ConnectInfo(peer)) = req.extensions().get::<ConnectInfo<SocketAddr>>() else {
+        return Ok(());
+    };
+    let ip = client_ip(peer.ip(), req.headers(), state.trusted_proxies.as_ref());
+    state.check_ip_rate(&ip.to_string(), class)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state::test_builder;
+    use crate::testing::TestServer;
+    use axum::http::HeaderValue;
+
+    fn ip(s: &str) -> Result<IpAddr> {
+        s.parse().map_err(|_| Error::Validation(s.into()))
+    }
+
+    #[test]
+    fn parses_blocks_and_bare_addresses() -> Result<()> {
+        let list = IpAllowlist::parse("10.0.0.0/8, 192.168.1.7 ,2001:db8::/32")?;
+        assert!(list.contains(ip("10.20.30.40")?));
+        assert!(list.contains(ip("192.168.1.7")?));
+        assert!(!list.contains(ip("192.168.1.8")?));
+        assert!(list.contains(ip("2001:db8::1")?));
+        assert!(list.contains(ip("::ffff:10.1.1.1")?));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_or_empty_spec_rejected() {
+        assert!(matches!(IpAllowlist::parse("10.0.0.0/33"), Err(Error::Config(_))));
+        assert!(matches!(IpAllowlist::parse(" , "), Err(Error::Config(_))));
+    }
+
+    #[test]
+    fn forwarded_for_only_honoured_from_trusted_proxy() -> Result<()> {
+        let proxies = IpAllowlist::parse("10.0.0.1")?;
+        let mut headers = HeaderMap::new();
+        headers.insert(FORWARDED_FOR, HeaderValue::from_static("1.1.1.1, 203.0.113.9"));
+        assert_eq!(client_ip(ip("10.0.0.1")?, &headers, Some(&proxies)), ip("203.0.113.9")?);
+        assert_eq!(client_ip(ip("10.0.0.2")?, &headers, Some(&proxies)), ip("10.0.0.2")?);
+        assert_eq!(client_ip(ip("10.0.0.1")?, &headers, None), ip("10.0.0.1")?);
+        Ok(())
+    }
+
+    async fn server_with_allowlist(spec: &str) -> Result<TestServer> {
+        let mut builder = test_builder()?;
+        builder.mint_ip_allowlist = Some(IpAllowlist::parse(spec)?);
+        TestServer::spawn_with_state(builder.build()?).await
+    }
+
+    async fn post(server: &TestServer, path: &str, body: serde_json::Value) -> Result<reqwest::StatusCode> {
+        let resp = reqwest::Client::new()
+            .post(server.url(path))
+            .json(&body)
+            .send()
+            .await
+            .map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        Ok(resp.status())
+    }
+
+    #[tokio::test]
+    async fn mint_from_in_range_ip_succeeds() -> Result<()> {
+        let server = server_with_allowlist("127.0.0.0/8").await?;
+        let status = post(&server, "/mint", serde_json::json!({"sub": "agent-1", "action": "deploy"})).await?;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn mint_from_out_of_range_ip_rejected() -> Result<()> {
+        let server = server_with_allowlist("10.0.0.0/8").await?;
+        let status = post(&server, "/mint", serde_json::json!({"sub": "agent-1", "action": "deploy"})).await?;
+        assert_eq!(status, reqwest::StatusCode::FORBIDDEN);
+        let status = post(&server, "/proxy", serde_json::json!({"token": "x.y"})).await?;
+        assert_ne!(status, reqwest::StatusCode::FORBIDDEN);
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn read_limit_does_not_spend_write_budget() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.rate_limits.read_per_ip_per_min = Some(2);
+        let server = TestServer::spawn_with_state(builder.build()?).await?;
+        for _ in 0..2 {
+            let status = post(&server, "/proxy", serde_json::json!({"token": "x.y"})).await?;
+            assert_ne!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+        }
+        let status = post(&server, "/proxy", serde_json::json!({"token": "x.y"})).await?;
+        assert_eq!(status, reqwest::StatusCode::TOO_MANY_REQUESTS);
+        let status = post(&server, "/mint", serde_json::json!({"sub": "agent-1", "action": "deploy"})).await?;
+        assert_eq!(status, reqwest::StatusCode::OK);
+        server.shutdown().await
+    }
+}
@@ -1,5 +1,5 @@
 //! In-memory JTI replay protection with expiry and capacity limits.
-//! Used by: handlers::proxy, state.
+//! Used by: storage.
 
 use std::collections::HashMap;
 use std::sync::Mutex;
@@ -29,7 +29,7 @@ impl JtiStore {
         let mut entries = self.entries.lock().map_err(lock_err("jti"))?;
         Self::cleanup_expired_inner(&mut entries);
         if entries.len() >= self.max_capacity {
-            return Err(Error::ServiceUnavailable("JTI store at capacity".into()));
+            return Err(Error::CapacityExceeded("JTI store at capacity".into()));
         }
         if entries.contains_key(jti) {
             return Err(Error::ReplayDetected(jti.to_owned()));
@@ -38,6 +38,11 @@ impl JtiStore {
         Ok(())
     }
 
+    pub fn contains(&self, jti: &str) -> Result<bool> {
+        let entries = self.entries.lock().map_err(lock_err("jti"))?;
+        Ok(entries.get(jti).is_some_and(|exp| *exp > chrono::Utc::now().timestamp()))
+    }
+
     fn cleanup_expired_inner(entries: &mut HashMap<String, i64>) {
         let now = chrono::Utc::now().timestamp();
         entries.retain(|_, exp| *exp > now);
@@ -86,13 +91,13 @@ mod tests {
     }
 
     #[test]
-    fn capacity_limit_returns_503() -> Result<()> {
+    fn capacity_limit_returns_429() -> Result<()> {
         let store = JtiStore::with_capacity(2);
         let exp = future_exp();
         store.check_and_insert("jti-1", exp)?;
         store.check_and_insert("jti-2", exp)?;
         let result = store.check_and_insert("jti-3", exp);
-        assert!(matches!(result, Err(Error::ServiceUnavailable(_))));
+        assert!(matches!(result, Err(Error::CapacityExceeded(_))));
         Ok(())
     }
 
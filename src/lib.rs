@@ -0,0 +1,27 @@
+//! AgentMint: cryptographic proof of human authorization for AI agent actions.
+
+pub mod audit;
+pub mod canary;
+pub mod config;
+pub mod console;
+pub mod cors;
+pub mod error;
+pub mod handlers;
+pub mod heartbeat;
+pub mod ipfilter;
+pub mod jti;
+pub mod oidc;
+pub mod openapi;
+pub mod policy;
+pub mod preauth;
+pub mod ratelimit;
+pub mod refresh;
+pub mod server;
+pub mod spike;
+pub mod state;
+pub mod storage;
+pub mod telemetry;
+#[cfg(any(test, feature = "test-util"))]
+pub mod testing;
+pub mod token;
+pub mod webauthn;
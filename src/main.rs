@@ -1,31 +1,70 @@
-//! AgentMint: cryptographic proof of human authorization for AI agent actions.
-
-pub mod audit;
-pub mod console;
-pub mod error;
-pub mod handlers;
-pub mod jti;
-pub mod oidc;
-pub mod policy;
-pub mod ratelimit;
-pub mod server;
-pub mod state;
-pub mod telemetry;
-pub mod token;
-pub mod webauthn;
+//! AgentMint server binary.
+
+use agentmint::config::Config;
+use agentmint::audit::retention;
+use agentmint::{canary, console, server, state};
+
+fn jwks_warmup_enabled() -> bool {
+    std::env::var("OIDC_JWKS_WARMUP").map(|v| v != "false").unwrap_or(true)
+}
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     tracing_subscriber::fmt::init();
-    console::print_banner();
+    let quiet = console::quiet_mode(std::env::args().skip(1));
+    console::print_banner(quiet);
 
     tracing::info!(version = env!("CARGO_PKG_VERSION"), "agentmint starting");
 
-    let addr = std::env::var("BIND_ADDR").unwrap_or_else(|_| "0.0.0.0:3000".into());
-    let state = state::build_state("agentmint.db")?;
+    let config = Config::from_env()?;
+    config.log_summary();
+    let addr = config.bind_addr.clone();
+    let state = state::build_state(config, "agentmint.db")?;
+
+    console::print_startup(&addr, quiet);
+
+    let sampler_state = state.clone();
+    tokio::spawn(async move {
+        sampler_state.metrics_history.run_sampler(&sampler_state.metrics, &sampler_state.heartbeats).await;
+    });
+
+    if let Some(interval) = canary::interval_from_env().filter(|_| state.can_sign()) {
+        let canary_state = state.clone();
+        tokio::spawn(async move { canary::run(&canary_state, interval).await });
+    }
+
+    if state.audit_writer.is_some() {
+        let writer_state = state.clone();
+        tokio::spawn(async move {
+            let Some(writer) = &writer_state.audit_writer else { return };
+            if let Err(e) = writer.run(&writer_state.audit_log, &writer_state.metrics, &writer_state.heartbeats).await {
+                tracing::error!(error = %e, "audit writer stopped");
+            }
+        });
+    }
+
+    if state.audit_retention.is_some() {
+        tokio::spawn(retention::run_pruner(state.clone()));
+    }
+
+    if state.minter_keys.is_some() {
+        let reload_state = state.clone();
+        tokio::spawn(async move {
+            let Some(keys) = &reload_state.minter_keys else { return };
+            keys.run_reloader(&reload_state.heartbeats).await;
+        });
+    }
 
-    tracing::info!(bind = %addr, jti_capacity = 100_000, max_ttl = 300, "config");
-    console::print_startup(&addr);
+    if state.oidc.is_some() && jwks_warmup_enabled() {
+        let warmup_state = state.clone();
+        tokio::spawn(async move {
+            let Some(oidc) = &warmup_state.oidc else { return };
+            match oidc.warm_up().await {
+                Ok(keys) => tracing::info!(keys, "JWKS warm-up complete"),
+                Err(e) => tracing::warn!(error = %e, "JWKS warm-up failed; keys will be fetched on first use"),
+            }
+        });
+    }
 
     server::run(state, &addr).await?;
     Ok(())
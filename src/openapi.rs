@@ -0,0 +1,147 @@
+//! OpenAPI document derived from handler request/response types.
+//! Used by: handlers::openapi.
+
+use utoipa::OpenApi;
+
+use crate::audit::bundle::AuditBundle;
+use crate::audit::sqlite::AuditEntry;
+use crate::handlers::{admin, audit, batch, breakglass, delegate, exchange, health, jwks, metrics, mint, preauth, proxy, refresh, verification, whoami};
+use crate::oidc::IdTokenClaims;
+use crate::ratelimit::SubjectRateState;
+use crate::telemetry::{MetricsSnapshot, RouteLatency, SubjectCount, TimedSnapshot};
+use crate::token::claims::Audience;
+use crate::token::offline::{VerificationBundle, VerificationConfig};
+use crate::webauthn;
+
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "AgentMint", description = "Cryptographic proof that a human authorized an AI agent action"),
+    paths(
+        health::health,
+        health::health_head,
+        health::ready,
+        verification::verification_bundle,
+        verification::pubkey,
+        jwks::jwks,
+        mint::mint,
+        batch::mint_batch,
+        breakglass::breakglass,
+        delegate::delegate,
+        exchange::exchange,
+        proxy::proxy,
+        proxy::proxy_external,
+        proxy::proxy_binary,
+        proxy::complete,
+        refresh::refresh,
+        preauth::preauth,
+        audit::recent,
+        audit::bundle,
+        metrics::metrics,
+        metrics::history,
+        metrics::latency,
+        admin::replay_offenders,
+        admin::revoke,
+        admin::signing_key_status,
+        admin::subject_rate,
+        admin::reset_subject_rate,
+        whoami::whoami,
+        webauthn::register_start,
+        webauthn::register_finish,
+        webauthn::auth_start,
+        webauthn::auth_finish,
+        webauthn::reenroll_start,
+    ),
+    components(schemas(
+        health::ReadyResponse,
+        mint::MintRequest,
+        mint::MintResponse,
+        mint::TokenFormat,
+        Audience,
+        batch::BatchMintRequest,
+        batch::BatchMintItem,
+        batch::BatchMintResponse,
+        breakglass::BreakglassRequest,
+        delegate::DelegateRequest,
+        delegate::DelegateResponse,
+        exchange::ExchangeRequest,
+        admin::RevokeRequest,
+        admin::RevokeResponse,
+        admin::AcceptedKid,
+        admin::SigningKeyStatus,
+        proxy::ProxyRequest,
+        proxy::ProxyResponse,
+        proxy::CompleteRequest,
+        proxy::CompleteResponse,
+        proxy::Outcome,
+        refresh::RefreshRequest,
+        preauth::PreauthRequest,
+        preauth::PreauthResponse,
+        whoami::WhoamiRequest,
+        whoami::WhoamiResponse,
+        IdTokenClaims,
+        AuditEntry,
+        AuditBundle,
+        VerificationBundle,
+        VerificationConfig,
+        jwks::Jwk,
+        jwks::JwkSet,
+        MetricsSnapshot,
+        TimedSnapshot,
+        RouteLatency,
+        SubjectCount,
+        SubjectRateState,
+        webauthn::RegStartReq,
+        webauthn::RegStartRes,
+        webauthn::RegFinishReq,
+        webauthn::AuthStartReq,
+        webauthn::AuthStartRes,
+        webauthn::AuthFinishReq,
+        webauthn::SuccessRes,
+    ))
+)]
+pub struct ApiDoc;
+
+pub fn spec() -> utoipa::openapi::OpenApi {
+    ApiDoc::openapi()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn schema_properties(name: &str) -> crate::error::Result<Vec<String>> {
+        let doc = serde_json::to_value(spec())?;
+        let props = doc["components"]["schemas"][name]["properties"]
+            .as_object()
+            .map(|p| p.keys().cloned().collect())
+            .unwrap_or_default();
+        Ok(props)
+    }
+
+    #[test]
+    fn mint_request_schema_has_fields() -> crate::error::Result<()> {
+        let props = schema_properties("MintRequest")?;
+        for field in ["sub", "action", "ttl_seconds", "id_token", "scope", "delegates_to", "requires_checkpoint", "max_delegation_depth"] {
+            assert!(props.iter().any(|p| p == field), "missing {field}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn mint_response_schema_has_fields() -> crate::error::Result<()> {
+        let props = schema_properties("MintResponse")?;
+        for field in ["token", "jti", "exp", "receipt_type"] {
+            assert!(props.iter().any(|p| p == field), "missing {field}");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn spec_covers_core_routes() -> crate::error::Result<()> {
+        let doc = serde_json::to_value(spec())?;
+        for path in ["/mint", "/proxy", "/audit", "/metrics", "/webauthn/auth/finish"] {
+            assert!(doc["paths"].get(path).is_some(), "missing {path}");
+        }
+        Ok(())
+    }
+}
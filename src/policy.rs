@@ -1,11 +1,48 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 const DEFAULT_PATH: &str = "policies.json";
+const DEFAULT_UNIT: &str = "USD";
+const DEFAULT_MIN_TTL: i64 = 5;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct PolicyLimit {
+    #[serde(default)]
+    pub max_amount: Option<u64>,
+    #[serde(default)]
+    pub default_ttl_seconds: Option<i64>,
+    #[serde(default)]
+    pub max_ttl_seconds: Option<i64>,
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// Mints of this action must present an id_token verified at mint time.
+    #[serde(default)]
+    pub require_oidc: bool,
+    /// Mints of this action must carry a human approver, from an id_token or a pre-authorization.
+    #[serde(default)]
+    pub require_approval: bool,
+    /// Plans may list `type:*` as a scope entry; on the `*` entry, a bare `*` scope.
+    #[serde(default)]
+    pub allow_wildcard_scope: bool,
+}
+
+/// A subject's own ceiling for one action type, replacing that type's `max_amount` for that subject only.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SubjectLimit {
     pub max_amount: u64,
+    /// Falls back to the action type's unit.
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// `policies.json`: limits by action type, plus an optional `subjects` section of per-`sub` overrides.
+#[derive(Deserialize)]
+struct PolicyFile {
+    #[serde(default)]
+    subjects: HashMap<String, HashMap<String, SubjectLimit>>,
+    #[serde(flatten)]
+    limits: HashMap<String, PolicyLimit>,
 }
 
 #[derive(Debug)]
@@ -13,36 +50,145 @@ pub struct Violation<'a> {
     pub action_type: &'a str,
     pub limit: u64,
     pub requested: u64,
+    pub unit: &'a str,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PolicyDenial {
+    pub action_type: String,
+    pub limit: u64,
+    pub requested: u64,
+    pub unit: String,
+}
+
+impl From<Violation<'_>> for PolicyDenial {
+    fn from(v: Violation<'_>) -> Self {
+        Self {
+            action_type: v.action_type.to_owned(),
+            limit: v.limit,
+            requested: v.requested,
+            unit: v.unit.to_owned(),
+        }
+    }
+}
+
+impl std::fmt::Display for PolicyDenial {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} limit is {} {}. Requested: {} {}",
+            self.action_type, self.limit, self.unit, self.requested, self.unit
+        )
+    }
+}
+
+/// What to do with a requested TTL below the configured floor.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BelowFloor {
+    Clamp,
+    Reject,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TtlFloor {
+    pub seconds: i64,
+    pub below: BelowFloor,
+}
+
+impl Default for TtlFloor {
+    fn default() -> Self {
+        Self { seconds: DEFAULT_MIN_TTL, below: BelowFloor::Clamp }
+    }
+}
+
+impl TtlFloor {
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> crate::error::Result<Self> {
+        let seconds = match get("MIN_TTL_SECONDS") {
+            Some(v) => v
+                .parse::<i64>()
+                .ok()
+                .filter(|s| *s >= 1)
+                .ok_or_else(|| crate::error::Error::Config(format!("MIN_TTL_SECONDS must be a positive integer, got {v}")))?,
+            None => DEFAULT_MIN_TTL,
+        };
+        let below = match get("MIN_TTL_MODE").as_deref() {
+            Some("reject") => BelowFloor::Reject,
+            Some("clamp") | None => BelowFloor::Clamp,
+            Some(other) => {
+                return Err(crate::error::Error::Config(format!("MIN_TTL_MODE must be clamp or reject, got {other}")));
+            }
+        };
+        Ok(Self { seconds, below })
+    }
 }
 
 #[derive(Debug, Clone, Default)]
 pub struct PolicyEngine {
     limits: HashMap<Box<str>, PolicyLimit>,
+    /// Overrides by `sub`, then action type.
+    subjects: HashMap<Box<str>, HashMap<Box<str>, SubjectLimit>>,
 }
 
 impl PolicyEngine {
+    /// Action types are keyed by `normalize_action`, the form mints are looked up in.
     pub fn new(limits: HashMap<Box<str>, PolicyLimit>) -> Self {
-        Self { limits }
+        Self { limits: limits.into_iter().map(|(k, v)| (normalized(&k), v)).collect(), subjects: HashMap::new() }
+    }
+
+    pub fn with_subject_limits(mut self, subjects: HashMap<Box<str>, HashMap<Box<str>, SubjectLimit>>) -> Self {
+        self.subjects = subjects
+            .into_iter()
+            .map(|(sub, by_type)| (sub, by_type.into_iter().map(|(k, v)| (normalized(&k), v)).collect()))
+            .collect();
+        self
     }
 
     pub fn from_file(path: &str) -> Result<Self, Error> {
         let content = std::fs::read_to_string(path)?;
-        let raw: HashMap<String, PolicyLimit> = serde_json::from_str(&content)?;
-        let limits = raw.into_iter().map(|(k, v)| (k.into_boxed_str(), v)).collect();
-        Ok(Self { limits })
+        Self::from_json(&content)
+    }
+
+    fn from_json(content: &str) -> Result<Self, Error> {
+        let raw: PolicyFile = serde_json::from_str(content)?;
+        let limits = normalized_keys(raw.limits)?;
+        let subjects = raw
+            .subjects
+            .into_iter()
+            .map(|(sub, by_type)| Ok((sub.into_boxed_str(), normalized_keys(by_type)?)))
+            .collect::<Result<_, Error>>()?;
+        Ok(Self { limits, subjects })
     }
 
     pub fn from_default_file() -> Self {
         Self::from_file(DEFAULT_PATH).unwrap_or_default()
     }
 
+    pub fn limit_for(&self, action: &str) -> Option<&PolicyLimit> {
+        self.limits.get(parse_action_type(action))
+    }
+
+    /// Whether a `*` or `type:*` scope entry is allowed; anything else is not a wildcard and passes.
+    pub fn allows_scope_wildcard(&self, entry: &str) -> bool {
+        if entry != "*" && !entry.ends_with(":*") {
+            return true;
+        }
+        self.limit_for(entry).is_some_and(|l| l.allow_wildcard_scope)
+    }
+
+    /// `sub`'s own limit for the action type wins over the action type's when lower; a higher one
+    /// applies only when `verified`, i.e. an OIDC id_token proved the caller is `sub`.
     #[inline]
-    pub fn check<'a>(&self, action: &'a str) -> Result<(), Violation<'a>> {
+    pub fn check<'a>(&'a self, sub: &str, verified: bool, action: &'a str) -> Result<(), Violation<'a>> {
         let action_type = parse_action_type(action);
-
-        let limit = match self.limits.get(action_type) {
-            Some(l) => l,
-            None => return Ok(()),
+        let limit = self.limits.get(action_type);
+        let unit = limit.and_then(|l| l.unit.as_deref());
+        let global = limit.and_then(|l| l.max_amount);
+
+        let (max_amount, unit) = match (self.subjects.get(sub).and_then(|s| s.get(action_type)), global) {
+            (Some(s), Some(g)) if !verified && s.max_amount > g => (g, unit),
+            (Some(s), _) => (s.max_amount, s.unit.as_deref().or(unit)),
+            (None, Some(g)) => (g, unit),
+            (None, None) => return Ok(()),
         };
 
         let amount = match parse_amount(action) {
@@ -50,11 +196,12 @@ impl PolicyEngine {
             None => return Ok(()),
         };
 
-        if amount > limit.max_amount {
+        if amount > max_amount {
             return Err(Violation {
                 action_type,
-                limit: limit.max_amount,
+                limit: max_amount,
                 requested: amount,
+                unit: unit.unwrap_or(DEFAULT_UNIT),
             });
         }
 
@@ -62,6 +209,26 @@ impl PolicyEngine {
     }
 }
 
+/// Trimmed and lowercased, so `Deploy ` and `deploy` hit the same policy entry.
+pub fn normalize_action(action: &str) -> String {
+    action.trim().to_ascii_lowercase()
+}
+
+fn normalized(key: &str) -> Box<str> {
+    normalize_action(key).into_boxed_str()
+}
+
+/// Action-type keys from `policies.json`, normalized; two that normalize alike are ambiguous.
+fn normalized_keys<V>(entries: HashMap<String, V>) -> Result<HashMap<Box<str>, V>, Error> {
+    let mut keys = HashMap::with_capacity(entries.len());
+    for (key, value) in entries {
+        if keys.insert(normalized(&key), value).is_some() {
+            return Err(Error::DuplicateKey(normalize_action(&key)));
+        }
+    }
+    Ok(keys)
+}
+
 #[inline]
 fn parse_action_type(action: &str) -> &str {
     match action.find(':') {
@@ -87,6 +254,7 @@ fn parse_amount(action: &str) -> Option<u64> {
 pub enum Error {
     Io(std::io::Error),
     Parse(serde_json::Error),
+    DuplicateKey(String),
 }
 
 impl From<std::io::Error> for Error {
@@ -106,6 +274,7 @@ impl std::fmt::Display for Error {
         match self {
             Self::Io(e) => write!(f, "io error: {}", e),
             Self::Parse(e) => write!(f, "parse error: {}", e),
+            Self::DuplicateKey(k) => write!(f, "policy for {} is listed twice, differing only in case or spacing", k),
         }
     }
 }
@@ -119,7 +288,7 @@ mod tests {
     fn engine(policies: &[(&str, u64)]) -> PolicyEngine {
         let limits = policies
             .iter()
-            .map(|(k, v)| (Box::from(*k), PolicyLimit { max_amount: *v }))
+            .map(|(k, v)| (Box::from(*k), PolicyLimit { max_amount: Some(*v), ..Default::default() }))
             .collect();
         PolicyEngine::new(limits)
     }
@@ -172,50 +341,155 @@ mod tests {
         }
     }
 
+    mod file_format {
+        use super::*;
+
+        #[test]
+        fn ttl_fields_are_optional() -> Result<(), Error> {
+            let raw: HashMap<String, PolicyLimit> = serde_json::from_str(
+                r#"{"refund": {"max_amount": 50}, "deploy": {"default_ttl_seconds": 10, "max_ttl_seconds": 30}}"#,
+            )?;
+            assert_eq!(raw["refund"].max_amount, Some(50));
+            assert_eq!(raw["refund"].default_ttl_seconds, None);
+            assert_eq!(raw["deploy"].max_amount, None);
+            assert_eq!(raw["deploy"].max_ttl_seconds, Some(30));
+            Ok(())
+        }
+
+        #[test]
+        fn subjects_section_is_optional_and_not_an_action_type() -> Result<(), Error> {
+            let plain = PolicyEngine::from_json(r#"{"refund": {"max_amount": 50}}"#)?;
+            assert!(plain.subjects.is_empty());
+            let e = PolicyEngine::from_json(
+                r#"{"refund": {"max_amount": 50}, "subjects": {"svc-billing": {"refund": {"max_amount": 500}}}}"#,
+            )?;
+            assert!(e.limit_for("subjects").is_none());
+            assert_eq!(e.subjects["svc-billing"]["refund"].max_amount, 500);
+            assert!(PolicyEngine::from_json(r#"{"subjects": {"svc": {"refund": {"max_ttl_seconds": 5}}}}"#).is_err());
+            Ok(())
+        }
+
+        #[test]
+        fn mixed_case_keys_normalized_at_load() -> Result<(), Error> {
+            let e = PolicyEngine::from_json(
+                r#"{" Refund": {"max_amount": 50}, "subjects": {"svc-billing": {"REFUND": {"max_amount": 500}}}}"#,
+            )?;
+            assert_eq!(e.check("agent-1", true, "refund:amount:51").unwrap_err().limit, 50);
+            assert!(e.check("svc-billing", true, "refund:amount:500").is_ok());
+            let clash = PolicyEngine::from_json(r#"{"refund": {"max_amount": 50}, "Refund": {"max_amount": 5000}}"#);
+            assert!(matches!(clash, Err(Error::DuplicateKey(k)) if k == "refund"));
+            Ok(())
+        }
+
+        #[test]
+        fn identity_flags_default_off() -> Result<(), Error> {
+            let raw: HashMap<String, PolicyLimit> =
+                serde_json::from_str(r#"{"refund": {"require_oidc": true}, "read": {}}"#)?;
+            assert!(raw["refund"].require_oidc && !raw["refund"].require_approval);
+            assert!(!raw["read"].require_oidc && !raw["read"].require_approval);
+            Ok(())
+        }
+    }
+
     mod check {
         use super::*;
 
         #[test]
         fn under_limit_passes() {
             let e = engine(&[("refund", 50)]);
-            assert!(e.check("refund:amount:49").is_ok());
-            assert!(e.check("refund:amount:50").is_ok());
+            assert!(e.check("agent-1", true, "refund:amount:49").is_ok());
+            assert!(e.check("agent-1", true, "refund:amount:50").is_ok());
         }
 
         #[test]
         fn over_limit_fails() {
             let e = engine(&[("refund", 50)]);
-            let err = e.check("refund:amount:51").unwrap_err();
+            let err = e.check("agent-1", true, "refund:amount:51").unwrap_err();
             assert_eq!(err.action_type, "refund");
             assert_eq!(err.limit, 50);
             assert_eq!(err.requested, 51);
+            assert_eq!(err.unit, "USD");
+        }
+
+        #[test]
+        fn violation_converts_to_denial() {
+            let e = engine(&[("refund", 50)]);
+            let denial = PolicyDenial::from(e.check("agent-1", true, "refund:amount:51").unwrap_err());
+            assert_eq!(denial.to_string(), "refund limit is 50 USD. Requested: 51 USD");
         }
 
         #[test]
         fn no_amount_passes() {
             let e = engine(&[("refund", 50)]);
-            assert!(e.check("refund:order:123").is_ok());
+            assert!(e.check("agent-1", true, "refund:order:123").is_ok());
         }
 
         #[test]
         fn unknown_action_passes() {
             let e = engine(&[("refund", 50)]);
-            assert!(e.check("deploy:amount:9999").is_ok());
+            assert!(e.check("agent-1", true, "deploy:amount:9999").is_ok());
         }
 
         #[test]
         fn empty_engine_passes() {
             let e = PolicyEngine::default();
-            assert!(e.check("refund:amount:9999").is_ok());
+            assert!(e.check("agent-1", true, "refund:amount:9999").is_ok());
+        }
+
+        #[test]
+        fn ttl_only_policy_passes_amount_check() {
+            let limits = [(Box::from("report"), PolicyLimit { default_ttl_seconds: Some(600), ..Default::default() })];
+            let e = PolicyEngine::new(limits.into_iter().collect());
+            assert!(e.check("agent-1", true, "report:amount:9999").is_ok());
+        }
+
+        fn with_override(sub: &str, action_type: &str, max_amount: u64) -> PolicyEngine {
+            let limit = SubjectLimit { max_amount, unit: None };
+            let subjects = [(Box::from(sub), [(Box::from(action_type), limit)].into_iter().collect())];
+            engine(&[("refund", 50)]).with_subject_limits(subjects.into_iter().collect())
+        }
+
+        #[test]
+        fn subject_override_raises_the_global_limit() {
+            let e = with_override("svc-billing", "refund", 500);
+            assert!(e.check("svc-billing", true, "refund:amount:500").is_ok());
+            let err = e.check("svc-billing", true, "refund:amount:501").unwrap_err();
+            assert_eq!((err.limit, err.unit), (500, "USD"));
+            assert_eq!(e.check("agent-1", true, "refund:amount:51").unwrap_err().limit, 50);
+        }
+
+        #[test]
+        fn subject_override_lowers_the_global_limit() {
+            let e = with_override("intern-bot", "refund", 10);
+            assert_eq!(e.check("intern-bot", true, "refund:amount:11").unwrap_err().limit, 10);
+            assert!(e.check("agent-1", true, "refund:amount:50").is_ok());
+        }
+
+        #[test]
+        fn subject_override_applies_without_a_global_limit() {
+            let e = with_override("svc-billing", "compute", 100);
+            assert!(e.check("svc-billing", true, "compute:amount:101").is_err());
+            assert!(e.check("agent-1", true, "compute:amount:101").is_ok());
+        }
+
+        #[test]
+        fn unverified_subject_cannot_use_a_raised_override() {
+            let e = with_override("svc-billing", "refund", 500);
+            let err = e.check("svc-billing", false, "refund:amount:51").unwrap_err();
+            assert_eq!((err.limit, err.unit), (50, "USD"));
+            let lowered = with_override("intern-bot", "refund", 10);
+            assert_eq!(lowered.check("intern-bot", false, "refund:amount:11").unwrap_err().limit, 10);
+            let uncapped = with_override("svc-billing", "compute", 100);
+            assert!(uncapped.check("svc-billing", false, "compute:amount:101").is_err());
         }
 
         #[test]
         fn multiple_policies() {
             let e = engine(&[("refund", 50), ("compute", 200)]);
-            assert!(e.check("refund:amount:50").is_ok());
-            assert!(e.check("compute:amount:200").is_ok());
-            assert!(e.check("refund:amount:51").is_err());
-            assert!(e.check("compute:amount:201").is_err());
+            assert!(e.check("agent-1", true, "refund:amount:50").is_ok());
+            assert!(e.check("agent-1", true, "compute:amount:200").is_ok());
+            assert!(e.check("agent-1", true, "refund:amount:51").is_err());
+            assert!(e.check("agent-1", true, "compute:amount:201").is_err());
         }
     }
 }
\ No newline at end of file
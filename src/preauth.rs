@@ -0,0 +1,167 @@
+//! Pre-authorized action batches: one approval covering a declared list of future mints.
+//! Used by: handlers::mint, handlers::preauth, state.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::RngCore;
+
+use crate::error::{Error, Result, lock_err};
+
+const DEFAULT_MAX_CAPACITY: usize = 10_000;
+pub const MAX_ACTIONS: usize = 50;
+
+pub struct PreauthStore {
+    batches: Mutex<HashMap<String, PreauthBatch>>,
+    max_capacity: usize,
+    /// Expired batches are kept this long before eviction, matching the token expiry leeway.
+    retain_past_expiry: i64,
+}
+
+/// The verified identity that approved a batch, and when its id_token stops vouching for it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Approval {
+    pub approved_by: String,
+    pub id_token_exp: i64,
+}
+
+struct PreauthBatch {
+    sub: String,
+    approval: Option<Approval>,
+    remaining: Vec<String>,
+    expires_at: i64,
+}
+
+fn generate_preauth_id() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+/// The live batch `id` for `sub`, and where `action` sits in its remaining list.
+fn find<'a>(batches: &'a mut HashMap<String, PreauthBatch>, id: &str, sub: &str, action: &str) -> Result<(&'a mut PreauthBatch, usize)> {
+    let batch = batches
+        .get_mut(id)
+        .filter(|batch| batch.expires_at > now())
+        .ok_or_else(|| Error::Unauthorized("unknown or expired pre-authorization".into()))?;
+    if batch.sub != sub {
+        return Err(Error::Unauthorized("pre-authorization belongs to a different sub".into()));
+    }
+    let Some(pos) = batch.remaining.iter().position(|a| a == action) else {
+        return Err(Error::Unauthorized(format!("action {} not pre-authorized", action)));
+    };
+    Ok((batch, pos))
+}
+
+impl PreauthStore {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAX_CAPACITY)
+    }
+
+    pub fn with_capacity(max_capacity: usize) -> Self {
+        Self {
+            batches: Mutex::new(HashMap::new()),
+            max_capacity,
+            retain_past_expiry: 0,
+        }
+    }
+
+    pub fn retaining_past_expiry(mut self, leeway: std::time::Duration) -> Self {
+        self.retain_past_expiry = i64::try_from(leeway.as_secs()).unwrap_or(i64::MAX);
+        self
+    }
+
+    /// Every mint drawn from the batch carries `approval`, and is capped to its id_token like a direct mint.
+    pub fn issue(&self, sub: &str, approval: Option<Approval>, actions: Vec<String>, expires_at: i64) -> Result<String> {
+        if actions.is_empty() || actions.len() > MAX_ACTIONS {
+            return Err(Error::Validation(format!("actions must contain 1-{} entries", MAX_ACTIONS)));
+        }
+        let mut batches = self.batches.lock().map_err(lock_err("preauth"))?;
+        let cutoff = now().saturating_sub(self.retain_past_expiry);
+        batches.retain(|_, batch| batch.expires_at > cutoff);
+        if batches.len() >= self.max_capacity {
+            return Err(Error::CapacityExceeded("pre-authorization store at capacity".into()));
+        }
+        let id = generate_preauth_id();
+        batches.insert(id.clone(), PreauthBatch { sub: sub.into(), approval, remaining: actions, expires_at });
+        Ok(id)
+    }
+
+    /// The batch's approval if `action` is still pre-authorized for `sub`; nothing is drawn down.
+    pub fn peek(&self, id: &str, sub: &str, action: &str) -> Result<Option<Approval>> {
+        let mut batches = self.batches.lock().map_err(lock_err("preauth"))?;
+        let (batch, _) = find(&mut batches, id, sub, action)?;
+        Ok(batch.approval.clone())
+    }
+
+    /// Draws down one action and returns the batch's approval.
+    pub fn consume(&self, id: &str, sub: &str, action: &str) -> Result<Option<Approval>> {
+        let mut batches = self.batches.lock().map_err(lock_err("preauth"))?;
+        let (batch, pos) = find(&mut batches, id, sub, action)?;
+        batch.remaining.swap_remove(pos);
+        let approval = batch.approval.clone();
+        if batch.remaining.is_empty() {
+            batches.remove(id);
+        }
+        Ok(approval)
+    }
+
+    pub fn remaining(&self, id: &str) -> Result<usize> {
+        let batches = self.batches.lock().map_err(lock_err("preauth"))?;
+        Ok(batches.get(id).map_or(0, |batch| batch.remaining.len()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn actions(list: &[&str]) -> Vec<String> {
+        list.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn each_action_consumed_once() -> Result<()> {
+        let store = PreauthStore::new();
+        let id = store.issue("agent-1", None, actions(&["refund:amount:30", "refund:amount:30"]), now() + 60)?;
+        store.peek(&id, "agent-1", "refund:amount:30")?;
+        assert_eq!(store.remaining(&id)?, 2);
+        store.consume(&id, "agent-1", "refund:amount:30")?;
+        assert_eq!(store.remaining(&id)?, 1);
+        store.consume(&id, "agent-1", "refund:amount:30")?;
+        assert_eq!(store.remaining(&id)?, 0);
+        assert!(matches!(store.consume(&id, "agent-1", "refund:amount:30"), Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn other_sub_cannot_draw_down() -> Result<()> {
+        let store = PreauthStore::new();
+        let id = store.issue("agent-1", None, actions(&["deploy"]), now() + 60)?;
+        assert!(matches!(store.consume(&id, "agent-2", "deploy"), Err(Error::Unauthorized(_))));
+        assert_eq!(store.remaining(&id)?, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn expired_batch_rejected() -> Result<()> {
+        let store = PreauthStore::new();
+        let id = store.issue("agent-1", None, actions(&["deploy"]), now() - 1)?;
+        assert!(matches!(store.consume(&id, "agent-1", "deploy"), Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn empty_or_oversized_batch_rejected() {
+        let store = PreauthStore::new();
+        assert!(matches!(store.issue("agent-1", None, vec![], now() + 60), Err(Error::Validation(_))));
+        let many = vec!["deploy".to_string(); MAX_ACTIONS + 1];
+        assert!(matches!(store.issue("agent-1", None, many, now() + 60), Err(Error::Validation(_))));
+    }
+}
@@ -1,37 +1,173 @@
-//! Rate limiting with global, per-IP, and per-user limits.
+//! Rate limiting with global and opt-in per-IP limits (each with separate read and write budgets)
+//! and per-user limits, plus batch size and concurrency caps and a cap on concurrent signing and verification.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::IpAddr;
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
-const WINDOW: Duration = Duration::from_secs(60);
+use serde::Serialize;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use utoipa::ToSchema;
+
+use crate::error::{lock_err, Error};
+use crate::ipfilter::IpAllowlist;
+
+pub(crate) const WINDOW: Duration = Duration::from_secs(60);
 const CLEANUP_INTERVAL: Duration = Duration::from_secs(300);
+pub(crate) const DEFAULT_MAX_BATCH_SIZE: usize = 100;
+pub(crate) const DEFAULT_BATCH_CONCURRENCY: usize = 4;
+const CRYPTO_QUEUE_WAIT: Duration = Duration::from_millis(250);
+
+/// Which global and per-IP budget a request draws on. Verification traffic runs far hotter
+/// than minting, so the two are counted apart and neither can starve the other.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RequestClass {
+    /// `/proxy`, `/audit`, `/metrics`.
+    Read,
+    /// Token issuance, `/delegate`, `/revoke`, and WebAuthn ceremonies.
+    Write,
+}
 
 pub struct RateLimiter {
     config: RateLimitConfig,
     state: Mutex<RateLimitState>,
 }
 
+#[derive(Debug, Clone, PartialEq)]
 pub struct RateLimitConfig {
-    pub global_per_sec: u32,
-    pub per_ip_per_min: u32,
+    pub global_read_per_sec: u32,
+    pub global_write_per_sec: u32,
+    /// `None` leaves the class unlimited per IP: clients behind one NAT or proxy share an address.
+    pub read_per_ip_per_min: Option<u32>,
+    pub write_per_ip_per_min: Option<u32>,
     pub per_user_per_min: u32,
+    /// `RATE_LIMIT_MAX_TRACKED`: IPs (per class) and users with an open window; the least
+    /// recently seen is evicted, getting a fresh window if it returns.
+    pub max_tracked: usize,
+    pub exemptions: RateExemptions,
 }
 
 impl Default for RateLimitConfig {
     fn default() -> Self {
         Self {
-            global_per_sec: 1000,
-            per_ip_per_min: 100,
+            global_read_per_sec: 1000,
+            global_write_per_sec: 100,
+            read_per_ip_per_min: None,
+            write_per_ip_per_min: None,
             per_user_per_min: 20,
+            max_tracked: 100_000,
+            exemptions: RateExemptions::default(),
+        }
+    }
+}
+
+impl RateLimitConfig {
+    /// `RATE_LIMIT_GLOBAL_READ_PER_SEC` and `RATE_LIMIT_GLOBAL_WRITE_PER_SEC` override the global
+    /// budgets; `RATE_LIMIT_READ_PER_MIN` and `RATE_LIMIT_WRITE_PER_MIN` turn on the per-IP ones.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> crate::error::Result<Self> {
+        let positive = |name: &str| -> crate::error::Result<Option<u32>> {
+            let Some(v) = get(name) else {
+                return Ok(None);
+            };
+            match v.parse::<u32>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(Error::Config(format!("{name} must be a positive integer, got {v:?}"))),
+            }
+        };
+        let max_tracked = positive("RATE_LIMIT_MAX_TRACKED")?.map(|n| n as usize);
+        let defaults = Self::default();
+        Ok(Self {
+            global_read_per_sec: positive("RATE_LIMIT_GLOBAL_READ_PER_SEC")?.unwrap_or(defaults.global_read_per_sec),
+            global_write_per_sec: positive("RATE_LIMIT_GLOBAL_WRITE_PER_SEC")?.unwrap_or(defaults.global_write_per_sec),
+            read_per_ip_per_min: positive("RATE_LIMIT_READ_PER_MIN")?,
+            write_per_ip_per_min: positive("RATE_LIMIT_WRITE_PER_MIN")?,
+            max_tracked: max_tracked.unwrap_or(defaults.max_tracked),
+            exemptions: RateExemptions::from_lookup(get)?,
+            ..defaults
+        })
+    }
+
+    fn global_per_sec(&self, class: RequestClass) -> u32 {
+        match class {
+            RequestClass::Read => self.global_read_per_sec,
+            RequestClass::Write => self.global_write_per_sec,
+        }
+    }
+
+    fn per_ip_per_min(&self, class: RequestClass) -> Option<u32> {
+        match class {
+            RequestClass::Read => self.read_per_ip_per_min,
+            RequestClass::Write => self.write_per_ip_per_min,
+        }
+    }
+}
+
+/// Trusted callers (health-checkers, orchestrators) that skip every limit, including the global one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RateExemptions {
+    ips: Option<IpAllowlist>,
+    subjects: HashSet<String>,
+}
+
+impl RateExemptions {
+    pub fn parse(ips: Option<&str>, subjects: Option<&str>) -> crate::error::Result<Self> {
+        Ok(Self {
+            ips: ips.map(IpAllowlist::parse).transpose()?,
+            subjects: subjects
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(String::from)
+                .collect(),
+        })
+    }
+
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> crate::error::Result<Self> {
+        let read = |name: &str| get(name).filter(|v| !v.trim().is_empty());
+        let exemptions = Self::parse(read("RATE_EXEMPT_IPS").as_deref(), read("RATE_EXEMPT_SUBJECTS").as_deref())?;
+        if exemptions.ips.is_some() || !exemptions.subjects.is_empty() {
+            tracing::info!(subjects = exemptions.subjects.len(), ips = exemptions.ips.is_some(), "rate-limit exemptions enabled");
         }
+        Ok(exemptions)
+    }
+
+    fn ip(&self, ip: &str) -> bool {
+        let Some(list) = &self.ips else { return false };
+        ip.parse::<IpAddr>().is_ok_and(|ip| list.contains(ip))
+    }
+
+    fn subject(&self, sub: &str) -> bool {
+        self.subjects.contains(sub)
     }
 }
 
+/// A subject's position in its per-user window, for support debugging.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SubjectRateState {
+    pub sub: String,
+    pub count: u32,
+    pub limit: u32,
+    pub window_secs: u64,
+    /// Seconds until the count resets; 0 when no window is open.
+    pub resets_in_secs: u64,
+    pub exempt: bool,
+}
+
+/// Outcome of an allowed request; `Exempt` means no counter was touched.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    Counted,
+    Exempt,
+}
+
 struct RateLimitState {
-    ip_counts: HashMap<Box<str>, WindowCounter>,
-    user_counts: HashMap<Box<str>, WindowCounter>,
-    global_count: WindowCounter,
+    read_ip_counts: TrackedCounters,
+    write_ip_counts: TrackedCounters,
+    user_counts: TrackedCounters,
+    global_read_count: WindowCounter,
+    global_write_count: WindowCounter,
     last_cleanup: Instant,
 }
 
@@ -56,49 +192,122 @@ impl WindowCounter {
     }
 }
 
+/// Window counters by key, bounded by evicting the least recently seen in O(log n).
+#[derive(Default)]
+struct TrackedCounters {
+    entries: HashMap<Box<str>, (WindowCounter, u64)>,
+    /// Last-use tick to key; the first entry is the eviction candidate.
+    order: BTreeMap<u64, Box<str>>,
+    tick: u64,
+}
+
+impl TrackedCounters {
+    /// Counter for `key`, evicting the least recently seen entry when `max_tracked` are held.
+    fn counter(&mut self, key: &str, max_tracked: usize) -> &mut WindowCounter {
+        self.tick += 1;
+        let tick = self.tick;
+        match self.entries.get(key) {
+            Some((_, last)) => {
+                self.order.remove(last);
+            }
+            None if self.entries.len() >= max_tracked => {
+                if let Some((_, lru)) = self.order.pop_first() {
+                    self.entries.remove(&lru);
+                }
+            }
+            None => {}
+        }
+        self.order.insert(tick, key.into());
+        let (counter, last) = self.entries.entry(key.into()).or_insert_with(|| (WindowCounter::new(), tick));
+        *last = tick;
+        counter
+    }
+
+    fn get(&self, key: &str) -> Option<&WindowCounter> {
+        self.entries.get(key).map(|(counter, _)| counter)
+    }
+
+    fn remove(&mut self, key: &str) -> bool {
+        let Some((_, last)) = self.entries.remove(key) else {
+            return false;
+        };
+        self.order.remove(&last);
+        true
+    }
+
+    fn retain(&mut self, keep: impl Fn(&WindowCounter) -> bool) {
+        let order = &mut self.order;
+        self.entries.retain(|_, (counter, last)| {
+            let kept = keep(counter);
+            if !kept {
+                order.remove(last);
+            }
+            kept
+        });
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+}
+
 impl RateLimiter {
     pub fn new(config: RateLimitConfig) -> Self {
         Self {
             config,
             state: Mutex::new(RateLimitState {
-                ip_counts: HashMap::new(),
-                user_counts: HashMap::new(),
-                global_count: WindowCounter::new(),
+                read_ip_counts: TrackedCounters::default(),
+                write_ip_counts: TrackedCounters::default(),
+                user_counts: TrackedCounters::default(),
+                global_read_count: WindowCounter::new(),
+                global_write_count: WindowCounter::new(),
                 last_cleanup: Instant::now(),
             }),
         }
     }
 
-    pub fn check_ip(&self, ip: &str) -> Result<(), RateLimitError> {
+    pub fn check_ip(&self, ip: &str, class: RequestClass) -> Result<Admission, RateLimitError> {
+        if self.config.exemptions.ip(ip) {
+            tracing::debug!(ip, "rate limit skipped for exempt IP");
+            return Ok(Admission::Exempt);
+        }
         let mut state = self.state.lock().unwrap();
         self.maybe_cleanup(&mut state);
 
-        // Global check (per second)
-        if !state.global_count.increment(self.config.global_per_sec, Duration::from_secs(1)) {
+        // Global check (per second), against the budget for this class
+        let global = match class {
+            RequestClass::Read => &mut state.global_read_count,
+            RequestClass::Write => &mut state.global_write_count,
+        };
+        if !global.increment(self.config.global_per_sec(class), Duration::from_secs(1)) {
             return Err(RateLimitError::Global);
         }
 
-        // Per-IP check (per minute)
-        let counter = state.ip_counts
-            .entry(ip.into())
-            .or_insert_with(WindowCounter::new);
-
-        if !counter.increment(self.config.per_ip_per_min, WINDOW) {
+        // Per-IP check (per minute), when enabled for this class
+        let Some(limit) = self.config.per_ip_per_min(class) else {
+            return Ok(Admission::Counted);
+        };
+        let counts = match class {
+            RequestClass::Read => &mut state.read_ip_counts,
+            RequestClass::Write => &mut state.write_ip_counts,
+        };
+        let counter = counts.counter(ip, self.config.max_tracked);
+
+        if !counter.increment(limit, WINDOW) {
             return Err(RateLimitError::PerIp {
-                limit: self.config.per_ip_per_min,
+                limit,
                 window_secs: WINDOW.as_secs(),
             });
         }
 
-        Ok(())
+        Ok(Admission::Counted)
     }
 
-    pub fn check_user(&self, user_id: &str) -> Result<(), RateLimitError> {
+    /// `user_id` is whatever the caller claims, so `RATE_EXEMPT_SUBJECTS` never applies here.
+    pub fn check_user(&self, user_id: &str) -> Result<Admission, RateLimitError> {
         let mut state = self.state.lock().unwrap();
 
-        let counter = state.user_counts
-            .entry(user_id.into())
-            .or_insert_with(WindowCounter::new);
+        let counter = state.user_counts.counter(user_id, self.config.max_tracked);
 
         if !counter.increment(self.config.per_user_per_min, WINDOW) {
             return Err(RateLimitError::PerUser {
@@ -107,15 +316,42 @@ impl RateLimiter {
             });
         }
 
-        Ok(())
+        Ok(Admission::Counted)
+    }
+
+    pub fn subject_state(&self, sub: &str) -> Result<SubjectRateState, Error> {
+        let state = self.state.lock().map_err(lock_err("ratelimit"))?;
+        let open = state
+            .user_counts
+            .get(sub)
+            .map(|c| (c.count, c.window_start.elapsed()))
+            .filter(|(_, elapsed)| *elapsed <= WINDOW);
+        Ok(SubjectRateState {
+            sub: sub.to_owned(),
+            count: open.map_or(0, |(count, _)| count),
+            limit: self.config.per_user_per_min,
+            window_secs: WINDOW.as_secs(),
+            resets_in_secs: open.map_or(0, |(_, elapsed)| WINDOW.saturating_sub(elapsed).as_secs()),
+            exempt: self.is_exempt_subject(sub),
+        })
+    }
+
+    /// Drops the subject's window so its next request starts a fresh count. Returns whether one existed.
+    pub fn reset_subject(&self, sub: &str) -> Result<bool, Error> {
+        Ok(self.state.lock().map_err(lock_err("ratelimit"))?.user_counts.remove(sub))
+    }
+
+    pub fn is_exempt_subject(&self, sub: &str) -> bool {
+        self.config.exemptions.subject(sub)
     }
 
     fn maybe_cleanup(&self, state: &mut RateLimitState) {
         let now = Instant::now();
         if now.duration_since(state.last_cleanup) > CLEANUP_INTERVAL {
             let cutoff = now - WINDOW - Duration::from_secs(60);
-            state.ip_counts.retain(|_, c| c.window_start > cutoff);
-            state.user_counts.retain(|_, c| c.window_start > cutoff);
+            state.read_ip_counts.retain(|c| c.window_start > cutoff);
+            state.write_ip_counts.retain(|c| c.window_start > cutoff);
+            state.user_counts.retain(|c| c.window_start > cutoff);
             state.last_cleanup = now;
         }
     }
@@ -123,7 +359,7 @@ impl RateLimiter {
     #[allow(dead_code)]
     pub fn stats(&self) -> (usize, usize) {
         let state = self.state.lock().unwrap();
-        (state.ip_counts.len(), state.user_counts.len())
+        (state.read_ip_counts.len() + state.write_ip_counts.len(), state.user_counts.len())
     }
 }
 
@@ -148,55 +384,138 @@ impl std::fmt::Display for RateLimitError {
     }
 }
 
+/// Per-request batch size cap, and a process-wide cap on batch items in flight so
+/// large batches cannot starve single-item requests.
+pub struct BatchLimits {
+    pub max_size: usize,
+    permits: Semaphore,
+}
+
+impl Default for BatchLimits {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_BATCH_SIZE, DEFAULT_BATCH_CONCURRENCY)
+    }
+}
+
+impl BatchLimits {
+    pub fn new(max_size: usize, concurrency: usize) -> Self {
+        Self { max_size, permits: Semaphore::new(concurrency.max(1)) }
+    }
+
+    pub fn check_size(&self, len: usize) -> crate::error::Result<()> {
+        match len {
+            0 => Err(Error::Validation("batch must not be empty".into())),
+            n if n > self.max_size => Err(Error::Validation(format!("batch of {n} exceeds MAX_BATCH_SIZE {}", self.max_size))),
+            _ => Ok(()),
+        }
+    }
+
+    pub async fn acquire(&self) -> crate::error::Result<SemaphorePermit<'_>> {
+        self.permits
+            .acquire()
+            .await
+            .map_err(|_| Error::ServiceUnavailable("batch limiter closed".into()))
+    }
+}
+
+/// Process-wide cap on requests that sign or verify tokens, so a burst of CPU-bound crypto
+/// cannot take every core from `/health`, `/metrics`, and the rest.
+pub struct CryptoLimits {
+    permits: Semaphore,
+}
+
+/// One permit short of the CPU count, so at least one tokio worker stays free for everything else.
+impl Default for CryptoLimits {
+    fn default() -> Self {
+        Self::new(std::thread::available_parallelism().map(usize::from).unwrap_or(1).saturating_sub(1))
+    }
+}
+
+impl CryptoLimits {
+    pub fn new(concurrency: usize) -> Self {
+        Self { permits: Semaphore::new(concurrency.max(1)) }
+    }
+
+    /// Queues for up to `CRYPTO_QUEUE_WAIT`, then sheds.
+    pub async fn acquire(&self) -> crate::error::Result<SemaphorePermit<'_>> {
+        let shed = || Error::ServiceUnavailable("signing and verification are at capacity".into());
+        match tokio::time::timeout(CRYPTO_QUEUE_WAIT, self.permits.acquire()).await {
+            Ok(permit) => permit.map_err(|_| shed()),
+            Err(_) => Err(shed()),
+        }
+    }
+
+    pub fn try_acquire(&self) -> crate::error::Result<SemaphorePermit<'_>> {
+        self.permits
+            .try_acquire()
+            .map_err(|_| Error::ServiceUnavailable("signing and verification are at capacity".into()))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[tokio::test]
+    async fn crypto_permits_queue_briefly_then_shed() -> crate::error::Result<()> {
+        let limits = std::sync::Arc::new(CryptoLimits::new(2));
+        let first = limits.acquire().await?;
+        let _second = limits.acquire().await?;
+        assert!(matches!(limits.acquire().await, Err(Error::ServiceUnavailable(_))));
+
+        let waiter = {
+            let limits = limits.clone();
+            tokio::spawn(async move { limits.acquire().await.map(drop) })
+        };
+        tokio::time::sleep(CRYPTO_QUEUE_WAIT / 4).await;
+        drop(first);
+        waiter.await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?
+    }
+
     #[test]
     fn allows_under_limit() {
         let limiter = RateLimiter::new(RateLimitConfig {
-            global_per_sec: 1000,
-            per_ip_per_min: 5,
+            write_per_ip_per_min: Some(5),
             per_user_per_min: 5,
+            ..RateLimitConfig::default()
         });
 
         for _ in 0..5 {
-            assert!(limiter.check_ip("127.0.0.1").is_ok());
+            assert!(limiter.check_ip("127.0.0.1", RequestClass::Write).is_ok());
         }
     }
 
     #[test]
     fn blocks_over_limit() {
         let limiter = RateLimiter::new(RateLimitConfig {
-            global_per_sec: 1000,
-            per_ip_per_min: 2,
+            write_per_ip_per_min: Some(2),
             per_user_per_min: 5,
+            ..RateLimitConfig::default()
         });
 
-        assert!(limiter.check_ip("127.0.0.1").is_ok());
-        assert!(limiter.check_ip("127.0.0.1").is_ok());
-        assert!(limiter.check_ip("127.0.0.1").is_err());
+        assert!(limiter.check_ip("127.0.0.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("127.0.0.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("127.0.0.1", RequestClass::Write).is_err());
     }
 
     #[test]
     fn separate_ips_have_separate_limits() {
         let limiter = RateLimiter::new(RateLimitConfig {
-            global_per_sec: 1000,
-            per_ip_per_min: 1,
+            write_per_ip_per_min: Some(1),
             per_user_per_min: 5,
+            ..RateLimitConfig::default()
         });
 
-        assert!(limiter.check_ip("1.1.1.1").is_ok());
-        assert!(limiter.check_ip("1.1.1.1").is_err());
-        assert!(limiter.check_ip("2.2.2.2").is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_err());
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
     }
 
     #[test]
     fn user_rate_limit() {
         let limiter = RateLimiter::new(RateLimitConfig {
-            global_per_sec: 1000,
-            per_ip_per_min: 100,
             per_user_per_min: 2,
+            ..RateLimitConfig::default()
         });
 
         assert!(limiter.check_user("alice").is_ok());
@@ -204,4 +523,146 @@ mod tests {
         assert!(limiter.check_user("alice").is_err());
         assert!(limiter.check_user("bob").is_ok());
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn exempt_ip_never_throttled() -> crate::error::Result<()> {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            write_per_ip_per_min: Some(2),
+            exemptions: RateExemptions::parse(Some("10.0.0.0/8"), None)?,
+            ..RateLimitConfig::default()
+        });
+
+        for _ in 0..10 {
+            assert!(matches!(limiter.check_ip("10.1.2.3", RequestClass::Write), Ok(Admission::Exempt)));
+        }
+        assert!(matches!(limiter.check_ip("192.0.2.1", RequestClass::Write), Ok(Admission::Counted)));
+        assert!(limiter.check_ip("192.0.2.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("192.0.2.1", RequestClass::Write).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn claimed_exempt_subject_still_throttled_per_user() -> crate::error::Result<()> {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            per_user_per_min: 1,
+            exemptions: RateExemptions::parse(None, Some("healthcheck, orchestrator"))?,
+            ..RateLimitConfig::default()
+        });
+
+        assert!(limiter.is_exempt_subject("orchestrator"));
+        assert!(matches!(limiter.check_user("orchestrator"), Ok(Admission::Counted)));
+        assert!(limiter.check_user("orchestrator").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn subject_state_reflects_window_and_reset_clears_it() -> Result<(), Error> {
+        let limiter = RateLimiter::new(RateLimitConfig { per_user_per_min: 2, ..RateLimitConfig::default() });
+
+        assert_eq!(limiter.subject_state("alice")?.count, 0);
+        for _ in 0..3 {
+            let _ = limiter.check_user("alice");
+        }
+        let state = limiter.subject_state("alice")?;
+        assert_eq!((state.count, state.limit), (3, 2));
+        assert!(state.resets_in_secs > 0);
+
+        assert!(limiter.reset_subject("alice")?);
+        assert!(!limiter.reset_subject("alice")?);
+        assert!(limiter.check_user("alice").is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn tracked_ips_bounded() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            global_write_per_sec: 1000,
+            write_per_ip_per_min: Some(100),
+            max_tracked: 50,
+            ..RateLimitConfig::default()
+        });
+
+        for i in 0..1000 {
+            assert!(limiter.check_ip(&format!("10.0.{}.{}", i / 256, i % 256), RequestClass::Write).is_ok());
+        }
+        assert_eq!(limiter.stats().0, 50);
+    }
+
+    #[test]
+    fn evicted_ip_gets_fresh_window() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            write_per_ip_per_min: Some(1),
+            max_tracked: 2,
+            ..RateLimitConfig::default()
+        });
+
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_err());
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("3.3.3.3", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+    }
+
+    #[test]
+    fn least_recently_seen_ip_evicted_first() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            write_per_ip_per_min: Some(1),
+            max_tracked: 2,
+            ..RateLimitConfig::default()
+        });
+
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_err());
+        assert!(limiter.check_ip("3.3.3.3", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_err());
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
+    }
+
+    #[test]
+    fn saturated_reads_leave_write_budget_intact() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            read_per_ip_per_min: Some(3),
+            write_per_ip_per_min: Some(2),
+            ..RateLimitConfig::default()
+        });
+
+        for _ in 0..3 {
+            assert!(limiter.check_ip("1.1.1.1", RequestClass::Read).is_ok());
+        }
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Read).is_err());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        assert!(matches!(
+            limiter.check_ip("1.1.1.1", RequestClass::Write),
+            Err(RateLimitError::PerIp { limit: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn per_ip_limits_are_opt_in() {
+        let limiter = RateLimiter::new(RateLimitConfig::default());
+
+        for _ in 0..RateLimitConfig::default().global_write_per_sec {
+            assert!(limiter.check_ip("1.1.1.1", RequestClass::Write).is_ok());
+        }
+        assert_eq!(limiter.stats().0, 0);
+    }
+
+    #[test]
+    fn writes_have_their_own_global_budget() {
+        let limiter = RateLimiter::new(RateLimitConfig {
+            global_read_per_sec: 3,
+            global_write_per_sec: 2,
+            ..RateLimitConfig::default()
+        });
+
+        for _ in 0..3 {
+            assert!(limiter.check_ip("1.1.1.1", RequestClass::Read).is_ok());
+        }
+        assert!(matches!(limiter.check_ip("2.2.2.2", RequestClass::Read), Err(RateLimitError::Global)));
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
+        assert!(limiter.check_ip("2.2.2.2", RequestClass::Write).is_ok());
+        assert!(matches!(limiter.check_ip("3.3.3.3", RequestClass::Write), Err(RateLimitError::Global)));
+    }
+}
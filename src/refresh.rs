@@ -0,0 +1,242 @@
+//! Server-side refresh tokens with single-use rotation and reuse detection.
+//! Used by: handlers::mint, handlers::refresh, state.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use rand::RngCore;
+
+use crate::error::{Error, Result, lock_err};
+use crate::token::claims::Claims;
+
+const DEFAULT_MAX_CAPACITY: usize = 100_000;
+const REFRESH_TTL_SECONDS: i64 = 3600;
+const MAX_FAMILY_AGE_SECONDS: i64 = 86_400;
+
+pub struct RefreshStore {
+    state: Mutex<RefreshState>,
+    max_capacity: usize,
+    /// Rotated tokens are remembered this long past expiry, so late reuse is still detected.
+    retain_past_expiry: i64,
+}
+
+#[derive(Default)]
+struct RefreshState {
+    tokens: HashMap<String, RefreshEntry>,
+    revoked_families: HashSet<String>,
+    /// The family of every access token issued from a refresh token, by `jti`.
+    families_by_jti: HashMap<String, String>,
+}
+
+struct RefreshEntry {
+    family: String,
+    template: Claims,
+    access_ttl: i64,
+    family_started: i64,
+    expires_at: i64,
+    rotated: bool,
+}
+
+pub struct RefreshGrant {
+    /// The next access token's claims: the minted template renewed under a fresh `jti`.
+    pub claims: Claims,
+    pub refresh_token: String,
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+fn now() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+impl RefreshStore {
+    pub fn new() -> Self {
+        Self::with_capacity(DEFAULT_MAX_CAPACITY)
+    }
+
+    pub fn with_capacity(max_capacity: usize) -> Self {
+        Self {
+            state: Mutex::new(RefreshState::default()),
+            max_capacity,
+            retain_past_expiry: 0,
+        }
+    }
+
+    pub fn retaining_past_expiry(mut self, leeway: std::time::Duration) -> Self {
+        self.retain_past_expiry = i64::try_from(leeway.as_secs()).unwrap_or(i64::MAX);
+        self
+    }
+
+    /// Starts a family named after the minted token's `jti`.
+    pub fn issue(&self, template: &Claims, access_ttl: i64) -> Result<String> {
+        let mut state = self.state.lock().map_err(lock_err("refresh"))?;
+        let family = template.jti.clone();
+        let token = self.insert(&mut state, family.clone(), template.clone(), access_ttl, now())?;
+        state.families_by_jti.insert(template.jti.clone(), family);
+        Ok(token)
+    }
+
+    /// Stops the family that issued `jti`, if any; returns whether there was one.
+    pub fn revoke_family_of(&self, jti: &str) -> Result<bool> {
+        let mut state = self.state.lock().map_err(lock_err("refresh"))?;
+        let Some(family) = state.families_by_jti.get(jti).cloned() else {
+            return Ok(false);
+        };
+        state.revoked_families.insert(family);
+        Ok(true)
+    }
+
+    pub fn rotate(&self, refresh_token: &str) -> Result<RefreshGrant> {
+        let mut guard = self.state.lock().map_err(lock_err("refresh"))?;
+        let state = &mut *guard;
+        let entry = state
+            .tokens
+            .get_mut(refresh_token)
+            .ok_or_else(|| Error::Unauthorized("unknown refresh token".into()))?;
+
+        if state.revoked_families.contains(&entry.family) {
+            return Err(Error::Unauthorized("refresh token revoked".into()));
+        }
+        if entry.rotated {
+            state.revoked_families.insert(entry.family.clone());
+            return Err(Error::ReplayDetected(format!("refresh token reuse in family {}", entry.family)));
+        }
+        if entry.expires_at <= now() {
+            return Err(Error::Unauthorized("refresh token expired".into()));
+        }
+
+        entry.rotated = true;
+        let family = entry.family.clone();
+        let template = entry.template.clone();
+        let access_ttl = entry.access_ttl;
+        let family_started = entry.family_started;
+
+        let claims = template.renewed(access_ttl);
+        let refresh_token = self.insert(state, family.clone(), template, access_ttl, family_started)?;
+        state.families_by_jti.insert(claims.jti.clone(), family);
+        Ok(RefreshGrant { claims, refresh_token })
+    }
+
+    fn insert(
+        &self,
+        state: &mut RefreshState,
+        family: String,
+        template: Claims,
+        access_ttl: i64,
+        family_started: i64,
+    ) -> Result<String> {
+        self.cleanup_expired_inner(state);
+        if state.tokens.len() >= self.max_capacity {
+            return Err(Error::CapacityExceeded("refresh store at capacity".into()));
+        }
+        let expires_at = (now() + REFRESH_TTL_SECONDS).min(family_started + MAX_FAMILY_AGE_SECONDS);
+        let token = generate_refresh_token();
+        state.tokens.insert(token.clone(), RefreshEntry {
+            family,
+            template,
+            access_ttl,
+            family_started,
+            expires_at,
+            rotated: false,
+        });
+        Ok(token)
+    }
+
+    fn cleanup_expired_inner(&self, state: &mut RefreshState) {
+        let cutoff = now().saturating_sub(self.retain_past_expiry);
+        state.tokens.retain(|_, entry| entry.expires_at > cutoff);
+        let live: HashSet<&String> = state.tokens.values().map(|e| &e.family).collect();
+        state.revoked_families.retain(|family| live.contains(family));
+        state.families_by_jti.retain(|_, family| live.contains(family));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template() -> Claims {
+        Claims::new("agent-1".into(), "deploy".into(), 60)
+    }
+
+    #[test]
+    fn rotate_returns_grant_and_new_token() -> Result<()> {
+        let store = RefreshStore::new();
+        let first = store.issue(&template(), 60)?;
+        let grant = store.rotate(&first)?;
+        assert_eq!(grant.claims.sub, "agent-1");
+        assert_eq!((grant.claims.exp - grant.claims.iat).num_seconds(), 60);
+        assert_ne!(grant.refresh_token, first);
+        Ok(())
+    }
+
+    #[test]
+    fn revoking_any_issued_jti_stops_the_family() -> Result<()> {
+        let store = RefreshStore::new();
+        let original = template();
+        let first = store.issue(&original, 60)?;
+        let grant = store.rotate(&first)?;
+        assert!(store.revoke_family_of(&grant.claims.jti)?);
+        assert!(matches!(store.rotate(&grant.refresh_token), Err(Error::Unauthorized(_))));
+
+        let other = store.issue(&template(), 60)?;
+        assert!(store.revoke_family_of(&original.jti)?);
+        assert!(!store.revoke_family_of("not-issued-here")?);
+        store.rotate(&other).map(drop)
+    }
+
+    #[test]
+    fn rotated_token_cannot_be_used_again() -> Result<()> {
+        let store = RefreshStore::new();
+        let first = store.issue(&template(), 60)?;
+        store.rotate(&first)?;
+        let result = store.rotate(&first);
+        assert!(matches!(result, Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn reuse_revokes_whole_family() -> Result<()> {
+        let store = RefreshStore::new();
+        let first = store.issue(&template(), 60)?;
+        let second = store.rotate(&first)?.refresh_token;
+        assert!(store.rotate(&first).is_err());
+        let result = store.rotate(&second);
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn reuse_just_past_expiry_still_detected() -> Result<()> {
+        let store = RefreshStore::new().retaining_past_expiry(std::time::Duration::from_secs(5));
+        let first = store.issue(&template(), 60)?;
+        store.rotate(&first)?;
+        let mut state = store.state.lock().map_err(lock_err("refresh"))?;
+        state.tokens.get_mut(&first).ok_or_else(|| Error::Unauthorized("rotated token evicted".into()))?.expires_at = now() - 1;
+        drop(state);
+        store.issue(&template(), 60)?;
+        assert!(matches!(store.rotate(&first), Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_token_rejected() {
+        let store = RefreshStore::new();
+        assert!(matches!(store.rotate("nope"), Err(Error::Unauthorized(_))));
+    }
+
+    #[test]
+    fn capacity_limit_returns_429() -> Result<()> {
+        let store = RefreshStore::with_capacity(1);
+        store.issue(&template(), 60)?;
+        let result = store.issue(&template(), 60);
+        assert!(matches!(result, Err(Error::CapacityExceeded(_))));
+        Ok(())
+    }
+}
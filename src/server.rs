@@ -1,12 +1,16 @@
 //! Axum router and server setup with security headers.
 
+use std::net::SocketAddr;
+use std::time::Instant;
+
+use axum::extract::{MatchedPath, State};
 use axum::http::header::{self, HeaderValue};
 use axum::response::Response;
 use axum::routing::{get, post};
 use axum::{Router, middleware};
-use tower_http::cors::CorsLayer;
 
 use crate::handlers;
+use crate::ipfilter;
 use crate::state::AppState;
 use crate::webauthn;
 
@@ -15,27 +19,123 @@ async fn security_headers(req: axum::extract::Request, next: middleware::Next) -
     let h = resp.headers_mut();
     h.insert(header::X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
     h.insert(header::X_FRAME_OPTIONS, HeaderValue::from_static("DENY"));
-    h.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    // Handlers serving public, cacheable data (e.g. `/pubkey`) set their own.
+    h.entry(header::CACHE_CONTROL).or_insert(HeaderValue::from_static("no-store"));
     resp
 }
 
+/// Records per-route latency and logs requests slower than `SLOW_REQUEST_MS`.
+async fn track_latency(
+    State(state): State<AppState>,
+    route: MatchedPath,
+    req: axum::extract::Request,
+    next: middleware::Next,
+) -> Response {
+    let start = Instant::now();
+    let resp = next.run(req).await;
+    let elapsed = start.elapsed();
+    state.metrics.route_latency.observe(route.as_str(), elapsed);
+    if elapsed >= state.slow_request_threshold {
+        state.metrics.record_slow_request();
+        tracing::warn!(
+            route = route.as_str(),
+            status = resp.status().as_u16(),
+            elapsed_ms = elapsed.as_millis() as u64,
+            "slow request"
+        );
+    }
+    resp
+}
+
+/// Admits a signing or verifying request only once it holds a `CRYPTO_CONCURRENCY` permit, so a
+/// shed request has consumed nothing.
+async fn admit_crypto(
+    State(state): State<AppState>,
+    req: axum::extract::Request,
+    next: middleware::Next,
+) -> crate::error::Result<Response> {
+    let _permit = state.crypto_permit().await?;
+    Ok(next.run(req).await)
+}
+
 pub fn build_router(state: AppState) -> Router {
-    Router::new()
-        // Core endpoints
-        .route("/health", get(handlers::health::health))
+    // Token-issuing endpoints, gated by MINT_IP_ALLOWLIST
+    let issuing = Router::new()
         .route("/mint", post(handlers::mint::mint))
-        .route("/delegate", post(handlers::delegate::delegate))
+        .route("/mint/batch", post(handlers::batch::mint_batch))
+        .route("/mint/breakglass", post(handlers::breakglass::breakglass))
+        .route("/refresh", post(handlers::refresh::refresh))
+        .route_layer(middleware::from_fn_with_state(state.clone(), admit_crypto))
+        .route("/preauth", post(handlers::preauth::preauth))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ipfilter::require_mint_ip));
+    // Verification and read-only routes draw on the read budgets
+    let reads = Router::new()
         .route("/proxy", post(handlers::proxy::proxy))
+        .route("/proxy/external", post(handlers::proxy::proxy_external))
+        .route("/proxy/binary", post(handlers::proxy::proxy_binary))
+        .route("/proxy/complete", post(handlers::proxy::complete))
+        .route_layer(middleware::from_fn_with_state(state.clone(), admit_crypto))
+        .route("/verification-bundle", get(handlers::verification::verification_bundle))
         .route("/audit", get(handlers::audit::recent))
+        .route("/audit/bundle", get(handlers::audit::bundle))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ipfilter::limit_reads));
+    // Health and metrics; kept at the root under ROUTE_PREFIX when OPS_ROUTES_AT_ROOT=true
+    let ops = Router::new()
         .route("/metrics", get(handlers::metrics::metrics))
-        // WebAuthn endpoints
+        .route("/metrics/history", get(handlers::metrics::history))
+        .route("/metrics/latency", get(handlers::metrics::latency))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ipfilter::limit_reads))
+        .route("/health", get(handlers::health::health).head(handlers::health::health_head))
+        .route("/ready", get(handlers::health::ready));
+    // Everything that signs tokens; not mounted in VERIFY_ONLY deployments, so those paths 404.
+    let signing = match state.can_sign() {
+        true => issuing.merge(
+            Router::new()
+                .route("/delegate", post(handlers::delegate::delegate))
+                .route("/token/exchange", post(handlers::exchange::exchange))
+                .route_layer(middleware::from_fn_with_state(state.clone(), admit_crypto)),
+        ),
+        false => Router::new(),
+    };
+    // WebAuthn endpoints
+    let ceremonies = Router::new()
         .route("/webauthn/register/start", post(webauthn::register_start))
         .route("/webauthn/register/finish", post(webauthn::register_finish))
         .route("/webauthn/auth/start", post(webauthn::auth_start))
         .route("/webauthn/auth/finish", post(webauthn::auth_finish))
+        .route("/webauthn/reenroll/start", post(webauthn::reenroll_start));
+    // Issuance, WebAuthn, and revocation draw on the write budgets
+    let writes = signing
+        .merge(ceremonies)
+        .route("/revoke", post(handlers::admin::revoke))
+        .route_layer(middleware::from_fn_with_state(state.clone(), ipfilter::limit_writes));
+
+    let app = Router::new()
+        // Core endpoints
+        .route("/pubkey", get(handlers::verification::pubkey))
+        .route("/jwks.json", get(handlers::jwks::jwks))
+        .merge(writes)
+        .merge(reads)
+        .route("/openapi.json", get(handlers::openapi::openapi))
+        // Admin endpoints
+        .route("/admin/replays", get(handlers::admin::replay_offenders))
+        .route("/signing-key/status", get(handlers::admin::signing_key_status))
+        .route(
+            "/ratelimit/subject/:sub",
+            get(handlers::admin::subject_rate).delete(handlers::admin::reset_subject_rate),
+        )
+        .route("/oidc/whoami", post(handlers::whoami::whoami));
+
+    let routes = match &state.route_prefix {
+        None => app.merge(ops),
+        Some(prefix) if state.ops_routes_at_root => Router::new().nest(prefix, app).merge(ops),
+        Some(prefix) => Router::new().nest(prefix, app.merge(ops)),
+    };
+    routes
         // Middleware
+        .route_layer(middleware::from_fn_with_state(state.clone(), track_latency))
         .layer(middleware::from_fn(security_headers))
-        .layer(CorsLayer::permissive())
+        .layer(state.cors.layer())
         .with_state(state)
 }
 
@@ -45,7 +145,196 @@ pub async fn run(state: AppState, addr: &str) -> std::io::Result<()> {
 }
 
 pub async fn run_with_listener(state: AppState, listener: tokio::net::TcpListener) -> std::io::Result<()> {
+    run_until(state, listener, std::future::pending()).await
+}
+
+pub async fn run_until<F>(state: AppState, listener: tokio::net::TcpListener, shutdown: F) -> std::io::Result<()>
+where
+    F: std::future::Future<Output = ()> + Send + 'static,
+{
     let router = build_router(state);
     tracing::info!("listening on {:?}", listener.local_addr());
-    axum::serve(listener, router).await
+    axum::serve(listener, router.into_make_service_with_connect_info::<SocketAddr>())
+        .with_graceful_shutdown(shutdown)
+        .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cors::CorsSettings;
+    use crate::error::{Error, Result};
+    use crate::handlers::health::HealthBody;
+    use crate::state::test_builder;
+    use crate::token::claims::Claims;
+    use crate::testing::TestServer;
+    use crate::token::sign::{generate_keypair, sign_token};
+
+    #[tokio::test]
+    async fn slow_handler_logged_and_histogrammed() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.slow_request_threshold = std::time::Duration::from_millis(20);
+        let state = builder.build()?;
+
+        let app: Router = Router::new()
+            .route("/slow/:id", get(|| async {
+                tokio::time::sleep(std::time::Duration::from_millis(30)).await;
+            }))
+            .route("/fast", get(|| async {}))
+            .route_layer(middleware::from_fn_with_state(state.clone(), track_latency))
+            .with_state(state.clone());
+        let server = TestServer::spawn_router(state.clone(), app).await?;
+
+        for path in ["/slow/1", "/slow/2", "/fast"] {
+            reqwest::get(server.url(path)).await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        }
+
+        assert_eq!(state.metrics.snapshot().slow_requests, 2);
+        let report = state.metrics.route_latency.report();
+        let slow = report.iter().find(|r| r.route == "/slow/:id");
+        assert_eq!(slow.map(|r| r.count), Some(2));
+        assert!(slow.is_some_and(|r| r.max_us >= 30_000));
+        assert_eq!(report.iter().find(|r| r.route == "/fast").map(|r| r.count), Some(1));
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn preflight_reports_configured_cors_policy() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.cors = CorsSettings {
+            origins: Some(vec![HeaderValue::from_static("https://console.example")]),
+            max_age: std::time::Duration::from_secs(120),
+            ..CorsSettings::default()
+        };
+        let server = TestServer::spawn_with_state(builder.build()?).await?;
+
+        let resp = reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, server.url("/mint"))
+            .header("origin", "https://console.example")
+            .header("access-control-request-method", "POST")
+            .header("access-control-request-headers", "content-type")
+            .send()
+            .await
+            .map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        let header = |name: &str| resp.headers().get(name).and_then(|v| v.to_str().ok()).map(str::to_owned);
+
+        assert_eq!(header("access-control-max-age").as_deref(), Some("120"));
+        assert_eq!(header("access-control-allow-origin").as_deref(), Some("https://console.example"));
+        assert_eq!(header("access-control-allow-methods").as_deref(), Some("GET,POST,DELETE"));
+        assert_eq!(header("access-control-allow-headers").as_deref(), Some("content-type,authorization,x-admin-key,x-verify-key"));
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn verify_only_serves_proxy_but_not_mint() -> Result<()> {
+        let minter = generate_keypair();
+        let mut builder = test_builder()?;
+        builder.verify_only = Some(minter.verifying_key());
+        let state = builder.build()?;
+        assert!(state.signing_key.is_none());
+        let server = TestServer::spawn_with_state(state).await?;
+
+        let client = reqwest::Client::new();
+        let post = |path: &str, body: serde_json::Value| client.post(server.url(path)).json(&body).send();
+        let mint = post("/mint", serde_json::json!({ "sub": "agent-1", "action": "deploy" })).await;
+        let mint = mint.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(mint.status(), reqwest::StatusCode::NOT_FOUND);
+
+        let token = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 60), &minter)?;
+        let proxied = post("/proxy", serde_json::json!({ "token": token })).await;
+        let proxied = proxied.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(proxied.status(), reqwest::StatusCode::OK);
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn health_serves_configured_body_and_empty_head() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.health_body = HealthBody::parse(Some(r#"{"status":"up"}"#.into()))?;
+        let server = TestServer::spawn_with_state(builder.build()?).await?;
+
+        let client = reqwest::Client::new();
+        let get = client.get(server.url("/health")).send().await;
+        let get = get.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(get.status(), reqwest::StatusCode::OK);
+        assert_eq!(get.headers().get("content-type").and_then(|v| v.to_str().ok()), Some("application/json"));
+        assert_eq!(get.text().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?, r#"{"status":"up"}"#);
+
+        let head = client.head(server.url("/health")).send().await;
+        let head = head.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(head.status(), reqwest::StatusCode::OK);
+        assert!(head.bytes().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?.is_empty());
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn saturated_crypto_sheds_before_side_effects_while_health_stays_fast() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.crypto_limits = crate::ratelimit::CryptoLimits::new(2);
+        let state = builder.build()?;
+        let server = TestServer::spawn_with_state(state.clone()).await?;
+        let client = reqwest::Client::new();
+        let send = |path: &str, body: serde_json::Value| client.post(server.url(path)).json(&body).send();
+        let mint = || send("/mint", serde_json::json!({ "sub": "agent-1", "action": "deploy", "refresh": true }));
+        let minted = mint().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        let minted: serde_json::Value = minted.json().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        let refresh = serde_json::json!({ "refresh_token": minted["refresh_token"] });
+
+        let held = [state.crypto_limits.try_acquire()?, state.crypto_limits.try_acquire()?];
+        for _ in 0..3 {
+            let resp = mint().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+            assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        }
+        let shed = send("/refresh", refresh.clone()).await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(shed.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+        let started = Instant::now();
+        let health = client.get(server.url("/health")).send().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(health.status(), reqwest::StatusCode::OK);
+        assert!(started.elapsed() < std::time::Duration::from_secs(1));
+        assert_eq!(state.metrics.snapshot().crypto_shed, 4);
+
+        drop(held);
+        let refreshed = send("/refresh", refresh).await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+        assert_eq!(refreshed.status(), reqwest::StatusCode::OK);
+        server.shutdown().await
+    }
+
+    async fn status_under(prefix: &str, ops_at_root: bool, paths: &[(&str, reqwest::StatusCode)]) -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.route_prefix = Some(prefix.into());
+        builder.ops_routes_at_root = ops_at_root;
+        let server = TestServer::spawn_with_state(builder.build()?).await?;
+        let client = reqwest::Client::new();
+        for (path, expected) in paths {
+            let resp = match path.ends_with("/mint") {
+                true => client.post(server.url(path)).json(&serde_json::json!({ "sub": "agent-1", "action": "deploy" })),
+                false => client.get(server.url(path)),
+            };
+            let resp = resp.send().await.map_err(|e| Error::ServiceUnavailable(e.to_string()))?;
+            assert_eq!(resp.status(), *expected, "{path}");
+        }
+        server.shutdown().await
+    }
+
+    #[tokio::test]
+    async fn routes_served_only_under_route_prefix() -> Result<()> {
+        use reqwest::StatusCode;
+        let nested = [
+            ("/auth/agentmint/mint", StatusCode::OK),
+            ("/auth/agentmint/health", StatusCode::OK),
+            ("/auth/agentmint/metrics", StatusCode::OK),
+            ("/mint", StatusCode::NOT_FOUND),
+            ("/health", StatusCode::NOT_FOUND),
+        ];
+        status_under("/auth/agentmint", false, &nested).await?;
+
+        let ops_at_root = [
+            ("/auth/agentmint/mint", StatusCode::OK),
+            ("/health", StatusCode::OK),
+            ("/metrics", StatusCode::OK),
+            ("/auth/agentmint/health", StatusCode::NOT_FOUND),
+            ("/mint", StatusCode::NOT_FOUND),
+        ];
+        status_under("/auth/agentmint", true, &ops_at_root).await
+    }
 }
@@ -0,0 +1,224 @@
+//! Request-rate spike detection over a sliding window, with an optional outgoing webhook.
+//! Used by: config, state.
+
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+use url::Url;
+
+use crate::error::{Error, Result};
+
+const DEFAULT_WINDOW: Duration = Duration::from_secs(10);
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpikeSettings {
+    pub threshold_per_sec: u64,
+    pub window: Duration,
+    pub webhook: Option<Url>,
+}
+
+impl SpikeSettings {
+    /// `REQUEST_SPIKE_PER_SEC` enables detection; `REQUEST_SPIKE_WINDOW_SECS` and
+    /// `REQUEST_SPIKE_WEBHOOK_URL` refine it and are rejected without it.
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let number = |name: &str| -> Result<Option<u64>> {
+            let Some(v) = get(name) else {
+                return Ok(None);
+            };
+            match v.parse::<u64>() {
+                Ok(n) if n > 0 => Ok(Some(n)),
+                _ => Err(Error::Config(format!("{name} must be a positive integer, got {v:?}"))),
+            }
+        };
+        let window = number("REQUEST_SPIKE_WINDOW_SECS")?;
+        let webhook = get("REQUEST_SPIKE_WEBHOOK_URL")
+            .map(|v| {
+                Url::parse(&v)
+                    .ok()
+                    .filter(|u| matches!(u.scheme(), "http" | "https"))
+                    .ok_or_else(|| Error::Config(format!("REQUEST_SPIKE_WEBHOOK_URL: not an http(s) URL: {v}")))
+            })
+            .transpose()?;
+        let Some(threshold_per_sec) = number("REQUEST_SPIKE_PER_SEC")? else {
+            if window.is_some() || webhook.is_some() {
+                return Err(Error::Config(
+                    "REQUEST_SPIKE_WINDOW_SECS / REQUEST_SPIKE_WEBHOOK_URL are set without REQUEST_SPIKE_PER_SEC".into(),
+                ));
+            }
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            threshold_per_sec,
+            window: window.map(Duration::from_secs).unwrap_or(DEFAULT_WINDOW),
+            webhook,
+        }))
+    }
+
+    fn limit(&self) -> u64 {
+        self.threshold_per_sec.saturating_mul(self.window.as_secs().max(1))
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SpikeEvent {
+    pub event: &'static str,
+    pub requests: u64,
+    pub window_secs: u64,
+    pub threshold_per_sec: u64,
+    pub at: String,
+}
+
+/// Requests per whole second since `origin`, covering the trailing window only.
+struct Window {
+    origin: Instant,
+    seconds: VecDeque<(u64, u64)>,
+    total: u64,
+    fired: bool,
+}
+
+impl Window {
+    /// Counts one request at `now` and returns the requests in the window ending then.
+    fn record(&mut self, now: Instant, window_secs: u64) -> u64 {
+        let second = now.saturating_duration_since(self.origin).as_secs();
+        while let Some(&(oldest, count)) = self.seconds.front() {
+            if oldest + window_secs > second {
+                break;
+            }
+            self.total -= count;
+            self.seconds.pop_front();
+        }
+        match self.seconds.back_mut() {
+            Some((last, count)) if *last >= second => *count += 1,
+            _ => self.seconds.push_back((second, 1)),
+        }
+        self.total += 1;
+        self.total
+    }
+}
+
+/// Fires when the trailing window's count passes `threshold_per_sec * window`, and again only
+/// once it has fallen back under; a burst is one event, not one per request.
+pub struct SpikeDetector {
+    settings: SpikeSettings,
+    window: Mutex<Window>,
+    /// Shared by every delivery, so a burst of events reuses one connection pool.
+    client: reqwest::Client,
+}
+
+impl SpikeDetector {
+    pub fn new(settings: SpikeSettings) -> Result<Self> {
+        let client = reqwest::Client::builder()
+            .timeout(WEBHOOK_TIMEOUT)
+            .build()
+            .map_err(|e| Error::Config(format!("request spike webhook client: {e}")))?;
+        let window = Window { origin: Instant::now(), seconds: VecDeque::new(), total: 0, fired: false };
+        Ok(Self { settings, window: Mutex::new(window), client })
+    }
+
+    pub fn settings(&self) -> &SpikeSettings {
+        &self.settings
+    }
+
+    /// Counts one request at `now`; returns the spike event when this request crosses the threshold.
+    pub fn observe(&self, now: Instant) -> Option<SpikeEvent> {
+        let mut window = self.window.lock().ok()?;
+        let requests = window.record(now, self.settings.window.as_secs().max(1));
+        if requests <= self.settings.limit() {
+            window.fired = false;
+            return None;
+        }
+        if window.fired {
+            return None;
+        }
+        window.fired = true;
+        Some(SpikeEvent {
+            event: "request_spike",
+            requests,
+            window_secs: self.settings.window.as_secs(),
+            threshold_per_sec: self.settings.threshold_per_sec,
+            at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+
+    /// Posts `event` to the webhook in the background; delivery failures are logged, never retried.
+    pub fn notify(&self, event: SpikeEvent) {
+        let Some(url) = self.settings.webhook.clone() else {
+            return;
+        };
+        let Ok(runtime) = tokio::runtime::Handle::try_current() else {
+            tracing::warn!("request spike webhook skipped: no async runtime");
+            return;
+        };
+        let request = self.client.post(url).json(&event);
+        runtime.spawn(async move {
+            let sent = request.send().await;
+            if let Err(e) = sent.and_then(|r| r.error_for_status()) {
+                tracing::error!(error = %e, "request spike webhook failed");
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn settings(threshold_per_sec: u64, window_secs: u64) -> SpikeSettings {
+        SpikeSettings { threshold_per_sec, window: Duration::from_secs(window_secs), webhook: None }
+    }
+
+    #[test]
+    fn burst_fires_once_per_window() -> Result<()> {
+        let detector = SpikeDetector::new(settings(2, 10))?;
+        let start = Instant::now();
+        let fired: Vec<_> = (0..30).filter_map(|_| detector.observe(start)).collect();
+        assert_eq!(fired.len(), 1);
+        assert_eq!(fired[0].requests, 21);
+
+        assert!(detector.observe(start + Duration::from_secs(10)).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn steady_low_traffic_never_fires() -> Result<()> {
+        let detector = SpikeDetector::new(settings(2, 10))?;
+        let start = Instant::now();
+        let fired = (0..600).filter_map(|i| detector.observe(start + Duration::from_millis(i * 500))).count();
+        assert_eq!(fired, 0);
+        Ok(())
+    }
+
+    #[test]
+    fn burst_straddling_a_window_boundary_fires() -> Result<()> {
+        let detector = SpikeDetector::new(settings(2, 10))?;
+        let start = Instant::now();
+        let late = (0..15).filter_map(|_| detector.observe(start + Duration::from_secs(9))).count();
+        let early = (0..15).filter_map(|_| detector.observe(start + Duration::from_secs(11))).count();
+        assert_eq!((late, early), (0, 1));
+
+        assert!(detector.observe(start + Duration::from_secs(30)).is_none());
+        let again = (0..25).filter_map(|_| detector.observe(start + Duration::from_secs(31))).count();
+        assert_eq!(again, 1);
+        Ok(())
+    }
+
+    #[test]
+    fn dependent_settings_require_threshold() -> Result<()> {
+        let load = |vars: &[(&str, &str)]| {
+            let vars: HashMap<&str, &str> = vars.iter().copied().collect();
+            SpikeSettings::from_lookup(&|name| vars.get(name).map(|v| v.to_string()))
+        };
+        assert_eq!(load(&[])?, None);
+        assert!(matches!(load(&[("REQUEST_SPIKE_WINDOW_SECS", "5")]), Err(Error::Config(_))));
+        assert!(matches!(load(&[("REQUEST_SPIKE_PER_SEC", "0")]), Err(Error::Config(_))));
+        let webhook = [("REQUEST_SPIKE_PER_SEC", "50"), ("REQUEST_SPIKE_WEBHOOK_URL", "ftp://x")];
+        assert!(matches!(load(&webhook), Err(Error::Config(_))));
+        let loaded = load(&[("REQUEST_SPIKE_PER_SEC", "50")])?;
+        assert_eq!(loaded.map(|s| s.window), Some(DEFAULT_WINDOW));
+        Ok(())
+    }
+}
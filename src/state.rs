@@ -1,92 +1,814 @@
 //! Shared application state.
 
-use std::sync::Arc;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::{Arc, OnceLock};
 use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::time::Duration;
 
+use chrono::{DateTime, Utc};
 use ed25519_dalek::{SigningKey, VerifyingKey};
+use ring::hmac;
 
+use crate::audit::retention::AuditRetention;
+use crate::audit::sink;
 use crate::audit::sqlite::AuditLog;
-use crate::error::Result;
-use crate::jti::memory::JtiStore;
-use crate::oidc::OidcVerifier;
-use crate::policy::PolicyEngine;
-use crate::ratelimit::{RateLimiter, RateLimitConfig};
-use crate::telemetry::Metrics;
-use crate::token::sign::generate_keypair;
+use crate::audit::writer::AuditWriter;
+use crate::config::{Config, DEFAULT_AUDIT_BUNDLE_ENTRIES, DEFAULT_MAX_SCOPES, DEFAULT_SLOW_REQUEST};
+use crate::cors::CorsSettings;
+use crate::handlers::health::HealthBody;
+use crate::heartbeat::Heartbeats;
+use crate::error::{Error, Result, TokenFault};
+use crate::ipfilter::IpAllowlist;
+use crate::oidc::{OidcVerifier, ResultCache, DEFAULT_RESULT_CACHE_CAPACITY};
+use crate::policy::{normalize_action, PolicyEngine, TtlFloor};
+use crate::preauth::PreauthStore;
+use crate::ratelimit::{Admission, BatchLimits, CryptoLimits, RateLimiter, RateLimitConfig, RequestClass};
+use crate::refresh::RefreshStore;
+use crate::spike::{SpikeDetector, SpikeSettings};
+use crate::storage::{ChallengeStore, CredentialStore, QuotaStore, ReplayGuard, RevocationStore, Storage};
+use crate::telemetry::{Metrics, MetricsHistory};
+use crate::token::claims::{Audience, Claims, DEFAULT_EXPIRY_LEEWAY, DEFAULT_IAT_LEEWAY};
+use crate::token::keys::{
+    hmac_key, key_first_seen, key_id, load_previous_key, load_signing_key, load_verifying_key, GraceKey, PinnedKey, DEFAULT_KID,
+};
+use crate::token::keyset::KeySet;
+use crate::token::offline::VerificationBundle;
+use crate::token::sign::{generate_keypair, sign_token_binary, sign_token_hs256, sign_token_with_prefix};
+use crate::token::verify::{
+    verify_pinned_allow_expired, verify_token_allow_expired, verify_token_binary_allow_expired,
+    verify_token_with_keys_allow_expired,
+};
 use crate::webauthn::WebAuthnState;
 
 pub struct AppStateInner {
-    pub signing_key: SigningKey,
+    /// `None` in `VERIFY_ONLY` deployments, which never hold private key material, and in HS256 mode.
+    pub signing_key: Option<SigningKey>,
+    /// When `signing_key` was created: `SIGNING_KEY_CREATED_AT`, else when its file was first seen
+    /// (recorded in the `.created` sidecar), or startup for an ephemeral key.
+    pub signing_key_created: DateTime<Utc>,
+    /// In HS256 mode, a throwaway key that nothing is signed or verified with.
     pub verifying_key: VerifyingKey,
-    pub jti_store: JtiStore,
-    pub audit_log: AuditLog,
+    /// HS256 mode (`TOKEN_HMAC_SECRET`): tokens are signed and verified with this shared secret only.
+    pub hmac_key: Option<hmac::Key>,
+    pub previous_key: Option<GraceKey>,
+    /// `VERIFYING_KEYS_JWKS`: other minters' keys by `kid`, reloaded in the background.
+    pub minter_keys: Option<KeySet>,
+    pub jti_store: Arc<dyn ReplayGuard>,
+    pub revocations: Arc<dyn RevocationStore>,
+    pub quotas: Arc<dyn QuotaStore>,
+    pub credentials: Arc<dyn CredentialStore>,
+    pub challenges: Arc<dyn ChallengeStore>,
+    pub refresh_store: RefreshStore,
+    pub preauth_store: PreauthStore,
+    pub audit_log: Arc<AuditLog>,
+    /// Pruning windows for `audit_log`; `None` keeps every entry.
+    pub audit_retention: Option<AuditRetention>,
+    pub audit_writer: Option<AuditWriter>,
     pub metrics: Metrics,
+    pub metrics_history: MetricsHistory,
+    pub heartbeats: Heartbeats,
     pub policy: PolicyEngine,
+    pub ttl_floor: TtlFloor,
     pub oidc: Option<OidcVerifier>,
+    pub oidc_cache: ResultCache,
+    pub cap_ttl_to_id_token: bool,
     pub webauthn: Option<WebAuthnState>,
     pub rate_limiter: RateLimiter,
+    pub mint_daily_quota: Option<u64>,
+    pub max_scopes: usize,
+    pub audit_bundle_max_entries: usize,
+    pub normalize_actions: bool,
+    pub strict_requests: bool,
+    pub cors: CorsSettings,
+    pub health_body: HealthBody,
+    pub batch_limits: BatchLimits,
+    /// `CRYPTO_CONCURRENCY`: signing or verifying requests allowed in flight at once.
+    pub crypto_limits: CryptoLimits,
+    pub slow_request_threshold: Duration,
     pub require_oidc: bool,
+    pub admin_key: Option<String>,
+    pub token_prefix: Option<String>,
+    pub issuer: Option<String>,
+    pub default_audience: Option<String>,
+    pub require_audience: bool,
+    pub proxy_audience: Option<String>,
+    pub accept_legacy_tokens: bool,
+    /// How long past `exp` a token is still accepted.
+    pub expiry_leeway: Duration,
+    /// How far ahead of our clock a token's `iat` may be.
+    pub iat_leeway: Duration,
+    pub proxy_allowed_issuers: Option<Vec<String>>,
+    pub exchange_audiences: Option<Vec<String>>,
+    /// Audiences published in the verification bundle.
+    pub verification_audiences: Vec<String>,
+    /// The signed verification bundle, built on first request; nothing it covers changes at runtime.
+    pub verification_bundle: OnceLock<VerificationBundle>,
+    pub mint_ip_allowlist: Option<IpAllowlist>,
+    pub trusted_proxies: Option<IpAllowlist>,
     pub request_count: AtomicU64,
+    pub request_spike: Option<SpikeDetector>,
+    /// `ROUTE_PREFIX`: base path the router is nested under.
+    pub route_prefix: Option<String>,
+    pub ops_routes_at_root: bool,
 }
 
 pub type AppState = Arc<AppStateInner>;
 
 impl AppStateInner {
+    /// Counts a request and, with `REQUEST_SPIKE_PER_SEC` set, reports a windowed rate spike.
     pub fn increment_requests(&self) {
-        let n = self.request_count.fetch_add(1, Relaxed) + 1;
-        if n % 1000 == 0 {
-            tracing::warn!(count = n, "high request volume");
+        self.request_count.fetch_add(1, Relaxed);
+        let Some(spike) = &self.request_spike else {
+            return;
+        };
+        if let Some(event) = spike.observe(std::time::Instant::now()) {
+            tracing::warn!(requests = event.requests, window_secs = event.window_secs, "request rate spike");
+            self.metrics.record_request_spike();
+            spike.notify(event);
+        }
+    }
+
+    /// Per-user limit via `rate_limiter`, counting exemptions and throttles in `metrics`.
+    pub fn check_user_rate(&self, user_id: &str) -> Result<()> {
+        match self.rate_limiter.check_user(user_id) {
+            Ok(Admission::Counted) => Ok(()),
+            Ok(Admission::Exempt) => {
+                self.metrics.record_rate_limit_exempt();
+                Ok(())
+            }
+            Err(e) => {
+                self.metrics.record_rate_limited();
+                Err(Error::RateLimited(e.to_string()))
+            }
+        }
+    }
+
+    /// Per-IP limit for `class` via `rate_limiter`, counted like `check_user_rate`.
+    pub fn check_ip_rate(&self, ip: &str, class: RequestClass) -> Result<()> {
+        match self.rate_limiter.check_ip(ip, class) {
+            Ok(Admission::Counted) => Ok(()),
+            Ok(Admission::Exempt) => {
+                self.metrics.record_rate_limit_exempt();
+                Ok(())
+            }
+            Err(e) => {
+                self.metrics.record_rate_limited();
+                crate::console::log_rate_limited(ip, &e.to_string());
+                Err(Error::RateLimited(e.to_string()))
+            }
+        }
+    }
+
+    /// Per-subject `MINT_DAILY_QUOTA` over UTC days. Counted in `quotas`, so the SQLite
+    /// backend keeps a subject's budget across restarts. `RATE_EXEMPT_SUBJECTS` skips it only
+    /// when `approved_by` shows an OIDC id_token verified the caller as `sub`.
+    pub async fn consume_mint_quota(&self, sub: &str, approved_by: Option<&str>) -> Result<()> {
+        let Some(limit) = self.mint_daily_quota else {
+            return Ok(());
+        };
+        if approved_by == Some(sub) && self.rate_limiter.is_exempt_subject(sub) {
+            tracing::debug!(sub, "daily mint quota skipped for exempt subject");
+            self.metrics.record_rate_limit_exempt();
+            return Ok(());
+        }
+        let day = chrono::Utc::now().timestamp().div_euclid(SECONDS_PER_DAY);
+        if !self.quotas.consume(&format!("mint:{sub}"), day, limit).await? {
+            self.metrics.record_rate_limited();
+            return Err(Error::RateLimited(format!("daily mint quota of {limit} exceeded")));
+        }
+        Ok(())
+    }
+
+    /// Revokes one token by `jti`, every token of `sub`, or both, until the unix-seconds `until`.
+    /// Keys are namespaced so a jti can never collide with a subject.
+    pub async fn revoke(&self, jti: Option<&str>, sub: Option<&str>, until: i64) -> Result<()> {
+        if let Some(jti) = jti {
+            self.revocations.revoke(&format!("jti:{jti}"), until).await?;
+            self.refresh_store.revoke_family_of(jti)?;
+        }
+        if let Some(sub) = sub {
+            self.revocations.revoke(&format!("sub:{sub}"), until).await?;
+        }
+        Ok(())
+    }
+
+    /// `Revoked` when the token's `jti`, any token it derives from, or its `sub` is on the revocation list.
+    pub async fn check_revocation(&self, claims: &Claims) -> Result<()> {
+        let ancestors = match &claims.ancestors {
+            Some(ancestors) => ancestors.as_slice(),
+            None => claims.parent_jti.as_slice(),
+        };
+        for jti in ancestors.iter().chain(std::iter::once(&claims.jti)) {
+            if self.revocations.is_revoked(&format!("jti:{jti}")).await? {
+                return Err(Error::Revoked(format!("jti {jti} is revoked")));
+            }
+        }
+        if self.revocations.is_revoked(&format!("sub:{}", claims.sub)).await? {
+            return Err(Error::Revoked(format!("tokens for {} are revoked", claims.sub)));
+        }
+        Ok(())
+    }
+
+    /// The Ed25519 minting key; `Forbidden` in `VERIFY_ONLY` deployments and in HS256 mode.
+    pub fn signing_key(&self) -> Result<&SigningKey> {
+        self.signing_key.as_ref().ok_or_else(|| Error::Forbidden("this deployment holds no Ed25519 signing key".into()))
+    }
+
+    /// Whether tokens can be minted here: an Ed25519 signing key or an HS256 secret.
+    pub fn can_sign(&self) -> bool {
+        self.signing_key.is_some() || self.hmac_key.is_some()
+    }
+
+    /// HS256 verification needs the secret itself, so there is no public key to hand out.
+    fn ensure_publishable(&self) -> Result<()> {
+        match self.hmac_key {
+            Some(_) => Err(Error::Forbidden("HS256 mode has no public key to publish".into())),
+            None => Ok(()),
+        }
+    }
+
+    /// The current verifying key, for publication; `Forbidden` in HS256 mode.
+    pub fn published_key(&self) -> Result<&VerifyingKey> {
+        self.ensure_publishable()?;
+        Ok(&self.verifying_key)
+    }
+
+    /// Applies `NORMALIZE_ACTIONS` (on unless set to `false`) to a client-supplied action or scope entry.
+    pub fn normalize_action(&self, action: String) -> String {
+        match self.normalize_actions {
+            true => normalize_action(&action),
+            false => action,
+        }
+    }
+
+    /// Stamps `iss` with the configured issuer and `aud` with `DEFAULT_AUDIENCE` unless the
+    /// claims already carry them.
+    pub fn sign(&self, claims: &Claims) -> Result<String> {
+        let claims = self.stamp(claims);
+        let prefix = self.token_prefix.as_deref();
+        match &self.hmac_key {
+            Some(key) => sign_token_hs256(&claims, key, prefix),
+            None => sign_token_with_prefix(&claims, self.signing_key()?, prefix),
+        }
+    }
+
+    /// Binary-form counterpart of `sign`; binary tokens are Ed25519-only.
+    pub fn sign_binary(&self, claims: &Claims) -> Result<Vec<u8>> {
+        if self.hmac_key.is_some() {
+            return Err(Error::Validation("binary tokens are not available in HS256 mode".into()));
+        }
+        sign_token_binary(&self.stamp(claims), self.signing_key()?)
+    }
+
+    /// Fills an unset `iss` and `aud` from `TOKEN_ISSUER` and `DEFAULT_AUDIENCE`.
+    fn stamp<'a>(&self, claims: &'a Claims) -> Cow<'a, Claims> {
+        let keep_iss = claims.iss.is_some() || self.issuer.is_none();
+        let keep_aud = claims.aud.is_some() || self.default_audience.is_none();
+        if keep_iss && keep_aud {
+            return Cow::Borrowed(claims);
+        }
+        Cow::Owned(Claims {
+            iss: claims.iss.clone().or_else(|| self.issuer.clone()),
+            aud: claims.aud.clone().or_else(|| self.default_audience.clone().map(Audience::One)),
+            ..claims.clone()
+        })
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let claims = self.verify_allow_expired(token)?;
+        if claims.is_expired(self.expiry_leeway) {
+            return Err(Error::TokenExpired);
+        }
+        Ok(claims)
+    }
+
+    /// The key named by the token's `kid` (current or an active grace key); an expired token
+    /// still yields its claims. Headerless tokens predate `kid`, so with `accept_legacy_tokens`
+    /// one signed by the previous key still gets its grace window.
+    pub fn verify_allow_expired(&self, token: &str) -> Result<Claims> {
+        let prefix = self.token_prefix.as_deref();
+        if let Some(key) = &self.hmac_key {
+            return verify_pinned_allow_expired(token, &PinnedKey::HS256(key.clone()), prefix, self.iat_leeway)
+                .and_then(|c| self.check_audience_present(c));
+        }
+        let claims = match verify_token_with_keys_allow_expired(token, &self.verification_keys(), prefix, self.iat_leeway) {
+            Err(Error::InvalidSignature) if self.accept_legacy_tokens => {
+                self.with_previous_key(|key| verify_token_allow_expired(token, key, prefix, self.iat_leeway))
+            }
+            result => result,
+        };
+        claims.and_then(|c| self.check_audience_present(c))
+    }
+
+    /// Keys a token may currently be signed with, for publication; `Forbidden` in HS256 mode.
+    pub fn public_keys(&self) -> Result<Vec<VerifyingKey>> {
+        self.ensure_publishable()?;
+        Ok(self.active_keys())
+    }
+
+    /// The current key, then the previous key while its grace lasts.
+    fn active_keys(&self) -> Vec<VerifyingKey> {
+        let grace = self.previous_key.as_ref().filter(|k| k.is_active()).map(|k| k.key);
+        std::iter::once(self.verifying_key).chain(grace).collect()
+    }
+
+    /// `verify_allow_expired`, falling back to `minter_keys` for a `kid` that is not ours. Only the
+    /// consuming `/proxy` path takes other minters' tokens; minting from a parent never does.
+    pub fn verify_any_minter_allow_expired(&self, token: &str) -> Result<Claims> {
+        let Some(minters) = &self.minter_keys else {
+            return self.verify_allow_expired(token);
+        };
+        match self.verify_allow_expired(token) {
+            Err(Error::InvalidToken(TokenFault::UnknownKey, _)) => {}
+            result => return result,
+        }
+        minters.verify_allow_expired(token, self.token_prefix.as_deref(), self.iat_leeway).and_then(|c| self.check_audience_present(c))
+    }
+
+    /// `active_keys` by `kid`, plus the current key under `DEFAULT_KID` while legacy tokens are accepted.
+    fn verification_keys(&self) -> HashMap<String, VerifyingKey> {
+        let mut keys: HashMap<String, VerifyingKey> = self.active_keys().iter().map(|k| (key_id(k), *k)).collect();
+        if self.accept_legacy_tokens {
+            keys.insert(DEFAULT_KID.to_owned(), self.verifying_key);
+        }
+        keys
+    }
+
+    /// Binary-form counterpart of `verify_allow_expired`; binary tokens are Ed25519-only.
+    pub fn verify_binary_allow_expired(&self, token: &[u8]) -> Result<Claims> {
+        if self.hmac_key.is_some() {
+            return Err(Error::InvalidToken(TokenFault::AlgorithmMismatch, "binary tokens are not accepted in HS256 mode".into()));
+        }
+        self.with_grace_key(|key| verify_token_binary_allow_expired(token, key, self.iat_leeway))
+            .and_then(|c| self.check_audience_present(c))
+    }
+
+    /// A `CRYPTO_CONCURRENCY` permit, taken before a signing or verifying request has any side
+    /// effects and held until it completes; shed with 503 when none frees up in time.
+    pub async fn crypto_permit(&self) -> Result<tokio::sync::SemaphorePermit<'_>> {
+        self.crypto_limits.acquire().await.inspect_err(|_| {
+            tracing::warn!("crypto concurrency limit reached; request shed");
+            self.metrics.record_crypto_shed();
+        })
+    }
+
+    /// With `REQUIRE_AUDIENCE`, a token bound to no service is not accepted anywhere.
+    pub(crate) fn check_audience_present(&self, claims: Claims) -> Result<Claims> {
+        if self.require_audience && claims.aud.is_none() {
+            return Err(Error::InvalidToken(TokenFault::WrongAudience, "token carries no audience".into()));
+        }
+        Ok(claims)
+    }
+
+    fn with_grace_key(&self, verify: impl Fn(&VerifyingKey) -> Result<Claims>) -> Result<Claims> {
+        match verify(&self.verifying_key) {
+            Err(Error::InvalidSignature) => self.with_previous_key(verify),
+            result => result,
+        }
+    }
+
+    fn with_previous_key(&self, verify: impl Fn(&VerifyingKey) -> Result<Claims>) -> Result<Claims> {
+        match self.previous_key.as_ref().filter(|k| k.is_active()) {
+            Some(grace) => verify(&grace.key),
+            None => Err(Error::InvalidSignature),
         }
     }
 }
 
-struct StateBuilder {
-    audit: AuditLog,
-    policy: PolicyEngine,
-    oidc: Option<OidcVerifier>,
-    webauthn: Option<WebAuthnState>,
+const SECONDS_PER_DAY: i64 = 86_400;
+pub const TEST_ADMIN_KEY: &str = "test-admin-key";
+
+pub(crate) struct StateBuilder {
+    pub(crate) signing_key: Option<SigningKey>,
+    pub(crate) signing_key_created: Option<DateTime<Utc>>,
+    /// Verify-only: the public key to accept; no signing key is generated.
+    pub(crate) verify_only: Option<VerifyingKey>,
+    /// HS256 mode: no Ed25519 signing key is loaded or generated.
+    pub(crate) hmac_key: Option<hmac::Key>,
+    pub(crate) previous_key: Option<GraceKey>,
+    pub(crate) minter_keys: Option<KeySet>,
+    pub(crate) require_oidc: bool,
+    pub(crate) admin_key: Option<String>,
+    pub(crate) token_prefix: Option<String>,
+    pub(crate) issuer: Option<String>,
+    pub(crate) default_audience: Option<String>,
+    pub(crate) require_audience: bool,
+    pub(crate) proxy_audience: Option<String>,
+    pub(crate) accept_legacy_tokens: bool,
+    pub(crate) expiry_leeway: Duration,
+    pub(crate) iat_leeway: Duration,
+    pub(crate) proxy_allowed_issuers: Option<Vec<String>>,
+    pub(crate) exchange_audiences: Option<Vec<String>>,
+    pub(crate) verification_audiences: Vec<String>,
+    pub(crate) metrics_history: MetricsHistory,
+    pub(crate) audit: AuditLog,
+    pub(crate) audit_writer: Option<AuditWriter>,
+    pub(crate) audit_retention: Option<AuditRetention>,
+    pub(crate) storage: Storage,
+    pub(crate) policy: PolicyEngine,
+    pub(crate) ttl_floor: TtlFloor,
+    pub(crate) batch_limits: BatchLimits,
+    pub(crate) crypto_limits: CryptoLimits,
+    pub(crate) rate_limits: RateLimitConfig,
+    pub(crate) mint_daily_quota: Option<u64>,
+    pub(crate) max_scopes: usize,
+    pub(crate) audit_bundle_max_entries: usize,
+    pub(crate) normalize_actions: bool,
+    pub(crate) strict_requests: bool,
+    pub(crate) cors: CorsSettings,
+    pub(crate) health_body: HealthBody,
+    pub(crate) slow_request_threshold: Duration,
+    pub(crate) oidc: Option<OidcVerifier>,
+    pub(crate) oidc_cache_capacity: usize,
+    pub(crate) cap_ttl_to_id_token: bool,
+    pub(crate) webauthn: Option<WebAuthnState>,
+    pub(crate) mint_ip_allowlist: Option<IpAllowlist>,
+    pub(crate) trusted_proxies: Option<IpAllowlist>,
+    pub(crate) request_spike: Option<SpikeSettings>,
+    pub(crate) route_prefix: Option<String>,
+    pub(crate) ops_routes_at_root: bool,
 }
 
 impl StateBuilder {
-    fn build(self) -> AppState {
-        let signing_key = generate_keypair();
-        let verifying_key = signing_key.verifying_key();
-        let require_oidc = std::env::var("REQUIRE_OIDC").map(|v| v == "true").unwrap_or(false);
+    /// `Config` has already refused `REQUIRE_OIDC` without a verifier unless `ALLOW_OIDC_LOCKDOWN` is set.
+    pub(crate) fn build(self) -> Result<AppState> {
+        if self.require_oidc && self.oidc.is_none() {
+            tracing::warn!("REQUIRE_OIDC=true without OIDC: minting is locked down (ALLOW_OIDC_LOCKDOWN=true)");
+        }
+        let (signing_key, verifying_key) = match (self.verify_only, &self.hmac_key) {
+            (Some(key), _) => (None, key),
+            (None, Some(_)) => (None, generate_keypair().verifying_key()),
+            (None, None) => {
+                let signing_key = self.signing_key.unwrap_or_else(generate_keypair);
+                let verifying_key = signing_key.verifying_key();
+                (Some(signing_key), verifying_key)
+            }
+        };
 
-        if require_oidc && self.oidc.is_none() {
-            tracing::warn!("REQUIRE_OIDC=true but no OIDC configured");
+        let signing_key_created = self.signing_key_created.unwrap_or_else(Utc::now);
+        let metrics = Metrics::new();
+        if signing_key.is_some() {
+            metrics.set_signing_key_created(signing_key_created);
         }
 
-        Arc::new(AppStateInner {
+        Ok(Arc::new(AppStateInner {
             signing_key,
+            signing_key_created,
             verifying_key,
-            jti_store: JtiStore::new(),
-            audit_log: self.audit,
-            metrics: Metrics::new(),
+            hmac_key: self.hmac_key,
+            previous_key: self.previous_key,
+            minter_keys: self.minter_keys,
+            jti_store: self.storage.replay,
+            revocations: self.storage.revocations,
+            quotas: self.storage.quotas,
+            credentials: self.storage.credentials,
+            challenges: self.storage.challenges,
+            refresh_store: RefreshStore::new().retaining_past_expiry(self.expiry_leeway),
+            preauth_store: PreauthStore::new().retaining_past_expiry(self.expiry_leeway),
+            audit_log: Arc::new(self.audit),
+            audit_writer: self.audit_writer,
+            audit_retention: self.audit_retention,
+            metrics,
+            metrics_history: self.metrics_history,
+            heartbeats: Heartbeats::new(),
             policy: self.policy,
+            ttl_floor: self.ttl_floor,
             oidc: self.oidc,
+            oidc_cache: ResultCache::new(self.oidc_cache_capacity),
+            cap_ttl_to_id_token: self.cap_ttl_to_id_token,
             webauthn: self.webauthn,
-            rate_limiter: RateLimiter::new(RateLimitConfig::default()),
-            require_oidc,
+            rate_limiter: RateLimiter::new(self.rate_limits),
+            mint_daily_quota: self.mint_daily_quota,
+            max_scopes: self.max_scopes,
+            audit_bundle_max_entries: self.audit_bundle_max_entries,
+            normalize_actions: self.normalize_actions,
+            strict_requests: self.strict_requests,
+            cors: self.cors,
+            health_body: self.health_body,
+            batch_limits: self.batch_limits,
+            crypto_limits: self.crypto_limits,
+            slow_request_threshold: self.slow_request_threshold,
+            require_oidc: self.require_oidc,
+            admin_key: self.admin_key,
+            token_prefix: self.token_prefix,
+            issuer: self.issuer,
+            default_audience: self.default_audience,
+            require_audience: self.require_audience,
+            proxy_audience: self.proxy_audience,
+            accept_legacy_tokens: self.accept_legacy_tokens,
+            expiry_leeway: self.expiry_leeway,
+            iat_leeway: self.iat_leeway,
+            proxy_allowed_issuers: self.proxy_allowed_issuers,
+            exchange_audiences: self.exchange_audiences,
+            verification_audiences: self.verification_audiences,
+            verification_bundle: OnceLock::new(),
+            mint_ip_allowlist: self.mint_ip_allowlist,
+            trusted_proxies: self.trusted_proxies,
             request_count: AtomicU64::new(0),
-        })
+            request_spike: self.request_spike.map(SpikeDetector::new).transpose()?,
+            route_prefix: self.route_prefix,
+            ops_routes_at_root: self.ops_routes_at_root,
+        }))
     }
 }
 
-pub fn build_state(db_path: &str) -> Result<AppState> {
-    Ok(StateBuilder {
-        audit: AuditLog::open(db_path)?,
+pub fn build_state(config: Config, db_path: &str) -> Result<AppState> {
+    let webauthn = config
+        .webauthn
+        .map(|w| {
+            WebAuthnState::new(&w.rp_id, &w.rp_origin)
+                .map(|wa| wa.with_challenge_cap(w.max_challenges_per_user).with_strict_user_ids(w.strict_user_ids))
+        })
+        .transpose()
+        .map_err(|e| Error::Config(format!("WebAuthn: {e:?}")))?;
+    let verification_audiences = match (config.verification_audiences, &config.default_audience) {
+        (Some(audiences), _) => audiences,
+        (None, default) => default.iter().cloned().collect(),
+    };
+    let signing_key = config.signing_key_path.as_deref().map(load_signing_key).transpose()?;
+    let signing_key_created = match (&signing_key, config.signing_key_path.as_deref()) {
+        (Some(key), Some(path)) => Some(config.signing_key_created_at.unwrap_or_else(|| key_first_seen(path, &key.verifying_key()))),
+        _ => None,
+    };
+    StateBuilder {
+        signing_key,
+        signing_key_created,
+        verify_only: config.verifying_key_path.as_deref().map(load_verifying_key).transpose()?,
+        hmac_key: config.hmac_secret.as_deref().map(|s| hmac_key(s.as_bytes())),
+        previous_key: config.previous_key.map(|p| load_previous_key(&p.path, p.grace_seconds)).transpose()?,
+        minter_keys: config.minter_keys.map(|m| KeySet::load(&m.path, m.reload_every)).transpose()?,
+        require_oidc: config.require_oidc,
+        admin_key: config.admin_key,
+        token_prefix: config.token_prefix,
+        issuer: config.token_issuer,
+        default_audience: config.default_audience,
+        require_audience: config.require_audience,
+        proxy_audience: config.proxy_audience,
+        accept_legacy_tokens: config.accept_legacy_tokens,
+        expiry_leeway: config.expiry_leeway,
+        iat_leeway: config.iat_leeway,
+        proxy_allowed_issuers: config.proxy_allowed_issuers,
+        exchange_audiences: config.exchange_audiences,
+        verification_audiences,
+        metrics_history: MetricsHistory::new(config.metrics_history_depth, config.metrics_history_interval),
+        audit: AuditLog::open(db_path)?.with_jti_reuse(config.audit_allow_jti_reuse)?.with_sinks(sink::open(&config.audit_sinks)?),
+        audit_writer: config.audit_queue.map(AuditWriter::from_settings),
+        audit_retention: config.audit_retention,
+        storage: Storage::open(config.storage_backend, db_path)?,
         policy: PolicyEngine::from_default_file(),
-        oidc: OidcVerifier::from_env(),
-        webauthn: WebAuthnState::from_env(),
-    }.build())
+        ttl_floor: config.ttl_floor,
+        batch_limits: BatchLimits::new(config.max_batch_size, config.batch_concurrency),
+        crypto_limits: config.crypto_concurrency.map(CryptoLimits::new).unwrap_or_default(),
+        rate_limits: config.rate_limits,
+        mint_daily_quota: config.mint_daily_quota,
+        max_scopes: config.max_scopes,
+        audit_bundle_max_entries: config.audit_bundle_max_entries,
+        normalize_actions: config.normalize_actions,
+        strict_requests: config.strict_requests,
+        cors: config.cors,
+        health_body: config.health_body,
+        slow_request_threshold: config.slow_request_threshold,
+        oidc: config.oidc.map(|o| OidcVerifier::new(&o.issuer, &o.audience, &o.jwks_uri).with_leeway(config.expiry_leeway)),
+        oidc_cache_capacity: config.oidc_cache_capacity,
+        cap_ttl_to_id_token: config.cap_ttl_to_id_token,
+        webauthn,
+        mint_ip_allowlist: config.mint_ip_allowlist,
+        trusted_proxies: config.trusted_proxies,
+        request_spike: config.request_spike,
+        route_prefix: config.route_prefix,
+        ops_routes_at_root: config.ops_routes_at_root,
+    }.build()
 }
 
 pub fn build_test_state() -> Result<AppState> {
+    test_builder()?.build()
+}
+
+pub(crate) fn test_builder() -> Result<StateBuilder> {
     Ok(StateBuilder {
+        signing_key: None,
+        signing_key_created: None,
+        verify_only: None,
+        hmac_key: None,
+        previous_key: None,
+        minter_keys: None,
+        require_oidc: false,
+        admin_key: Some(TEST_ADMIN_KEY.into()),
+        token_prefix: None,
+        issuer: None,
+        default_audience: None,
+        require_audience: false,
+        proxy_audience: None,
+        accept_legacy_tokens: true,
+        expiry_leeway: DEFAULT_EXPIRY_LEEWAY,
+        iat_leeway: DEFAULT_IAT_LEEWAY,
+        proxy_allowed_issuers: None,
+        exchange_audiences: None,
+        verification_audiences: Vec::new(),
+        metrics_history: MetricsHistory::new(60, std::time::Duration::from_secs(60)),
         audit: AuditLog::open_in_memory()?,
+        audit_writer: None,
+        audit_retention: None,
+        storage: Storage::memory(),
         policy: PolicyEngine::default(),
+        ttl_floor: TtlFloor::default(),
+        batch_limits: BatchLimits::default(),
+        crypto_limits: CryptoLimits::default(),
+        rate_limits: RateLimitConfig::default(),
+        mint_daily_quota: None,
+        max_scopes: DEFAULT_MAX_SCOPES,
+        audit_bundle_max_entries: DEFAULT_AUDIT_BUNDLE_ENTRIES,
+        normalize_actions: true,
+        strict_requests: false,
+        cors: CorsSettings::default(),
+        health_body: HealthBody::default(),
+        slow_request_threshold: DEFAULT_SLOW_REQUEST,
         oidc: None,
+        oidc_cache_capacity: DEFAULT_RESULT_CACHE_CAPACITY,
+        cap_ttl_to_id_token: false,
         webauthn: None,
-    }.build())
+        mint_ip_allowlist: None,
+        trusted_proxies: None,
+        request_spike: None,
+        route_prefix: None,
+        ops_routes_at_root: false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ratelimit::RateExemptions;
+
+    #[tokio::test]
+    async fn revoking_a_root_revokes_every_descendant() -> Result<()> {
+        let state = test_builder()?.build()?;
+        let root = Claims::new("alice".into(), "deploy".into(), 300);
+        let child = Claims::new_delegated("agent-1".into(), "deploy".into(), 300, &root);
+        let grandchild = Claims::new_delegated("agent-2".into(), "deploy".into(), 300, &child);
+        assert_eq!(grandchild.ancestors.as_deref(), Some([root.jti.clone(), child.jti.clone()].as_slice()));
+
+        state.check_revocation(&grandchild).await?;
+        state.revoke(Some(&root.jti), None, chrono::Utc::now().timestamp() + 600).await?;
+        assert!(matches!(state.check_revocation(&grandchild).await, Err(Error::Revoked(_))));
+        assert!(matches!(state.check_revocation(&child).await, Err(Error::Revoked(_))));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exempt_subject_skips_the_mint_quota_only_when_verified() -> Result<()> {
+        let mut builder = test_builder()?;
+        builder.rate_limits.exemptions = RateExemptions::parse(None, Some("healthcheck"))?;
+        builder.mint_daily_quota = Some(1);
+        let state = builder.build()?;
+
+        for _ in 0..3 {
+            state.consume_mint_quota("healthcheck", Some("healthcheck")).await?;
+        }
+        state.consume_mint_quota("healthcheck", None).await?;
+        assert!(matches!(state.consume_mint_quota("healthcheck", None).await, Err(Error::RateLimited(_))));
+        let snapshot = state.metrics.snapshot();
+        assert_eq!((snapshot.rate_limit_exempt, snapshot.rate_limited), (3, 1));
+        Ok(())
+    }
+
+    fn token_from_previous_key(previous: &SigningKey) -> Result<String> {
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        sign_token_with_prefix(&claims, previous, None)
+    }
+
+    #[test]
+    fn previous_key_verifies_during_grace() -> Result<()> {
+        let previous = generate_keypair();
+        let token = token_from_previous_key(&previous)?;
+        let grace = GraceKey::new(previous.verifying_key(), chrono::Duration::seconds(60));
+        let state = StateBuilder { previous_key: Some(grace), ..test_builder()? }.build()?;
+        assert_eq!(state.verify(&token)?.sub, "agent-1");
+        Ok(())
+    }
+
+    /// Two-segment `payload.signature` token, as minted before the `kid` header.
+    fn legacy_token(key: &SigningKey) -> Result<String> {
+        use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+        use base64::Engine;
+        use ed25519_dalek::Signer;
+
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&Claims::new("agent-1".into(), "deploy".into(), 60))?);
+        Ok(format!("{payload}.{}", URL_SAFE_NO_PAD.encode(key.sign(payload.as_bytes()).to_bytes())))
+    }
+
+    #[test]
+    fn legacy_tokens_accepted_only_while_enabled() -> Result<()> {
+        let state = test_builder()?.build()?;
+        let legacy = legacy_token(state.signing_key()?)?;
+        let current = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        assert_eq!(state.verify(&legacy)?.sub, "agent-1");
+        assert_eq!(state.verify(&current)?.sub, "agent-1");
+
+        let mut builder = test_builder()?;
+        builder.accept_legacy_tokens = false;
+        let state = builder.build()?;
+        let legacy = legacy_token(state.signing_key()?)?;
+        let current = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        assert!(matches!(state.verify(&legacy), Err(Error::InvalidToken(TokenFault::LegacyFormat, _))));
+        assert_eq!(state.verify(&current)?.sub, "agent-1");
+        Ok(())
+    }
+
+    #[test]
+    fn headerless_token_from_previous_key_verifies_during_grace() -> Result<()> {
+        let previous = generate_keypair();
+        let token = legacy_token(&previous)?;
+        let grace = GraceKey::new(previous.verifying_key(), chrono::Duration::seconds(60));
+        let state = StateBuilder { previous_key: Some(grace), ..test_builder()? }.build()?;
+        assert_eq!(state.verify(&token)?.sub, "agent-1");
+        assert!(matches!(test_builder()?.build()?.verify(&token), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn previous_key_rejected_when_removed_or_expired() -> Result<()> {
+        let previous = generate_keypair();
+        let token = token_from_previous_key(&previous)?;
+
+        let state = test_builder()?.build()?;
+        assert!(matches!(state.verify(&token), Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+
+        let expired = GraceKey::new(previous.verifying_key(), chrono::Duration::seconds(-1));
+        let state = StateBuilder { previous_key: Some(expired), ..test_builder()? }.build()?;
+        assert!(matches!(state.verify(&token), Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn required_audience_rejects_tokens_minted_without_one() -> Result<()> {
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        let state = StateBuilder { require_audience: true, ..test_builder()? }.build()?;
+        let result = state.verify(&state.sign(&claims)?);
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+
+        let mut scoped = claims.clone();
+        scoped.aud = Some(Audience::One("billing".into()));
+        assert!(state.verify(&state.sign(&scoped)?).is_ok());
+        Ok(())
+    }
+
+    #[test]
+    fn default_audience_stamped_and_verifies_under_enforcement() -> Result<()> {
+        let builder = StateBuilder {
+            default_audience: Some("billing".into()),
+            require_audience: true,
+            ..test_builder()?
+        };
+        let state = builder.build()?;
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        let verified = state.verify(&state.sign(&claims)?)?;
+        assert_eq!(verified.aud, Some(Audience::One("billing".into())));
+
+        let mut explicit = claims;
+        explicit.aud = Some(Audience::One("search".into()));
+        assert_eq!(state.verify(&state.sign(&explicit)?)?.aud, explicit.aud);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn request_burst_fires_spike_webhook() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let app = axum::Router::new().route(
+            "/hook",
+            axum::routing::post(move |axum::Json(event): axum::Json<serde_json::Value>| async move {
+                let _ = tx.send(event);
+            }),
+        );
+        let receiver = crate::testing::TestServer::spawn_router(build_test_state()?, app).await?;
+
+        let mut builder = test_builder()?;
+        builder.request_spike = Some(SpikeSettings {
+            threshold_per_sec: 1,
+            window: Duration::from_secs(60),
+            webhook: Some(receiver.url("/hook").parse()?),
+        });
+        let state = builder.build()?;
+        for _ in 0..60 {
+            state.increment_requests();
+        }
+        assert_eq!(state.metrics.snapshot().request_spikes, 0);
+        for _ in 0..10 {
+            state.increment_requests();
+        }
+        assert_eq!(state.metrics.snapshot().request_spikes, 1);
+
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await?.ok_or("webhook not called")?;
+        assert_eq!(event["event"], "request_spike");
+        assert_eq!(event["requests"], 61);
+        Ok(receiver.shutdown().await?)
+    }
+
+    #[test]
+    fn lockdown_builds_without_a_verifier() -> Result<()> {
+        let builder = StateBuilder { require_oidc: true, ..test_builder()? };
+        assert!(builder.build()?.require_oidc);
+        Ok(())
+    }
 }
@@ -0,0 +1,178 @@
+//! In-memory storage backends (the default).
+//! Used by: storage.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+
+use crate::error::{Error, Result, lock_err};
+use crate::jti::memory::JtiStore;
+use crate::storage::{ChallengeStore, CredentialStore, QuotaStore, ReplayGuard, RevocationStore};
+
+const MAX_REVOCATIONS: usize = 100_000;
+const MAX_CHALLENGES: usize = 10_000;
+const MAX_QUOTA_KEYS: usize = 100_000;
+
+#[async_trait]
+impl ReplayGuard for JtiStore {
+    async fn check_and_insert(&self, jti: &str, exp: i64) -> Result<()> {
+        JtiStore::check_and_insert(self, jti, exp)
+    }
+
+    async fn was_consumed(&self, jti: &str) -> Result<bool> {
+        JtiStore::contains(self, jti)
+    }
+}
+
+pub struct MemoryRevocations {
+    entries: Mutex<HashMap<Box<str>, i64>>,
+}
+
+impl MemoryRevocations {
+    pub fn new() -> Self {
+        Self { entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl RevocationStore for MemoryRevocations {
+    async fn revoke(&self, key: &str, until: i64) -> Result<()> {
+        let mut entries = self.entries.lock().map_err(lock_err("revocations"))?;
+        let now = chrono::Utc::now().timestamp();
+        entries.retain(|_, exp| *exp > now);
+        if entries.len() >= MAX_REVOCATIONS && !entries.contains_key(key) {
+            return Err(Error::CapacityExceeded("revocation store at capacity".into()));
+        }
+        let exp = entries.entry(key.into()).or_insert(until);
+        *exp = (*exp).max(until);
+        Ok(())
+    }
+
+    async fn is_revoked(&self, key: &str) -> Result<bool> {
+        let entries = self.entries.lock().map_err(lock_err("revocations"))?;
+        let now = chrono::Utc::now().timestamp();
+        Ok(entries.get(key).is_some_and(|exp| *exp > now))
+    }
+}
+
+/// Lost on restart; use the SQLite backend when quotas must survive one.
+pub struct MemoryQuotas {
+    entries: Mutex<HashMap<Box<str>, (i64, u64)>>,
+}
+
+impl MemoryQuotas {
+    pub fn new() -> Self {
+        Self { entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl QuotaStore for MemoryQuotas {
+    async fn consume(&self, key: &str, window: i64, limit: u64) -> Result<bool> {
+        let mut entries = self.entries.lock().map_err(lock_err("quotas"))?;
+        if !entries.contains_key(key) && entries.len() >= MAX_QUOTA_KEYS {
+            entries.retain(|_, (w, _)| *w >= window);
+            if entries.len() >= MAX_QUOTA_KEYS {
+                return Err(Error::CapacityExceeded("quota store at capacity".into()));
+            }
+        }
+        let (w, used) = entries.entry(key.into()).or_insert((window, 0));
+        if *w < window {
+            *w = window;
+            *used = 0;
+        }
+        if *used >= limit {
+            return Ok(false);
+        }
+        *used += 1;
+        Ok(true)
+    }
+}
+
+pub struct MemoryCredentials {
+    entries: Mutex<HashMap<Box<str>, String>>,
+    handles: Mutex<HashMap<Box<str>, String>>,
+}
+
+impl MemoryCredentials {
+    pub fn new() -> Self {
+        Self { entries: Mutex::new(HashMap::new()), handles: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl CredentialStore for MemoryCredentials {
+    async fn get(&self, user_id: &str) -> Result<Option<String>> {
+        let entries = self.entries.lock().map_err(lock_err("credentials"))?;
+        Ok(entries.get(user_id).cloned())
+    }
+
+    async fn insert(&self, user_id: &str, credential: String, replace: bool) -> Result<bool> {
+        let mut entries = self.entries.lock().map_err(lock_err("credentials"))?;
+        if !replace && entries.contains_key(user_id) {
+            return Ok(false);
+        }
+        entries.insert(user_id.into(), credential);
+        Ok(true)
+    }
+
+    async fn user_handle(&self, user_id: &str) -> Result<Option<String>> {
+        let handles = self.handles.lock().map_err(lock_err("credentials"))?;
+        Ok(handles.get(user_id).cloned())
+    }
+
+    async fn set_user_handle(&self, user_id: &str, handle: String) -> Result<()> {
+        self.handles.lock().map_err(lock_err("credentials"))?.insert(user_id.into(), handle);
+        Ok(())
+    }
+}
+
+pub struct MemoryChallenges {
+    entries: Mutex<HashMap<Box<str>, (String, Instant)>>,
+}
+
+impl MemoryChallenges {
+    pub fn new() -> Self {
+        Self { entries: Mutex::new(HashMap::new()) }
+    }
+}
+
+#[async_trait]
+impl ChallengeStore for MemoryChallenges {
+    async fn put(&self, key: &str, challenge: String, ttl: Duration) -> Result<()> {
+        self.put_capped(key, challenge, ttl, &[], 1).await.map(|_| ())
+    }
+
+    async fn put_capped(&self, key: &str, challenge: String, ttl: Duration, siblings: &[String], cap: usize) -> Result<bool> {
+        let mut entries = self.entries.lock().map_err(lock_err("challenges"))?;
+        let now = Instant::now();
+        entries.retain(|_, (_, expires)| *expires > now);
+        let live = siblings.iter().filter(|s| s.as_str() != key && entries.contains_key(s.as_str())).count();
+        if live >= cap {
+            return Ok(false);
+        }
+        if entries.len() >= MAX_CHALLENGES {
+            return Err(Error::CapacityExceeded("challenge store at capacity".into()));
+        }
+        entries.insert(key.into(), (challenge, now + ttl));
+        Ok(true)
+    }
+
+    async fn take(&self, key: &str) -> Result<Option<String>> {
+        let mut entries = self.entries.lock().map_err(lock_err("challenges"))?;
+        Ok(entries
+            .remove(key)
+            .filter(|(_, expires)| Instant::now() < *expires)
+            .map(|(challenge, _)| challenge))
+    }
+
+    async fn peek(&self, key: &str) -> Result<Option<String>> {
+        let entries = self.entries.lock().map_err(lock_err("challenges"))?;
+        Ok(entries
+            .get(key)
+            .filter(|(_, expires)| Instant::now() < *expires)
+            .map(|(challenge, _)| challenge.clone()))
+    }
+}
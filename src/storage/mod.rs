@@ -0,0 +1,225 @@
+//! Pluggable storage for replay protection, revocation, long-window quotas, and WebAuthn state.
+//! Used by: state, handlers::proxy, webauthn.
+
+pub mod memory;
+pub mod sqlite;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+use crate::error::{Error, Result};
+use crate::jti::memory::JtiStore;
+
+/// Single-use JTI tracking; a second insert of the same JTI is a replay.
+#[async_trait]
+pub trait ReplayGuard: Send + Sync {
+    async fn check_and_insert(&self, jti: &str, exp: i64) -> Result<()>;
+    /// Whether `jti` was inserted and has not yet expired out of the guard.
+    async fn was_consumed(&self, jti: &str) -> Result<bool>;
+}
+
+/// Revoked keys (jti or sub), each held until its unix-seconds expiry.
+#[async_trait]
+pub trait RevocationStore: Send + Sync {
+    async fn revoke(&self, key: &str, until: i64) -> Result<()>;
+    async fn is_revoked(&self, key: &str) -> Result<bool>;
+}
+
+/// Use counters for long windows (daily quotas), one per `(key, window)`; older windows are discarded.
+#[async_trait]
+pub trait QuotaStore: Send + Sync {
+    /// Records one use and returns true, or returns false without recording once `limit` is reached.
+    async fn consume(&self, key: &str, window: i64, limit: u64) -> Result<bool>;
+}
+
+/// Serialized WebAuthn credentials keyed by user id.
+#[async_trait]
+pub trait CredentialStore: Send + Sync {
+    async fn get(&self, user_id: &str) -> Result<Option<String>>;
+    /// Returns false without writing when the user exists and `replace` is not set.
+    async fn insert(&self, user_id: &str, credential: String, replace: bool) -> Result<bool>;
+    /// The WebAuthn user handle recorded for `user_id`'s credential.
+    async fn user_handle(&self, user_id: &str) -> Result<Option<String>>;
+    async fn set_user_handle(&self, user_id: &str, handle: String) -> Result<()>;
+}
+
+/// Short-lived serialized ceremony state; `take` is single-use and ignores expired entries.
+#[async_trait]
+pub trait ChallengeStore: Send + Sync {
+    async fn put(&self, key: &str, challenge: String, ttl: Duration) -> Result<()>;
+    /// `put`, unless `cap` of the `siblings` other than `key` are live; checked and written
+    /// atomically. Returns whether it stored.
+    async fn put_capped(&self, key: &str, challenge: String, ttl: Duration, siblings: &[String], cap: usize) -> Result<bool>;
+    async fn take(&self, key: &str) -> Result<Option<String>>;
+    /// Reads a live entry without consuming it.
+    async fn peek(&self, key: &str) -> Result<Option<String>>;
+}
+
+pub struct Storage {
+    pub replay: Arc<dyn ReplayGuard>,
+    pub revocations: Arc<dyn RevocationStore>,
+    pub quotas: Arc<dyn QuotaStore>,
+    pub credentials: Arc<dyn CredentialStore>,
+    pub challenges: Arc<dyn ChallengeStore>,
+}
+
+impl Storage {
+    pub fn memory() -> Self {
+        Self {
+            replay: Arc::new(JtiStore::new()),
+            revocations: Arc::new(memory::MemoryRevocations::new()),
+            quotas: Arc::new(memory::MemoryQuotas::new()),
+            credentials: Arc::new(memory::MemoryCredentials::new()),
+            challenges: Arc::new(memory::MemoryChallenges::new()),
+        }
+    }
+
+    pub fn sqlite(path: &str) -> Result<Self> {
+        let store = Arc::new(sqlite::SqliteStore::open(path)?);
+        Ok(Self {
+            replay: store.clone(),
+            revocations: store.clone(),
+            quotas: store.clone(),
+            credentials: store.clone(),
+            challenges: store,
+        })
+    }
+
+    pub fn open(backend: StorageBackend, db_path: &str) -> Result<Self> {
+        match backend {
+            StorageBackend::Memory => Ok(Self::memory()),
+            StorageBackend::Sqlite => {
+                tracing::info!(path = %db_path, "sqlite storage backend");
+                Self::sqlite(db_path)
+            }
+        }
+    }
+}
+
+/// `STORAGE_BACKEND`: where replay, revocation, quota, and WebAuthn state is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum StorageBackend {
+    #[default]
+    Memory,
+    Sqlite,
+}
+
+impl StorageBackend {
+    pub fn from_lookup(get: &impl Fn(&str) -> Option<String>) -> Result<Self> {
+        match get("STORAGE_BACKEND").as_deref() {
+            None | Some("memory") => Ok(Self::Memory),
+            Some("sqlite") => Ok(Self::Sqlite),
+            Some(other) => Err(Error::Config(format!("STORAGE_BACKEND must be memory or sqlite, got {other}"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn future(seconds: i64) -> i64 {
+        chrono::Utc::now().timestamp() + seconds
+    }
+
+    async fn replay_conformance(guard: &dyn ReplayGuard) -> Result<()> {
+        guard.check_and_insert("jti-1", future(300)).await?;
+        guard.check_and_insert("jti-2", future(300)).await?;
+        assert!(guard.was_consumed("jti-2").await?);
+        assert!(!guard.was_consumed("jti-3").await?);
+        let result = guard.check_and_insert("jti-1", future(300)).await;
+        assert!(matches!(result, Err(Error::ReplayDetected(_))));
+        Ok(())
+    }
+
+    async fn revocation_conformance(store: &dyn RevocationStore) -> Result<()> {
+        assert!(!store.is_revoked("jti-1").await?);
+        store.revoke("jti-1", future(300)).await?;
+        store.revoke("stale", future(-1)).await?;
+        assert!(store.is_revoked("jti-1").await?);
+        assert!(!store.is_revoked("stale").await?);
+        assert!(!store.is_revoked("jti-2").await?);
+        Ok(())
+    }
+
+    async fn quota_conformance(store: &dyn QuotaStore) -> Result<()> {
+        assert!(store.consume("mint:alice", 10, 2).await?);
+        assert!(store.consume("mint:alice", 10, 2).await?);
+        assert!(!store.consume("mint:alice", 10, 2).await?);
+        assert!(store.consume("mint:bob", 10, 2).await?);
+        assert!(store.consume("mint:alice", 11, 2).await?);
+        Ok(())
+    }
+
+    async fn credential_conformance(store: &dyn CredentialStore) -> Result<()> {
+        assert_eq!(store.get("alice").await?, None);
+        assert!(store.insert("alice", "old".into(), false).await?);
+        assert!(!store.insert("alice", "other".into(), false).await?);
+        assert_eq!(store.get("alice").await?.as_deref(), Some("old"));
+        assert!(store.insert("alice", "new".into(), true).await?);
+        assert_eq!(store.get("alice").await?.as_deref(), Some("new"));
+
+        assert_eq!(store.user_handle("alice").await?, None);
+        store.set_user_handle("alice", "handle-1".into()).await?;
+        store.set_user_handle("alice", "handle-2".into()).await?;
+        assert_eq!(store.user_handle("alice").await?.as_deref(), Some("handle-2"));
+        assert_eq!(store.user_handle("bob").await?, None);
+        Ok(())
+    }
+
+    async fn challenge_conformance(store: &dyn ChallengeStore) -> Result<()> {
+        store.put("reg:alice", "state".into(), Duration::from_secs(60)).await?;
+        assert_eq!(store.peek("reg:alice").await?.as_deref(), Some("state"));
+        assert_eq!(store.take("reg:alice").await?.as_deref(), Some("state"));
+        assert_eq!(store.take("reg:alice").await?, None);
+        store.put("auth:bob", "stale".into(), Duration::ZERO).await?;
+        assert_eq!(store.peek("auth:bob").await?, None);
+        assert_eq!(store.take("auth:bob").await?, None);
+
+        let siblings = ["reg:carol".to_owned(), "auth:carol".to_owned()];
+        assert!(store.put_capped("reg:carol", "reg".into(), Duration::from_secs(60), &siblings, 1).await?);
+        assert!(store.put_capped("reg:carol", "reg again".into(), Duration::from_secs(60), &siblings, 1).await?);
+        assert!(!store.put_capped("auth:carol", "auth".into(), Duration::from_secs(60), &siblings, 1).await?);
+        assert_eq!(store.peek("auth:carol").await?, None);
+        assert!(store.put_capped("auth:carol", "auth".into(), Duration::from_secs(60), &siblings, 2).await?);
+        Ok(())
+    }
+
+    async fn conformance(storage: Storage) -> Result<()> {
+        replay_conformance(storage.replay.as_ref()).await?;
+        revocation_conformance(storage.revocations.as_ref()).await?;
+        quota_conformance(storage.quotas.as_ref()).await?;
+        credential_conformance(storage.credentials.as_ref()).await?;
+        challenge_conformance(storage.challenges.as_ref()).await
+    }
+
+    #[tokio::test]
+    async fn memory_backend_conforms() -> Result<()> {
+        conformance(Storage::memory()).await
+    }
+
+    #[tokio::test]
+    async fn sqlite_backend_conforms() -> Result<()> {
+        conformance(Storage::sqlite(":memory:")?).await
+    }
+
+    #[tokio::test]
+    async fn sqlite_quota_survives_reopen() -> Result<()> {
+        let path = std::env::temp_dir().join(format!("agentmint-quota-{}.db", uuid::Uuid::new_v4()));
+        let path = path.to_string_lossy().into_owned();
+        {
+            let storage = Storage::sqlite(&path)?;
+            assert!(storage.quotas.consume("mint:alice", 10, 2).await?);
+            assert!(storage.quotas.consume("mint:alice", 10, 2).await?);
+        }
+        let reopened = Storage::sqlite(&path)?;
+        let exhausted = !reopened.quotas.consume("mint:alice", 10, 2).await?;
+        let other_subject = reopened.quotas.consume("mint:bob", 10, 2).await?;
+        std::fs::remove_file(&path).ok();
+        assert!(exhausted, "consumed quota was reset by reopening the store");
+        assert!(other_subject);
+        Ok(())
+    }
+}
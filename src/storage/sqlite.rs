@@ -0,0 +1,219 @@
+//! SQLite storage backend, shared across instances that point at the same database file.
+//! Used by: storage.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use rusqlite::{Connection, OptionalExtension, TransactionBehavior, params};
+
+use crate::error::{Error, Result, lock_err};
+use crate::storage::{ChallengeStore, CredentialStore, QuotaStore, ReplayGuard, RevocationStore};
+
+pub struct SqliteStore {
+    conn: Mutex<Connection>,
+}
+
+fn now_secs() -> i64 {
+    chrono::Utc::now().timestamp()
+}
+
+fn now_millis() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+impl SqliteStore {
+    pub fn open(path: &str) -> Result<Self> {
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS replay_jti (
+                jti TEXT PRIMARY KEY,
+                exp INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS revocations (
+                key TEXT PRIMARY KEY,
+                until INTEGER NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS quota_windows (
+                key TEXT NOT NULL,
+                window_start INTEGER NOT NULL,
+                used INTEGER NOT NULL,
+                PRIMARY KEY (key, window_start)
+            );
+            CREATE TABLE IF NOT EXISTS webauthn_credentials (
+                user_id TEXT PRIMARY KEY,
+                credential TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS webauthn_user_handles (
+                user_id TEXT PRIMARY KEY,
+                handle TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS webauthn_challenges (
+                key TEXT PRIMARY KEY,
+                challenge TEXT NOT NULL,
+                expires_at_ms INTEGER NOT NULL
+            );",
+        )?;
+        Ok(Self { conn: Mutex::new(conn) })
+    }
+}
+
+#[async_trait]
+impl ReplayGuard for SqliteStore {
+    async fn check_and_insert(&self, jti: &str, exp: i64) -> Result<()> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        conn.execute("DELETE FROM replay_jti WHERE exp <= ?1", params![now_secs()])?;
+        let inserted = conn.execute("INSERT OR IGNORE INTO replay_jti (jti, exp) VALUES (?1, ?2)", params![jti, exp])?;
+        if inserted == 0 {
+            return Err(Error::ReplayDetected(jti.to_owned()));
+        }
+        Ok(())
+    }
+
+    async fn was_consumed(&self, jti: &str) -> Result<bool> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM replay_jti WHERE jti = ?1 AND exp > ?2)",
+            params![jti, now_secs()],
+            |row| row.get(0),
+        )?)
+    }
+}
+
+#[async_trait]
+impl RevocationStore for SqliteStore {
+    async fn revoke(&self, key: &str, until: i64) -> Result<()> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        conn.execute("DELETE FROM revocations WHERE until <= ?1", params![now_secs()])?;
+        conn.execute(
+            "INSERT INTO revocations (key, until) VALUES (?1, ?2)
+                ON CONFLICT(key) DO UPDATE SET until = MAX(until, excluded.until)",
+            params![key, until],
+        )?;
+        Ok(())
+    }
+
+    async fn is_revoked(&self, key: &str) -> Result<bool> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        Ok(conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM revocations WHERE key = ?1 AND until > ?2)",
+            params![key, now_secs()],
+            |row| row.get(0),
+        )?)
+    }
+}
+
+#[async_trait]
+impl QuotaStore for SqliteStore {
+    async fn consume(&self, key: &str, window: i64, limit: u64) -> Result<bool> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        conn.execute("DELETE FROM quota_windows WHERE key = ?1 AND window_start < ?2", params![key, window])?;
+        let limit = i64::try_from(limit).unwrap_or(i64::MAX);
+        let changed = conn.execute(
+            "INSERT INTO quota_windows (key, window_start, used) SELECT ?1, ?2, 1 WHERE ?3 > 0
+                ON CONFLICT(key, window_start) DO UPDATE SET used = used + 1 WHERE used < ?3",
+            params![key, window, limit],
+        )?;
+        Ok(changed > 0)
+    }
+}
+
+#[async_trait]
+impl CredentialStore for SqliteStore {
+    async fn get(&self, user_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        Ok(conn
+            .query_row(
+                "SELECT credential FROM webauthn_credentials WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    async fn insert(&self, user_id: &str, credential: String, replace: bool) -> Result<bool> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        let sql = match replace {
+            true => "INSERT OR REPLACE INTO webauthn_credentials (user_id, credential) VALUES (?1, ?2)",
+            false => "INSERT OR IGNORE INTO webauthn_credentials (user_id, credential) VALUES (?1, ?2)",
+        };
+        Ok(conn.execute(sql, params![user_id, credential])? > 0)
+    }
+
+    async fn user_handle(&self, user_id: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        Ok(conn
+            .query_row(
+                "SELECT handle FROM webauthn_user_handles WHERE user_id = ?1",
+                params![user_id],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    async fn set_user_handle(&self, user_id: &str, handle: String) -> Result<()> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        conn.execute(
+            "INSERT OR REPLACE INTO webauthn_user_handles (user_id, handle) VALUES (?1, ?2)",
+            params![user_id, handle],
+        )?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl ChallengeStore for SqliteStore {
+    async fn put(&self, key: &str, challenge: String, ttl: Duration) -> Result<()> {
+        self.put_capped(key, challenge, ttl, &[], 1).await.map(|_| ())
+    }
+
+    /// An immediate transaction, so instances sharing the file cannot both pass the check.
+    async fn put_capped(&self, key: &str, challenge: String, ttl: Duration, siblings: &[String], cap: usize) -> Result<bool> {
+        let mut conn = self.conn.lock().map_err(lock_err("storage"))?;
+        let tx = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+        let now = now_millis();
+        let ttl_ms = i64::try_from(ttl.as_millis()).unwrap_or(i64::MAX);
+        tx.execute("DELETE FROM webauthn_challenges WHERE expires_at_ms <= ?1", params![now])?;
+        let mut live = 0;
+        for sibling in siblings.iter().filter(|s| s.as_str() != key) {
+            let found = tx
+                .query_row("SELECT 1 FROM webauthn_challenges WHERE key = ?1", params![sibling], |_| Ok(()))
+                .optional()?;
+            live += usize::from(found.is_some());
+        }
+        if live >= cap {
+            return Ok(false);
+        }
+        tx.execute(
+            "INSERT OR REPLACE INTO webauthn_challenges (key, challenge, expires_at_ms) VALUES (?1, ?2, ?3)",
+            params![key, challenge, now.saturating_add(ttl_ms)],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    async fn take(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        let entry: Option<(String, i64)> = conn
+            .query_row(
+                "SELECT challenge, expires_at_ms FROM webauthn_challenges WHERE key = ?1",
+                params![key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        conn.execute("DELETE FROM webauthn_challenges WHERE key = ?1", params![key])?;
+        Ok(entry.filter(|(_, expires)| now_millis() < *expires).map(|(challenge, _)| challenge))
+    }
+
+    async fn peek(&self, key: &str) -> Result<Option<String>> {
+        let conn = self.conn.lock().map_err(lock_err("storage"))?;
+        let challenge = conn
+            .query_row(
+                "SELECT challenge FROM webauthn_challenges WHERE key = ?1 AND expires_at_ms > ?2",
+                params![key, now_millis()],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(challenge)
+    }
+}
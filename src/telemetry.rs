@@ -1,20 +1,189 @@
 //! Metrics tracking.
 
+use chrono::{DateTime, Utc};
 use serde::Serialize;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicU8, Ordering};
+use std::time::Duration;
+use utoipa::ToSchema;
+
+use crate::heartbeat::Heartbeats;
+
+const MAX_TRACKED_SUBJECTS: usize = 10_000;
+pub(crate) const DEFAULT_HISTORY_DEPTH: usize = 60;
+pub(crate) const DEFAULT_HISTORY_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_TRACKED_ROUTES: usize = 256;
+const SAMPLER_TASK: &str = "metrics_sampler";
+/// Upper bounds (ms) of the latency buckets; a final overflow bucket catches the rest.
+pub const LATENCY_BUCKETS_MS: [u64; 10] = [1, 5, 10, 25, 50, 100, 250, 500, 1000, 5000];
+/// Inclusive upper bounds (seconds) of the minted-TTL buckets; a final overflow bucket catches the rest.
+pub const TTL_BUCKETS_SECS: [u64; 6] = [10, 30, 60, 120, 300, 900];
+
+pub struct SubjectCounter {
+    counts: Mutex<HashMap<Box<str>, u64>>,
+    max_subjects: usize,
+}
+
+#[derive(Debug, Serialize, ToSchema, PartialEq)]
+pub struct SubjectCount {
+    pub sub: String,
+    pub count: u64,
+}
+
+impl SubjectCounter {
+    pub fn new(max_subjects: usize) -> Self {
+        Self {
+            counts: Mutex::new(HashMap::new()),
+            max_subjects,
+        }
+    }
+
+    pub fn increment(&self, sub: &str) {
+        let Ok(mut counts) = self.counts.lock() else {
+            return;
+        };
+        if let Some(count) = counts.get_mut(sub) {
+            *count += 1;
+            return;
+        }
+        if counts.len() >= self.max_subjects {
+            Self::evict_lowest(&mut counts);
+        }
+        counts.insert(sub.into(), 1);
+    }
+
+    fn evict_lowest(counts: &mut HashMap<Box<str>, u64>) {
+        let lowest = counts.iter().min_by_key(|(_, c)| **c).map(|(s, _)| s.clone());
+        if let Some(sub) = lowest {
+            counts.remove(&sub);
+        }
+    }
+
+    pub fn get(&self, sub: &str) -> u64 {
+        self.counts
+            .lock()
+            .map(|c| c.get(sub).copied().unwrap_or(0))
+            .unwrap_or(0)
+    }
+
+    pub fn top(&self, n: usize) -> Vec<SubjectCount> {
+        let Ok(counts) = self.counts.lock() else {
+            return Vec::new();
+        };
+        let mut top: Vec<SubjectCount> = counts
+            .iter()
+            .map(|(sub, count)| SubjectCount { sub: sub.to_string(), count: *count })
+            .collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.sub.cmp(&b.sub)));
+        top.truncate(n);
+        top
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    count: u64,
+    total_us: u64,
+    max_us: u64,
+}
+
+impl Histogram {
+    fn observe(&mut self, elapsed: Duration) {
+        let ms = elapsed.as_millis() as u64;
+        let us = elapsed.as_micros() as u64;
+        let idx = LATENCY_BUCKETS_MS.iter().position(|le| ms < *le).unwrap_or(LATENCY_BUCKETS_MS.len());
+        self.buckets[idx] += 1;
+        self.count += 1;
+        self.total_us = self.total_us.saturating_add(us);
+        self.max_us = self.max_us.max(us);
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RouteLatency {
+    pub route: String,
+    pub count: u64,
+    pub total_us: u64,
+    pub max_us: u64,
+    /// Counts per bucket of `LATENCY_BUCKETS_MS`, plus a trailing overflow bucket.
+    pub buckets: Vec<u64>,
+}
+
+/// Per-route latency histograms, keyed by matched route pattern (not raw path).
+pub struct RouteLatencies {
+    routes: Mutex<HashMap<Box<str>, Histogram>>,
+}
+
+impl RouteLatencies {
+    pub fn new() -> Self {
+        Self { routes: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn observe(&self, route: &str, elapsed: Duration) {
+        let Ok(mut routes) = self.routes.lock() else {
+            return;
+        };
+        if !routes.contains_key(route) && routes.len() >= MAX_TRACKED_ROUTES {
+            return;
+        }
+        routes.entry(route.into()).or_default().observe(elapsed);
+    }
+
+    pub fn report(&self) -> Vec<RouteLatency> {
+        let Ok(routes) = self.routes.lock() else {
+            return Vec::new();
+        };
+        let mut report: Vec<RouteLatency> = routes
+            .iter()
+            .map(|(route, h)| RouteLatency {
+                route: route.to_string(),
+                count: h.count,
+                total_us: h.total_us,
+                max_us: h.max_us,
+                buckets: h.buckets.to_vec(),
+            })
+            .collect();
+        report.sort_by(|a, b| a.route.cmp(&b.route));
+        report
+    }
+}
 
 pub struct Metrics {
     pub tokens_minted: AtomicU64,
     pub tokens_verified: AtomicU64,
+    pub verify_latency_us_total: AtomicU64,
+    pub verify_latency_us_max: AtomicU64,
     pub tokens_rejected: AtomicU64,
     pub replays_blocked: AtomicU64,
     pub policy_denials: AtomicU64,
     pub oidc_failures: AtomicU64,
+    pub oidc_cache_hits: AtomicU64,
+    pub oidc_cache_evictions: AtomicU64,
     pub rate_limited: AtomicU64,
     pub webauthn_registers: AtomicU64,
     pub webauthn_successes: AtomicU64,
     pub webauthn_failures: AtomicU64,
     pub webauthn_lockouts: AtomicU64,
+    pub refresh_reuse_detected: AtomicU64,
+    pub audit_dropped: AtomicU64,
+    pub breakglass_mints: AtomicU64,
+    pub slow_requests: AtomicU64,
+    pub request_spikes: AtomicU64,
+    pub crypto_shed: AtomicU64,
+    pub rate_limit_exempt: AtomicU64,
+    pub expired_replay: AtomicU64,
+    pub policy_unmatched: AtomicU64,
+    /// 0 = not run yet, 1 = passed, 2 = failed
+    canary: AtomicU8,
+    /// Unix seconds the active signing key was created; 0 without one.
+    signing_key_created: AtomicI64,
+    pub audit_queue_depth: AtomicU64,
+    ttl_buckets: [AtomicU64; TTL_BUCKETS_SECS.len() + 1],
+    pub ttl_clamped: AtomicU64,
+    pub replays_by_subject: SubjectCounter,
+    pub route_latency: RouteLatencies,
 }
 
 impl Metrics {
@@ -22,15 +191,35 @@ impl Metrics {
         Self {
             tokens_minted: AtomicU64::new(0),
             tokens_verified: AtomicU64::new(0),
+            verify_latency_us_total: AtomicU64::new(0),
+            verify_latency_us_max: AtomicU64::new(0),
             tokens_rejected: AtomicU64::new(0),
             replays_blocked: AtomicU64::new(0),
             policy_denials: AtomicU64::new(0),
             oidc_failures: AtomicU64::new(0),
+            oidc_cache_hits: AtomicU64::new(0),
+            oidc_cache_evictions: AtomicU64::new(0),
             rate_limited: AtomicU64::new(0),
             webauthn_registers: AtomicU64::new(0),
             webauthn_successes: AtomicU64::new(0),
             webauthn_failures: AtomicU64::new(0),
             webauthn_lockouts: AtomicU64::new(0),
+            refresh_reuse_detected: AtomicU64::new(0),
+            audit_dropped: AtomicU64::new(0),
+            breakglass_mints: AtomicU64::new(0),
+            slow_requests: AtomicU64::new(0),
+            request_spikes: AtomicU64::new(0),
+            crypto_shed: AtomicU64::new(0),
+            rate_limit_exempt: AtomicU64::new(0),
+            expired_replay: AtomicU64::new(0),
+            policy_unmatched: AtomicU64::new(0),
+            canary: AtomicU8::new(0),
+            signing_key_created: AtomicI64::new(0),
+            audit_queue_depth: AtomicU64::new(0),
+            ttl_buckets: std::array::from_fn(|_| AtomicU64::new(0)),
+            ttl_clamped: AtomicU64::new(0),
+            replays_by_subject: SubjectCounter::new(MAX_TRACKED_SUBJECTS),
+            route_latency: RouteLatencies::new(),
         }
     }
 
@@ -38,16 +227,30 @@ impl Metrics {
         self.tokens_minted.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_verify(&self) {
+    /// Buckets the TTL a token was actually minted with; `clamped` when that differs from the requested one.
+    pub fn record_mint_ttl(&self, ttl: i64, clamped: bool) {
+        let ttl = u64::try_from(ttl).unwrap_or(0);
+        let idx = TTL_BUCKETS_SECS.iter().position(|le| ttl <= *le).unwrap_or(TTL_BUCKETS_SECS.len());
+        self.ttl_buckets[idx].fetch_add(1, Ordering::Relaxed);
+        if clamped {
+            self.ttl_clamped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a successful verification and folds its end-to-end latency into the aggregate.
+    pub fn record_verify(&self, latency_us: u64) {
         self.tokens_verified.fetch_add(1, Ordering::Relaxed);
+        self.verify_latency_us_total.fetch_add(latency_us, Ordering::Relaxed);
+        self.verify_latency_us_max.fetch_max(latency_us, Ordering::Relaxed);
     }
 
     pub fn record_reject(&self) {
         self.tokens_rejected.fetch_add(1, Ordering::Relaxed);
     }
 
-    pub fn record_replay(&self) {
+    pub fn record_replay(&self, sub: &str) {
         self.replays_blocked.fetch_add(1, Ordering::Relaxed);
+        self.replays_by_subject.increment(sub);
     }
 
     pub fn record_policy_denial(&self) {
@@ -58,6 +261,14 @@ impl Metrics {
         self.oidc_failures.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn record_oidc_cache_hit(&self) {
+        self.oidc_cache_hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_oidc_cache_eviction(&self) {
+        self.oidc_cache_evictions.fetch_add(1, Ordering::Relaxed);
+    }
+
     pub fn record_rate_limited(&self) {
         self.rate_limited.fetch_add(1, Ordering::Relaxed);
     }
@@ -78,36 +289,196 @@ impl Metrics {
         self.webauthn_lockouts.fetch_add(1, Ordering::Relaxed);
     }
 
+    pub fn record_refresh_reuse(&self) {
+        self.refresh_reuse_detected.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_breakglass(&self) {
+        self.breakglass_mints.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_slow_request(&self) {
+        self.slow_requests.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_request_spike(&self) {
+        self.request_spikes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_crypto_shed(&self) {
+        self.crypto_shed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_rate_limit_exempt(&self) {
+        self.rate_limit_exempt.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_expired_replay(&self) {
+        self.expired_replay.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_policy_unmatched(&self) {
+        self.policy_unmatched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_canary(&self, ok: bool) {
+        self.canary.store(if ok { 1 } else { 2 }, Ordering::Relaxed);
+    }
+
+    pub fn canary_ok(&self) -> Option<bool> {
+        match self.canary.load(Ordering::Relaxed) {
+            0 => None,
+            state => Some(state == 1),
+        }
+    }
+
+    pub fn set_signing_key_created(&self, at: DateTime<Utc>) {
+        self.signing_key_created.store(at.timestamp(), Ordering::Relaxed);
+    }
+
+    pub fn signing_key_age_seconds(&self) -> Option<u64> {
+        match self.signing_key_created.load(Ordering::Relaxed) {
+            0 => None,
+            created => Some(u64::try_from(Utc::now().timestamp() - created).unwrap_or(0)),
+        }
+    }
+
+    pub fn record_audit_dropped(&self) {
+        self.audit_dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn set_audit_queue_depth(&self, depth: usize) {
+        self.audit_queue_depth.store(depth as u64, Ordering::Relaxed);
+    }
+
     pub fn snapshot(&self) -> MetricsSnapshot {
+        let verified = self.tokens_verified.load(Ordering::Relaxed);
         MetricsSnapshot {
             tokens_minted: self.tokens_minted.load(Ordering::Relaxed),
-            tokens_verified: self.tokens_verified.load(Ordering::Relaxed),
+            tokens_verified: verified,
+            verify_latency_avg_us: self.verify_latency_us_total.load(Ordering::Relaxed).checked_div(verified).unwrap_or(0),
+            verify_latency_max_us: self.verify_latency_us_max.load(Ordering::Relaxed),
             tokens_rejected: self.tokens_rejected.load(Ordering::Relaxed),
             replays_blocked: self.replays_blocked.load(Ordering::Relaxed),
             policy_denials: self.policy_denials.load(Ordering::Relaxed),
             oidc_failures: self.oidc_failures.load(Ordering::Relaxed),
+            oidc_cache_hits: self.oidc_cache_hits.load(Ordering::Relaxed),
+            oidc_cache_evictions: self.oidc_cache_evictions.load(Ordering::Relaxed),
             rate_limited: self.rate_limited.load(Ordering::Relaxed),
             webauthn_registers: self.webauthn_registers.load(Ordering::Relaxed),
             webauthn_successes: self.webauthn_successes.load(Ordering::Relaxed),
             webauthn_failures: self.webauthn_failures.load(Ordering::Relaxed),
             webauthn_lockouts: self.webauthn_lockouts.load(Ordering::Relaxed),
+            refresh_reuse_detected: self.refresh_reuse_detected.load(Ordering::Relaxed),
+            audit_dropped: self.audit_dropped.load(Ordering::Relaxed),
+            breakglass_mints: self.breakglass_mints.load(Ordering::Relaxed),
+            slow_requests: self.slow_requests.load(Ordering::Relaxed),
+            request_spikes: self.request_spikes.load(Ordering::Relaxed),
+            crypto_shed: self.crypto_shed.load(Ordering::Relaxed),
+            rate_limit_exempt: self.rate_limit_exempt.load(Ordering::Relaxed),
+            expired_replay: self.expired_replay.load(Ordering::Relaxed),
+            policy_unmatched: self.policy_unmatched.load(Ordering::Relaxed),
+            canary_ok: self.canary_ok(),
+            signing_key_age_seconds: self.signing_key_age_seconds(),
+            audit_queue_depth: self.audit_queue_depth.load(Ordering::Relaxed),
+            ttl_histogram: self.ttl_buckets.iter().map(|b| b.load(Ordering::Relaxed)).collect(),
+            ttl_clamped: self.ttl_clamped.load(Ordering::Relaxed),
         }
     }
 }
 
-#[derive(Serialize)]
+#[derive(Clone, Serialize, ToSchema)]
 pub struct MetricsSnapshot {
     pub tokens_minted: u64,
     pub tokens_verified: u64,
+    /// Mean `/proxy` verification time in microseconds; 0 before the first verification.
+    pub verify_latency_avg_us: u64,
+    pub verify_latency_max_us: u64,
     pub tokens_rejected: u64,
     pub replays_blocked: u64,
     pub policy_denials: u64,
     pub oidc_failures: u64,
+    /// Mints whose id_token was already verified and still cached (`OIDC_CACHE_CAPACITY`).
+    pub oidc_cache_hits: u64,
+    pub oidc_cache_evictions: u64,
     pub rate_limited: u64,
     pub webauthn_registers: u64,
     pub webauthn_successes: u64,
     pub webauthn_failures: u64,
     pub webauthn_lockouts: u64,
+    pub refresh_reuse_detected: u64,
+    pub audit_dropped: u64,
+    pub breakglass_mints: u64,
+    pub slow_requests: u64,
+    /// Windows in which the request rate crossed `REQUEST_SPIKE_PER_SEC`.
+    pub request_spikes: u64,
+    /// Signing or verifying requests refused with 503 because `CRYPTO_CONCURRENCY` slots stayed full.
+    pub crypto_shed: u64,
+    /// Requests let through by `RATE_EXEMPT_IPS` / `RATE_EXEMPT_SUBJECTS`.
+    pub rate_limit_exempt: u64,
+    /// Expired tokens whose jti had already been consumed; still reported to clients as expired.
+    pub expired_replay: u64,
+    /// Mints and proxied tokens whose action type has no policy entry; allowed, not denied.
+    pub policy_unmatched: u64,
+    /// None until the first self-test runs (`CANARY_INTERVAL_SECS`).
+    pub canary_ok: Option<bool>,
+    /// Seconds since the active Ed25519 signing key was created; None in verify-only and HS256 modes.
+    pub signing_key_age_seconds: Option<u64>,
+    pub audit_queue_depth: u64,
+    /// Minted TTLs (after clamping) per bucket of `TTL_BUCKETS_SECS`, plus a trailing overflow bucket.
+    pub ttl_histogram: Vec<u64>,
+    /// Mints whose requested `ttl_seconds` was changed by the clamp, TTL floor, or a per-action ceiling.
+    pub ttl_clamped: u64,
+}
+
+#[derive(Clone, Serialize, ToSchema)]
+pub struct TimedSnapshot {
+    pub at: DateTime<Utc>,
+    pub metrics: MetricsSnapshot,
+}
+
+pub struct MetricsHistory {
+    samples: Mutex<VecDeque<TimedSnapshot>>,
+    depth: usize,
+    interval: Duration,
+}
+
+impl MetricsHistory {
+    pub fn new(depth: usize, interval: Duration) -> Self {
+        Self {
+            samples: Mutex::new(VecDeque::with_capacity(depth)),
+            depth: depth.max(1),
+            interval,
+        }
+    }
+
+    pub fn record(&self, metrics: MetricsSnapshot) {
+        let Ok(mut samples) = self.samples.lock() else {
+            return;
+        };
+        if samples.len() >= self.depth {
+            samples.pop_front();
+        }
+        samples.push_back(TimedSnapshot { at: Utc::now(), metrics });
+    }
+
+    pub fn series(&self) -> Vec<TimedSnapshot> {
+        self.samples
+            .lock()
+            .map(|s| s.iter().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub async fn run_sampler(&self, metrics: &Metrics, heartbeats: &Heartbeats) {
+        heartbeats.register(SAMPLER_TASK, self.interval);
+        let mut ticker = tokio::time::interval(self.interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            self.record(metrics.snapshot());
+            heartbeats.beat(SAMPLER_TASK);
+        }
+    }
 }
 
 #[cfg(test)]
@@ -129,6 +500,78 @@ mod tests {
         assert_eq!(m.snapshot().rate_limited, 1);
     }
 
+    #[test]
+    fn replays_attributed_to_subject() {
+        let m = Metrics::new();
+        m.record_replay("mallory");
+        m.record_replay("mallory");
+        m.record_replay("alice");
+        assert_eq!(m.snapshot().replays_blocked, 3);
+        assert_eq!(m.replays_by_subject.top(1), vec![SubjectCount { sub: "mallory".into(), count: 2 }]);
+    }
+
+    #[test]
+    fn subject_counter_is_bounded() {
+        let counter = SubjectCounter::new(2);
+        counter.increment("a");
+        counter.increment("a");
+        counter.increment("b");
+        counter.increment("c");
+        assert_eq!(counter.top(10).len(), 2);
+        assert_eq!(counter.get("a"), 2);
+        assert_eq!(counter.get("b"), 0);
+    }
+
+    #[test]
+    fn history_bounded_by_depth_and_ordered() {
+        let m = Metrics::new();
+        let history = MetricsHistory::new(3, Duration::from_secs(60));
+        for _ in 0..5 {
+            m.record_mint();
+            history.record(m.snapshot());
+        }
+        let series = history.series();
+        let minted: Vec<u64> = series.iter().map(|s| s.metrics.tokens_minted).collect();
+        assert_eq!(minted, vec![3, 4, 5]);
+        assert!(series.windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[tokio::test]
+    async fn sampler_records_each_interval() {
+        let m = Metrics::new();
+        let history = MetricsHistory::new(10, Duration::from_millis(5));
+        let _ = tokio::time::timeout(Duration::from_millis(100), history.run_sampler(&m, &Heartbeats::new())).await;
+        let series = history.series();
+        assert!(series.len() >= 3);
+        assert!(series.len() <= 10);
+        assert!(series.windows(2).all(|w| w[0].at <= w[1].at));
+    }
+
+    #[test]
+    fn latency_bucketed_per_route() {
+        let latencies = RouteLatencies::new();
+        latencies.observe("/mint", Duration::from_micros(500));
+        latencies.observe("/mint", Duration::from_millis(30));
+        latencies.observe("/audit", Duration::from_secs(10));
+        let report = latencies.report();
+        assert_eq!(report[0].route, "/audit");
+        assert_eq!(report[0].buckets[LATENCY_BUCKETS_MS.len()], 1);
+        assert_eq!(report[1].count, 2);
+        assert_eq!(report[1].buckets[0], 1);
+        assert_eq!(report[1].buckets[4], 1);
+    }
+
+    #[test]
+    fn record_verify_aggregates_latency() {
+        let m = Metrics::new();
+        m.record_verify(100);
+        m.record_verify(300);
+        let s = m.snapshot();
+        assert_eq!(s.tokens_verified, 2);
+        assert_eq!(s.verify_latency_avg_us, 200);
+        assert_eq!(s.verify_latency_max_us, 300);
+    }
+
     #[test]
     fn record_webauthn_success_increments() {
         let m = Metrics::new();
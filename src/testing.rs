@@ -0,0 +1,101 @@
+//! In-process server handle for black-box integration tests (feature `test-util`).
+//! Used by: downstream integration tests.
+
+use std::net::SocketAddr;
+
+use axum::Router;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::error::{Error, Result};
+use crate::server;
+use crate::state::{build_test_state, AppState};
+
+pub struct TestServer {
+    pub base_url: String,
+    pub state: AppState,
+    shutdown: oneshot::Sender<()>,
+    task: JoinHandle<std::io::Result<()>>,
+}
+
+impl TestServer {
+    pub async fn spawn() -> Result<Self> {
+        Self::spawn_with_state(build_test_state()?).await
+    }
+
+    pub async fn spawn_with_state(state: AppState) -> Result<Self> {
+        let router = server::build_router(state.clone());
+        Self::spawn_router(state, router).await
+    }
+
+    /// Serves `router` in place of the full service: a single route under test, or a stub a
+    /// webhook points at.
+    pub async fn spawn_router(state: AppState, router: Router) -> Result<Self> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+            .await
+            .map_err(|e| Error::ServiceUnavailable(format!("bind: {e}")))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| Error::ServiceUnavailable(format!("local_addr: {e}")))?;
+        let (shutdown, signal) = oneshot::channel::<()>();
+        let app = router.into_make_service_with_connect_info::<SocketAddr>();
+        let task = tokio::spawn(async move {
+            axum::serve(listener, app)
+                .with_graceful_shutdown(async {
+                    let _ = signal.await;
+                })
+                .await
+        });
+        Ok(Self {
+            base_url: format!("http://{addr}"),
+            state,
+            shutdown,
+            task,
+        })
+    }
+
+    pub fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    pub async fn shutdown(self) -> Result<()> {
+        let _ = self.shutdown.send(());
+        self.task
+            .await
+            .map_err(|e| Error::ServiceUnavailable(format!("server task: {e}")))?
+            .map_err(|e| Error::ServiceUnavailable(format!("server: {e}")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::{json, Value};
+
+    fn http_err(e: reqwest::Error) -> Error {
+        Error::ServiceUnavailable(e.to_string())
+    }
+
+    #[tokio::test]
+    async fn mint_and_verify_through_spawned_server() -> Result<()> {
+        let server = TestServer::spawn().await?;
+        let client = reqwest::Client::new();
+
+        let minted: Value = client
+            .post(server.url("/mint"))
+            .json(&json!({ "sub": "agent-1", "action": "deploy" }))
+            .send().await.map_err(http_err)?
+            .json().await.map_err(http_err)?;
+
+        let verified = client
+            .post(server.url("/proxy"))
+            .json(&json!({ "token": minted["token"] }))
+            .send().await.map_err(http_err)?;
+        assert!(verified.status().is_success());
+        let body: Value = verified.json().await.map_err(http_err)?;
+        assert_eq!(body["sub"], "agent-1");
+        assert_eq!(body["jti"], minted["jti"]);
+
+        server.shutdown().await
+    }
+}
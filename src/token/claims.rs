@@ -1,17 +1,86 @@
 //! JWT-like claims for agent authorization tokens.
-//! Used by: token::sign, token::verify, handlers::mint, handlers::delegate.
+//! Used by: token::sign, token::verify, handlers::mint, handlers::delegate, handlers::refresh.
+
+use std::time::Duration;
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// How far ahead of the verifier's clock an `iat` may sit before the token is treated as forged or misdated.
+pub const DEFAULT_IAT_LEEWAY: Duration = Duration::from_secs(60);
+/// How long past `exp` a token still counts as live, for a verifier whose clock runs ahead of the minter's.
+pub const DEFAULT_EXPIRY_LEEWAY: Duration = Duration::from_secs(5);
+
+/// Signature algorithm named in the payload; absent means EdDSA.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Algorithm {
+    EdDSA,
+    ES256,
+    /// HMAC-SHA256 under a shared secret (`TOKEN_HMAC_SECRET`); never published.
+    HS256,
+}
+
+/// `aud` as a single service or a set; a scalar stays a scalar on the wire.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, ToSchema)]
+#[serde(untagged)]
+pub enum Audience {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Audience {
+    pub fn contains(&self, expected: &str) -> bool {
+        match self {
+            Self::One(aud) => aud == expected,
+            Self::Many(auds) => auds.iter().any(|a| a == expected),
+        }
+    }
+
+    pub fn values(&self) -> &[String] {
+        match self {
+            Self::One(aud) => std::slice::from_ref(aud),
+            Self::Many(auds) => auds,
+        }
+    }
+}
+
+/// `cnf` (RFC 7800): the client key a sender-constrained token is bound to.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Confirmation {
+    /// Base64url Ed25519 public key.
+    pub ed25519: String,
+}
 
+/// Optional first token segment naming the signing key; a token without one is signed by
+/// the verifier's `DEFAULT_KID` key.
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct TokenHeader {
+    pub kid: String,
+}
+
+/// Every optional field is modeled, so unknown keys are rejected rather than silently carried.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct Claims {
     pub jti: String,
     pub sub: String,
     pub action: String,
     pub iat: DateTime<Utc>,
     pub exp: DateTime<Utc>,
+    /// Not valid before this instant; tokens minted ahead of time.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub nbf: Option<DateTime<Utc>>,
 
+    /// Set from `TOKEN_ISSUER` when the token is signed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub iss: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alg: Option<Algorithm>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aud: Option<Audience>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub receipt_type: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -24,10 +93,19 @@ pub struct Claims {
     pub max_delegation_depth: Option<u32>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub parent_jti: Option<String>,
+    /// Every token this one derives from, root first; revoking any of them revokes this one.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ancestors: Option<Vec<String>>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub original_approver: Option<String>,
+    /// The human identity (verified OIDC subject) behind the mint; absent for unauthenticated mints.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub approved_by: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub depth: Option<u32>,
+    /// Presenting the token requires a proof signed by this key; see `token::pop`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cnf: Option<Confirmation>,
 }
 
 impl Claims {
@@ -39,14 +117,21 @@ impl Claims {
             action,
             iat: now,
             exp: now + chrono::Duration::seconds(ttl_seconds),
+            nbf: None,
+            iss: None,
+            alg: None,
+            aud: None,
             receipt_type: None,
             scope: None,
             delegates_to: None,
             requires_checkpoint: None,
             max_delegation_depth: None,
             parent_jti: None,
+            ancestors: None,
             original_approver: None,
+            approved_by: None,
             depth: None,
+            cnf: None,
         }
     }
 
@@ -78,19 +163,83 @@ impl Claims {
         let mut claims = Self::new(agent_id, action, ttl_seconds);
         claims.receipt_type = Some("delegated".into());
         claims.parent_jti = Some(parent.jti.clone());
+        claims.ancestors = Some(parent.lineage());
+        claims.aud = parent.aud.clone();
         claims.original_approver = Some(
             parent.original_approver.clone().unwrap_or_else(|| parent.sub.clone())
         );
+        claims.approved_by = parent.approved_by.clone();
         claims.depth = Some(parent.depth.unwrap_or(0) + 1);
         claims.scope = parent.scope.clone();
         claims.delegates_to = parent.delegates_to.clone();
         claims.requires_checkpoint = parent.requires_checkpoint.clone();
         claims.max_delegation_depth = parent.max_delegation_depth;
+        claims.cnf = parent.cnf.clone();
+        claims
+    }
+
+    /// A narrower token for the next hop: one action and audience, the parent's approvers and
+    /// holder binding, and no further delegation.
+    pub fn exchanged(action: String, audience: Audience, ttl_seconds: i64, parent: &Claims) -> Self {
+        let mut claims = Self::new(parent.sub.clone(), action, ttl_seconds);
+        claims.receipt_type = Some("exchanged".into());
+        claims.parent_jti = Some(parent.jti.clone());
+        claims.ancestors = Some(parent.lineage());
+        claims.aud = Some(audience);
+        claims.original_approver = parent.original_approver.clone();
+        claims.approved_by = parent.approved_by.clone();
+        claims.cnf = parent.cnf.clone();
+        claims.max_delegation_depth = Some(0);
         claims
     }
 
-    pub fn is_expired(&self) -> bool {
-        Utc::now() > self.exp
+    /// The same grant under a fresh `jti` and expiry, descended from this token.
+    pub fn renewed(&self, ttl_seconds: i64) -> Self {
+        let now = Utc::now();
+        Self {
+            jti: uuid::Uuid::new_v4().to_string(),
+            iat: now,
+            exp: now + chrono::Duration::seconds(ttl_seconds),
+            ancestors: Some(self.lineage()),
+            ..self.clone()
+        }
+    }
+
+    /// `ancestors` followed by this token's own `jti`: the ancestry a derived token inherits.
+    pub fn lineage(&self) -> Vec<String> {
+        let mut lineage = self.ancestors.clone().unwrap_or_default();
+        lineage.push(self.jti.clone());
+        lineage
+    }
+
+    /// Delays validity by `delay_seconds`; the TTL window then starts at `nbf`, not at `iat`.
+    pub fn not_before(mut self, delay_seconds: i64) -> Self {
+        let delay = chrono::Duration::seconds(delay_seconds);
+        self.nbf = Some(self.iat + delay);
+        self.exp += delay;
+        self
+    }
+
+    /// Expired once `exp` is more than `leeway` in the past.
+    pub fn is_expired(&self, leeway: Duration) -> bool {
+        let leeway = chrono::Duration::from_std(leeway).unwrap_or(chrono::Duration::MAX);
+        Utc::now().checked_sub_signed(leeway).is_some_and(|now| now > self.exp)
+    }
+
+    /// The first second at which `is_expired(leeway)` holds, rounded up; a consumed jti must be
+    /// remembered until then or it could be replayed inside the leeway.
+    pub fn accepted_until(&self, leeway: Duration) -> i64 {
+        let leeway = chrono::Duration::from_std(leeway).unwrap_or(chrono::Duration::MAX);
+        self.exp.checked_add_signed(leeway).map_or(i64::MAX, |end| end.timestamp().saturating_add(1))
+    }
+
+    pub fn not_yet_valid(&self) -> bool {
+        self.nbf.is_some_and(|nbf| Utc::now() < nbf)
+    }
+
+    pub fn issued_in_future(&self, leeway: Duration) -> bool {
+        let leeway = chrono::Duration::from_std(leeway).unwrap_or(chrono::Duration::MAX);
+        Utc::now().checked_add_signed(leeway).is_some_and(|now| self.iat > now)
     }
 }
 
@@ -111,7 +260,8 @@ mod tests {
     #[test]
     fn claims_with_zero_ttl_are_expired() {
         let claims = Claims::new("agent-1".into(), "deploy".into(), 0);
-        assert!(claims.is_expired());
+        assert!(claims.is_expired(Duration::ZERO));
+        assert!(!claims.is_expired(DEFAULT_EXPIRY_LEEWAY));
     }
 
     #[test]
@@ -123,6 +273,17 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn audience_accepts_scalar_or_list() -> crate::error::Result<()> {
+        let one: Audience = serde_json::from_str(r#""payments""#)?;
+        let many: Audience = serde_json::from_str(r#"["payments","ledger"]"#)?;
+        assert_eq!(one, Audience::One("payments".into()));
+        assert!(many.contains("ledger"));
+        assert!(!many.contains("deploy"));
+        assert_eq!(serde_json::to_string(&one)?, r#""payments""#);
+        Ok(())
+    }
+
     #[test]
     fn plan_claims_have_orchestration_fields() {
         let claims = Claims::new_plan(
@@ -161,6 +322,15 @@ mod tests {
         assert_eq!(child.depth, Some(1));
     }
 
+    #[test]
+    fn renewed_claims_get_fresh_jti_and_expiry() {
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 0);
+        let renewed = claims.renewed(300);
+        assert_ne!(renewed.jti, claims.jti);
+        assert_eq!(renewed.sub, claims.sub);
+        assert!(!renewed.is_expired(DEFAULT_EXPIRY_LEEWAY));
+    }
+
     #[test]
     fn old_style_claims_skip_none_fields() -> crate::error::Result<()> {
         let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
@@ -0,0 +1,208 @@
+//! Verifying keys of other minters, read from a local JWKS file and reloaded on an interval.
+//! Used by: state, main.
+
+use std::collections::HashMap;
+use std::sync::RwLock;
+use std::time::Duration;
+
+use ed25519_dalek::VerifyingKey;
+use serde::Deserialize;
+
+use crate::error::{Error, Result, TokenFault};
+use crate::heartbeat::Heartbeats;
+use crate::token::claims::Claims;
+use crate::token::keys::{PinnedKey, decode_public_key};
+use crate::token::verify::{token_kid, verify_pinned_allow_expired};
+
+pub const DEFAULT_RELOAD_INTERVAL: Duration = Duration::from_secs(300);
+const RELOAD_TASK: &str = "jwks_reload";
+
+/// The JWK members a verifier needs, plus the `iss` its minter stamps; `alg`, `use`, and anything else are ignored.
+#[derive(Deserialize)]
+struct FileJwk {
+    kty: String,
+    crv: Option<String>,
+    kid: String,
+    x: Option<String>,
+    iss: Option<String>,
+}
+
+/// One minter's key and the only issuer whose tokens it may verify.
+struct MinterKey {
+    key: VerifyingKey,
+    iss: String,
+}
+
+#[derive(Deserialize)]
+struct FileJwkSet {
+    keys: Vec<FileJwk>,
+}
+
+/// Every Ed25519 key in the file by `kid`. Any other key type, a key without `iss`, a duplicate `kid`, or an
+/// empty set is an error.
+fn read_jwks(path: &str) -> Result<HashMap<String, MinterKey>> {
+    let text = std::fs::read_to_string(path).map_err(|e| Error::Config(format!("read JWKS {path}: {e}")))?;
+    let set: FileJwkSet = serde_json::from_str(&text).map_err(|e| Error::Config(format!("JWKS {path}: {e}")))?;
+    if set.keys.is_empty() {
+        return Err(Error::Config(format!("JWKS {path} lists no keys")));
+    }
+    let mut keys = HashMap::with_capacity(set.keys.len());
+    for jwk in set.keys {
+        let x = jwk
+            .x
+            .filter(|_| jwk.kty == "OKP" && jwk.crv.as_deref() == Some("Ed25519"))
+            .ok_or_else(|| Error::Config(format!("JWKS {path}: key {} is not an Ed25519 OKP key", jwk.kid)))?;
+        let key = decode_public_key(&x).map_err(|e| Error::Config(format!("JWKS {path}: key {}: {e}", jwk.kid)))?;
+        let iss = jwk
+            .iss
+            .filter(|iss| !iss.is_empty())
+            .ok_or_else(|| Error::Config(format!("JWKS {path}: key {} names no iss", jwk.kid)))?;
+        if keys.insert(jwk.kid.clone(), MinterKey { key, iss }).is_some() {
+            return Err(Error::Config(format!("JWKS {path}: duplicate kid {}", jwk.kid)));
+        }
+    }
+    Ok(keys)
+}
+
+pub struct KeySet {
+    path: String,
+    reload_every: Duration,
+    keys: RwLock<HashMap<String, MinterKey>>,
+}
+
+impl KeySet {
+    /// Reads `path` once; an unreadable or malformed file fails startup.
+    pub fn load(path: &str, reload_every: Duration) -> Result<Self> {
+        let keys = read_jwks(path)?;
+        tracing::info!(path, keys = keys.len(), "minter verifying keys loaded");
+        Ok(Self { path: path.to_owned(), reload_every, keys: RwLock::new(keys) })
+    }
+
+    pub fn keys(&self) -> HashMap<String, VerifyingKey> {
+        self.keys.read().map(|k| k.iter().map(|(kid, m)| (kid.clone(), m.key)).collect()).unwrap_or_default()
+    }
+
+    /// Verifies with the key the token's `kid` names, then requires the `iss` that key is bound to.
+    pub fn verify_allow_expired(&self, token: &str, prefix: Option<&str>, iat_leeway: Duration) -> Result<Claims> {
+        let kid = token_kid(token, prefix)?.unwrap_or_default();
+        let (key, iss) = {
+            let keys = self.keys.read().map_err(|_| Error::Config("JWKS key set lock poisoned".into()))?;
+            let minter = keys
+                .get(&kid)
+                .ok_or_else(|| Error::InvalidToken(TokenFault::UnknownKey, format!("no verifying key with kid {kid}")))?;
+            (minter.key, minter.iss.clone())
+        };
+        let claims = verify_pinned_allow_expired(token, &PinnedKey::EdDSA(key), prefix, iat_leeway)?;
+        if claims.iss.as_deref() != Some(iss.as_str()) {
+            return Err(Error::InvalidToken(TokenFault::WrongIssuer, format!("key {kid} only verifies tokens from {iss}")));
+        }
+        Ok(claims)
+    }
+
+    /// Re-reads the file and swaps in its keys. On failure the previous keys stay in place.
+    pub fn reload(&self) -> Result<usize> {
+        let keys = read_jwks(&self.path)?;
+        let count = keys.len();
+        let mut current = self.keys.write().map_err(|_| Error::Config("JWKS key set lock poisoned".into()))?;
+        *current = keys;
+        Ok(count)
+    }
+
+    pub async fn run_reloader(&self, heartbeats: &Heartbeats) {
+        heartbeats.register(RELOAD_TASK, self.reload_every);
+        let mut ticker = tokio::time::interval(self.reload_every);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            match self.reload() {
+                Ok(keys) => tracing::debug!(path = %self.path, keys, "minter verifying keys reloaded"),
+                Err(e) => tracing::error!(error = %e, "JWKS reload failed; keeping the previous keys"),
+            }
+            heartbeats.beat(RELOAD_TASK);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::jwks::Jwk;
+    use crate::state::test_builder;
+    use crate::token::sign::{generate_keypair, sign_token};
+    use ed25519_dalek::SigningKey;
+
+    fn write_jwks(path: &std::path::Path, minters: &[&SigningKey]) -> Result<()> {
+        let mut keys = Vec::with_capacity(minters.len());
+        for (i, key) in minters.iter().enumerate() {
+            let mut jwk = serde_json::to_value(Jwk::ed25519(&key.verifying_key()))?;
+            jwk["iss"] = format!("minter-{i}").into();
+            keys.push(jwk);
+        }
+        std::fs::write(path, serde_json::to_vec(&serde_json::json!({ "keys": keys }))?)
+            .map_err(|e| Error::Config(e.to_string()))
+    }
+
+    fn claims_from(sub: &str, iss: &str) -> Claims {
+        Claims { iss: Some(iss.into()), ..Claims::new(sub.into(), "deploy".into(), 60) }
+    }
+
+    #[test]
+    fn tokens_from_every_listed_minter_verify_until_reloaded_away() -> Result<()> {
+        let (a, b) = (generate_keypair(), generate_keypair());
+        let path = std::env::temp_dir().join(format!("agentmint-jwks-{}.json", uuid::Uuid::new_v4()));
+        write_jwks(&path, &[&a, &b])?;
+
+        let mut builder = test_builder()?;
+        builder.minter_keys = Some(KeySet::load(&path.to_string_lossy(), DEFAULT_RELOAD_INTERVAL)?);
+        let state = builder.build()?;
+        let from_a = sign_token(&claims_from("agent-a", "minter-0"), &a)?;
+        let from_b = sign_token(&claims_from("agent-b", "minter-1"), &b)?;
+        assert_eq!(state.verify_any_minter_allow_expired(&from_a)?.sub, "agent-a");
+        assert_eq!(state.verify_any_minter_allow_expired(&from_b)?.sub, "agent-b");
+
+        write_jwks(&path, &[&b])?;
+        assert_eq!(state.minter_keys.as_ref().map(KeySet::reload).transpose()?, Some(1));
+        assert!(state.verify_any_minter_allow_expired(&from_a).is_err());
+        let from_b = sign_token(&claims_from("agent-b", "minter-0"), &b)?;
+        assert_eq!(state.verify_any_minter_allow_expired(&from_b)?.sub, "agent-b");
+        let own = state.sign(&Claims::new("agent-1".into(), "deploy".into(), 60))?;
+        assert_eq!(state.verify_any_minter_allow_expired(&own)?.sub, "agent-1");
+        std::fs::remove_file(&path).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    #[test]
+    fn minter_keys_verify_only_their_own_issuer_and_only_on_the_proxy_path() -> Result<()> {
+        let a = generate_keypair();
+        let path = std::env::temp_dir().join(format!("agentmint-jwks-{}.json", uuid::Uuid::new_v4()));
+        write_jwks(&path, &[&a])?;
+        let mut builder = test_builder()?;
+        builder.minter_keys = Some(KeySet::load(&path.to_string_lossy(), DEFAULT_RELOAD_INTERVAL)?);
+        let state = builder.build()?;
+
+        let spoofed = sign_token(&claims_from("agent-a", "someone-else"), &a)?;
+        let spoofed = state.verify_any_minter_allow_expired(&spoofed);
+        assert!(matches!(spoofed, Err(Error::InvalidToken(TokenFault::WrongIssuer, _))));
+        let foreign = sign_token(&claims_from("agent-a", "minter-0"), &a)?;
+        assert!(matches!(state.verify(&foreign), Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+        std::fs::remove_file(&path).map_err(|e| Error::Config(e.to_string()))
+    }
+
+    #[test]
+    fn malformed_reload_keeps_previous_keys() -> Result<()> {
+        let a = generate_keypair();
+        let path = std::env::temp_dir().join(format!("agentmint-jwks-{}.json", uuid::Uuid::new_v4()));
+        write_jwks(&path, &[&a])?;
+        let set = KeySet::load(&path.to_string_lossy(), DEFAULT_RELOAD_INTERVAL)?;
+
+        std::fs::write(&path, r#"{"keys": [{"kty": "RSA", "kid": "r1", "n": "AQAB", "e": "AQAB"}]}"#)
+            .map_err(|e| Error::Config(e.to_string()))?;
+        assert!(matches!(set.reload(), Err(Error::Config(_))));
+        std::fs::write(&path, r#"{"keys": []}"#).map_err(|e| Error::Config(e.to_string()))?;
+        assert!(matches!(set.reload(), Err(Error::Config(_))));
+        let unbound = serde_json::to_vec(&serde_json::json!({ "keys": [Jwk::ed25519(&a.verifying_key())] }))?;
+        std::fs::write(&path, unbound).map_err(|e| Error::Config(e.to_string()))?;
+        assert!(matches!(set.reload(), Err(Error::Config(_))));
+        assert_eq!(set.keys().len(), 1);
+        std::fs::remove_file(&path).map_err(|e| Error::Config(e.to_string()))
+    }
+}
@@ -2,5 +2,9 @@
 //! Used by: handlers, state.
 
 pub mod claims;
+pub mod keys;
+pub mod keyset;
+pub mod offline;
+pub mod pop;
 pub mod sign;
 pub mod verify;
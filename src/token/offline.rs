@@ -0,0 +1,175 @@
+//! Verification kits: the public key, algorithm, issuer, and audiences a service needs to
+//! verify tokens without calling AgentMint, and the standalone verifier built from one.
+//! Used by: handlers::verification.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use ed25519_dalek::{Signer, SigningKey};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::error::{Error, Result, TokenFault};
+use crate::token::claims::{Algorithm, Claims};
+use crate::token::keys::{decode_public_key, PinnedKey, SigningDomain};
+use crate::token::verify::{reject_headerless, verify_pinned};
+
+/// What the bundle signature covers.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct VerificationConfig {
+    /// base64url Ed25519 public key.
+    pub public_key: String,
+    #[schema(value_type = String)]
+    pub alg: Algorithm,
+    /// Required `iss`; `None` accepts tokens without one.
+    pub issuer: Option<String>,
+    /// A token must name at least one of these in `aud`; empty skips the audience check.
+    pub audiences: Vec<String>,
+    pub token_prefix: Option<String>,
+    /// `TOKEN_ACCEPT_LEGACY`: whether headerless two-segment tokens verify. Bundles without it accept them.
+    #[serde(default = "accept_legacy_by_default")]
+    pub accept_legacy_tokens: bool,
+}
+
+fn accept_legacy_by_default() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct VerificationBundle {
+    /// JSON-encoded `VerificationConfig`; the signature covers exactly these bytes.
+    pub payload: String,
+    /// base64url Ed25519 signature over `payload` in `SigningDomain::VerificationBundle`, by the key it names.
+    pub signature: String,
+}
+
+impl VerificationBundle {
+    pub fn sign(config: &VerificationConfig, key: &SigningKey) -> Result<Self> {
+        let payload = serde_json::to_string(config)?;
+        let signature = key.sign(&SigningDomain::VerificationBundle.message(payload.as_bytes()));
+        Ok(Self { payload, signature: URL_SAFE_NO_PAD.encode(signature.to_bytes()) })
+    }
+}
+
+/// Verifies tokens against a bundle's settings alone; no network, no replay tracking.
+pub struct Verifier {
+    key: PinnedKey,
+    config: VerificationConfig,
+}
+
+impl Verifier {
+    /// The bundle must be signed by the key it carries. That proves integrity, not origin:
+    /// compare `public_key` with a copy obtained out of band before trusting it.
+    pub fn from_bundle(bundle: &VerificationBundle) -> Result<Self> {
+        let config: VerificationConfig = serde_json::from_str(&bundle.payload)?;
+        if config.alg != Algorithm::EdDSA {
+            return Err(Error::Validation(format!("unsupported bundle algorithm {:?}", config.alg)));
+        }
+        let key = PinnedKey::EdDSA(decode_public_key(&config.public_key)?);
+        let signed = SigningDomain::VerificationBundle.message(bundle.payload.as_bytes());
+        key.verify(&signed, &URL_SAFE_NO_PAD.decode(&bundle.signature)?)?;
+        Ok(Self { key, config })
+    }
+
+    pub fn config(&self) -> &VerificationConfig {
+        &self.config
+    }
+
+    pub fn verify(&self, token: &str) -> Result<Claims> {
+        let prefix = self.config.token_prefix.as_deref();
+        if !self.config.accept_legacy_tokens {
+            reject_headerless(token, prefix)?;
+        }
+        let claims = verify_pinned(token, &self.key, prefix)?;
+        if let Some(issuer) = &self.config.issuer {
+            if claims.iss.as_ref() != Some(issuer) {
+                return Err(Error::InvalidToken(TokenFault::WrongIssuer, format!("token not issued by {issuer}")));
+            }
+        }
+        let audiences = &self.config.audiences;
+        if !audiences.is_empty() && !claims.aud.as_ref().is_some_and(|aud| audiences.iter().any(|a| aud.contains(a))) {
+            return Err(Error::InvalidToken(TokenFault::WrongAudience, "token not valid for any bundled audience".into()));
+        }
+        Ok(claims)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::claims::Audience;
+    use crate::token::sign::{generate_keypair, sign_token};
+
+    fn bundle(key: &SigningKey) -> Result<VerificationBundle> {
+        VerificationBundle::sign(&config(key), key)
+    }
+
+    fn config(key: &SigningKey) -> VerificationConfig {
+        VerificationConfig {
+            public_key: URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes()),
+            alg: Algorithm::EdDSA,
+            issuer: Some("https://mint.example".into()),
+            audiences: vec!["billing".into()],
+            token_prefix: None,
+            accept_legacy_tokens: true,
+        }
+    }
+
+    fn token(key: &SigningKey, iss: Option<&str>, aud: &str) -> Result<String> {
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        claims.iss = iss.map(str::to_owned);
+        claims.aud = Some(Audience::One(aud.into()));
+        sign_token(&claims, key)
+    }
+
+    #[test]
+    fn bundled_verifier_enforces_issuer_and_audience() -> Result<()> {
+        let key = generate_keypair();
+        let verifier = Verifier::from_bundle(&bundle(&key)?)?;
+
+        assert_eq!(verifier.verify(&token(&key, Some("https://mint.example"), "billing")?)?.sub, "agent-1");
+        let wrong_issuer = verifier.verify(&token(&key, Some("https://other.example"), "billing")?);
+        assert!(matches!(wrong_issuer, Err(Error::InvalidToken(TokenFault::WrongIssuer, _))));
+        let no_issuer = verifier.verify(&token(&key, None, "billing")?);
+        assert!(matches!(no_issuer, Err(Error::InvalidToken(TokenFault::WrongIssuer, _))));
+        let wrong_audience = verifier.verify(&token(&key, Some("https://mint.example"), "search")?);
+        assert!(matches!(wrong_audience, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+        let other_key = verifier.verify(&token(&generate_keypair(), Some("https://mint.example"), "billing")?);
+        assert!(matches!(other_key, Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn legacy_tokens_follow_the_bundle() -> Result<()> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        claims.iss = Some("https://mint.example".into());
+        claims.aud = Some(Audience::One("billing".into()));
+        let payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+        let legacy = format!("{payload}.{}", URL_SAFE_NO_PAD.encode(key.sign(payload.as_bytes()).to_bytes()));
+
+        assert_eq!(Verifier::from_bundle(&bundle(&key)?)?.verify(&legacy)?.sub, "agent-1");
+        let strict = VerificationConfig { accept_legacy_tokens: false, ..config(&key) };
+        let verifier = Verifier::from_bundle(&VerificationBundle::sign(&strict, &key)?)?;
+        assert!(matches!(verifier.verify(&legacy), Err(Error::InvalidToken(TokenFault::LegacyFormat, _))));
+        assert_eq!(verifier.verify(&token(&key, Some("https://mint.example"), "billing")?)?.sub, "agent-1");
+        Ok(())
+    }
+
+    #[test]
+    fn altered_bundle_rejected() -> Result<()> {
+        let mut tampered = bundle(&generate_keypair())?;
+        tampered.payload = tampered.payload.replacen("billing", "search", 1);
+        assert!(matches!(Verifier::from_bundle(&tampered), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn signature_from_another_domain_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let mut lifted = bundle(&key)?;
+        let signed = SigningDomain::AuditBundle.message(lifted.payload.as_bytes());
+        lifted.signature = URL_SAFE_NO_PAD.encode(key.sign(&signed).to_bytes());
+        assert!(matches!(Verifier::from_bundle(&lifted), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+}
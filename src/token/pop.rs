@@ -0,0 +1,116 @@
+//! Proof of possession for sender-constrained tokens: a `cnf` key in the claims, and a fresh,
+//! timestamped proof signed with the matching private key that `/proxy` requires alongside the token.
+//! Used by: handlers::mint, handlers::proxy, handlers::delegate, handlers::exchange.
+
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use chrono::Utc;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+
+use crate::error::{Error, Result, TokenFault};
+use crate::token::claims::{Claims, Confirmation};
+use crate::token::keys::decode_public_key;
+
+/// Domain separation so a proof can never double as a signature over anything else.
+const PROOF_CONTEXT: &str = "agentmint-pop:";
+/// How far a proof's timestamp may sit from the server clock, either way, before it is stale.
+pub const PROOF_MAX_SKEW_SECS: i64 = 30;
+
+fn proof_message(jti: &str, issued_at: i64) -> Vec<u8> {
+    format!("{PROOF_CONTEXT}{jti}:{issued_at}").into_bytes()
+}
+
+fn invalid_proof(msg: &str) -> Error {
+    Error::InvalidToken(TokenFault::InvalidProof, msg.into())
+}
+
+/// The `cnf` claim for a client-supplied base64url Ed25519 public key.
+pub fn confirmation(public_key: &str) -> Result<Confirmation> {
+    let key = decode_public_key(public_key)?;
+    Ok(Confirmation { ed25519: URL_SAFE_NO_PAD.encode(key.as_bytes()) })
+}
+
+/// Client side: `<unix seconds>.<signature>` over the token's `jti` and the current time, signed
+/// with the key named in its `cnf`.
+pub fn sign_proof(jti: &str, key: &SigningKey) -> String {
+    sign_proof_at(jti, Utc::now().timestamp(), key)
+}
+
+pub fn sign_proof_at(jti: &str, issued_at: i64, key: &SigningKey) -> String {
+    let signature = URL_SAFE_NO_PAD.encode(key.sign(&proof_message(jti, issued_at)).to_bytes());
+    format!("{issued_at}.{signature}")
+}
+
+/// Unbound tokens need no proof; a bound one needs a proof from exactly its `cnf` key, made
+/// within `PROOF_MAX_SKEW_SECS` of now, so a captured proof stops working with it.
+pub fn check_proof(claims: &Claims, proof: Option<&str>) -> Result<()> {
+    check_proof_at(claims, proof, Utc::now().timestamp())
+}
+
+fn check_proof_at(claims: &Claims, proof: Option<&str>, now: i64) -> Result<()> {
+    let Some(cnf) = &claims.cnf else {
+        return Ok(());
+    };
+    let proof = proof.ok_or_else(|| invalid_proof("token is key-bound; proof of possession required"))?;
+    let key = decode_public_key(&cnf.ed25519).map_err(|_| invalid_proof("token carries an unusable cnf key"))?;
+    let (issued_at, signature) = proof
+        .split_once('.')
+        .and_then(|(at, sig)| Some((at.parse::<i64>().ok()?, URL_SAFE_NO_PAD.decode(sig).ok()?)))
+        .and_then(|(at, bytes)| Some((at, Signature::from_slice(&bytes).ok()?)))
+        .ok_or_else(|| invalid_proof("proof must be <unix seconds>.<base64url Ed25519 signature>"))?;
+    if issued_at.abs_diff(now) > PROOF_MAX_SKEW_SECS.unsigned_abs() {
+        return Err(invalid_proof("proof is stale; sign a fresh one"));
+    }
+    key.verify(&proof_message(&claims.jti, issued_at), &signature)
+        .map_err(|_| invalid_proof("proof was not signed by the token's cnf key"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::sign::generate_keypair;
+
+    fn bound_to(key: &SigningKey) -> Result<Claims> {
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        claims.cnf = Some(confirmation(&URL_SAFE_NO_PAD.encode(key.verifying_key().as_bytes()))?);
+        Ok(claims)
+    }
+
+    #[test]
+    fn bound_token_needs_proof_from_its_key() -> Result<()> {
+        let (a, b) = (generate_keypair(), generate_keypair());
+        let claims = bound_to(&a)?;
+        check_proof(&claims, Some(&sign_proof(&claims.jti, &a)))?;
+
+        let wrong_key = check_proof(&claims, Some(&sign_proof(&claims.jti, &b)));
+        assert!(matches!(wrong_key, Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+        let other_jti = check_proof(&claims, Some(&sign_proof("another-jti", &a)));
+        assert!(matches!(other_jti, Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+        assert!(matches!(check_proof(&claims, None), Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn captured_proof_goes_stale() -> Result<()> {
+        let key = generate_keypair();
+        let claims = bound_to(&key)?;
+        let now = Utc::now().timestamp();
+        let captured = sign_proof_at(&claims.jti, now, &key);
+        check_proof_at(&claims, Some(&captured), now + PROOF_MAX_SKEW_SECS)?;
+        let replayed = check_proof_at(&claims, Some(&captured), now + PROOF_MAX_SKEW_SECS + 1);
+        assert!(matches!(replayed, Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+
+        let signature = captured.split_once('.').map(|(_, s)| s).unwrap_or_default();
+        let retimed = format!("{}.{signature}", now + 60);
+        let forged = check_proof_at(&claims, Some(&retimed), now + 60);
+        assert!(matches!(forged, Err(Error::InvalidToken(TokenFault::InvalidProof, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn unbound_token_ignores_proof() -> Result<()> {
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 60);
+        check_proof(&claims, None)?;
+        check_proof(&claims, Some("garbage"))
+    }
+}
@@ -1,19 +1,58 @@
-//! Ed25519 token signing.
-//! Used by: handlers::mint.
+//! Ed25519 token signing with an optional routing prefix, plus a compact binary form.
+//! Used by: handlers::mint, handlers::delegate, handlers::refresh.
 
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use ed25519_dalek::{SigningKey, Signer};
+use ed25519_dalek::{SigningKey, Signer, SIGNATURE_LENGTH};
+use ring::hmac;
 
-use crate::error::Result;
-use crate::token::claims::Claims;
+use crate::error::{Error, Result};
+use crate::token::claims::{Algorithm, Claims, TokenHeader};
+use crate::token::keys::{key_id, SigningDomain};
 
 pub fn sign_token(claims: &Claims, key: &SigningKey) -> Result<String> {
+    sign_token_with_prefix(claims, key, None)
+}
+
+/// `<prefix><header>.<payload>.<signature>`; the signature covers both the header (`kid`) and the payload.
+pub fn sign_token_with_prefix(claims: &Claims, key: &SigningKey, prefix: Option<&str>) -> Result<String> {
+    let header = TokenHeader { kid: key_id(&key.verifying_key()) };
+    let encoded_header = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&header)?);
+    let encoded_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+    let signed = format!("{encoded_header}.{encoded_payload}");
+    let encoded_signature = URL_SAFE_NO_PAD.encode(key.sign(signed.as_bytes()).to_bytes());
+    Ok(format!("{}{signed}.{encoded_signature}", prefix.unwrap_or("")))
+}
+
+/// `<prefix><payload>.<tag>`: HMAC-SHA256 over the payload under a shared secret, with `alg: HS256`
+/// stamped into the claims so Ed25519 verifiers reject the token outright. No `kid` header: there
+/// is only ever the one secret.
+pub fn sign_token_hs256(claims: &Claims, key: &hmac::Key, prefix: Option<&str>) -> Result<String> {
+    let claims = Claims { alg: Some(Algorithm::HS256), ..claims.clone() };
+    let encoded_payload = URL_SAFE_NO_PAD.encode(serde_json::to_vec(&claims)?);
+    let tag = hmac::sign(key, encoded_payload.as_bytes());
+    Ok(format!("{}{encoded_payload}.{}", prefix.unwrap_or(""), URL_SAFE_NO_PAD.encode(tag.as_ref())))
+}
+
+/// `[u16 big-endian payload length][JSON payload][64-byte signature]`, for agents that cannot
+/// afford base64. The signature covers the payload in `SigningDomain::BinaryToken`. Never carries
+/// a routing prefix.
+pub fn sign_token_binary(claims: &Claims, key: &SigningKey) -> Result<Vec<u8>> {
     let payload = serde_json::to_vec(claims)?;
-    let encoded_payload = URL_SAFE_NO_PAD.encode(&payload);
-    let signature = key.sign(encoded_payload.as_bytes());
-    let encoded_signature = URL_SAFE_NO_PAD.encode(signature.to_bytes());
-    Ok(format!("{}.{}", encoded_payload, encoded_signature))
+    let len = u16::try_from(payload.len()).map_err(|_| Error::Signing("claims too large for a binary token".into()))?;
+    let mut token = Vec::with_capacity(2 + payload.len() + SIGNATURE_LENGTH);
+    token.extend_from_slice(&len.to_be_bytes());
+    token.extend_from_slice(&payload);
+    token.extend_from_slice(&key.sign(&SigningDomain::BinaryToken.message(&payload)).to_bytes());
+    Ok(token)
+}
+
+pub fn validate_prefix(prefix: &str) -> Result<()> {
+    let valid_chars = prefix.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_');
+    if prefix.is_empty() || prefix.len() > 16 || !valid_chars {
+        return Err(Error::Validation("token prefix must be 1-16 chars (alphanumeric, underscore)".into()));
+    }
+    Ok(())
 }
 
 pub fn generate_keypair() -> SigningKey {
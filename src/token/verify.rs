@@ -1,12 +1,17 @@
-//! Ed25519 token verification with size limits.
-//! Used by: handlers::proxy.
+//! Ed25519 token verification (string and binary forms) with size limits.
+//! Used by: handlers::proxy, token::offline.
+
+use std::collections::HashMap;
+use std::time::Duration;
 
 use base64::Engine;
 use base64::engine::general_purpose::URL_SAFE_NO_PAD;
-use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use ed25519_dalek::{VerifyingKey, SIGNATURE_LENGTH};
+use serde::Deserialize;
 
-use crate::error::{Error, Result};
-use crate::token::claims::Claims;
+use crate::error::{Error, Result, TokenFault};
+use crate::token::claims::{Algorithm, Claims, TokenHeader, DEFAULT_EXPIRY_LEEWAY, DEFAULT_IAT_LEEWAY};
+use crate::token::keys::{decode_public_key, PinnedKey, SigningDomain, DEFAULT_KID};
 
 const MAX_TOKEN_BYTES: usize = 2048;
 
@@ -14,42 +19,251 @@ fn validate_base64_url(input: &str) -> Result<()> {
     if input.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_' || b == b'=') {
         return Ok(());
     }
-    Err(Error::InvalidToken("invalid base64url characters".into()))
+    Err(Error::InvalidToken(TokenFault::InvalidEncoding, "invalid base64url characters".into()))
 }
 
-pub fn verify_token(token: &str, key: &VerifyingKey) -> Result<Claims> {
+fn encoding_err(e: base64::DecodeError) -> Error {
+    Error::InvalidToken(TokenFault::InvalidEncoding, e.to_string())
+}
+
+#[derive(Deserialize)]
+struct AlgHeader {
+    alg: Option<Algorithm>,
+}
+
+/// The algorithm the payload claims, read before the signature is checked.
+fn claimed_alg_json(payload: &[u8]) -> Algorithm {
+    serde_json::from_slice::<AlgHeader>(payload)
+        .ok()
+        .and_then(|h| h.alg)
+        .unwrap_or(Algorithm::EdDSA)
+}
+
+/// Cheap structural checks that shed garbage before the signature operation: the payload
+/// must look like a JSON object and the signature must have the key's fixed length (64 bytes
+/// for Ed25519 and fixed-encoding ES256, 32 for HS256). Neither replaces verification.
+fn precheck(payload: &[u8], signature: &[u8], expected_len: usize) -> Result<()> {
+    if payload.first() != Some(&b'{') {
+        return Err(Error::InvalidToken(TokenFault::InvalidPayload, "payload is not a JSON object".into()));
+    }
+    if signature.len() != expected_len {
+        return Err(Error::InvalidToken(
+            TokenFault::InvalidEncoding,
+            format!("signature is {} bytes, expected {expected_len}", signature.len()),
+        ));
+    }
+    Ok(())
+}
+
+/// A string token taken apart; `signed` is the text the signature covers.
+struct Segments<'a> {
+    header: Option<TokenHeader>,
+    payload: Vec<u8>,
+    signed: &'a str,
+    signature: Vec<u8>,
+}
+
+/// Size, prefix, encoding, and structure checks, in that order, for `[header.]payload.signature`.
+fn split_token<'a>(token: &'a str, prefix: Option<&str>, signature_len: usize) -> Result<Segments<'a>> {
     if token.len() > MAX_TOKEN_BYTES {
-        return Err(Error::InvalidToken("token exceeds size limit".into()));
+        return Err(Error::InvalidToken(TokenFault::TooLarge, "token exceeds size limit".into()));
+    }
+    let token = strip_prefix(token, prefix)?;
+    let (signed, sig_b64) = token
+        .rsplit_once('.')
+        .ok_or_else(|| Error::InvalidToken(TokenFault::MissingSeparator, "missing separator".into()))?;
+    let (header_b64, payload_b64) = match signed.split_once('.') {
+        Some((header, payload)) => (Some(header), payload),
+        None => (None, signed),
+    };
+    for segment in header_b64.into_iter().chain([payload_b64, sig_b64]) {
+        validate_base64_url(segment)?;
     }
 
-    let (payload_b64, sig_b64) = token
-        .split_once('.')
-        .ok_or_else(|| Error::InvalidToken("missing separator".into()))?;
+    let header = header_b64.map(parse_header).transpose()?;
+    let payload = URL_SAFE_NO_PAD.decode(payload_b64).map_err(encoding_err)?;
+    let signature = URL_SAFE_NO_PAD.decode(sig_b64).map_err(encoding_err)?;
+    precheck(&payload, &signature, signature_len)?;
+    Ok(Segments { header, payload, signed, signature })
+}
+
+fn parse_header(encoded: &str) -> Result<TokenHeader> {
+    let bytes = URL_SAFE_NO_PAD.decode(encoded).map_err(encoding_err)?;
+    serde_json::from_slice(&bytes).map_err(|e| Error::InvalidToken(TokenFault::InvalidPayload, format!("header: {e}")))
+}
+
+fn strip_prefix<'a>(token: &'a str, prefix: Option<&str>) -> Result<&'a str> {
+    let Some(prefix) = prefix else {
+        return Ok(token);
+    };
+    token
+        .strip_prefix(prefix)
+        .ok_or_else(|| Error::InvalidToken(TokenFault::WrongPrefix, "missing or wrong token prefix".into()))
+}
 
-    validate_base64_url(payload_b64)?;
-    validate_base64_url(sig_b64)?;
+pub fn verify_token(token: &str, key: &VerifyingKey) -> Result<Claims> {
+    verify_token_with_prefix(token, key, None)
+}
 
-    let sig_bytes = URL_SAFE_NO_PAD.decode(sig_b64)?;
-    let signature = Signature::from_slice(&sig_bytes)
-        .map_err(|e| Error::InvalidToken(e.to_string()))?;
+/// Verifies the token and requires `expected` to be one of its audiences.
+pub fn verify_token_for(token: &str, key: &VerifyingKey, expected: &str) -> Result<Claims> {
+    let claims = verify_token(token, key)?;
+    check_audience(&claims, expected)?;
+    Ok(claims)
+}
 
-    key.verify(payload_b64.as_bytes(), &signature)
-        .map_err(|_| Error::InvalidSignature)?;
+/// A token without `aud` is not bound to any service and fails an audience check.
+pub fn check_audience(claims: &Claims, expected: &str) -> Result<()> {
+    match &claims.aud {
+        Some(aud) if aud.contains(expected) => Ok(()),
+        _ => Err(Error::InvalidToken(TokenFault::WrongAudience, format!("token not valid for audience {expected}"))),
+    }
+}
+
+/// Verifies with a caller-supplied base64url Ed25519 public key instead of the server's own.
+pub fn verify_with_public_key(token: &str, public_key: &str, prefix: Option<&str>) -> Result<Claims> {
+    verify_token_with_prefix(token, &decode_public_key(public_key)?, prefix)
+}
+
+pub fn verify_token_with_prefix(token: &str, key: &VerifyingKey, prefix: Option<&str>) -> Result<Claims> {
+    verify_pinned(token, &PinnedKey::EdDSA(*key), prefix)
+}
+
+/// Like `verify_token_with_prefix`, but an expired token still yields its (authentic) claims.
+pub fn verify_token_allow_expired(token: &str, key: &VerifyingKey, prefix: Option<&str>, iat_leeway: Duration) -> Result<Claims> {
+    verify_pinned_allow_expired(token, &PinnedKey::EdDSA(*key), prefix, iat_leeway)
+}
+
+pub fn verify_token_binary(token: &[u8], key: &VerifyingKey) -> Result<Claims> {
+    let claims = verify_token_binary_allow_expired(token, key, DEFAULT_IAT_LEEWAY)?;
+    if claims.is_expired(DEFAULT_EXPIRY_LEEWAY) {
+        return Err(Error::TokenExpired);
+    }
+    Ok(claims)
+}
+
+/// Binary counterpart of `verify_pinned_allow_expired`: the length prefix must account for every byte.
+pub fn verify_token_binary_allow_expired(token: &[u8], key: &VerifyingKey, iat_leeway: Duration) -> Result<Claims> {
+    if token.len() > MAX_TOKEN_BYTES {
+        return Err(Error::InvalidToken(TokenFault::TooLarge, "token exceeds size limit".into()));
+    }
+    let malformed = || Error::InvalidToken(TokenFault::InvalidEncoding, "binary token length mismatch".into());
+    let (len, rest) = token.split_first_chunk::<2>().ok_or_else(malformed)?;
+    let len = usize::from(u16::from_be_bytes(*len));
+    if rest.len() != len + SIGNATURE_LENGTH {
+        return Err(malformed());
+    }
+    let (payload, signature) = rest.split_at(len);
+    precheck(payload, signature, SIGNATURE_LENGTH)?;
+
+    let key = PinnedKey::EdDSA(*key);
+    let alg = claimed_alg_json(payload);
+    if alg != key.alg() {
+        return Err(Error::InvalidToken(
+            TokenFault::AlgorithmMismatch,
+            format!("token claims {alg:?} but key is pinned to {:?}", key.alg()),
+        ));
+    }
+    key.verify(&SigningDomain::BinaryToken.message(payload), signature)?;
+    parse_claims(payload, iat_leeway)
+}
+
+/// Verifies against a key pinned to one algorithm; a token claiming any other is rejected outright.
+pub fn verify_pinned(token: &str, key: &PinnedKey, prefix: Option<&str>) -> Result<Claims> {
+    let claims = verify_pinned_allow_expired(token, key, prefix, DEFAULT_IAT_LEEWAY)?;
+    if claims.is_expired(DEFAULT_EXPIRY_LEEWAY) {
+        return Err(Error::TokenExpired);
+    }
+    Ok(claims)
+}
 
-    let payload_bytes = URL_SAFE_NO_PAD.decode(payload_b64)?;
-    let claims: Claims = serde_json::from_slice(&payload_bytes)?;
+/// Size, encoding, structure, algorithm, signature, and payload checks, with an `iat` up to
+/// `iat_leeway` ahead tolerated; expiry is left to the caller.
+/// A single pinned key ignores the header's `kid`: the signature alone decides.
+pub fn verify_pinned_allow_expired(token: &str, key: &PinnedKey, prefix: Option<&str>, iat_leeway: Duration) -> Result<Claims> {
+    verify_segments(&split_token(token, prefix, key.signature_len())?, key, iat_leeway)
+}
 
-    if claims.is_expired() {
+/// Verifies against whichever of `keys` the token's `kid` names, so tokens signed by an
+/// outgoing key keep verifying while it stays in the set.
+pub fn verify_token_with_keys(token: &str, keys: &HashMap<String, VerifyingKey>, prefix: Option<&str>) -> Result<Claims> {
+    let claims = verify_token_with_keys_allow_expired(token, keys, prefix, DEFAULT_IAT_LEEWAY)?;
+    if claims.is_expired(DEFAULT_EXPIRY_LEEWAY) {
         return Err(Error::TokenExpired);
     }
+    Ok(claims)
+}
+
+/// The `kid` the token's header names, read before any signature check; `None` when headerless.
+pub fn token_kid(token: &str, prefix: Option<&str>) -> Result<Option<String>> {
+    Ok(split_token(token, prefix, SIGNATURE_LENGTH)?.header.map(|h| h.kid))
+}
+
+/// `TOKEN_ACCEPT_LEGACY=false` for verifiers pinned to a single Ed25519 key, which would otherwise
+/// accept a headerless token: refuses one before any signature check.
+pub fn reject_headerless(token: &str, prefix: Option<&str>) -> Result<()> {
+    match token_kid(token, prefix)? {
+        Some(_) => Ok(()),
+        None => Err(Error::InvalidToken(TokenFault::LegacyFormat, "headerless legacy tokens are not accepted".into())),
+    }
+}
+
+/// A headerless (legacy two-segment) token is looked up under `DEFAULT_KID` and rejected when
+/// the set has no such key; a `kid` not in `keys` is rejected before any signature check.
+pub fn verify_token_with_keys_allow_expired(
+    token: &str,
+    keys: &HashMap<String, VerifyingKey>,
+    prefix: Option<&str>,
+    iat_leeway: Duration,
+) -> Result<Claims> {
+    let segments = split_token(token, prefix, SIGNATURE_LENGTH)?;
+    let kid = match &segments.header {
+        Some(header) => header.kid.as_str(),
+        None if keys.contains_key(DEFAULT_KID) => DEFAULT_KID,
+        None => {
+            return Err(Error::InvalidToken(TokenFault::LegacyFormat, "headerless legacy tokens are not accepted".into()))
+        }
+    };
+    let key = keys
+        .get(kid)
+        .ok_or_else(|| Error::InvalidToken(TokenFault::UnknownKey, format!("no verifying key with kid {kid}")))?;
+    verify_segments(&segments, &PinnedKey::EdDSA(*key), iat_leeway)
+}
+
+fn verify_segments(segments: &Segments, key: &PinnedKey, iat_leeway: Duration) -> Result<Claims> {
+    let alg = claimed_alg_json(&segments.payload);
+    if alg != key.alg() {
+        return Err(Error::InvalidToken(
+            TokenFault::AlgorithmMismatch,
+            format!("token claims {alg:?} but key is pinned to {:?}", key.alg()),
+        ));
+    }
+    key.verify(segments.signed.as_bytes(), &segments.signature)?;
+    parse_claims(&segments.payload, iat_leeway)
+}
 
+/// Parses an authenticated payload and rejects an `iat` more than `iat_leeway` ahead, or an `nbf`
+/// still in the future.
+fn parse_claims(payload: &[u8], iat_leeway: Duration) -> Result<Claims> {
+    let claims: Claims =
+        serde_json::from_slice(payload).map_err(|e| Error::InvalidToken(TokenFault::InvalidPayload, e.to_string()))?;
+    if claims.issued_in_future(iat_leeway) {
+        return Err(Error::InvalidToken(
+            TokenFault::InvalidField,
+            format!("iat {} is more than {}s in the future", claims.iat.to_rfc3339(), iat_leeway.as_secs()),
+        ));
+    }
+    if claims.not_yet_valid() {
+        return Err(Error::TokenNotYetValid);
+    }
     Ok(claims)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::token::sign::{generate_keypair, sign_token};
+    use crate::token::claims::Audience;
+    use crate::token::sign::{generate_keypair, sign_token, sign_token_binary, sign_token_with_prefix, validate_prefix};
 
     #[test]
     fn valid_token_verifies() -> Result<()> {
@@ -62,23 +276,228 @@ mod tests {
         Ok(())
     }
 
+    fn sign_raw(payload: &serde_json::Value, key: &ed25519_dalek::SigningKey) -> Result<String> {
+        use ed25519_dalek::Signer;
+        let payload_b64 = URL_SAFE_NO_PAD.encode(serde_json::to_vec(payload)?);
+        let sig = key.sign(payload_b64.as_bytes());
+        Ok(format!("{payload_b64}.{}", URL_SAFE_NO_PAD.encode(sig.to_bytes())))
+    }
+
+    #[test]
+    fn unknown_claim_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let mut payload = serde_json::to_value(Claims::new("agent-1".into(), "deploy".into(), 300))?;
+        let well_formed = sign_raw(&payload, &key)?;
+        assert_eq!(verify_token(&well_formed, &key.verifying_key())?.sub, "agent-1");
+
+        payload["admin"] = serde_json::Value::Bool(true);
+        let smuggled = sign_raw(&payload, &key)?;
+        let result = verify_token(&smuggled, &key.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidPayload, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn multi_audience_token_verifies_for_any_member() -> Result<()> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "pay".into(), 300);
+        claims.aud = Some(Audience::Many(vec!["payments".into(), "ledger".into()]));
+        let token = sign_token(&claims, &key)?;
+        verify_token_for(&token, &key.verifying_key(), "payments")?;
+        verify_token_for(&token, &key.verifying_key(), "ledger")?;
+        let result = verify_token_for(&token, &key.verifying_key(), "deploy");
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::WrongAudience, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn scalar_audience_and_missing_audience() -> Result<()> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "pay".into(), 300);
+        let unbound = sign_token(&claims, &key)?;
+        claims.aud = Some(Audience::One("payments".into()));
+        let scalar = sign_token(&claims, &key)?;
+        verify_token_for(&scalar, &key.verifying_key(), "payments")?;
+        assert!(verify_token_for(&scalar, &key.verifying_key(), "ledger").is_err());
+        assert!(verify_token_for(&unbound, &key.verifying_key(), "payments").is_err());
+        Ok(())
+    }
+
+    struct Es256Signer {
+        pair: ring::signature::EcdsaKeyPair,
+        rng: ring::rand::SystemRandom,
+    }
+
+    impl Es256Signer {
+        fn generate() -> std::result::Result<Self, Box<dyn std::error::Error>> {
+            use ring::signature::{EcdsaKeyPair, ECDSA_P256_SHA256_FIXED_SIGNING};
+            let rng = ring::rand::SystemRandom::new();
+            let pkcs8 = EcdsaKeyPair::generate_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, &rng)
+                .map_err(|e| e.to_string())?;
+            let pair = EcdsaKeyPair::from_pkcs8(&ECDSA_P256_SHA256_FIXED_SIGNING, pkcs8.as_ref(), &rng)
+                .map_err(|e| e.to_string())?;
+            Ok(Self { pair, rng })
+        }
+
+        fn pinned(&self) -> PinnedKey {
+            use ring::signature::KeyPair;
+            PinnedKey::ES256(self.pair.public_key().as_ref().to_vec())
+        }
+
+        fn sign(&self, claims: &Claims) -> std::result::Result<String, Box<dyn std::error::Error>> {
+            let payload_b64 = URL_SAFE_NO_PAD.encode(serde_json::to_vec(claims)?);
+            let sig = self.pair.sign(&self.rng, payload_b64.as_bytes()).map_err(|e| e.to_string())?;
+            Ok(format!("{payload_b64}.{}", URL_SAFE_NO_PAD.encode(sig.as_ref())))
+        }
+    }
+
+    #[test]
+    fn es256_token_verifies_only_against_es256_key() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let es = Es256Signer::generate()?;
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.alg = Some(Algorithm::ES256);
+        let token = es.sign(&claims)?;
+
+        assert_eq!(verify_pinned(&token, &es.pinned(), None)?.sub, "agent-1");
+        let ed = PinnedKey::EdDSA(generate_keypair().verifying_key());
+        let result = verify_pinned(&token, &ed, None);
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::AlgorithmMismatch, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn eddsa_token_rejected_by_es256_key() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let key = generate_keypair();
+        let token = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 300), &key)?;
+        let result = verify_pinned(&token, &Es256Signer::generate()?.pinned(), None);
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::AlgorithmMismatch, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn relabelled_alg_cannot_borrow_another_key() -> std::result::Result<(), Box<dyn std::error::Error>> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        claims.alg = Some(Algorithm::ES256);
+        let token = sign_token(&claims, &key)?;
+        let result = verify_token(&token, &key.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::AlgorithmMismatch, _))));
+        Ok(())
+    }
+
     #[test]
     fn expired_token_rejected() -> Result<()> {
         let key = generate_keypair();
-        let claims = Claims::new("agent-1".into(), "deploy".into(), 0);
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 0);
+        claims.exp -= chrono::Duration::seconds(10);
         let token = sign_token(&claims, &key)?;
         let result = verify_token(&token, &key.verifying_key());
         assert!(matches!(result, Err(Error::TokenExpired)));
         Ok(())
     }
 
+    #[test]
+    fn expiry_within_leeway_tolerated() -> Result<()> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 0);
+        claims.exp -= chrono::Duration::seconds(2);
+        assert_eq!(verify_token(&sign_token(&claims, &key)?, &key.verifying_key())?, claims);
+        assert!(claims.is_expired(std::time::Duration::ZERO));
+        Ok(())
+    }
+
+    #[test]
+    fn token_rejected_until_its_nbf() -> Result<()> {
+        let key = generate_keypair();
+        let pending = Claims::new("agent-1".into(), "deploy".into(), 60).not_before(30);
+        let result = verify_token(&sign_token(&pending, &key)?, &key.verifying_key());
+        assert!(matches!(result, Err(Error::TokenNotYetValid)));
+
+        let mut minted_earlier = Claims::new("agent-1".into(), "deploy".into(), 60);
+        minted_earlier.iat -= chrono::Duration::seconds(45);
+        minted_earlier.exp -= chrono::Duration::seconds(45);
+        let now_valid = minted_earlier.not_before(30);
+        assert_eq!(verify_token(&sign_token(&now_valid, &key)?, &key.verifying_key())?, now_valid);
+        Ok(())
+    }
+
+    #[test]
+    fn future_iat_rejected_beyond_leeway() -> Result<()> {
+        let key = generate_keypair();
+        let mut claims = Claims::new("agent-1".into(), "deploy".into(), 600);
+        claims.iat += chrono::Duration::minutes(5);
+        let result = verify_token(&sign_token(&claims, &key)?, &key.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+
+        let mut far_ahead = Claims::new("agent-1".into(), "deploy".into(), 60);
+        far_ahead.iat += chrono::Duration::hours(1);
+        far_ahead.exp += chrono::Duration::hours(1);
+        let result = verify_token(&sign_token(&far_ahead, &key)?, &key.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidField, m)) if m.contains("in the future")));
+
+        let fresh = Claims::new("agent-1".into(), "deploy".into(), 60);
+        assert_eq!(verify_token(&sign_token(&fresh, &key)?, &key.verifying_key())?, fresh);
+
+        claims.iat = chrono::Utc::now() + chrono::Duration::seconds(30);
+        assert_eq!(verify_token(&sign_token(&claims, &key)?, &key.verifying_key())?, claims);
+        let strict = verify_pinned_allow_expired(&sign_token(&claims, &key)?, &PinnedKey::EdDSA(key.verifying_key()), None, Duration::from_secs(10));
+        assert!(matches!(strict, Err(Error::InvalidToken(TokenFault::InvalidField, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn key_set_selects_by_kid_while_both_keys_are_present() -> Result<()> {
+        use crate::token::keys::key_id;
+        let (old, new) = (generate_keypair(), generate_keypair());
+        let keys = HashMap::from([
+            (key_id(&old.verifying_key()), old.verifying_key()),
+            (key_id(&new.verifying_key()), new.verifying_key()),
+            (DEFAULT_KID.to_owned(), new.verifying_key()),
+        ]);
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        assert_eq!(verify_token_with_keys(&sign_token(&claims, &old)?, &keys, None)?, claims);
+        assert_eq!(verify_token_with_keys(&sign_token(&claims, &new)?, &keys, None)?, claims);
+
+        let headerless = sign_raw(&serde_json::to_value(&claims)?, &new)?;
+        assert_eq!(verify_token_with_keys(&headerless, &keys, None)?, claims);
+        let headerless_old = sign_raw(&serde_json::to_value(&claims)?, &old)?;
+        assert!(matches!(verify_token_with_keys(&headerless_old, &keys, None), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_kid_rejected() -> Result<()> {
+        let (known, stranger) = (generate_keypair(), generate_keypair());
+        let keys = HashMap::from([(DEFAULT_KID.to_owned(), known.verifying_key())]);
+        let token = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 300), &stranger)?;
+        let result = verify_token_with_keys(&token, &keys, None);
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::UnknownKey, _))));
+        Ok(())
+    }
+
+    #[test]
+    fn hs256_token_verifies_only_with_its_secret() -> Result<()> {
+        use crate::token::keys::hmac_key;
+        use crate::token::sign::sign_token_hs256;
+
+        let secret = hmac_key(b"an-internal-shared-secret-of-32-bytes");
+        let token = sign_token_hs256(&Claims::new("agent-1".into(), "deploy".into(), 300), &secret, None)?;
+        let verified = verify_pinned(&token, &PinnedKey::HS256(secret), None)?;
+        assert_eq!(verified.alg, Some(Algorithm::HS256));
+
+        let wrong = PinnedKey::HS256(hmac_key(b"some-other-shared-secret-of-32-bytes"));
+        assert!(matches!(verify_pinned(&token, &wrong, None), Err(Error::InvalidSignature)));
+        assert!(verify_token(&token, &generate_keypair().verifying_key()).is_err());
+        Ok(())
+    }
+
     #[test]
     fn tampered_token_rejected() -> Result<()> {
         let key = generate_keypair();
         let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
         let token = sign_token(&claims, &key)?;
-        let parts: Vec<&str> = token.split('.').collect();
-        let tampered = format!("{}x.{}", parts[0], parts[1]);
+        let (signed, signature) = token.rsplit_once('.').ok_or(Error::InvalidSignature)?;
+        let tampered = format!("{signed}x.{signature}");
         let result = verify_token(&tampered, &key.verifying_key());
         assert!(matches!(result, Err(Error::InvalidSignature)));
         Ok(())
@@ -95,11 +514,65 @@ mod tests {
         Ok(())
     }
 
+    #[test]
+    fn external_public_key_verifies_only_its_own_tokens() -> Result<()> {
+        let key = generate_keypair();
+        let token = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 300), &key)?;
+        let own = URL_SAFE_NO_PAD.encode(key.verifying_key().to_bytes());
+        let other = URL_SAFE_NO_PAD.encode(generate_keypair().verifying_key().to_bytes());
+
+        assert_eq!(verify_with_public_key(&token, &own, None)?.sub, "agent-1");
+        assert!(matches!(verify_with_public_key(&token, &other, None), Err(Error::InvalidSignature)));
+        assert!(matches!(verify_with_public_key(&token, "c2hvcnQ", None), Err(Error::Validation(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn binary_token_round_trips() -> Result<()> {
+        let key = generate_keypair();
+        let claims = Claims::new("sensor-7".into(), "read:temp".into(), 300);
+        let token = sign_token_binary(&claims, &key)?;
+        assert!(token.len() < sign_token(&claims, &key)?.len());
+        assert_eq!(verify_token_binary(&token, &key.verifying_key())?, claims);
+        Ok(())
+    }
+
+    #[test]
+    fn truncated_or_altered_binary_token_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let token = sign_token_binary(&Claims::new("sensor-7".into(), "read:temp".into(), 300), &key)?;
+        let verifying = key.verifying_key();
+
+        for cut in [0, 1, 2, token.len() / 2, token.len() - 1] {
+            let result = verify_token_binary(&token[..cut], &verifying);
+            assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidEncoding, _))), "cut at {cut}");
+        }
+        let mut flipped = token.clone();
+        flipped[5] ^= 1;
+        assert!(verify_token_binary(&flipped, &verifying).is_err());
+        let mut padded = token;
+        padded.push(0);
+        assert!(verify_token_binary(&padded, &verifying).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn binary_token_signed_outside_its_domain_rejected() -> Result<()> {
+        use ed25519_dalek::Signer;
+        let key = generate_keypair();
+        let payload = serde_json::to_vec(&Claims::new("sensor-7".into(), "read:temp".into(), 300))?;
+        let mut token = (payload.len() as u16).to_be_bytes().to_vec();
+        token.extend_from_slice(&payload);
+        token.extend_from_slice(&key.sign(&SigningDomain::AuditBundle.message(&payload)).to_bytes());
+        assert!(matches!(verify_token_binary(&token, &key.verifying_key()), Err(Error::InvalidSignature)));
+        Ok(())
+    }
+
     #[test]
     fn missing_separator_rejected() {
         let key = generate_keypair();
         let result = verify_token("nodothere", &key.verifying_key());
-        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::MissingSeparator, _))));
     }
 
     #[test]
@@ -107,13 +580,81 @@ mod tests {
         let key = generate_keypair();
         let huge = "A".repeat(MAX_TOKEN_BYTES + 1);
         let result = verify_token(&huge, &key.verifying_key());
-        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::TooLarge, _))));
     }
 
     #[test]
     fn invalid_base64_chars_rejected() {
         let key = generate_keypair();
         let result = verify_token("pay load.sig!nature", &key.verifying_key());
-        assert!(matches!(result, Err(Error::InvalidToken(_))));
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidEncoding, _))));
+    }
+
+    #[test]
+    fn undecodable_signature_is_encoding_fault() {
+        let key = generate_keypair();
+        let result = verify_token("abc.x", &key.verifying_key());
+        assert!(matches!(result, Err(Error::InvalidToken(TokenFault::InvalidEncoding, _))));
+    }
+
+    #[test]
+    fn prefixed_token_verifies() -> Result<()> {
+        let key = generate_keypair();
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let token = sign_token_with_prefix(&claims, &key, Some("am1_"))?;
+        assert!(token.starts_with("am1_"));
+        let verified = verify_token_with_prefix(&token, &key.verifying_key(), Some("am1_"))?;
+        assert_eq!(verified.jti, claims.jti);
+        Ok(())
+    }
+
+    #[test]
+    fn wrong_or_missing_prefix_rejected() -> Result<()> {
+        let key = generate_keypair();
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let other = sign_token_with_prefix(&claims, &key, Some("xx1_"))?;
+        let bare = sign_token(&claims, &key)?;
+        for token in [other, bare] {
+            let result = verify_token_with_prefix(&token, &key.verifying_key(), Some("am1_"));
+            assert!(matches!(result, Err(Error::InvalidToken(TokenFault::WrongPrefix, _))));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn prefixless_mode_unchanged() -> Result<()> {
+        let key = generate_keypair();
+        let claims = Claims::new("agent-1".into(), "deploy".into(), 300);
+        let token = sign_token_with_prefix(&claims, &key, None)?;
+        assert_eq!(verify_token(&token, &key.verifying_key())?.jti, claims.jti);
+        Ok(())
+    }
+
+    #[test]
+    fn prefix_config_validated() {
+        assert!(validate_prefix("am1_").is_ok());
+        assert!(validate_prefix("").is_err());
+        assert!(validate_prefix("am.1").is_err());
+    }
+
+    #[test]
+    fn malformed_tokens_rejected_before_signature_check() -> Result<()> {
+        let key = generate_keypair().verifying_key();
+        let forged = sign_token(&Claims::new("agent-1".into(), "deploy".into(), 300), &generate_keypair())?;
+        assert!(matches!(verify_token(&forged, &key), Err(Error::InvalidSignature)));
+
+        let (signed, _) = forged.rsplit_once('.').ok_or(Error::InvalidSignature)?;
+        assert_eq!(signed.matches('.').count(), 1);
+        let short_sig = format!("{signed}.{}", URL_SAFE_NO_PAD.encode([0u8; 63]));
+        assert!(matches!(verify_token(&short_sig, &key), Err(Error::InvalidToken(TokenFault::InvalidEncoding, _))));
+
+        let not_json = format!("{}.{}", URL_SAFE_NO_PAD.encode(b"[1,2]"), URL_SAFE_NO_PAD.encode([0u8; 64]));
+        assert!(matches!(verify_token(&not_json, &key), Err(Error::InvalidToken(TokenFault::InvalidPayload, _))));
+
+        let mut binary = 5u16.to_be_bytes().to_vec();
+        binary.extend_from_slice(b"hello");
+        binary.extend_from_slice(&[0u8; 64]);
+        assert!(matches!(verify_token_binary(&binary, &key), Err(Error::InvalidToken(TokenFault::InvalidPayload, _))));
+        Ok(())
     }
 }
@@ -5,28 +5,43 @@ use std::collections::HashMap;
 use std::sync::RwLock;
 use std::time::{Duration, Instant};
 use url::Url;
+use utoipa::ToSchema;
 use webauthn_rs::prelude::*;
 
-use crate::error::{Error, Result};
+use crate::error::{lock_err, Error, Result};
 use crate::state::AppState;
+use crate::storage::CredentialStore;
 
 // Hardening constants
-const MAX_CHALLENGES: usize = 10_000;
 const CHALLENGE_TTL: Duration = Duration::from_secs(300);
 const LOCKOUT_THRESHOLD: u32 = 5;
 const LOCKOUT_DURATION: Duration = Duration::from_secs(900);
+const REENROLL_WINDOW: Duration = Duration::from_secs(120);
+/// One registration and one authentication ceremony at a time.
+pub(crate) const DEFAULT_CHALLENGES_PER_USER: usize = 2;
 
 pub struct WebAuthnState {
     core: Webauthn,
-    reg_challenges: RwLock<HashMap<Box<str>, ChallengeEntry<PasskeyRegistration>>>,
-    auth_challenges: RwLock<HashMap<Box<str>, ChallengeEntry<PasskeyAuthentication>>>,
-    credentials: RwLock<HashMap<Box<str>, Passkey>>,
+    /// Live ceremonies (registration, authentication) one user may hold at once.
+    challenge_cap: usize,
+    /// Reject `user_id`s that are not UUIDs instead of assigning a handle; see `user_handle`.
+    strict_user_ids: bool,
     failures: RwLock<HashMap<Box<str>, FailureRecord>>,
+    approvals: RwLock<HashMap<Box<str>, Instant>>,
 }
 
-struct ChallengeEntry<T> {
-    data: T,
-    created: Instant,
+#[derive(Serialize, Deserialize)]
+struct RegistrationChallenge {
+    state: PasskeyRegistration,
+    replaces: bool,
+    /// What the client was sent; kept for first registrations so a repeated start (e.g. a double
+    /// click) gets it back with its original expiry, and whichever response the client acts on
+    /// still matches the stored state.
+    #[serde(default)]
+    issued: Option<CreationChallengeResponse>,
+    /// The user handle the credential is created under; recorded with it on finish.
+    #[serde(default)]
+    handle: Option<Uuid>,
 }
 
 struct FailureRecord {
@@ -41,21 +56,35 @@ impl WebAuthnState {
 
         Ok(Self {
             core,
-            reg_challenges: RwLock::new(HashMap::new()),
-            auth_challenges: RwLock::new(HashMap::new()),
-            credentials: RwLock::new(HashMap::new()),
+            challenge_cap: DEFAULT_CHALLENGES_PER_USER,
+            strict_user_ids: false,
             failures: RwLock::new(HashMap::new()),
+            approvals: RwLock::new(HashMap::new()),
         })
     }
 
-    pub fn from_env() -> Option<Self> {
-        let rp_id = std::env::var("WEBAUTHN_RP_ID").ok()?;
-        let rp_origin = std::env::var("WEBAUTHN_RP_ORIGIN").ok()?;
+    pub fn with_challenge_cap(mut self, cap: usize) -> Self {
+        self.challenge_cap = cap.max(1);
+        self
+    }
 
-        Self::new(&rp_id, &rp_origin)
-            .inspect(|_| tracing::info!(rp_id = %rp_id, "WebAuthn enabled"))
-            .inspect_err(|e| tracing::warn!(error = ?e, "WebAuthn config failed"))
-            .ok()
+    pub fn with_strict_user_ids(mut self, strict: bool) -> Self {
+        self.strict_user_ids = strict;
+        self
+    }
+
+    /// The WebAuthn user handle to register `user_id` under. A UUID is used as is. Any other
+    /// string keeps the handle recorded with its credential, or gets a random one, so the handle
+    /// reveals nothing about `user_id`; in strict mode it is rejected instead.
+    async fn user_handle(&self, credentials: &dyn CredentialStore, user_id: &str) -> Result<Uuid> {
+        if let Ok(uuid) = Uuid::parse_str(user_id) {
+            return Ok(uuid);
+        }
+        if self.strict_user_ids {
+            return Err(Error::Validation("user_id must be a UUID (WEBAUTHN_STRICT_USER_IDS=true)".into()));
+        }
+        let recorded = credentials.user_handle(user_id).await?.and_then(|h| Uuid::parse_str(&h).ok());
+        Ok(recorded.unwrap_or_else(Uuid::new_v4))
     }
 
     #[inline]
@@ -63,84 +92,167 @@ impl WebAuthnState {
         opt.ok_or_else(|| Error::Unauthorized("WebAuthn not configured".into()))
     }
 
-    fn is_locked_out(&self, user_id: &str) -> bool {
-        let failures = self.failures.read().unwrap();
+    fn is_locked_out(&self, user_id: &str) -> Result<bool> {
+        let failures = self.failures.read().map_err(lock_err("webauthn failures"))?;
         if let Some(record) = failures.get(user_id) {
             if record.count >= LOCKOUT_THRESHOLD {
-                return record.last_failure.elapsed() < LOCKOUT_DURATION;
+                return Ok(record.last_failure.elapsed() < LOCKOUT_DURATION);
             }
         }
-        false
+        Ok(false)
     }
 
-    fn record_failure(&self, user_id: &str) {
-        let mut failures = self.failures.write().unwrap();
+    fn record_failure(&self, user_id: &str) -> Result<()> {
+        let mut failures = self.failures.write().map_err(lock_err("webauthn failures"))?;
         let record = failures.entry(user_id.into()).or_insert(FailureRecord {
             count: 0,
             last_failure: Instant::now(),
         });
         record.count += 1;
         record.last_failure = Instant::now();
+        Ok(())
     }
 
-    fn clear_failures(&self, user_id: &str) {
-        self.failures.write().unwrap().remove(user_id);
+    fn clear_failures(&self, user_id: &str) -> Result<()> {
+        self.failures.write().map_err(lock_err("webauthn failures"))?.remove(user_id);
+        Ok(())
     }
 
-    fn cleanup_expired<T>(map: &mut HashMap<Box<str>, ChallengeEntry<T>>) {
-        map.retain(|_, entry| entry.created.elapsed() < CHALLENGE_TTL);
+    fn record_approval(&self, user_id: &str) -> Result<()> {
+        let mut approvals = self.approvals.write().map_err(lock_err("webauthn approvals"))?;
+        approvals.retain(|_, at| at.elapsed() < REENROLL_WINDOW);
+        approvals.insert(user_id.into(), Instant::now());
+        Ok(())
     }
 
-    fn check_capacity<T>(map: &HashMap<Box<str>, ChallengeEntry<T>>) -> Result<()> {
-        if map.len() >= MAX_CHALLENGES {
-            return Err(Error::ServiceUnavailable("challenge store at capacity".into()));
-        }
-        Ok(())
+    /// Consumes a recent successful authentication with the current credential; each one
+    /// authorizes a single re-enrollment.
+    fn take_approval(&self, user_id: &str) -> Result<bool> {
+        Ok(self
+            .approvals
+            .write()
+            .map_err(lock_err("webauthn approvals"))?
+            .remove(user_id)
+            .is_some_and(|at| at.elapsed() < REENROLL_WINDOW))
+    }
+
+}
+
+// === Storage helpers ===
+
+fn reg_key(user_id: &str) -> String {
+    format!("reg:{user_id}")
+}
+
+fn auth_key(user_id: &str) -> String {
+    format!("auth:{user_id}")
+}
+
+/// Whether the handle an authenticator returned is the one `user_id` registered under. Passes
+/// when either is unknown: the handle is optional in an assertion, and credentials registered
+/// before handles were recorded have none.
+async fn handle_matches(state: &AppState, user_id: &str, returned: Option<&[u8]>) -> Result<bool> {
+    let Some(returned) = returned else {
+        return Ok(true);
+    };
+    let recorded = state.credentials.user_handle(user_id).await?;
+    Ok(recorded.is_none_or(|h| Uuid::parse_str(&h).is_ok_and(|h| h.as_bytes().as_slice() == returned)))
+}
+
+/// Whether `used` is the credential currently registered. An assertion started before the
+/// credential was replaced or removed carries the old one and must not authenticate.
+fn is_current(stored: Option<&CredentialID>, used: &CredentialID) -> bool {
+    stored == Some(used)
+}
+
+async fn load_passkey(state: &AppState, user_id: &str) -> Result<Option<Passkey>> {
+    let stored = state.credentials.get(user_id).await?;
+    Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
+}
+
+/// Stores `challenge` under `key`, replacing that ceremony's previous state. Refused when the
+/// user's other live ceremonies already fill `challenge_cap`; the store checks and writes in one step.
+async fn put_challenge<T: Serialize>(
+    state: &AppState,
+    wa: &WebAuthnState,
+    user_id: &str,
+    key: &str,
+    challenge: &T,
+) -> Result<()> {
+    let siblings = [reg_key(user_id), auth_key(user_id)];
+    let challenge = serde_json::to_string(challenge)?;
+    if !state.challenges.put_capped(key, challenge, CHALLENGE_TTL, &siblings, wa.challenge_cap).await? {
+        return Err(Error::RateLimited("too many WebAuthn ceremonies in flight for this user".into()));
     }
+    Ok(())
+}
+
+async fn peek_challenge<T: serde::de::DeserializeOwned>(state: &AppState, key: &str) -> Result<Option<T>> {
+    let stored = state.challenges.peek(key).await?;
+    Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
+}
+
+async fn take_challenge<T: serde::de::DeserializeOwned>(state: &AppState, key: &str) -> Result<Option<T>> {
+    let stored = state.challenges.take(key).await?;
+    Ok(stored.map(|json| serde_json::from_str(&json)).transpose()?)
 }
 
 // === Types ===
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegStartReq {
     pub user_id: String,
     pub user_name: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct RegStartRes {
+    #[schema(value_type = Object)]
     pub challenge: CreationChallengeResponse,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct RegFinishReq {
     pub user_id: String,
+    #[schema(value_type = Object)]
     pub credential: RegisterPublicKeyCredential,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct AuthStartReq {
     pub user_id: String,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct AuthStartRes {
+    #[schema(value_type = Object)]
     pub challenge: RequestChallengeResponse,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, ToSchema)]
 pub struct AuthFinishReq {
     pub user_id: String,
+    #[schema(value_type = Object)]
     pub credential: PublicKeyCredential,
 }
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct SuccessRes {
     pub success: bool,
 }
 
 // === Handlers ===
 
+#[utoipa::path(
+    post,
+    path = "/webauthn/register/start",
+    request_body = RegStartReq,
+    responses(
+        (status = 200, body = RegStartRes),
+        (status = 401, description = "WebAuthn not configured or ceremony failed"),
+        (status = 429, description = "rate limited, account locked, or too many ceremonies in flight"),
+    )
+)]
 pub async fn register_start(
     State(state): State<AppState>,
     Json(req): Json<RegStartReq>,
@@ -148,53 +260,109 @@ pub async fn register_start(
     let wa = WebAuthnState::require(state.webauthn.as_ref())?;
 
     // Rate limit per user
-    state.rate_limiter.check_user(&req.user_id)
-        .map_err(|e| Error::RateLimited(e.to_string()))?;
+    state.check_user_rate(&req.user_id)?;
+
+    if state.credentials.get(&req.user_id).await?.is_some() {
+        return Err(Error::Unauthorized("user already registered; authenticate and re-enroll".into()));
+    }
+
+    let pending: Option<RegistrationChallenge> = peek_challenge(&state, &reg_key(&req.user_id)).await?;
+    if let Some(challenge) = pending.and_then(|p| p.issued) {
+        return Ok(Json(RegStartRes { challenge }));
+    }
 
-    let user_id = Uuid::parse_str(&req.user_id).unwrap_or_else(|_| Uuid::new_v4());
+    let handle = wa.user_handle(state.credentials.as_ref(), &req.user_id).await?;
 
     let (challenge, reg_state) = wa.core
-        .start_passkey_registration(user_id, &req.user_name, &req.user_name, None)
+        .start_passkey_registration(handle, &req.user_name, &req.user_name, None)
         .map_err(|e| Error::Unauthorized(format!("{:?}", e)))?;
 
-    {
-        let mut challenges = wa.reg_challenges.write().unwrap();
-        WebAuthnState::cleanup_expired(&mut challenges);
-        WebAuthnState::check_capacity(&challenges)?;
-        challenges.insert(req.user_id.into_boxed_str(), ChallengeEntry {
-            data: reg_state,
-            created: Instant::now(),
-        });
+    let pending =
+        RegistrationChallenge { state: reg_state, replaces: false, issued: Some(challenge.clone()), handle: Some(handle) };
+    put_challenge(&state, wa, &req.user_id, &reg_key(&req.user_id), &pending).await?;
+
+    Ok(Json(RegStartRes { challenge }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/webauthn/reenroll/start",
+    request_body = RegStartReq,
+    responses(
+        (status = 200, body = RegStartRes),
+        (status = 401, description = "not registered or no fresh authentication"),
+        (status = 429, description = "rate limited, account locked, or too many ceremonies in flight"),
+    )
+)]
+pub async fn reenroll_start(
+    State(state): State<AppState>,
+    Json(req): Json<RegStartReq>,
+) -> Result<Json<RegStartRes>> {
+    let wa = WebAuthnState::require(state.webauthn.as_ref())?;
+
+    if wa.is_locked_out(&req.user_id)? {
+        return Err(Error::RateLimited("account temporarily locked".into()));
     }
 
+    state.check_user_rate(&req.user_id)?;
+
+    if !wa.take_approval(&req.user_id)? {
+        return Err(Error::Unauthorized("fresh authentication required to re-enroll".into()));
+    }
+
+    let existing = load_passkey(&state, &req.user_id)
+        .await?
+        .map(|passkey| passkey.cred_id().clone())
+        .ok_or_else(|| Error::Unauthorized("user not registered".into()))?;
+
+    let handle = wa.user_handle(state.credentials.as_ref(), &req.user_id).await?;
+
+    let (challenge, reg_state) = wa.core
+        .start_passkey_registration(handle, &req.user_name, &req.user_name, Some(vec![existing]))
+        .map_err(|e| Error::Unauthorized(format!("{:?}", e)))?;
+
+    state.challenges.take(&auth_key(&req.user_id)).await?;
+    let pending = RegistrationChallenge { state: reg_state, replaces: true, issued: None, handle: Some(handle) };
+    put_challenge(&state, wa, &req.user_id, &reg_key(&req.user_id), &pending).await?;
+
     Ok(Json(RegStartRes { challenge }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/webauthn/register/finish",
+    request_body = RegFinishReq,
+    responses(
+        (status = 200, body = SuccessRes),
+        (status = 401, description = "WebAuthn not configured or ceremony failed"),
+        (status = 429, description = "rate limited or account locked"),
+    )
+)]
 pub async fn register_finish(
     State(state): State<AppState>,
     Json(req): Json<RegFinishReq>,
 ) -> Result<Json<SuccessRes>> {
     let wa = WebAuthnState::require(state.webauthn.as_ref())?;
 
-    let entry = wa.reg_challenges
-        .write()
-        .unwrap()
-        .remove(req.user_id.as_str())
-        .ok_or_else(|| Error::Unauthorized("no pending registration".into()))?;
-
-    // Check TTL
-    if entry.created.elapsed() > CHALLENGE_TTL {
-        return Err(Error::Unauthorized("challenge expired".into()));
-    }
+    // Expired challenges are dropped by the store
+    let pending: RegistrationChallenge = take_challenge(&state, &reg_key(&req.user_id))
+        .await?
+        .ok_or_else(|| Error::Unauthorized("no pending registration or challenge expired".into()))?;
 
     let passkey = wa.core
-        .finish_passkey_registration(&req.credential, &entry.data)
+        .finish_passkey_registration(&req.credential, &pending.state)
         .map_err(|e| Error::Unauthorized(format!("{:?}", e)))?;
 
-    wa.credentials
-        .write()
-        .unwrap()
-        .insert(req.user_id.clone().into_boxed_str(), passkey);
+    let stored = serde_json::to_string(&passkey)?;
+    if !state.credentials.insert(&req.user_id, stored, pending.replaces).await? {
+        return Err(Error::Unauthorized("user already registered".into()));
+    }
+    if let Some(handle) = pending.handle {
+        state.credentials.set_user_handle(&req.user_id, handle.to_string()).await?;
+    }
+    if pending.replaces {
+        tracing::info!(user_id = %req.user_id, "credential re-enrolled; previous credential revoked");
+    }
 
     crate::console::log_webauthn_register(&req.user_id);
     state.metrics.record_webauthn_register();
@@ -202,6 +370,16 @@ pub async fn register_finish(
     Ok(Json(SuccessRes { success: true }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/webauthn/auth/start",
+    request_body = AuthStartReq,
+    responses(
+        (status = 200, body = AuthStartRes),
+        (status = 401, description = "WebAuthn not configured or ceremony failed"),
+        (status = 429, description = "rate limited, account locked, or too many ceremonies in flight"),
+    )
+)]
 pub async fn auth_start(
     State(state): State<AppState>,
     Json(req): Json<AuthStartReq>,
@@ -209,40 +387,38 @@ pub async fn auth_start(
     let wa = WebAuthnState::require(state.webauthn.as_ref())?;
 
     // Check lockout
-    if wa.is_locked_out(&req.user_id) {
+    if wa.is_locked_out(&req.user_id)? {
         crate::console::log_webauthn_lockout(&req.user_id);
         state.metrics.record_webauthn_lockout();
         return Err(Error::RateLimited("account temporarily locked".into()));
     }
 
     // Rate limit per user
-    state.rate_limiter.check_user(&req.user_id)
-        .map_err(|e| Error::RateLimited(e.to_string()))?;
-
-    let passkey = wa.credentials
-        .read()
-        .unwrap()
-        .get(req.user_id.as_str())
-        .cloned()
+    state.check_user_rate(&req.user_id)?;
+
+    let passkey = load_passkey(&state, &req.user_id)
+        .await?
         .ok_or_else(|| Error::Unauthorized("user not registered".into()))?;
 
     let (challenge, auth_state) = wa.core
         .start_passkey_authentication(&[passkey])
         .map_err(|e| Error::Unauthorized(format!("{:?}", e)))?;
 
-    {
-        let mut challenges = wa.auth_challenges.write().unwrap();
-        WebAuthnState::cleanup_expired(&mut challenges);
-        WebAuthnState::check_capacity(&challenges)?;
-        challenges.insert(req.user_id.into_boxed_str(), ChallengeEntry {
-            data: auth_state,
-            created: Instant::now(),
-        });
-    }
+    put_challenge(&state, wa, &req.user_id, &auth_key(&req.user_id), &auth_state).await?;
 
     Ok(Json(AuthStartRes { challenge }))
 }
 
+#[utoipa::path(
+    post,
+    path = "/webauthn/auth/finish",
+    request_body = AuthFinishReq,
+    responses(
+        (status = 200, body = SuccessRes),
+        (status = 401, description = "WebAuthn not configured or ceremony failed"),
+        (status = 429, description = "rate limited or account locked"),
+    )
+)]
 pub async fn auth_finish(
     State(state): State<AppState>,
     Json(req): Json<AuthFinishReq>,
@@ -250,33 +426,41 @@ pub async fn auth_finish(
     let wa = WebAuthnState::require(state.webauthn.as_ref())?;
 
     // Check lockout
-    if wa.is_locked_out(&req.user_id) {
+    if wa.is_locked_out(&req.user_id)? {
         return Err(Error::RateLimited("account temporarily locked".into()));
     }
 
-    let entry = wa.auth_challenges
-        .write()
-        .unwrap()
-        .remove(req.user_id.as_str())
-        .ok_or_else(|| Error::Unauthorized("no pending auth".into()))?;
-
-    // Check TTL
-    if entry.created.elapsed() > CHALLENGE_TTL {
-        return Err(Error::Unauthorized("challenge expired".into()));
-    }
-
-    match wa.core.finish_passkey_authentication(&req.credential, &entry.data) {
+    // Expired challenges are dropped by the store
+    let auth_state: PasskeyAuthentication = take_challenge(&state, &auth_key(&req.user_id))
+        .await?
+        .ok_or_else(|| Error::Unauthorized("no pending auth or challenge expired".into()))?;
+
+    let same_user = handle_matches(&state, &req.user_id, req.credential.get_user_unique_id()).await?;
+    let current = load_passkey(&state, &req.user_id).await?.map(|passkey| passkey.cred_id().clone());
+    let verified = wa.core
+        .finish_passkey_authentication(&req.credential, &auth_state)
+        .map_err(|e| format!("{:?}", e))
+        .and_then(|result| match same_user {
+            true => Ok(result),
+            false => Err("user handle does not match the registered credential".to_owned()),
+        })
+        .and_then(|result| match is_current(current.as_ref(), result.cred_id()) {
+            true => Ok(result),
+            false => Err("credential has been replaced or removed".to_owned()),
+        });
+    match verified {
         Ok(_) => {
-            wa.clear_failures(&req.user_id);
+            wa.clear_failures(&req.user_id)?;
+            wa.record_approval(&req.user_id)?;
             crate::console::log_webauthn_auth(&req.user_id);
             state.metrics.record_webauthn_success();
             Ok(Json(SuccessRes { success: true }))
         }
-        Err(e) => {
-            wa.record_failure(&req.user_id);
+        Err(reason) => {
+            wa.record_failure(&req.user_id)?;
             crate::console::log_webauthn_failure(&req.user_id);
             state.metrics.record_webauthn_failure();
-            Err(Error::Unauthorized(format!("{:?}", e)))
+            Err(Error::Unauthorized(reason))
         }
     }
 }
@@ -286,34 +470,161 @@ mod tests {
     use super::*;
 
     #[test]
-    fn from_env_returns_none_when_not_set() {
-        std::env::remove_var("WEBAUTHN_RP_ID");
-        std::env::remove_var("WEBAUTHN_RP_ORIGIN");
-        assert!(WebAuthnState::from_env().is_none());
-    }
-
-    #[test]
-    fn lockout_after_threshold() {
+    fn lockout_after_threshold() -> Result<()> {
         let wa = WebAuthnState::new("test.com", "https://test.com").unwrap();
         
         for _ in 0..LOCKOUT_THRESHOLD {
-            wa.record_failure("alice");
+            wa.record_failure("alice")?;
         }
         
-        assert!(wa.is_locked_out("alice"));
-        assert!(!wa.is_locked_out("bob"));
+        assert!(wa.is_locked_out("alice")?);
+        assert!(!wa.is_locked_out("bob")?);
+        Ok(())
     }
 
     #[test]
-    fn clear_failures_removes_lockout() {
+    fn clear_failures_removes_lockout() -> Result<()> {
         let wa = WebAuthnState::new("test.com", "https://test.com").unwrap();
         
         for _ in 0..LOCKOUT_THRESHOLD {
-            wa.record_failure("alice");
+            wa.record_failure("alice")?;
         }
         
-        assert!(wa.is_locked_out("alice"));
-        wa.clear_failures("alice");
-        assert!(!wa.is_locked_out("alice"));
+        assert!(wa.is_locked_out("alice")?);
+        wa.clear_failures("alice")?;
+        assert!(!wa.is_locked_out("alice")?);
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn state_with_webauthn() -> Result<AppState> {
+        let wa = WebAuthnState::new("test.com", "https://test.com")
+            .map_err(|e| Error::Config(format!("{e:?}")))?;
+        crate::state::StateBuilder { webauthn: Some(wa), ..crate::state::test_builder()? }.build()
+    }
+
+    fn reenroll_req() -> Json<RegStartReq> {
+        Json(RegStartReq { user_id: "alice".into(), user_name: "alice".into() })
+    }
+
+    #[tokio::test]
+    async fn repeated_register_start_reuses_live_challenge() -> Result<()> {
+        let state = state_with_webauthn()?;
+        let Json(sent) = register_start(State(state.clone()), reenroll_req()).await?;
+        let first = state.challenges.peek(&reg_key("alice")).await?;
+        let Json(resent) = register_start(State(state.clone()), reenroll_req()).await?;
+        assert!(first.is_some());
+        assert_eq!(state.challenges.peek(&reg_key("alice")).await?, first);
+        assert_eq!(serde_json::to_value(&resent.challenge)?, serde_json::to_value(&sent.challenge)?);
+
+        state.challenges.take(&reg_key("alice")).await?;
+        assert!(state.challenges.peek(&reg_key("alice")).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn per_user_challenge_cap_enforced() -> Result<()> {
+        let wa = WebAuthnState::new("test.com", "https://test.com")
+            .map_err(|e| Error::Config(format!("{e:?}")))?
+            .with_challenge_cap(1);
+        let state = crate::state::StateBuilder { webauthn: Some(wa), ..crate::state::test_builder()? }.build()?;
+        let wa = WebAuthnState::require(state.webauthn.as_ref())?;
+
+        put_challenge(&state, wa, "alice", &reg_key("alice"), &"reg").await?;
+        put_challenge(&state, wa, "alice", &reg_key("alice"), &"reg again").await?;
+        let second = put_challenge(&state, wa, "alice", &auth_key("alice"), &"auth").await;
+        assert!(matches!(second, Err(Error::RateLimited(_))));
+        put_challenge(&state, wa, "bob", &auth_key("bob"), &"auth").await?;
+
+        state.challenges.take(&reg_key("alice")).await?;
+        put_challenge(&state, wa, "alice", &auth_key("alice"), &"auth").await?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn registration_handle_is_the_one_authentication_checks() -> Result<()> {
+        let state = state_with_webauthn()?;
+        let wa = WebAuthnState::require(state.webauthn.as_ref())?;
+        let credentials = state.credentials.as_ref();
+        let uuid = Uuid::new_v4();
+        assert_eq!(wa.user_handle(credentials, &uuid.to_string()).await?, uuid);
+
+        let Json(_) = register_start(State(state.clone()), reenroll_req()).await?;
+        let pending: Option<RegistrationChallenge> = peek_challenge(&state, &reg_key("alice")).await?;
+        let handle = pending.and_then(|p| p.handle).ok_or_else(|| Error::Validation("no handle".into()))?;
+        credentials.set_user_handle("alice", handle.to_string()).await?;
+
+        assert_eq!(wa.user_handle(credentials, "alice").await?, handle);
+        assert!(handle_matches(&state, "alice", Some(handle.as_bytes())).await?);
+        assert!(handle_matches(&state, "alice", None).await?);
+        assert!(!handle_matches(&state, "alice", Some(Uuid::new_v4().as_bytes())).await?);
+        assert_ne!(wa.user_handle(credentials, "bob").await?, handle);
+        assert!(handle_matches(&state, "bob", Some(handle.as_bytes())).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn strict_mode_rejects_non_uuid_user_id() -> Result<()> {
+        let wa = WebAuthnState::new("test.com", "https://test.com")
+            .map_err(|e| Error::Config(format!("{e:?}")))?
+            .with_strict_user_ids(true);
+        let state = crate::state::StateBuilder { webauthn: Some(wa), ..crate::state::test_builder()? }.build()?;
+
+        let result = register_start(State(state.clone()), reenroll_req()).await;
+        assert!(matches!(result, Err(Error::Validation(_))));
+        assert!(state.challenges.peek(&reg_key("alice")).await?.is_none());
+
+        let user_id = Uuid::new_v4().to_string();
+        let req = Json(RegStartReq { user_id: user_id.clone(), user_name: "alice".into() });
+        let Json(_) = register_start(State(state.clone()), req).await?;
+        assert!(state.challenges.peek(&reg_key(&user_id)).await?.is_some());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn reenroll_requires_fresh_authentication() -> Result<()> {
+        let state = state_with_webauthn()?;
+        let result = reenroll_start(State(state.clone()), reenroll_req()).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        assert!(state.challenges.take(&reg_key("alice")).await?.is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn approval_is_single_use() -> Result<()> {
+        let wa = WebAuthnState::new("test.com", "https://test.com")
+            .map_err(|e| Error::Config(format!("{e:?}")))?;
+        assert!(!wa.take_approval("alice")?);
+        wa.record_approval("alice")?;
+        assert!(wa.take_approval("alice")?);
+        assert!(!wa.take_approval("alice")?);
+        Ok(())
+    }
+
+    #[test]
+    fn stale_approval_rejected() -> Result<()> {
+        let wa = WebAuthnState::new("test.com", "https://test.com")
+            .map_err(|e| Error::Config(format!("{e:?}")))?;
+        let stale = Instant::now()
+            .checked_sub(REENROLL_WINDOW + Duration::from_secs(1))
+            .ok_or_else(|| Error::Validation("monotonic clock younger than the re-enroll window".into()))?;
+        wa.approvals.write().map_err(lock_err("webauthn approvals"))?.insert("alice".into(), stale);
+        assert!(!wa.take_approval("alice")?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn removed_or_replaced_credential_rejected() -> Result<()> {
+        let old = CredentialID::from(vec![1u8; 16]);
+        let new = CredentialID::from(vec![2u8; 16]);
+        assert!(is_current(Some(&new), &new));
+        assert!(!is_current(Some(&new), &old));
+        assert!(!is_current(None, &old));
+
+        let state = state_with_webauthn()?;
+        let req = Json(AuthStartReq { user_id: "alice".into() });
+        let result = auth_start(State(state.clone()), req).await;
+        assert!(matches!(result, Err(Error::Unauthorized(_))));
+        assert!(state.challenges.peek(&auth_key("alice")).await?.is_none());
+        Ok(())
+    }
+}